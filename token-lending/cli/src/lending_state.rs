@@ -1,6 +1,6 @@
 use solana_program::instruction::Instruction;
 use solend_sdk::instruction::{
-    refresh_obligation, refresh_reserve, withdraw_obligation_collateral,
+    refresh_obligation_with_lending_market, refresh_reserve, withdraw_obligation_collateral,
 };
 use solend_sdk::state::{Obligation, Reserve};
 
@@ -76,6 +76,8 @@ impl SolendState {
                 reserve.liquidity.pyth_oracle_pubkey,
                 reserve.liquidity.switchboard_oracle_pubkey,
                 reserve.config.extra_oracle_pubkey,
+                reserve.config.underlying_oracle_pubkey,
+                Some(reserve.lending_market),
             )
         }));
 
@@ -87,10 +89,11 @@ impl SolendState {
         };
 
         // refresh obligation
-        instructions.push(refresh_obligation(
+        instructions.push(refresh_obligation_with_lending_market(
             self.lending_program_id,
             self.obligation_pubkey,
             reserve_pubkeys,
+            self.obligation.lending_market,
         ));
 
         instructions
@@ -1,7 +1,12 @@
 use lending_state::SolendState;
+use oracles::{health::get_oracle_health, OracleProgramIds};
 use solana_account_decoder::UiAccountEncoding;
 use solana_client::rpc_config::{RpcProgramAccountsConfig, RpcSendTransactionConfig};
-use solana_client::{rpc_config::RpcAccountInfoConfig, rpc_filter::RpcFilterType};
+use solana_client::{
+    rpc_config::RpcAccountInfoConfig,
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{account::from_account, sysvar};
 use solana_sdk::{commitment_config::CommitmentLevel, compute_budget::ComputeBudgetInstruction};
 use solend_program::{
     instruction::set_lending_market_owner_and_config,
@@ -10,10 +15,10 @@ use solend_program::{
 use solend_sdk::{
     instruction::{
         liquidate_obligation_and_redeem_reserve_collateral, redeem_reserve_collateral,
-        refresh_obligation, refresh_reserve,
+        refresh_obligation_with_lending_market, refresh_reserve,
     },
     state::Obligation,
-    state::ReserveType,
+    state::{PegMode, ReserveType},
 };
 
 mod lending_state;
@@ -31,7 +36,8 @@ use {
     },
     solana_client::rpc_client::RpcClient,
     solana_program::{
-        message::Message, native_token::lamports_to_sol, program_pack::Pack, pubkey::Pubkey,
+        account_info::AccountInfo, clock::Clock, message::Message, native_token::lamports_to_sol,
+        program_pack::Pack, pubkey::Pubkey,
     },
     solana_sdk::{
         commitment_config::CommitmentConfig,
@@ -41,9 +47,13 @@ use {
     },
     solend_sdk::{
         self,
-        instruction::{init_lending_market, init_reserve, update_reserve_config},
+        instruction::{
+            accept_market_owner, find_pending_market_owner_address, init_lending_market,
+            init_reserve, propose_market_owner, set_market_paused,
+            update_reserve_config_with_liquidation_threshold_ramp,
+        },
         math::WAD,
-        state::{LendingMarket, Reserve, ReserveConfig, ReserveFees},
+        state::{LendingMarket, PendingMarketOwner, Reserve, ReserveConfig, ReserveFees},
     },
     spl_token::{
         amount_to_ui_amount,
@@ -107,10 +117,18 @@ struct PartialReserveConfig {
     pub rate_limiter_window_duration: Option<u64>,
     /// Rate Limiter's max outflow per window
     pub rate_limiter_max_outflow: Option<u64>,
+    /// Whether the Rate Limiter credits inflows against outflows
+    pub rate_limiter_net_flow: Option<bool>,
     /// Added borrow weight in basis points
     pub added_borrow_weight_bps: Option<u64>,
     /// Type of the reseerve (Regular, Isolated)
     pub reserve_type: Option<ReserveType>,
+    /// Underlying price oracle for stake pool reserves
+    pub underlying_oracle_pubkey: Option<Pubkey>,
+    /// Pegged price mode
+    pub peg_mode: Option<PegMode>,
+    /// Max deviation from the peg price in basis points
+    pub peg_max_deviation_bps: Option<u16>,
 }
 
 /// Reserve Fees with optional fields
@@ -221,6 +239,41 @@ fn main() {
             SubCommand::with_name("view-all-markets")
                 .about("View all markets")
         )
+        .subcommand(
+            SubCommand::with_name("check-oracles")
+                .about("Check the oracles of every reserve in a market")
+                .arg(
+                    Arg::with_name("market")
+                        .long("market")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("market pubkey"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("view-limits")
+                .about("View the outflow rate limits for borrows and withdraws against a reserve")
+                .arg(
+                    Arg::with_name("reserve")
+                        .long("reserve")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("reserve pubkey"),
+                )
+                .arg(
+                    Arg::with_name("slot")
+                        .long("slot")
+                        .validator(is_parsable::<u64>)
+                        .value_name("SLOT")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Slot to project the limits at. Defaults to the current slot"),
+                )
+        )
         .subcommand(
             SubCommand::with_name("view-obligation")
                 .about("View obligation")
@@ -449,6 +502,15 @@ fn main() {
                         .required(true)
                         .help("Switchboard price feed account: https://switchboard.xyz/#/explorer"),
                 )
+                .arg(
+                    Arg::with_name("underlying_oracle")
+                        .long("underlying-oracle")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Underlying price oracle, when --pyth-price is an spl stake pool account"),
+                )
                 .arg(
                     Arg::with_name("optimal_utilization_rate")
                         .long("optimal-utilization-rate")
@@ -649,6 +711,26 @@ fn main() {
                         .default_value("Regular")
                         .help("Reserve type"),
                 )
+                .arg(
+                    Arg::with_name("peg_mode")
+                        .long("peg-mode")
+                        .validator(is_parsable::<PegMode>)
+                        .value_name("PEG_MODE")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("Disabled")
+                        .help("Pegged price mode: Disabled, FallbackToOracle or HaltBorrows"),
+                )
+                .arg(
+                    Arg::with_name("peg_max_deviation_bps")
+                        .long("peg-max-deviation-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("0")
+                        .help("Max deviation of the oracle price from $1 before a pegged reserve is depegged"),
+                )
         )
         .subcommand(
             SubCommand::with_name("set-lending-market-owner-and-config")
@@ -671,15 +753,6 @@ fn main() {
                         .required(true)
                         .help("Lending market address"),
                 )
-                .arg(
-                    Arg::with_name("new_lending_market_owner")
-                        .long("new-lending-market-owner")
-                        .validator(is_keypair)
-                        .value_name("KEYPAIR")
-                        .takes_value(true)
-                        .required(false)
-                        .help("Owner of the lending market"),
-                )
                 .arg(
                     Arg::with_name("rate_limiter_window_duration")
                         .long("rate-limiter-window-duration")
@@ -698,6 +771,51 @@ fn main() {
                         .required(false)
                         .help("Rate Limiter max outflow denominated in dollars within 1 window"),
                 )
+                .arg(
+                    Arg::with_name("rate_limiter_net_flow")
+                        .long("rate-limiter-net-flow")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Whether deposits and repays are credited against the market's outflow within 1 window"),
+                )
+                .arg(
+                    Arg::with_name("obligation_max_outflow")
+                        .long("obligation-max-outflow")
+                        .validator(is_parsable::<u32>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Max outflow from a single obligation denominated in dollars within 1 obligation outflow window. 0 disables the limit"),
+                )
+                .arg(
+                    Arg::with_name("obligation_outflow_window")
+                        .long("obligation-outflow-window")
+                        .validator(is_parsable::<u64>)
+                        .value_name("SLOTS")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Length of the sliding window obligation outflows are limited in"),
+                )
+                .arg(
+                    Arg::with_name("global_allowed_borrow_value")
+                        .long("global-allowed-borrow-value")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Cap on the allowed borrow value of any obligation, denominated in dollars"),
+                )
+                .arg(
+                    Arg::with_name("global_unhealthy_borrow_value")
+                        .long("global-unhealthy-borrow-value")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Cap on the unhealthy borrow value of any obligation, denominated in dollars"),
+                )
                 .arg(
                     Arg::with_name("whitelisted_liquidator")
                         .long("whitelisted-liquidator")
@@ -716,6 +834,103 @@ fn main() {
                         .help("Risk authority address"),
                 )
         )
+        .subcommand(
+            SubCommand::with_name("propose-market-owner")
+                .about("Propose a new lending market owner, which has to accept the ownership")
+                .arg(
+                    Arg::with_name("lending_market_owner")
+                        .long("lending-market-owner")
+                        .validator(is_keypair)
+                        .value_name("KEYPAIR")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Owner of the lending market"),
+                )
+                .arg(
+                    Arg::with_name("lending_market")
+                        .long("market")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Lending market address"),
+                )
+                .arg(
+                    Arg::with_name("new_lending_market_owner")
+                        .long("new-lending-market-owner")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Proposed owner of the lending market"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("accept-market-owner")
+                .about("Accept the ownership of a lending market")
+                .arg(
+                    Arg::with_name("new_lending_market_owner")
+                        .long("new-lending-market-owner")
+                        .validator(is_keypair)
+                        .value_name("KEYPAIR")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Proposed owner of the lending market"),
+                )
+                .arg(
+                    Arg::with_name("lending_market")
+                        .long("market")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Lending market address"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("pause-market")
+                .about("Pause deposits, borrows, withdraws, flash borrows and liquidations in a lending market")
+                .arg(
+                    Arg::with_name("signer")
+                        .long("signer")
+                        .validator(is_keypair)
+                        .value_name("KEYPAIR")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Lending market owner, risk authority or pause guardian"),
+                )
+                .arg(
+                    Arg::with_name("lending_market")
+                        .long("market")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Lending market address"),
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("unpause-market")
+                .about("Unpause a lending market")
+                .arg(
+                    Arg::with_name("lending_market_owner")
+                        .long("lending-market-owner")
+                        .validator(is_keypair)
+                        .value_name("KEYPAIR")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Owner of the lending market"),
+                )
+                .arg(
+                    Arg::with_name("lending_market")
+                        .long("market")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Lending market address"),
+                )
+        )
         .subcommand(
             SubCommand::with_name("update-reserve")
                 .about("Update a reserve config")
@@ -945,6 +1160,15 @@ fn main() {
                         .required(false)
                         .help("Switchboard price feed account: https://switchboard.xyz/#/explorer"),
                 )
+                .arg(
+                    Arg::with_name("underlying_oracle")
+                        .long("underlying-oracle")
+                        .validator(is_pubkey)
+                        .value_name("PUBKEY")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Underlying price oracle, when --pyth-price is an spl stake pool account"),
+                )
                 .arg(
                     Arg::with_name("rate_limiter_window_duration")
                         .long("rate-limiter-window-duration")
@@ -963,6 +1187,15 @@ fn main() {
                         .required(false)
                         .help("Rate Limiter max outflow of token amounts within 1 window"),
                 )
+                .arg(
+                    Arg::with_name("rate_limiter_net_flow")
+                        .long("rate-limiter-net-flow")
+                        .validator(is_parsable::<bool>)
+                        .value_name("BOOL")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Whether deposits and repays are credited against the reserve's outflow within 1 window"),
+                )
                 .arg(
                     Arg::with_name("added_borrow_weight_bps")
                         .long("added-borrow-weight-bps")
@@ -982,6 +1215,34 @@ fn main() {
                         .default_value("Regular")
                         .help("Reserve type"),
                 )
+                .arg(
+                    Arg::with_name("peg_mode")
+                        .long("peg-mode")
+                        .validator(is_parsable::<PegMode>)
+                        .value_name("PEG_MODE")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Pegged price mode: Disabled, FallbackToOracle or HaltBorrows"),
+                )
+                .arg(
+                    Arg::with_name("peg_max_deviation_bps")
+                        .long("peg-max-deviation-bps")
+                        .validator(is_parsable::<u16>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .help("Max deviation of the oracle price from $1 before a pegged reserve is depegged"),
+                )
+                .arg(
+                    Arg::with_name("liquidation_threshold_ramp_slots")
+                        .long("liquidation-threshold-ramp-slots")
+                        .validator(is_parsable::<u64>)
+                        .value_name("INTEGER")
+                        .takes_value(true)
+                        .required(false)
+                        .default_value("0")
+                        .help("Number of slots over which lowered liquidation thresholds take effect"),
+                )
         )
         .get_matches();
 
@@ -1025,7 +1286,35 @@ fn main() {
         ("view-reserve", Some(arg_matches)) => {
             let reserve = pubkey_of(arg_matches, "reserve").unwrap();
             let data = config.rpc_client.get_account_data(&reserve).unwrap();
-            print!("{:#?}", Reserve::unpack(&data));
+            let reserve = Reserve::unpack(&data);
+            print!("{:#?}", reserve);
+
+            let ramp = reserve
+                .as_ref()
+                .ok()
+                .and_then(|reserve| reserve.liquidation_threshold_ramp);
+            if let (Ok(reserve), Some(ramp)) = (&reserve, ramp) {
+                let slot = config.rpc_client.get_slot().unwrap();
+                println!();
+                println!(
+                    "Liquidation threshold ramp: {}% complete, slots {} to {}",
+                    ramp.progress(slot),
+                    ramp.start_slot,
+                    ramp.end_slot
+                );
+                println!(
+                    "  liquidation threshold: {}% -> {}%, currently {}%",
+                    ramp.start_liquidation_threshold,
+                    reserve.config.liquidation_threshold,
+                    reserve.liquidation_threshold(slot)
+                );
+                println!(
+                    "  max liquidation threshold: {}% -> {}%, currently {}%",
+                    ramp.start_max_liquidation_threshold,
+                    reserve.config.max_liquidation_threshold,
+                    reserve.max_liquidation_threshold(slot)
+                );
+            }
 
             Ok(())
         }
@@ -1065,6 +1354,15 @@ fn main() {
 
             Ok(())
         }
+        ("view-limits", Some(arg_matches)) => {
+            let reserve = pubkey_of(arg_matches, "reserve").unwrap();
+            let slot = value_of(arg_matches, "slot");
+            command_view_limits(&config, reserve, slot)
+        }
+        ("check-oracles", Some(arg_matches)) => {
+            let market = pubkey_of(arg_matches, "market").unwrap();
+            command_check_oracles(&config, market)
+        }
         ("create-market", Some(arg_matches)) => {
             let lending_market_owner = pubkey_of(arg_matches, "lending_market_owner").unwrap();
             let quote_currency = quote_currency_of(arg_matches, "quote_currency").unwrap();
@@ -1147,6 +1445,9 @@ fn main() {
                 value_of(arg_matches, "attributed_borrow_limit_open").unwrap();
             let attributed_borrow_limit_close =
                 value_of(arg_matches, "attributed_borrow_limit_close").unwrap();
+            let underlying_oracle_pubkey = pubkey_of(arg_matches, "underlying_oracle");
+            let peg_mode = value_of(arg_matches, "peg_mode").unwrap();
+            let peg_max_deviation_bps = value_of(arg_matches, "peg_max_deviation_bps").unwrap();
 
             let borrow_fee_wad = (borrow_fee * WAD as f64) as u64;
             let flash_loan_fee_wad = (flash_loan_fee * WAD as f64) as u64;
@@ -1204,6 +1505,9 @@ fn main() {
                     extra_oracle_pubkey: Some(extra_oracle_pubkey),
                     attributed_borrow_limit_open,
                     attributed_borrow_limit_close,
+                    underlying_oracle_pubkey,
+                    peg_mode,
+                    peg_max_deviation_bps,
                 },
                 source_liquidity_pubkey,
                 source_liquidity_owner_keypair,
@@ -1220,24 +1524,71 @@ fn main() {
             let lending_market_owner_keypair =
                 keypair_of(arg_matches, "lending_market_owner").unwrap();
             let lending_market_pubkey = pubkey_of(arg_matches, "lending_market").unwrap();
-            let new_lending_market_owner_keypair =
-                keypair_of(arg_matches, "new_lending_market_owner");
             let rate_limiter_window_duration =
                 value_of(arg_matches, "rate_limiter_window_duration");
             let rate_limiter_max_outflow = value_of(arg_matches, "rate_limiter_max_outflow");
+            let rate_limiter_net_flow = value_of(arg_matches, "rate_limiter_net_flow");
+            let obligation_max_outflow = value_of(arg_matches, "obligation_max_outflow");
+            let obligation_outflow_window = value_of(arg_matches, "obligation_outflow_window");
+            let global_allowed_borrow_value = value_of(arg_matches, "global_allowed_borrow_value");
+            let global_unhealthy_borrow_value =
+                value_of(arg_matches, "global_unhealthy_borrow_value");
             let whitelisted_liquidator_pubkey = pubkey_of(arg_matches, "whitelisted_liquidator");
             let risk_authority_pubkey = pubkey_of(arg_matches, "risk_authority").unwrap();
             command_set_lending_market_owner_and_config(
                 &mut config,
                 lending_market_pubkey,
                 lending_market_owner_keypair,
-                new_lending_market_owner_keypair,
                 rate_limiter_window_duration,
                 rate_limiter_max_outflow,
+                rate_limiter_net_flow,
+                obligation_max_outflow,
+                obligation_outflow_window,
+                global_allowed_borrow_value,
+                global_unhealthy_borrow_value,
                 whitelisted_liquidator_pubkey,
                 risk_authority_pubkey,
             )
         }
+        ("propose-market-owner", Some(arg_matches)) => {
+            let lending_market_owner_keypair =
+                keypair_of(arg_matches, "lending_market_owner").unwrap();
+            let lending_market_pubkey = pubkey_of(arg_matches, "lending_market").unwrap();
+            let new_lending_market_owner_pubkey =
+                pubkey_of(arg_matches, "new_lending_market_owner").unwrap();
+            command_propose_market_owner(
+                &mut config,
+                lending_market_pubkey,
+                lending_market_owner_keypair,
+                new_lending_market_owner_pubkey,
+            )
+        }
+        ("accept-market-owner", Some(arg_matches)) => {
+            let new_lending_market_owner_keypair =
+                keypair_of(arg_matches, "new_lending_market_owner").unwrap();
+            let lending_market_pubkey = pubkey_of(arg_matches, "lending_market").unwrap();
+            command_accept_market_owner(
+                &mut config,
+                lending_market_pubkey,
+                new_lending_market_owner_keypair,
+            )
+        }
+        ("pause-market", Some(arg_matches)) => {
+            let signer_keypair = keypair_of(arg_matches, "signer").unwrap();
+            let lending_market_pubkey = pubkey_of(arg_matches, "lending_market").unwrap();
+            command_set_market_paused(&mut config, lending_market_pubkey, signer_keypair, true)
+        }
+        ("unpause-market", Some(arg_matches)) => {
+            let lending_market_owner_keypair =
+                keypair_of(arg_matches, "lending_market_owner").unwrap();
+            let lending_market_pubkey = pubkey_of(arg_matches, "lending_market").unwrap();
+            command_set_market_paused(
+                &mut config,
+                lending_market_pubkey,
+                lending_market_owner_keypair,
+                false,
+            )
+        }
         ("update-reserve", Some(arg_matches)) => {
             let reserve_pubkey = pubkey_of(arg_matches, "reserve").unwrap();
             let lending_market_owner_keypair =
@@ -1268,8 +1619,14 @@ fn main() {
             let rate_limiter_window_duration =
                 value_of(arg_matches, "rate_limiter_window_duration");
             let rate_limiter_max_outflow = value_of(arg_matches, "rate_limiter_max_outflow");
+            let rate_limiter_net_flow = value_of(arg_matches, "rate_limiter_net_flow");
             let added_borrow_weight_bps = value_of(arg_matches, "added_borrow_weight_bps");
             let reserve_type = value_of(arg_matches, "reserve_type");
+            let underlying_oracle_pubkey = pubkey_of(arg_matches, "underlying_oracle");
+            let peg_mode = value_of(arg_matches, "peg_mode");
+            let peg_max_deviation_bps = value_of(arg_matches, "peg_max_deviation_bps");
+            let liquidation_threshold_ramp_slots =
+                value_of(arg_matches, "liquidation_threshold_ramp_slots").unwrap();
 
             let borrow_fee_wad = borrow_fee.map(|fee| (fee * WAD as f64) as u64);
             let flash_loan_fee_wad = flash_loan_fee.map(|fee| (fee * WAD as f64) as u64);
@@ -1300,9 +1657,14 @@ fn main() {
                     protocol_take_rate,
                     rate_limiter_window_duration,
                     rate_limiter_max_outflow,
+                    rate_limiter_net_flow,
                     added_borrow_weight_bps,
                     reserve_type,
+                    underlying_oracle_pubkey,
+                    peg_mode,
+                    peg_max_deviation_bps,
                 },
+                liquidation_threshold_ramp_slots,
                 pyth_product_pubkey,
                 pyth_price_pubkey,
                 switchboard_feed_pubkey,
@@ -1553,14 +1915,17 @@ fn command_liquidate_obligation(
             reserve.liquidity.pyth_oracle_pubkey,
             reserve.liquidity.switchboard_oracle_pubkey,
             reserve.config.extra_oracle_pubkey,
+            reserve.config.underlying_oracle_pubkey,
+            Some(reserve.lending_market),
         )
     }));
 
     // refresh obligation
-    instructions.push(refresh_obligation(
+    instructions.push(refresh_obligation_with_lending_market(
         config.lending_program_id,
         obligation_pubkey,
         reserve_pubkeys,
+        obligation_state.lending_market,
     ));
 
     instructions.push(liquidate_obligation_and_redeem_reserve_collateral(
@@ -1815,9 +2180,13 @@ fn command_set_lending_market_owner_and_config(
     config: &mut Config,
     lending_market_pubkey: Pubkey,
     lending_market_owner_keypair: Keypair,
-    new_lending_market_owner_keypair: Option<Keypair>,
     rate_limiter_window_duration: Option<u64>,
     rate_limiter_max_outflow: Option<u64>,
+    rate_limiter_net_flow: Option<bool>,
+    obligation_max_outflow: Option<u32>,
+    obligation_outflow_window: Option<u64>,
+    global_allowed_borrow_value: Option<u64>,
+    global_unhealthy_borrow_value: Option<u64>,
     whitelisted_liquidator_pubkey: Option<Pubkey>,
     risk_authority_pubkey: Pubkey,
 ) -> CommandResult {
@@ -1831,19 +2200,49 @@ fn command_set_lending_market_owner_and_config(
             config.lending_program_id,
             lending_market_pubkey,
             lending_market_owner_keypair.pubkey(),
-            if let Some(owner) = new_lending_market_owner_keypair {
-                owner.pubkey()
-            } else {
-                lending_market.owner
-            },
+            lending_market.owner,
             RateLimiterConfig {
                 window_duration: rate_limiter_window_duration
                     .unwrap_or(lending_market.rate_limiter.config.window_duration),
                 max_outflow: rate_limiter_max_outflow
                     .unwrap_or(lending_market.rate_limiter.config.max_outflow),
+                net_flow: rate_limiter_net_flow
+                    .unwrap_or(lending_market.rate_limiter.config.net_flow),
             },
             whitelisted_liquidator_pubkey,
             risk_authority_pubkey,
+            obligation_max_outflow.unwrap_or(lending_market.obligation_max_outflow),
+            obligation_outflow_window.unwrap_or(lending_market.obligation_outflow_window),
+            global_allowed_borrow_value.unwrap_or(lending_market.global_allowed_borrow_value),
+            global_unhealthy_borrow_value.unwrap_or(lending_market.global_unhealthy_borrow_value),
+        )],
+        Some(&config.fee_payer.pubkey()),
+        &recent_blockhash,
+    );
+
+    let transaction = Transaction::new(
+        &vec![config.fee_payer.as_ref(), &lending_market_owner_keypair],
+        message,
+        recent_blockhash,
+    );
+
+    send_transaction(config, transaction)?;
+    Ok(())
+}
+
+fn command_propose_market_owner(
+    config: &mut Config,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_keypair: Keypair,
+    new_lending_market_owner_pubkey: Pubkey,
+) -> CommandResult {
+    let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
+    let message = Message::new_with_blockhash(
+        &[propose_market_owner(
+            config.lending_program_id,
+            new_lending_market_owner_pubkey,
+            lending_market_pubkey,
+            lending_market_owner_keypair.pubkey(),
         )],
         Some(&config.fee_payer.pubkey()),
         &recent_blockhash,
@@ -1859,10 +2258,75 @@ fn command_set_lending_market_owner_and_config(
     Ok(())
 }
 
+fn command_set_market_paused(
+    config: &mut Config,
+    lending_market_pubkey: Pubkey,
+    signer_keypair: Keypair,
+    paused: bool,
+) -> CommandResult {
+    let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
+    let message = Message::new_with_blockhash(
+        &[set_market_paused(
+            config.lending_program_id,
+            paused,
+            lending_market_pubkey,
+            signer_keypair.pubkey(),
+        )],
+        Some(&config.fee_payer.pubkey()),
+        &recent_blockhash,
+    );
+
+    let transaction = Transaction::new(
+        &vec![config.fee_payer.as_ref(), &signer_keypair],
+        message,
+        recent_blockhash,
+    );
+
+    send_transaction(config, transaction)?;
+    Ok(())
+}
+
+fn command_accept_market_owner(
+    config: &mut Config,
+    lending_market_pubkey: Pubkey,
+    new_lending_market_owner_keypair: Keypair,
+) -> CommandResult {
+    let (pending_market_owner_pubkey, _) =
+        find_pending_market_owner_address(config.lending_program_id, lending_market_pubkey);
+    let pending_market_owner_info = config
+        .rpc_client
+        .get_account(&pending_market_owner_pubkey)?;
+    let pending_market_owner =
+        *PendingMarketOwner::from_account_data(&pending_market_owner_info.data)?;
+    println!("{:#?}", pending_market_owner);
+
+    let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
+    let message = Message::new_with_blockhash(
+        &[accept_market_owner(
+            config.lending_program_id,
+            lending_market_pubkey,
+            new_lending_market_owner_keypair.pubkey(),
+            pending_market_owner.proposed_by,
+        )],
+        Some(&config.fee_payer.pubkey()),
+        &recent_blockhash,
+    );
+
+    let transaction = Transaction::new(
+        &vec![config.fee_payer.as_ref(), &new_lending_market_owner_keypair],
+        message,
+        recent_blockhash,
+    );
+
+    send_transaction(config, transaction)?;
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments, clippy::unnecessary_unwrap)]
 fn command_update_reserve(
     config: &mut Config,
     reserve_config: PartialReserveConfig,
+    liquidation_threshold_ramp_slots: u64,
     pyth_product_pubkey: Option<Pubkey>,
     pyth_price_pubkey: Option<Pubkey>,
     switchboard_feed_pubkey: Option<Pubkey>,
@@ -2165,6 +2629,18 @@ fn command_update_reserve(
         reserve.rate_limiter.config.max_outflow = reserve_config.rate_limiter_max_outflow.unwrap();
     }
 
+    if reserve_config.rate_limiter_net_flow.is_some()
+        && reserve.rate_limiter.config.net_flow != reserve_config.rate_limiter_net_flow.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating rate_limiter_net_flow from {} to {}",
+            reserve.rate_limiter.config.net_flow,
+            reserve_config.rate_limiter_net_flow.unwrap(),
+        );
+        reserve.rate_limiter.config.net_flow = reserve_config.rate_limiter_net_flow.unwrap();
+    }
+
     if reserve_config.added_borrow_weight_bps.is_some()
         && reserve.config.added_borrow_weight_bps != reserve_config.added_borrow_weight_bps.unwrap()
     {
@@ -2189,6 +2665,42 @@ fn command_update_reserve(
         reserve.config.reserve_type = reserve_config.reserve_type.unwrap();
     }
 
+    if reserve_config.underlying_oracle_pubkey.is_some()
+        && reserve.config.underlying_oracle_pubkey != reserve_config.underlying_oracle_pubkey
+    {
+        no_change = false;
+        println!(
+            "Updating underlying_oracle_pubkey from {:?} to {}",
+            reserve.config.underlying_oracle_pubkey,
+            reserve_config.underlying_oracle_pubkey.unwrap(),
+        );
+        reserve.config.underlying_oracle_pubkey = reserve_config.underlying_oracle_pubkey;
+    }
+
+    if reserve_config.peg_mode.is_some()
+        && reserve.config.peg_mode != reserve_config.peg_mode.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating peg_mode from {:?} to {:?}",
+            reserve.config.peg_mode,
+            reserve_config.peg_mode.unwrap(),
+        );
+        reserve.config.peg_mode = reserve_config.peg_mode.unwrap();
+    }
+
+    if reserve_config.peg_max_deviation_bps.is_some()
+        && reserve.config.peg_max_deviation_bps != reserve_config.peg_max_deviation_bps.unwrap()
+    {
+        no_change = false;
+        println!(
+            "Updating peg_max_deviation_bps from {} to {}",
+            reserve.config.peg_max_deviation_bps,
+            reserve_config.peg_max_deviation_bps.unwrap(),
+        );
+        reserve.config.peg_max_deviation_bps = reserve_config.peg_max_deviation_bps.unwrap();
+    }
+
     if validate_reserve_config(reserve.config).is_err() {
         println!("Error: invalid reserve config");
         return Err("Error: invalid reserve config".into());
@@ -2202,13 +2714,15 @@ fn command_update_reserve(
     let recent_blockhash = config.rpc_client.get_latest_blockhash()?;
 
     let message = Message::new_with_blockhash(
-        &[update_reserve_config(
+        &[update_reserve_config_with_liquidation_threshold_ramp(
             config.lending_program_id,
             reserve.config,
             RateLimiterConfig {
                 window_duration: reserve.rate_limiter.config.window_duration,
                 max_outflow: reserve.rate_limiter.config.max_outflow,
+                net_flow: reserve.rate_limiter.config.net_flow,
             },
+            liquidation_threshold_ramp_slots,
             reserve_pubkey,
             lending_market_pubkey,
             lending_market_owner_keypair.pubkey(),
@@ -2232,6 +2746,136 @@ fn command_update_reserve(
 
 // HELPERS
 
+fn command_check_oracles(config: &Config, lending_market_pubkey: Pubkey) -> CommandResult {
+    let lending_market_info = config.rpc_client.get_account(&lending_market_pubkey)?;
+    let lending_market = LendingMarket::unpack_from_slice(lending_market_info.data.borrow())?;
+    let oracle_program_ids = OracleProgramIds::for_market(
+        &lending_market.oracle_program_id,
+        &lending_market.switchboard_oracle_program_id,
+    );
+
+    let clock_account = config.rpc_client.get_account(&sysvar::clock::id())?;
+    let clock: Clock = from_account(&clock_account).ok_or("Error: invalid clock sysvar")?;
+
+    let reserves = config.rpc_client.get_program_accounts_with_config(
+        &config.lending_program_id,
+        RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(Reserve::LEN as u64),
+                // the lending market follows the version and last update
+                RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    10,
+                    lending_market_pubkey.as_ref(),
+                )),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64Zstd),
+                ..RpcAccountInfoConfig::default()
+            },
+            with_context: Some(false),
+        },
+    )?;
+
+    let mut unhealthy_oracles = 0;
+    for (reserve_pubkey, reserve_account) in reserves {
+        let reserve = Reserve::unpack(&reserve_account.data)?;
+        println!(
+            "reserve {} (mint {})",
+            reserve_pubkey, reserve.liquidity.mint_pubkey
+        );
+
+        let oracles = [
+            ("primary", Some(reserve.liquidity.pyth_oracle_pubkey)),
+            (
+                "secondary",
+                Some(reserve.liquidity.switchboard_oracle_pubkey),
+            ),
+            ("extra", reserve.config.extra_oracle_pubkey),
+            ("underlying", reserve.config.underlying_oracle_pubkey),
+        ];
+        for (label, oracle_pubkey) in oracles.iter() {
+            let oracle_pubkey = match oracle_pubkey {
+                Some(oracle_pubkey) if *oracle_pubkey != solend_sdk::NULL_PUBKEY => *oracle_pubkey,
+                _ => continue,
+            };
+
+            let mut oracle_account = config.rpc_client.get_account(&oracle_pubkey)?;
+            let oracle_info = AccountInfo::new(
+                &oracle_pubkey,
+                false,
+                false,
+                &mut oracle_account.lamports,
+                &mut oracle_account.data,
+                &oracle_account.owner,
+                false,
+                oracle_account.rent_epoch,
+            );
+            let health = get_oracle_health(&oracle_info, &clock, &oracle_program_ids);
+            if !health.is_healthy() {
+                unhealthy_oracles += 1;
+            }
+
+            println!(
+                "  {} oracle {}: {}",
+                label,
+                oracle_pubkey,
+                if health.is_healthy() { "ok" } else { "FAILING" }
+            );
+            println!("    {:?}", health);
+        }
+    }
+
+    if unhealthy_oracles > 0 {
+        return Err(format!("Error: {} oracles are failing", unhealthy_oracles).into());
+    }
+
+    Ok(())
+}
+
+fn command_view_limits(
+    config: &Config,
+    reserve_pubkey: Pubkey,
+    slot: Option<u64>,
+) -> CommandResult {
+    let reserve = Reserve::unpack(&config.rpc_client.get_account_data(&reserve_pubkey)?)?;
+    let lending_market = LendingMarket::unpack(
+        &config
+            .rpc_client
+            .get_account_data(&reserve.lending_market)?,
+    )?;
+    let slot = match slot {
+        Some(slot) => slot,
+        None => config.rpc_client.get_slot()?,
+    };
+
+    let limits = reserve.outflow_limits(&lending_market, slot)?;
+    let decimals = reserve.liquidity.mint_decimals;
+
+    println!("reserve {} at slot {}", reserve_pubkey, slot);
+    println!(
+        "  market: {} of {} USD remaining, fully recovered at slot {}",
+        limits.lending_market_remaining_outflow,
+        lending_market.rate_limiter.config.max_outflow,
+        limits.lending_market_full_recovery_slot
+    );
+    println!(
+        "  reserve: {} of {} tokens remaining, fully recovered at slot {}",
+        amount_to_ui_amount(limits.reserve_remaining_outflow.try_floor_u64()?, decimals),
+        amount_to_ui_amount(reserve.rate_limiter.config.max_outflow, decimals),
+        limits.reserve_full_recovery_slot
+    );
+    println!(
+        "  max borrow or withdraw: {} tokens, limited by {:?}",
+        amount_to_ui_amount(
+            limits.max_outflow_liquidity_amount.try_floor_u64()?,
+            decimals
+        ),
+        limits.binding_limit
+    );
+
+    Ok(())
+}
+
 fn check_fee_payer_balance(config: &Config, required_balance: u64) -> Result<(), Error> {
     let balance = config.rpc_client.get_balance(&config.fee_payer.pubkey())?;
     if balance < required_balance {
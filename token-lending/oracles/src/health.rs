@@ -0,0 +1,444 @@
+#![allow(missing_docs)]
+use crate::pyth::{
+    account_deserialize, pyth_price_to_decimal, pyth_pull_price_to_decimal, PYTH_CONFIDENCE_RATIO,
+    STALE_AFTER_SECONDS_ELAPSED,
+};
+use crate::stake_pool::get_stake_pool_exchange_rate;
+use crate::switchboard::STALE_AFTER_SLOTS_ELAPSED;
+use crate::{OracleProgramIds, OracleType};
+use pyth_sdk_solana::state::PriceStatus;
+use pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, VerificationLevel};
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, sysvar::clock::Clock,
+};
+use solend_sdk::math::{Decimal, TryDiv, TryMul};
+use std::{convert::TryInto, result::Result};
+use switchboard_on_demand::on_demand::accounts::pull_feed::PullFeedAccountData as SbOnDemandFeed;
+use switchboard_v2::AggregatorAccountData;
+
+/// Reason a price would be rejected when refreshing a reserve
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OracleHealthIssue {
+    /// The oracle is the null pubkey
+    NullOracle,
+    /// The account isn't owned by an accepted oracle program
+    UnknownOwner,
+    /// The account couldn't be parsed as a feed of its oracle type
+    InvalidAccountData,
+    /// The price is older than the staleness limit of its oracle type, or isn't trading
+    Stale,
+    /// The price is negative
+    NegativePrice,
+    /// The confidence interval or range is too wide compared to the price
+    ConfidenceTooWide,
+    /// The pyth pull price update isn't fully verified
+    InsufficientVerification,
+}
+
+/// Readings of an oracle account along with every check it fails. Fields that couldn't be read
+/// are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OracleHealth {
+    pub oracle_type: Option<OracleType>,
+    pub price: Option<Decimal>,
+    /// Confidence interval (pyth), range (switchboard on demand) or standard deviation
+    /// (switchboard v2) of the price
+    pub conf: Option<Decimal>,
+    pub ema_price: Option<Decimal>,
+    pub age_slots: Option<u64>,
+    pub age_seconds: Option<i64>,
+    pub issues: Vec<OracleHealthIssue>,
+}
+
+impl OracleHealth {
+    /// Whether the price passes every check
+    pub fn is_healthy(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Reads an oracle account and reports why its price would be rejected, if at all. Unlike the
+/// price getters this never fails and doesn't stop at the first failed check.
+///
+/// The price of a stake pool is its exchange rate. Its underlying oracle has to be checked
+/// separately.
+pub fn get_oracle_health(
+    oracle_info: &AccountInfo,
+    clock: &Clock,
+    oracle_program_ids: &OracleProgramIds,
+) -> OracleHealth {
+    let mut health = OracleHealth::default();
+    if *oracle_info.key == solend_sdk::NULL_PUBKEY {
+        health.issues.push(OracleHealthIssue::NullOracle);
+        return health;
+    }
+
+    let oracle_type = match oracle_program_ids.oracle_type(oracle_info.owner) {
+        Some(oracle_type) => oracle_type,
+        None => {
+            health.issues.push(OracleHealthIssue::UnknownOwner);
+            return health;
+        }
+    };
+    health.oracle_type = Some(oracle_type);
+
+    let result = match oracle_type {
+        OracleType::Pyth => read_pyth(oracle_info, clock, &mut health),
+        OracleType::PythPull => read_pyth_pull(oracle_info, clock, &mut health),
+        OracleType::Switchboard => read_switchboard_v2(oracle_info, clock, &mut health),
+        OracleType::SbOnDemand => read_sb_on_demand(oracle_info, clock, &mut health),
+        OracleType::StakePool => {
+            get_stake_pool_exchange_rate(oracle_info).map(|rate| health.price = Some(rate))
+        }
+    };
+    if result.is_err() {
+        health.issues.push(OracleHealthIssue::InvalidAccountData);
+    }
+
+    health
+}
+
+fn read_pyth(
+    oracle_info: &AccountInfo,
+    clock: &Clock,
+    health: &mut OracleHealth,
+) -> Result<(), ProgramError> {
+    let data = &oracle_info.try_borrow_data()?;
+    let price_account = pyth_sdk_solana::state::load_price_account(data)
+        .map_err(|_| ProgramError::InvalidAccountData)?;
+
+    health.age_slots = Some(clock.slot.saturating_sub(price_account.agg.pub_slot));
+    health.age_seconds = Some(clock.unix_timestamp.saturating_sub(price_account.timestamp));
+    if price_account.agg.status != PriceStatus::Trading {
+        health.issues.push(OracleHealthIssue::Stale);
+    }
+
+    let pyth_price = pyth_sdk_solana::Price {
+        price: price_account.agg.price,
+        conf: price_account.agg.conf,
+        expo: price_account.expo,
+        publish_time: price_account.timestamp,
+    };
+    if pyth_price.price < 0 {
+        health.issues.push(OracleHealthIssue::NegativePrice);
+    } else {
+        if pyth_price.conf.saturating_mul(PYTH_CONFIDENCE_RATIO) > pyth_price.price as u64 {
+            health.issues.push(OracleHealthIssue::ConfidenceTooWide);
+        }
+        health.price = Some(pyth_price_to_decimal(&pyth_price)?);
+        health.conf = Some(pyth_price_to_decimal(&pyth_sdk_solana::Price {
+            price: pyth_price
+                .conf
+                .try_into()
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+            ..pyth_price
+        })?);
+    }
+
+    let ema_price = price_account
+        .to_price_feed(oracle_info.key)
+        .get_ema_price_unchecked();
+    if ema_price.price >= 0 {
+        health.ema_price = Some(pyth_price_to_decimal(&ema_price)?);
+    }
+
+    Ok(())
+}
+
+fn read_pyth_pull(
+    oracle_info: &AccountInfo,
+    clock: &Clock,
+    health: &mut OracleHealth,
+) -> Result<(), ProgramError> {
+    let price_feed_account: PriceUpdateV2 = account_deserialize(oracle_info)?;
+    let message = &price_feed_account.price_message;
+
+    health.age_slots = Some(clock.slot.saturating_sub(price_feed_account.posted_slot));
+    health.age_seconds = Some(clock.unix_timestamp.saturating_sub(message.publish_time));
+    if !price_feed_account
+        .verification_level
+        .gte(VerificationLevel::Full)
+    {
+        health
+            .issues
+            .push(OracleHealthIssue::InsufficientVerification);
+    }
+    if message
+        .publish_time
+        .saturating_add(STALE_AFTER_SECONDS_ELAPSED as i64)
+        < clock.unix_timestamp
+    {
+        health.issues.push(OracleHealthIssue::Stale);
+    }
+
+    let pyth_price = pyth_solana_receiver_sdk::price_update::Price {
+        price: message.price,
+        conf: message.conf,
+        exponent: message.exponent,
+        publish_time: message.publish_time,
+    };
+    if pyth_price.price < 0 {
+        health.issues.push(OracleHealthIssue::NegativePrice);
+    } else {
+        if pyth_price.conf.saturating_mul(PYTH_CONFIDENCE_RATIO) > pyth_price.price as u64 {
+            health.issues.push(OracleHealthIssue::ConfidenceTooWide);
+        }
+        health.price = Some(pyth_pull_price_to_decimal(&pyth_price)?);
+        health.conf = Some(pyth_pull_price_to_decimal(
+            &pyth_solana_receiver_sdk::price_update::Price {
+                price: pyth_price
+                    .conf
+                    .try_into()
+                    .map_err(|_| ProgramError::InvalidAccountData)?,
+                ..pyth_price
+            },
+        )?);
+    }
+
+    if message.ema_price >= 0 {
+        health.ema_price = Some(pyth_pull_price_to_decimal(
+            &pyth_solana_receiver_sdk::price_update::Price {
+                price: message.ema_price,
+                conf: message.ema_conf,
+                ..pyth_price
+            },
+        )?);
+    }
+
+    Ok(())
+}
+
+fn read_switchboard_v2(
+    oracle_info: &AccountInfo,
+    clock: &Clock,
+    health: &mut OracleHealth,
+) -> Result<(), ProgramError> {
+    let data = &oracle_info.try_borrow_data()?;
+    let feed = AggregatorAccountData::new_from_bytes(data)?;
+    let round = &feed.latest_confirmed_round;
+
+    let age_slots = clock.slot.saturating_sub(round.round_open_slot);
+    health.age_slots = Some(age_slots);
+    health.age_seconds = Some(
+        clock
+            .unix_timestamp
+            .saturating_sub(round.round_open_timestamp),
+    );
+    if age_slots >= STALE_AFTER_SLOTS_ELAPSED {
+        health.issues.push(OracleHealthIssue::Stale);
+    }
+
+    let result = feed.get_result()?;
+    match switchboard_decimal(result.mantissa, result.scale)? {
+        Some(price) => health.price = Some(price),
+        None => health.issues.push(OracleHealthIssue::NegativePrice),
+    }
+    health.conf = switchboard_decimal(
+        round.std_deviation.mantissa.abs(),
+        round.std_deviation.scale,
+    )?;
+
+    Ok(())
+}
+
+fn read_sb_on_demand(
+    oracle_info: &AccountInfo,
+    clock: &Clock,
+    health: &mut OracleHealth,
+) -> Result<(), ProgramError> {
+    let data = oracle_info.try_borrow_data()?;
+    let feed = SbOnDemandFeed::parse(data).map_err(|_| ProgramError::InvalidAccountData)?;
+
+    let age_slots = clock.slot.saturating_sub(feed.result.slot);
+    health.age_slots = Some(age_slots);
+    health.age_seconds = Some(
+        clock
+            .unix_timestamp
+            .saturating_sub(feed.last_update_timestamp),
+    );
+    if age_slots >= STALE_AFTER_SLOTS_ELAPSED {
+        health.issues.push(OracleHealthIssue::Stale);
+    }
+
+    let value = feed.value().ok_or(ProgramError::InvalidAccountData)?;
+    let range = feed.range().ok_or(ProgramError::InvalidAccountData)?;
+    health.price = switchboard_decimal(value.mantissa(), value.scale())?;
+    health.conf = switchboard_decimal(range.mantissa(), range.scale())?;
+    match (health.price, health.conf) {
+        (None, _) | (_, None) => health.issues.push(OracleHealthIssue::NegativePrice),
+        (Some(price), Some(range)) => {
+            // same ratio as get_switchboard_on_demand_oracle_price
+            if range.try_mul(10_u64)? > price {
+                health.issues.push(OracleHealthIssue::ConfidenceTooWide);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// mantissa * 10^-scale, or None if the mantissa is negative
+fn switchboard_decimal(mantissa: i128, scale: u32) -> Result<Option<Decimal>, ProgramError> {
+    if mantissa < 0 {
+        return Ok(None);
+    }
+    let exp = 10u128
+        .checked_pow(scale)
+        .ok_or(ProgramError::InvalidAccountData)?;
+    Ok(Some(
+        Decimal::from(mantissa as u128).try_div(Decimal::from(exp))?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pyth_mainnet;
+    use bytemuck::bytes_of_mut;
+    use pyth_sdk_solana::state::{
+        AccountType, CorpAction, PriceAccount, PriceInfo, PriceType, Rational, MAGIC, VERSION_2,
+    };
+    use solana_program::pubkey::Pubkey;
+
+    fn pyth_price_account(price: i64, conf: u64, status: PriceStatus) -> PriceAccount {
+        PriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            ptype: PriceType::Price,
+            expo: -1,
+            timestamp: 90,
+            ema_price: Rational {
+                val: 95,
+                numer: 950,
+                denom: 10,
+            },
+            agg: PriceInfo {
+                price,
+                conf,
+                status,
+                corp_act: CorpAction::NoCorpAct,
+                pub_slot: 10,
+            },
+            ..PriceAccount::default()
+        }
+    }
+
+    fn pyth_health(mut price_account: PriceAccount, owner: &Pubkey) -> OracleHealth {
+        let pubkey = Pubkey::new_unique();
+        let mut lamports = 20;
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            bytes_of_mut(&mut price_account),
+            owner,
+            false,
+            0,
+        );
+        let clock = Clock {
+            slot: 15,
+            unix_timestamp: 100,
+            ..Clock::default()
+        };
+
+        get_oracle_health(&account_info, &clock, &OracleProgramIds::default())
+    }
+
+    #[test]
+    fn test_pyth_health() {
+        let health = pyth_health(
+            pyth_price_account(100, 1, PriceStatus::Trading),
+            &pyth_mainnet::id(),
+        );
+        assert_eq!(
+            health,
+            OracleHealth {
+                oracle_type: Some(OracleType::Pyth),
+                price: Some(Decimal::from(10u64)),
+                conf: Some(Decimal::from_percent(10)),
+                ema_price: Some(Decimal::from_bps(95_000)),
+                age_slots: Some(5),
+                age_seconds: Some(10),
+                issues: vec![],
+            }
+        );
+        assert!(health.is_healthy());
+
+        // every failed check is reported
+        let health = pyth_health(
+            pyth_price_account(100, 11, PriceStatus::Unknown),
+            &pyth_mainnet::id(),
+        );
+        assert_eq!(
+            health.issues,
+            vec![
+                OracleHealthIssue::Stale,
+                OracleHealthIssue::ConfidenceTooWide
+            ]
+        );
+        assert_eq!(health.price, Some(Decimal::from(10u64)));
+
+        let health = pyth_health(
+            pyth_price_account(-100, 1, PriceStatus::Trading),
+            &pyth_mainnet::id(),
+        );
+        assert_eq!(health.issues, vec![OracleHealthIssue::NegativePrice]);
+        assert_eq!(health.price, None);
+    }
+
+    #[test]
+    fn test_invalid_oracle_health() {
+        let health = pyth_health(
+            pyth_price_account(100, 1, PriceStatus::Trading),
+            &Pubkey::new_unique(),
+        );
+        assert_eq!(
+            health,
+            OracleHealth {
+                issues: vec![OracleHealthIssue::UnknownOwner],
+                ..OracleHealth::default()
+            }
+        );
+
+        let health = pyth_health(
+            PriceAccount {
+                magic: MAGIC + 1,
+                ..pyth_price_account(100, 1, PriceStatus::Trading)
+            },
+            &pyth_mainnet::id(),
+        );
+        assert_eq!(
+            health,
+            OracleHealth {
+                oracle_type: Some(OracleType::Pyth),
+                issues: vec![OracleHealthIssue::InvalidAccountData],
+                ..OracleHealth::default()
+            }
+        );
+
+        let mut lamports = 20;
+        let mut data = vec![];
+        let owner = pyth_mainnet::id();
+        let account_info = AccountInfo::new(
+            &solend_sdk::NULL_PUBKEY,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            get_oracle_health(
+                &account_info,
+                &Clock::default(),
+                &OracleProgramIds::default()
+            )
+            .issues,
+            vec![OracleHealthIssue::NullOracle]
+        );
+    }
+}
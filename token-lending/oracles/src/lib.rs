@@ -1,78 +1,131 @@
+pub mod health;
+pub mod pegged;
+pub mod price_source;
 pub mod pyth;
+pub mod stake_pool;
 pub mod switchboard;
 
-use crate::pyth::get_pyth_price_unchecked;
-use crate::pyth::get_pyth_pull_price;
-use crate::pyth::get_pyth_pull_price_unchecked;
-use crate::switchboard::get_switchboard_price;
-use crate::switchboard::get_switchboard_price_on_demand;
-use crate::switchboard::get_switchboard_price_v2;
+use crate::price_source::PriceSourceRegistry;
 use solana_program::{
-    account_info::AccountInfo, msg, program_error::ProgramError, sysvar::clock::Clock,
+    account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey,
+    sysvar::clock::Clock,
 };
 use solend_sdk::error::LendingError;
 use solend_sdk::math::Decimal;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OracleType {
     Pyth,
     Switchboard,
     PythPull,
     SbOnDemand,
+    StakePool,
 }
 
-pub fn get_oracle_type(oracle_info: &AccountInfo) -> Result<OracleType, ProgramError> {
-    if *oracle_info.owner == pyth_mainnet::id() {
-        return Ok(OracleType::Pyth);
-    } else if *oracle_info.owner == pyth_pull_mainnet::id() {
-        return Ok(OracleType::PythPull);
-    } else if *oracle_info.owner == switchboard_v2_mainnet::id() {
-        return Ok(OracleType::Switchboard);
-    } else if *oracle_info.owner == switchboard_on_demand_mainnet::id() {
-        return Ok(OracleType::SbOnDemand);
+/// Program ids accepted as the owner of each kind of oracle account
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OracleProgramIds {
+    pub pyth: Vec<Pubkey>,
+    pub pyth_pull: Vec<Pubkey>,
+    pub switchboard_v2: Vec<Pubkey>,
+    pub switchboard_on_demand: Vec<Pubkey>,
+    pub stake_pool: Vec<Pubkey>,
+}
+
+impl Default for OracleProgramIds {
+    fn default() -> Self {
+        Self {
+            pyth: vec![pyth_mainnet::id()],
+            pyth_pull: vec![pyth_pull_mainnet::id()],
+            switchboard_v2: vec![switchboard_v2_mainnet::id(), switchboard_v2_devnet::id()],
+            switchboard_on_demand: vec![
+                switchboard_on_demand_mainnet::id(),
+                switchboard_on_demand_devnet::id(),
+            ],
+            stake_pool: vec![spl_stake_pool_mainnet::id()],
+        }
+    }
+}
+
+impl OracleProgramIds {
+    /// The mainnet program ids plus the oracle programs configured on a lending market. A market
+    /// oracle program id that isn't already known is accepted as a pyth program, and a market
+    /// switchboard program id as a switchboard v2 program. Unset ids are ignored.
+    pub fn for_market(oracle_program_id: &Pubkey, switchboard_oracle_program_id: &Pubkey) -> Self {
+        let mut ids = Self::default();
+        if is_market_program_id(&ids, oracle_program_id) {
+            ids.pyth.push(*oracle_program_id);
+        }
+        if is_market_program_id(&ids, switchboard_oracle_program_id) {
+            ids.switchboard_v2.push(*switchboard_oracle_program_id);
+        }
+        ids
+    }
+
+    /// Oracle type of accounts owned by `owner`, if it's an accepted program id
+    pub fn oracle_type(&self, owner: &Pubkey) -> Option<OracleType> {
+        if self.pyth.contains(owner) {
+            Some(OracleType::Pyth)
+        } else if self.pyth_pull.contains(owner) {
+            Some(OracleType::PythPull)
+        } else if self.switchboard_v2.contains(owner) {
+            Some(OracleType::Switchboard)
+        } else if self.switchboard_on_demand.contains(owner) {
+            Some(OracleType::SbOnDemand)
+        } else if self.stake_pool.contains(owner) {
+            Some(OracleType::StakePool)
+        } else {
+            None
+        }
     }
+}
 
-    msg!(
-        "Could not find oracle type for {:?} with owner {:?}",
-        oracle_info.key,
-        oracle_info.owner
-    );
-    Err(LendingError::InvalidOracleConfig.into())
+/// Whether a program id configured on a lending market should be added to `ids`
+fn is_market_program_id(ids: &OracleProgramIds, program_id: &Pubkey) -> bool {
+    *program_id != Pubkey::default()
+        && *program_id != solend_sdk::NULL_PUBKEY
+        && ids.oracle_type(program_id).is_none()
+}
+
+pub fn get_oracle_type(
+    oracle_info: &AccountInfo,
+    oracle_program_ids: &OracleProgramIds,
+) -> Result<OracleType, ProgramError> {
+    if oracle_program_ids.stake_pool.contains(oracle_info.owner) {
+        return Ok(OracleType::StakePool);
+    }
+
+    Ok(PriceSourceRegistry::with_program_ids(oracle_program_ids)
+        .find(oracle_info)?
+        .oracle_type())
 }
 
 pub fn get_single_price(
     oracle_account_info: &AccountInfo,
     clock: &Clock,
+    oracle_program_ids: &OracleProgramIds,
 ) -> Result<(Decimal, Option<Decimal>), ProgramError> {
-    match get_oracle_type(oracle_account_info)? {
-        OracleType::Pyth => {
-            let price = pyth::get_pyth_price(oracle_account_info, clock)?;
-            Ok((price.0, Some(price.1)))
-        }
-        OracleType::PythPull => {
-            let price = get_pyth_pull_price(oracle_account_info, clock)?;
-            Ok((price.0, Some(price.1)))
-        }
-        OracleType::Switchboard => {
-            let price = get_switchboard_price(oracle_account_info, clock)?;
-            Ok((price, None))
-        }
-        OracleType::SbOnDemand => {
-            let price = get_switchboard_price(oracle_account_info, clock)?;
-            Ok((price, None))
-        }
+    if let OracleType::StakePool = get_oracle_type(oracle_account_info, oracle_program_ids)? {
+        msg!("Stake pool oracles can only be priced together with their underlying oracle");
+        return Err(LendingError::InvalidOracleConfig.into());
     }
+
+    PriceSourceRegistry::with_program_ids(oracle_program_ids)
+        .get_single_price(oracle_account_info, clock)
 }
 
 pub fn get_single_price_unchecked(
     oracle_account_info: &AccountInfo,
     clock: &Clock,
+    oracle_program_ids: &OracleProgramIds,
 ) -> Result<Decimal, ProgramError> {
-    match get_oracle_type(oracle_account_info)? {
-        OracleType::Pyth => get_pyth_price_unchecked(oracle_account_info),
-        OracleType::PythPull => get_pyth_pull_price_unchecked(oracle_account_info),
-        OracleType::Switchboard => get_switchboard_price_v2(oracle_account_info, clock, false),
-        OracleType::SbOnDemand => get_switchboard_price_on_demand(oracle_account_info, clock, true),
+    if let OracleType::StakePool = get_oracle_type(oracle_account_info, oracle_program_ids)? {
+        msg!("Stake pool oracles can only be priced together with their underlying oracle");
+        return Err(LendingError::InvalidOracleConfig.into());
     }
+
+    PriceSourceRegistry::with_program_ids(oracle_program_ids)
+        .get_single_price_unchecked(oracle_account_info, clock)
 }
 
 /// Mainnet program id for Switchboard v2.
@@ -104,3 +157,87 @@ pub mod pyth_mainnet {
 pub mod pyth_pull_mainnet {
     solana_program::declare_id!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
 }
+
+/// Mainnet program id for the spl stake pool program
+pub mod spl_stake_pool_mainnet {
+    solana_program::declare_id!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_oracle_program_ids() {
+        // the on-demand devnet constant is the mainnet program, so the defaults only accept
+        // mainnet switchboard on-demand accounts
+        assert_eq!(
+            switchboard_on_demand_devnet::id(),
+            switchboard_on_demand_mainnet::id()
+        );
+        let ids = OracleProgramIds::default();
+        assert!(ids
+            .switchboard_on_demand
+            .iter()
+            .all(|id| *id == switchboard_on_demand_mainnet::id()));
+        assert_eq!(
+            ids.oracle_type(&switchboard_on_demand_mainnet::id()),
+            Some(OracleType::SbOnDemand)
+        );
+    }
+
+    #[test]
+    fn test_market_oracle_program_ids() {
+        let local_pyth = Pubkey::new_unique();
+        let local_switchboard = Pubkey::new_unique();
+        let ids = OracleProgramIds::for_market(&local_pyth, &local_switchboard);
+
+        assert_eq!(ids.oracle_type(&local_pyth), Some(OracleType::Pyth));
+        assert_eq!(ids.oracle_type(&pyth_mainnet::id()), Some(OracleType::Pyth));
+        assert_eq!(
+            ids.oracle_type(&local_switchboard),
+            Some(OracleType::Switchboard)
+        );
+        assert_eq!(
+            ids.oracle_type(&switchboard_v2_mainnet::id()),
+            Some(OracleType::Switchboard)
+        );
+        assert_eq!(ids.oracle_type(&Pubkey::new_unique()), None);
+
+        // a known program id keeps its type
+        let ids =
+            OracleProgramIds::for_market(&pyth_pull_mainnet::id(), &switchboard_v2_mainnet::id());
+        assert_eq!(ids, OracleProgramIds::default());
+        assert_eq!(
+            ids.oracle_type(&pyth_pull_mainnet::id()),
+            Some(OracleType::PythPull)
+        );
+
+        // unset program ids aren't accepted from the market
+        for program_id in [Pubkey::default(), solend_sdk::NULL_PUBKEY] {
+            let ids = OracleProgramIds::for_market(&program_id, &program_id);
+            assert_eq!(ids, OracleProgramIds::default());
+            assert_eq!(ids.oracle_type(&program_id), None);
+        }
+
+        let pubkey = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = vec![];
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &local_pyth,
+            false,
+            0,
+        );
+        let ids = OracleProgramIds::for_market(&local_pyth, &switchboard_v2_mainnet::id());
+        assert_eq!(get_oracle_type(&account_info, &ids), Ok(OracleType::Pyth));
+        assert_eq!(
+            get_oracle_type(&account_info, &OracleProgramIds::default()),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+    }
+}
@@ -0,0 +1,97 @@
+#![allow(missing_docs)]
+use solana_program::program_error::ProgramError;
+use solend_sdk::math::{Decimal, TryMul, TrySub};
+use std::result::Result;
+
+/// Price reported by pegged oracles, ie $1
+pub fn peg_price() -> Decimal {
+    Decimal::one()
+}
+
+/// Prices of a pegged reserve
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeggedPrice {
+    pub price: Decimal,
+    pub smoothed_price: Option<Decimal>,
+    /// Whether the oracle price is outside the peg band. If so, the oracle prices are returned
+    /// as is.
+    pub depegged: bool,
+}
+
+/// Reports the peg price as long as the oracle price is within `max_deviation_bps` of it. The
+/// oracle price is still read so that a depeg is noticed, in which case the oracle prices are
+/// returned unchanged.
+pub fn get_pegged_price(
+    oracle_price: Decimal,
+    oracle_smoothed_price: Option<Decimal>,
+    max_deviation_bps: u16,
+) -> Result<PeggedPrice, ProgramError> {
+    let deviation = if oracle_price > peg_price() {
+        oracle_price.try_sub(peg_price())?
+    } else {
+        peg_price().try_sub(oracle_price)?
+    };
+    let max_deviation = peg_price().try_mul(Decimal::from_bps(max_deviation_bps as u64))?;
+
+    if deviation > max_deviation {
+        return Ok(PeggedPrice {
+            price: oracle_price,
+            smoothed_price: oracle_smoothed_price,
+            depegged: true,
+        });
+    }
+
+    Ok(PeggedPrice {
+        price: peg_price(),
+        smoothed_price: oracle_smoothed_price.map(|_| peg_price()),
+        depegged: false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pegged_price() {
+        // within the band
+        assert_eq!(
+            get_pegged_price(
+                Decimal::from_bps(10_030),
+                Some(Decimal::from_bps(9_990)),
+                50
+            ),
+            Ok(PeggedPrice {
+                price: Decimal::one(),
+                smoothed_price: Some(Decimal::one()),
+                depegged: false,
+            })
+        );
+        assert_eq!(
+            get_pegged_price(Decimal::from_bps(9_950), None, 50),
+            Ok(PeggedPrice {
+                price: Decimal::one(),
+                smoothed_price: None,
+                depegged: false,
+            })
+        );
+
+        // outside of the band
+        assert_eq!(
+            get_pegged_price(Decimal::from_bps(9_949), Some(Decimal::from_bps(9_990)), 50),
+            Ok(PeggedPrice {
+                price: Decimal::from_bps(9_949),
+                smoothed_price: Some(Decimal::from_bps(9_990)),
+                depegged: true,
+            })
+        );
+        assert_eq!(
+            get_pegged_price(Decimal::from_bps(10_100), None, 50),
+            Ok(PeggedPrice {
+                price: Decimal::from_bps(10_100),
+                smoothed_price: None,
+                depegged: true,
+            })
+        );
+    }
+}
@@ -0,0 +1,346 @@
+#![allow(missing_docs)]
+use crate::pyth::{
+    get_pyth_oracle_price, get_pyth_price_unchecked, get_pyth_pull_oracle_price,
+    get_pyth_pull_price_unchecked,
+};
+use crate::switchboard::{
+    get_switchboard_on_demand_oracle_price, get_switchboard_price_on_demand,
+    get_switchboard_price_v2, get_switchboard_v2_oracle_price,
+};
+use crate::{OracleProgramIds, OracleType};
+use solana_program::{
+    account_info::AccountInfo, msg, program_error::ProgramError, pubkey::Pubkey,
+    sysvar::clock::Clock,
+};
+use solend_sdk::{error::LendingError, math::Decimal};
+use std::result::Result;
+
+/// Price reported by an oracle, normalized across providers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    /// Spot price
+    pub price: Decimal,
+    /// Confidence interval (pyth) or spread (switchboard) around the price
+    pub conf: Decimal,
+    /// Slot the price was published at
+    pub publish_slot: u64,
+    /// Unix timestamp the price was published at
+    pub publish_time: i64,
+    /// Exponential moving average price, if the provider reports one
+    pub ema_price: Option<Decimal>,
+}
+
+/// An oracle provider the lending program can read prices from
+pub trait PriceSource {
+    /// Oracle type reported for accounts of this source
+    fn oracle_type(&self) -> OracleType;
+
+    /// Whether accounts owned by `owner` belong to this source
+    fn is_owner(&self, owner: &Pubkey) -> bool;
+
+    /// Price with the provider's staleness and confidence checks applied
+    fn get_price(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<OraclePrice, ProgramError>;
+
+    /// Latest price without staleness checks
+    fn get_price_unchecked(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<Decimal, ProgramError>;
+}
+
+pub struct PythSource {
+    pub program_ids: Vec<Pubkey>,
+}
+
+impl PriceSource for PythSource {
+    fn oracle_type(&self) -> OracleType {
+        OracleType::Pyth
+    }
+
+    fn is_owner(&self, owner: &Pubkey) -> bool {
+        self.program_ids.contains(owner)
+    }
+
+    fn get_price(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<OraclePrice, ProgramError> {
+        get_pyth_oracle_price(oracle_info, clock)
+    }
+
+    fn get_price_unchecked(
+        &self,
+        oracle_info: &AccountInfo,
+        _clock: &Clock,
+    ) -> Result<Decimal, ProgramError> {
+        get_pyth_price_unchecked(oracle_info)
+    }
+}
+
+pub struct PythPullSource {
+    pub program_ids: Vec<Pubkey>,
+}
+
+impl PriceSource for PythPullSource {
+    fn oracle_type(&self) -> OracleType {
+        OracleType::PythPull
+    }
+
+    fn is_owner(&self, owner: &Pubkey) -> bool {
+        self.program_ids.contains(owner)
+    }
+
+    fn get_price(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<OraclePrice, ProgramError> {
+        get_pyth_pull_oracle_price(oracle_info, clock)
+    }
+
+    fn get_price_unchecked(
+        &self,
+        oracle_info: &AccountInfo,
+        _clock: &Clock,
+    ) -> Result<Decimal, ProgramError> {
+        get_pyth_pull_price_unchecked(oracle_info, &self.program_ids)
+    }
+}
+
+pub struct SwitchboardV2Source {
+    pub program_ids: Vec<Pubkey>,
+}
+
+impl PriceSource for SwitchboardV2Source {
+    fn oracle_type(&self) -> OracleType {
+        OracleType::Switchboard
+    }
+
+    fn is_owner(&self, owner: &Pubkey) -> bool {
+        self.program_ids.contains(owner)
+    }
+
+    fn get_price(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<OraclePrice, ProgramError> {
+        get_switchboard_v2_oracle_price(oracle_info, clock, true)
+    }
+
+    fn get_price_unchecked(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<Decimal, ProgramError> {
+        get_switchboard_price_v2(oracle_info, clock, false)
+    }
+}
+
+pub struct SbOnDemandSource {
+    pub program_ids: Vec<Pubkey>,
+}
+
+impl PriceSource for SbOnDemandSource {
+    fn oracle_type(&self) -> OracleType {
+        OracleType::SbOnDemand
+    }
+
+    fn is_owner(&self, owner: &Pubkey) -> bool {
+        self.program_ids.contains(owner)
+    }
+
+    fn get_price(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<OraclePrice, ProgramError> {
+        get_switchboard_on_demand_oracle_price(oracle_info, clock, true)
+    }
+
+    fn get_price_unchecked(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<Decimal, ProgramError> {
+        get_switchboard_price_on_demand(oracle_info, clock, true)
+    }
+}
+
+/// Resolves oracle accounts to the price source that owns them
+pub struct PriceSourceRegistry {
+    sources: Vec<Box<dyn PriceSource>>,
+}
+
+impl Default for PriceSourceRegistry {
+    fn default() -> Self {
+        Self::with_program_ids(&OracleProgramIds::default())
+    }
+}
+
+impl PriceSourceRegistry {
+    /// Registry without any sources
+    pub fn new() -> Self {
+        Self { sources: vec![] }
+    }
+
+    /// Registry of the built-in sources, accepting accounts owned by the given program ids
+    pub fn with_program_ids(oracle_program_ids: &OracleProgramIds) -> Self {
+        Self {
+            sources: vec![
+                Box::new(PythSource {
+                    program_ids: oracle_program_ids.pyth.clone(),
+                }),
+                Box::new(PythPullSource {
+                    program_ids: oracle_program_ids.pyth_pull.clone(),
+                }),
+                Box::new(SwitchboardV2Source {
+                    program_ids: oracle_program_ids.switchboard_v2.clone(),
+                }),
+                Box::new(SbOnDemandSource {
+                    program_ids: oracle_program_ids.switchboard_on_demand.clone(),
+                }),
+            ],
+        }
+    }
+
+    /// Adds a source. Sources are matched in registration order.
+    pub fn register(&mut self, source: Box<dyn PriceSource>) {
+        self.sources.push(source);
+    }
+
+    /// Finds the source owning the oracle account
+    pub fn find(&self, oracle_info: &AccountInfo) -> Result<&dyn PriceSource, ProgramError> {
+        match self.sources.iter().find(|s| s.is_owner(oracle_info.owner)) {
+            Some(source) => Ok(source.as_ref()),
+            None => {
+                msg!(
+                    "Could not find oracle type for {:?} with owner {:?}",
+                    oracle_info.key,
+                    oracle_info.owner
+                );
+                Err(LendingError::InvalidOracleConfig.into())
+            }
+        }
+    }
+
+    pub fn get_oracle_price(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<OraclePrice, ProgramError> {
+        self.find(oracle_info)?.get_price(oracle_info, clock)
+    }
+
+    /// Returns the price and, if the source reports one, the ema price
+    pub fn get_single_price(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<(Decimal, Option<Decimal>), ProgramError> {
+        let price = self.get_oracle_price(oracle_info, clock)?;
+        Ok((price.price, price.ema_price))
+    }
+
+    pub fn get_single_price_unchecked(
+        &self,
+        oracle_info: &AccountInfo,
+        clock: &Clock,
+    ) -> Result<Decimal, ProgramError> {
+        self.find(oracle_info)?
+            .get_price_unchecked(oracle_info, clock)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct MockSource {
+        owner: Pubkey,
+    }
+
+    impl PriceSource for MockSource {
+        fn oracle_type(&self) -> OracleType {
+            OracleType::Pyth
+        }
+
+        fn is_owner(&self, owner: &Pubkey) -> bool {
+            *owner == self.owner
+        }
+
+        fn get_price(
+            &self,
+            _oracle_info: &AccountInfo,
+            clock: &Clock,
+        ) -> Result<OraclePrice, ProgramError> {
+            Ok(OraclePrice {
+                price: Decimal::from(10u64),
+                conf: Decimal::from_percent(1),
+                publish_slot: clock.slot,
+                publish_time: clock.unix_timestamp,
+                ema_price: Some(Decimal::from(9u64)),
+            })
+        }
+
+        fn get_price_unchecked(
+            &self,
+            _oracle_info: &AccountInfo,
+            _clock: &Clock,
+        ) -> Result<Decimal, ProgramError> {
+            Ok(Decimal::from(11u64))
+        }
+    }
+
+    #[test]
+    fn test_registry_routes_to_registered_source() {
+        let mock_owner = Pubkey::new_unique();
+        let pubkey = Pubkey::new_unique();
+        let mut lamports = 20;
+        let mut data = vec![];
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &mock_owner,
+            false,
+            0,
+        );
+        let clock = Clock {
+            slot: 5,
+            ..Clock::default()
+        };
+
+        // not known by the default registry
+        let mut registry = PriceSourceRegistry::default();
+        assert_eq!(
+            registry.get_single_price(&account_info, &clock),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        registry.register(Box::new(MockSource { owner: mock_owner }));
+        assert_eq!(
+            registry.get_single_price(&account_info, &clock),
+            Ok((Decimal::from(10u64), Some(Decimal::from(9u64))))
+        );
+        assert_eq!(
+            registry.get_single_price_unchecked(&account_info, &clock),
+            Ok(Decimal::from(11u64))
+        );
+        assert_eq!(
+            registry
+                .get_oracle_price(&account_info, &clock)
+                .unwrap()
+                .publish_slot,
+            5
+        );
+    }
+}
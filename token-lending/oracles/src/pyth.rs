@@ -1,5 +1,7 @@
 #![allow(missing_docs)]
-use crate::{get_oracle_type, pyth_mainnet, pyth_pull_mainnet, OracleType};
+use crate::price_source::OraclePrice;
+use crate::stake_pool::validate_stake_pool_keys;
+use crate::{get_oracle_type, OracleProgramIds, OracleType};
 use anchor_lang::Key;
 use solend_sdk::{
     error::LendingError,
@@ -11,30 +13,43 @@ use anchor_lang::AccountDeserialize;
 use pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, VerificationLevel};
 use solana_program::{
     account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
-    sysvar::clock::Clock,
+    pubkey::Pubkey, sysvar::clock::Clock,
 };
 use std::{convert::TryInto, result::Result};
 
-const PYTH_CONFIDENCE_RATIO: u64 = 10;
+pub(crate) const PYTH_CONFIDENCE_RATIO: u64 = 10;
 const STALE_AFTER_SLOTS_ELAPSED: u64 = 240; // roughly 2 min
-const STALE_AFTER_SECONDS_ELAPSED: u64 = 120; // roughly 2 min
+pub(crate) const STALE_AFTER_SECONDS_ELAPSED: u64 = 120; // roughly 2 min
 
 /// validates pyth AccountInfos
 #[inline(always)]
-pub fn validate_pyth_keys(pyth_price_info: &AccountInfo) -> ProgramResult {
+pub fn validate_pyth_keys(
+    pyth_price_info: &AccountInfo,
+    oracle_program_ids: &OracleProgramIds,
+) -> ProgramResult {
     if *pyth_price_info.key == solend_sdk::NULL_PUBKEY {
         return Ok(());
     }
 
-    match get_oracle_type(pyth_price_info)? {
-        OracleType::Pyth => validate_pyth_price_account_info(pyth_price_info),
-        OracleType::PythPull => validate_pyth_pull_price_account_info(pyth_price_info),
+    match get_oracle_type(pyth_price_info, oracle_program_ids)? {
+        OracleType::Pyth => {
+            validate_pyth_price_account_info(pyth_price_info, &oracle_program_ids.pyth)
+        }
+        OracleType::PythPull => {
+            validate_pyth_pull_price_account_info(pyth_price_info, &oracle_program_ids.pyth_pull)
+        }
+        OracleType::StakePool => {
+            validate_stake_pool_keys(pyth_price_info, &oracle_program_ids.stake_pool)
+        }
         _ => Err(LendingError::InvalidOracleConfig.into()),
     }
 }
 
-pub fn validate_pyth_price_account_info(pyth_price_info: &AccountInfo) -> Result<(), ProgramError> {
-    if *pyth_price_info.owner != pyth_mainnet::id() {
+pub fn validate_pyth_price_account_info(
+    pyth_price_info: &AccountInfo,
+    pyth_program_ids: &[Pubkey],
+) -> Result<(), ProgramError> {
+    if !pyth_program_ids.contains(pyth_price_info.owner) {
         msg!("pyth price account is not owned by pyth program");
         return Err(ProgramError::IncorrectProgramId);
     }
@@ -50,8 +65,9 @@ pub fn validate_pyth_price_account_info(pyth_price_info: &AccountInfo) -> Result
 
 pub fn validate_pyth_pull_price_account_info(
     pyth_price_info: &AccountInfo,
+    pyth_pull_program_ids: &[Pubkey],
 ) -> Result<(), ProgramError> {
-    if *pyth_price_info.owner != pyth_pull_mainnet::id() {
+    if !pyth_pull_program_ids.contains(pyth_price_info.owner) {
         msg!("pyth price account is not owned by pyth program");
         return Err(ProgramError::IncorrectProgramId);
     }
@@ -83,8 +99,9 @@ pub fn get_pyth_price_unchecked(pyth_price_info: &AccountInfo) -> Result<Decimal
 
 pub fn get_pyth_pull_price_unchecked(
     pyth_price_info: &AccountInfo,
+    pyth_pull_program_ids: &[Pubkey],
 ) -> Result<Decimal, ProgramError> {
-    if *pyth_price_info.owner != pyth_pull_mainnet::id() {
+    if !pyth_pull_program_ids.contains(pyth_price_info.owner) {
         msg!("pyth price account is not owned by pyth program");
         return Err(ProgramError::IncorrectProgramId);
     }
@@ -109,6 +126,20 @@ pub fn get_pyth_price(
     pyth_price_info: &AccountInfo,
     clock: &Clock,
 ) -> Result<(Decimal, Decimal), ProgramError> {
+    let oracle_price = get_pyth_oracle_price(pyth_price_info, clock)?;
+    Ok((
+        oracle_price.price,
+        oracle_price
+            .ema_price
+            .ok_or(LendingError::InvalidOracleConfig)?,
+    ))
+}
+
+/// get_pyth_price, but also returns the confidence and publish slot/time of the price
+pub fn get_pyth_oracle_price(
+    pyth_price_info: &AccountInfo,
+    clock: &Clock,
+) -> Result<OraclePrice, ProgramError> {
     if *pyth_price_info.key == solend_sdk::NULL_PUBKEY {
         return Err(LendingError::NullOracleConfig.into());
     }
@@ -142,7 +173,14 @@ pub fn get_pyth_price(
         return Err(LendingError::InvalidOracleConfig.into());
     }
 
-    let market_price = pyth_price_to_decimal(&pyth_price);
+    let market_price = pyth_price_to_decimal(&pyth_price)?;
+    let conf = pyth_price_to_decimal(&pyth_sdk_solana::Price {
+        price: pyth_price
+            .conf
+            .try_into()
+            .map_err(|_| LendingError::MathOverflow)?,
+        ..pyth_price
+    })?;
     let ema_price = {
         let price_feed = price_account.to_price_feed(pyth_price_info.key);
         // this can be unchecked bc the ema price is only used to _limit_ borrows and withdraws.
@@ -154,7 +192,13 @@ pub fn get_pyth_price(
         pyth_price_to_decimal(&ema_price)?
     };
 
-    Ok((market_price?, ema_price))
+    Ok(OraclePrice {
+        price: market_price,
+        conf,
+        publish_slot: price_account.agg.pub_slot,
+        publish_time: pyth_price.publish_time,
+        ema_price: Some(ema_price),
+    })
 }
 
 pub fn account_deserialize<T: AccountDeserialize>(
@@ -175,6 +219,20 @@ pub fn get_pyth_pull_price(
     pyth_price_info: &AccountInfo,
     clock: &Clock,
 ) -> Result<(Decimal, Decimal), ProgramError> {
+    let oracle_price = get_pyth_pull_oracle_price(pyth_price_info, clock)?;
+    Ok((
+        oracle_price.price,
+        oracle_price
+            .ema_price
+            .ok_or(LendingError::InvalidOracleConfig)?,
+    ))
+}
+
+/// get_pyth_pull_price, but also returns the confidence and publish slot/time of the price
+pub fn get_pyth_pull_oracle_price(
+    pyth_price_info: &AccountInfo,
+    clock: &Clock,
+) -> Result<OraclePrice, ProgramError> {
     if *pyth_price_info.key == solend_sdk::NULL_PUBKEY {
         return Err(LendingError::NullOracleConfig.into());
     }
@@ -211,6 +269,13 @@ pub fn get_pyth_pull_price(
     }
 
     let market_price = pyth_pull_price_to_decimal(&pyth_price)?;
+    let conf = pyth_pull_price_to_decimal(&pyth_solana_receiver_sdk::price_update::Price {
+        price: pyth_price
+            .conf
+            .try_into()
+            .map_err(|_| LendingError::MathOverflow)?,
+        ..pyth_price
+    })?;
 
     let ema_price = {
         let ema_price = pyth_solana_receiver_sdk::price_update::Price {
@@ -222,10 +287,18 @@ pub fn get_pyth_pull_price(
         pyth_pull_price_to_decimal(&ema_price)?
     };
 
-    Ok((market_price, ema_price))
+    Ok(OraclePrice {
+        price: market_price,
+        conf,
+        publish_slot: price_feed_account.posted_slot,
+        publish_time: pyth_price.publish_time,
+        ema_price: Some(ema_price),
+    })
 }
 
-fn pyth_price_to_decimal(pyth_price: &pyth_sdk_solana::Price) -> Result<Decimal, ProgramError> {
+pub(crate) fn pyth_price_to_decimal(
+    pyth_price: &pyth_sdk_solana::Price,
+) -> Result<Decimal, ProgramError> {
     let price: u64 = pyth_price.price.try_into().map_err(|_| {
         msg!("Oracle price cannot be negative");
         LendingError::InvalidOracleConfig
@@ -254,7 +327,7 @@ fn pyth_price_to_decimal(pyth_price: &pyth_sdk_solana::Price) -> Result<Decimal,
     }
 }
 
-fn pyth_pull_price_to_decimal(
+pub(crate) fn pyth_pull_price_to_decimal(
     pyth_price: &pyth_solana_receiver_sdk::price_update::Price,
 ) -> Result<Decimal, ProgramError> {
     let price: u64 = pyth_price.price.try_into().map_err(|_| {
@@ -690,7 +763,10 @@ mod test {
         let ema_price = Decimal::from(134522707_u64)
             .try_div(Decimal::from(1000000_u64))
             .unwrap();
-        assert_eq!(get_pyth_pull_price_unchecked(&account_info).unwrap(), price);
+        assert_eq!(
+            get_pyth_pull_price_unchecked(&account_info, &[owner]).unwrap(),
+            price
+        );
 
         let clock = Clock {
             slot: 240,
@@ -0,0 +1,210 @@
+#![allow(missing_docs)]
+use crate::{get_single_price, OracleProgramIds};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    pubkey::Pubkey, sysvar::clock::Clock,
+};
+use solend_sdk::{
+    error::LendingError,
+    math::{Decimal, TryDiv, TryMul},
+};
+use std::{convert::TryInto, result::Result};
+
+/// spl-stake-pool `AccountType::StakePool` discriminator
+const STAKE_POOL_ACCOUNT_TYPE: u8 = 1;
+/// offset of `total_lamports` in the borsh-serialized `StakePool`. It's preceded by the account
+/// type (1), manager, staker, stake deposit authority (3 * 32), the withdraw bump seed (1) and
+/// the validator list, reserve stake, pool mint, manager fee account and token program (5 * 32)
+const TOTAL_LAMPORTS_OFFSET: usize = 258;
+/// offset of `pool_token_supply`, right after `total_lamports`
+const POOL_TOKEN_SUPPLY_OFFSET: usize = TOTAL_LAMPORTS_OFFSET + 8;
+
+/// validates that the account is an initialized spl stake pool
+pub fn validate_stake_pool_keys(
+    stake_pool_info: &AccountInfo,
+    stake_pool_program_ids: &[Pubkey],
+) -> ProgramResult {
+    if !stake_pool_program_ids.contains(stake_pool_info.owner) {
+        msg!("stake pool account is not owned by the stake pool program");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    get_stake_pool_exchange_rate(stake_pool_info)?;
+    Ok(())
+}
+
+/// Returns the amount of SOL one pool token can be redeemed for, ie
+/// total_lamports / pool_token_supply
+pub fn get_stake_pool_exchange_rate(
+    stake_pool_info: &AccountInfo,
+) -> Result<Decimal, ProgramError> {
+    let data = stake_pool_info.try_borrow_data()?;
+    if data.len() < POOL_TOKEN_SUPPLY_OFFSET + 8 || data[0] != STAKE_POOL_ACCOUNT_TYPE {
+        msg!("Couldn't load stake pool from account info");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let total_lamports = u64::from_le_bytes(
+        data[TOTAL_LAMPORTS_OFFSET..TOTAL_LAMPORTS_OFFSET + 8]
+            .try_into()
+            .unwrap(),
+    );
+    let pool_token_supply = u64::from_le_bytes(
+        data[POOL_TOKEN_SUPPLY_OFFSET..POOL_TOKEN_SUPPLY_OFFSET + 8]
+            .try_into()
+            .unwrap(),
+    );
+    if pool_token_supply == 0 {
+        msg!("Stake pool token supply is zero");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    Decimal::from(total_lamports).try_div(Decimal::from(pool_token_supply))
+}
+
+/// Prices a stake pool token as its exchange rate times the price of the underlying asset. The
+/// confidence and staleness checks are those of the underlying oracle.
+pub fn get_stake_pool_price(
+    stake_pool_info: &AccountInfo,
+    underlying_price_info: &AccountInfo,
+    clock: &Clock,
+    oracle_program_ids: &OracleProgramIds,
+) -> Result<(Decimal, Option<Decimal>), ProgramError> {
+    if !oracle_program_ids
+        .stake_pool
+        .contains(stake_pool_info.owner)
+    {
+        msg!("stake pool account is not owned by the stake pool program");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let exchange_rate = get_stake_pool_exchange_rate(stake_pool_info)?;
+    let (price, ema_price) = get_single_price(underlying_price_info, clock, oracle_program_ids)?;
+
+    Ok((
+        price.try_mul(exchange_rate)?,
+        ema_price.map(|p| p.try_mul(exchange_rate)).transpose()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spl_stake_pool_mainnet;
+
+    fn stake_pool_data(total_lamports: u64, pool_token_supply: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 611];
+        data[0] = STAKE_POOL_ACCOUNT_TYPE;
+        data[TOTAL_LAMPORTS_OFFSET..TOTAL_LAMPORTS_OFFSET + 8]
+            .copy_from_slice(&total_lamports.to_le_bytes());
+        data[POOL_TOKEN_SUPPLY_OFFSET..POOL_TOKEN_SUPPLY_OFFSET + 8]
+            .copy_from_slice(&pool_token_supply.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_stake_pool_exchange_rate() {
+        let pubkey = Pubkey::new_unique();
+        let owner = spl_stake_pool_mainnet::id();
+
+        let mut lamports = 20;
+        let mut data = stake_pool_data(12, 10);
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            get_stake_pool_exchange_rate(&account_info),
+            Ok(Decimal::from_percent(120))
+        );
+        assert_eq!(validate_stake_pool_keys(&account_info, &[owner]), Ok(()));
+
+        // empty pool
+        let mut lamports = 20;
+        let mut data = stake_pool_data(0, 0);
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            get_stake_pool_exchange_rate(&account_info),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        // wrong account type
+        let mut lamports = 20;
+        let mut data = stake_pool_data(12, 10);
+        data[0] = 2;
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        assert_eq!(
+            validate_stake_pool_keys(&account_info, &[owner]),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+
+        // wrong owner
+        let mut lamports = 20;
+        let mut data = stake_pool_data(12, 10);
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &pubkey,
+            false,
+            0,
+        );
+        assert_eq!(
+            validate_stake_pool_keys(&account_info, &[owner]),
+            Err(ProgramError::IncorrectProgramId)
+        );
+    }
+
+    #[test]
+    fn test_stake_pool_price_requires_stake_pool() {
+        let pubkey = Pubkey::new_unique();
+        let mut lamports = 20;
+        let mut data = stake_pool_data(11, 10);
+        let account_info = AccountInfo::new(
+            &pubkey,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &pubkey,
+            false,
+            0,
+        );
+
+        assert_eq!(
+            get_stake_pool_price(
+                &account_info,
+                &account_info,
+                &Clock::default(),
+                &OracleProgramIds::default()
+            ),
+            Err(LendingError::InvalidOracleConfig.into())
+        );
+    }
+}
@@ -1,15 +1,12 @@
 use crate::get_oracle_type;
-use crate::OracleType;
+use crate::price_source::OraclePrice;
+use crate::{OracleProgramIds, OracleType};
 use solend_sdk::math::TryDiv;
 use solend_sdk::math::TryMul;
 
-use crate::{
-    switchboard_on_demand_devnet, switchboard_on_demand_mainnet, switchboard_v2_devnet,
-    switchboard_v2_mainnet,
-};
 use solana_program::{
     account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
-    sysvar::clock::Clock,
+    pubkey::Pubkey, sysvar::clock::Clock,
 };
 use solend_sdk::{error::LendingError, math::Decimal};
 use std::result::Result;
@@ -17,21 +14,26 @@ use std::result::Result;
 use switchboard_on_demand::on_demand::accounts::pull_feed::PullFeedAccountData as SbOnDemandFeed;
 use switchboard_v2::AggregatorAccountData;
 
+pub(crate) const STALE_AFTER_SLOTS_ELAPSED: u64 = 240;
+
 pub fn get_switchboard_price(
     switchboard_feed_info: &AccountInfo,
     clock: &Clock,
+    oracle_program_ids: &OracleProgramIds,
 ) -> Result<Decimal, ProgramError> {
     if *switchboard_feed_info.key == solend_sdk::NULL_PUBKEY {
         return Err(LendingError::NullOracleConfig.into());
     }
-    if switchboard_feed_info.owner == &switchboard_v2_mainnet::id()
-        || switchboard_feed_info.owner == &switchboard_v2_devnet::id()
+    if oracle_program_ids
+        .switchboard_v2
+        .contains(switchboard_feed_info.owner)
     {
         return get_switchboard_price_v2(switchboard_feed_info, clock, true);
     }
 
-    if switchboard_feed_info.owner == &switchboard_on_demand_devnet::id()
-        || switchboard_feed_info.owner == &switchboard_on_demand_mainnet::id()
+    if oracle_program_ids
+        .switchboard_on_demand
+        .contains(switchboard_feed_info.owner)
     {
         return get_switchboard_price_on_demand(switchboard_feed_info, clock, true);
     }
@@ -43,7 +45,18 @@ pub fn get_switchboard_price_on_demand(
     clock: &Clock,
     check_staleness: bool,
 ) -> Result<Decimal, ProgramError> {
-    const STALE_AFTER_SLOTS_ELAPSED: u64 = 240;
+    Ok(
+        get_switchboard_on_demand_oracle_price(switchboard_feed_info, clock, check_staleness)?
+            .price,
+    )
+}
+
+/// get_switchboard_price_on_demand, but also returns the range and publish slot/time of the price
+pub fn get_switchboard_on_demand_oracle_price(
+    switchboard_feed_info: &AccountInfo,
+    clock: &Clock,
+    check_staleness: bool,
+) -> Result<OraclePrice, ProgramError> {
     let data = switchboard_feed_info.try_borrow_data()?;
     let feed = SbOnDemandFeed::parse(data).map_err(|_| ProgramError::InvalidAccountData)?;
     let slots_elapsed = clock
@@ -81,7 +94,13 @@ pub fn get_switchboard_price_on_demand(
         return Err(LendingError::InvalidOracleConfig.into());
     }
 
-    Ok(price)
+    Ok(OraclePrice {
+        price,
+        conf: range,
+        publish_slot: feed.result.slot,
+        publish_time: feed.last_update_timestamp,
+        ema_price: None,
+    })
 }
 
 pub fn get_switchboard_price_v2(
@@ -89,7 +108,16 @@ pub fn get_switchboard_price_v2(
     clock: &Clock,
     check_staleness: bool,
 ) -> Result<Decimal, ProgramError> {
-    const STALE_AFTER_SLOTS_ELAPSED: u64 = 240;
+    Ok(get_switchboard_v2_oracle_price(switchboard_feed_info, clock, check_staleness)?.price)
+}
+
+/// get_switchboard_price_v2, but also returns the standard deviation and publish slot/time of the
+/// latest confirmed round
+pub fn get_switchboard_v2_oracle_price(
+    switchboard_feed_info: &AccountInfo,
+    clock: &Clock,
+    check_staleness: bool,
+) -> Result<OraclePrice, ProgramError> {
     let data = &switchboard_feed_info.try_borrow_data()?;
     let feed = AggregatorAccountData::new_from_bytes(data)?;
 
@@ -109,29 +137,50 @@ pub fn get_switchboard_price_v2(
     }
     let price = Decimal::from(price_switchboard_desc.mantissa as u128);
     let exp = Decimal::from((10u128).checked_pow(price_switchboard_desc.scale).unwrap());
-    price.try_div(exp)
+
+    let std_deviation = feed.latest_confirmed_round.std_deviation;
+    let conf = Decimal::from(std_deviation.mantissa.unsigned_abs()).try_div(Decimal::from(
+        (10u128).checked_pow(std_deviation.scale).unwrap(),
+    ))?;
+
+    Ok(OraclePrice {
+        price: price.try_div(exp)?,
+        conf,
+        publish_slot: feed.latest_confirmed_round.round_open_slot,
+        publish_time: feed.latest_confirmed_round.round_open_timestamp,
+        ema_price: None,
+    })
 }
 
-pub fn validate_switchboard_keys(switchboard_feed_info: &AccountInfo) -> ProgramResult {
+pub fn validate_switchboard_keys(
+    switchboard_feed_info: &AccountInfo,
+    oracle_program_ids: &OracleProgramIds,
+) -> ProgramResult {
     if *switchboard_feed_info.key == solend_sdk::NULL_PUBKEY {
         return Ok(());
     }
 
-    match get_oracle_type(switchboard_feed_info)? {
-        OracleType::Switchboard => validate_switchboard_v2_keys(switchboard_feed_info),
-        OracleType::SbOnDemand => validate_sb_on_demand_keys(switchboard_feed_info),
+    match get_oracle_type(switchboard_feed_info, oracle_program_ids)? {
+        OracleType::Switchboard => {
+            validate_switchboard_v2_keys(switchboard_feed_info, &oracle_program_ids.switchboard_v2)
+        }
+        OracleType::SbOnDemand => validate_sb_on_demand_keys(
+            switchboard_feed_info,
+            &oracle_program_ids.switchboard_on_demand,
+        ),
         _ => Err(LendingError::InvalidOracleConfig.into()),
     }
 }
 
 /// validates switchboard AccountInfo
-fn validate_switchboard_v2_keys(switchboard_feed_info: &AccountInfo) -> ProgramResult {
+fn validate_switchboard_v2_keys(
+    switchboard_feed_info: &AccountInfo,
+    switchboard_v2_program_ids: &[Pubkey],
+) -> ProgramResult {
     if *switchboard_feed_info.key == solend_sdk::NULL_PUBKEY {
         return Ok(());
     }
-    if switchboard_feed_info.owner != &switchboard_v2_mainnet::id()
-        && switchboard_feed_info.owner != &switchboard_v2_devnet::id()
-    {
+    if !switchboard_v2_program_ids.contains(switchboard_feed_info.owner) {
         msg!("Switchboard account provided is not owned by the switchboard oracle program");
         return Err(LendingError::InvalidOracleConfig.into());
     }
@@ -143,14 +192,15 @@ fn validate_switchboard_v2_keys(switchboard_feed_info: &AccountInfo) -> ProgramR
 }
 
 /// validates switchboard on-demand AccountInfo
-pub fn validate_sb_on_demand_keys(switchboard_feed_info: &AccountInfo) -> ProgramResult {
+pub fn validate_sb_on_demand_keys(
+    switchboard_feed_info: &AccountInfo,
+    switchboard_on_demand_program_ids: &[Pubkey],
+) -> ProgramResult {
     if *switchboard_feed_info.key == solend_sdk::NULL_PUBKEY {
         return Ok(());
     }
 
-    if switchboard_feed_info.owner != &switchboard_on_demand_mainnet::id()
-        && switchboard_feed_info.owner != &switchboard_on_demand_devnet::id()
-    {
+    if !switchboard_on_demand_program_ids.contains(switchboard_feed_info.owner) {
         msg!("Switchboard account provided is not owned by the switchboard oracle program");
         return Err(LendingError::InvalidOracleConfig.into());
     }
@@ -4,24 +4,31 @@ use crate::state::Bonus;
 use crate::{
     self as solend_program,
     error::LendingError,
-    instruction::LendingInstruction,
+    instruction::{LendingInstruction, MAX_LIQUIDATION_BATCH_LEGS},
     math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub},
     state::{
-        validate_reserve_config, CalculateBorrowResult, CalculateLiquidationResult,
-        CalculateRepayResult, InitLendingMarketParams, InitObligationParams, InitReserveParams,
-        LendingMarket, NewReserveCollateralParams, NewReserveLiquidityParams, Obligation, Reserve,
+        validate_global_borrow_values, validate_obligation_outflow_limit, validate_reserve_config,
+        CalculateBorrowResult, CalculateLiquidationResult, CalculateRepayResult,
+        InitLendingMarketParams, InitObligationParams, InitReserveParams, LendingMarket,
+        NewReserveCollateralParams, NewReserveLiquidityParams, Obligation, Reserve,
         ReserveCollateral, ReserveConfig, ReserveLiquidity,
     },
 };
-use bytemuck::bytes_of;
+use bytemuck::{bytes_of, Zeroable};
 use oracles::get_single_price;
 use oracles::get_single_price_unchecked;
+use oracles::pegged::get_pegged_price;
 use oracles::pyth::validate_pyth_keys;
+use oracles::pyth::validate_pyth_pull_price_account_info;
+use oracles::stake_pool::get_stake_pool_price;
 use oracles::switchboard::validate_sb_on_demand_keys;
 use oracles::switchboard::validate_switchboard_keys;
-use oracles::{get_oracle_type, pyth::validate_pyth_price_account_info, OracleType};
+use oracles::{
+    get_oracle_type, pyth::validate_pyth_price_account_info, OracleProgramIds, OracleType,
+};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     entrypoint::ProgramResult,
     instruction::{get_stack_height, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT},
     msg,
@@ -35,17 +42,32 @@ use solana_program::{
 };
 use solend_sdk::{
     math::SaturatingSub,
-    state::{LendingMarketMetadata, RateLimiter, RateLimiterConfig, ReserveType},
+    state::{
+        ConfigDeltaBounds, ConfigTimelock, FlashLoanAllowlist, LendingMarketMetadata, MarketRole,
+        MarketRoles, PegMode, PendingConfigChange, PendingMarketOwner, ProtocolConfig, RateLimiter,
+        RateLimiterConfig, ReserveType, DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE,
+        DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE, MARKET_ROLES_PADDING_SIZE,
+        MAX_FLASH_LOAN_FEE_DISCOUNT_BPS, MAX_LIQUIDATION_THRESHOLD_RAMP_SLOTS,
+        MAX_QUEUED_CONFIG_CHANGE_ACCOUNTS, MAX_QUEUED_CONFIG_CHANGE_DATA_LEN,
+        PENDING_MARKET_OWNER_PADDING_SIZE,
+    },
 };
 
-use spl_token::state::Mint;
-use std::{cmp::min, result::Result};
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::{cmp::min, convert::TryInto, result::Result};
 
 /// solend market owner
 pub mod solend_market_owner {
     solana_program::declare_id!("5pHk2TmnqQzRF9L6egy5FfiyBgS7G9cMZ5RFaJAvghzw");
 }
 
+/// Length of a queued `UpdateMarketRoles` instruction, which leaves out the market roles padding
+const QUEUED_UPDATE_MARKET_ROLES_DATA_LEN: usize =
+    1 + std::mem::size_of::<MarketRoles>() - MARKET_ROLES_PADDING_SIZE;
+
+/// Maximum number of borrow and re-deposit rounds in a leverage loop
+const MAX_LEVERAGE_LOOP_ITERATIONS: usize = 10;
+
 /// Processes an instruction
 pub fn process_instruction(
     program_id: &Pubkey,
@@ -66,6 +88,10 @@ pub fn process_instruction(
             rate_limiter_config,
             whitelisted_liquidator,
             risk_authority,
+            obligation_max_outflow,
+            obligation_outflow_window,
+            global_allowed_borrow_value,
+            global_unhealthy_borrow_value,
         } => {
             msg!("Instruction: Set Lending Market Owner");
             process_set_lending_market_owner_and_config(
@@ -74,7 +100,12 @@ pub fn process_instruction(
                 rate_limiter_config,
                 whitelisted_liquidator,
                 risk_authority,
+                obligation_max_outflow,
+                obligation_outflow_window,
+                global_allowed_borrow_value,
+                global_unhealthy_borrow_value,
                 accounts,
+                false,
             )
         }
         LendingInstruction::InitReserve {
@@ -151,9 +182,17 @@ pub fn process_instruction(
         LendingInstruction::UpdateReserveConfig {
             config,
             rate_limiter_config,
+            liquidation_threshold_ramp_slots,
         } => {
             msg!("Instruction: UpdateReserveConfig");
-            process_update_reserve_config(program_id, config, rate_limiter_config, accounts)
+            process_update_reserve_config(
+                program_id,
+                config,
+                rate_limiter_config,
+                liquidation_threshold_ramp_slots,
+                accounts,
+                false,
+            )
         }
         LendingInstruction::LiquidateObligationAndRedeemReserveCollateral { liquidity_amount } => {
             msg!("Instruction: Liquidate Obligation and Redeem Reserve Collateral");
@@ -196,6 +235,79 @@ pub fn process_instruction(
             msg!("Instruction: Mark Obligation As Closable");
             process_set_obligation_closeability_status(program_id, closeable, accounts)
         }
+        LendingInstruction::UpdateFlashLoanAllowlist => {
+            msg!("Instruction: Update Flash Loan Allowlist");
+            let allowlist = FlashLoanAllowlist::new_from_bytes(input)?;
+            process_update_flash_loan_allowlist(program_id, allowlist, accounts)
+        }
+        LendingInstruction::LeverageLoop {
+            liquidity_amount,
+            target_ltv,
+        } => {
+            msg!("Instruction: Leverage Loop");
+            process_leverage_loop(program_id, liquidity_amount, target_ltv, accounts)
+        }
+        LendingInstruction::LiquidateObligationBatch { liquidity_amounts } => {
+            msg!("Instruction: Liquidate Obligation Batch");
+            process_liquidate_obligation_batch(program_id, &liquidity_amounts, accounts)
+        }
+        LendingInstruction::SetConfigTimelock { delay_slots } => {
+            msg!("Instruction: Set Config Timelock");
+            process_set_config_timelock(program_id, delay_slots, accounts, false)
+        }
+        LendingInstruction::QueueConfigChange { change } => {
+            msg!("Instruction: Queue Config Change");
+            process_queue_config_change(program_id, &change, &input[1..], accounts)
+        }
+        LendingInstruction::ExecuteConfigChange => {
+            msg!("Instruction: Execute Config Change");
+            process_execute_config_change(program_id, accounts)
+        }
+        LendingInstruction::CancelConfigChange => {
+            msg!("Instruction: Cancel Config Change");
+            process_cancel_config_change(program_id, accounts)
+        }
+        LendingInstruction::UpdateMarketRoles => {
+            msg!("Instruction: Update Market Roles");
+            let roles = MarketRoles::new_from_bytes(input)?;
+            process_update_market_roles(program_id, roles, accounts, false)
+        }
+        LendingInstruction::ProposeMarketOwner { new_owner } => {
+            msg!("Instruction: Propose Market Owner");
+            process_propose_market_owner(program_id, new_owner, accounts)
+        }
+        LendingInstruction::AcceptMarketOwner => {
+            msg!("Instruction: Accept Market Owner");
+            process_accept_market_owner(program_id, accounts)
+        }
+        LendingInstruction::SetMarketPaused { paused } => {
+            msg!("Instruction: Set Market Paused");
+            process_set_market_paused(program_id, paused, accounts)
+        }
+        LendingInstruction::InitProtocolConfig => {
+            msg!("Instruction: Init Protocol Config");
+            let protocol_config = ProtocolConfig::new_from_bytes(input)?;
+            process_init_protocol_config(program_id, protocol_config, accounts)
+        }
+        LendingInstruction::UpdateProtocolConfig => {
+            msg!("Instruction: Update Protocol Config");
+            let protocol_config = ProtocolConfig::new_from_bytes(input)?;
+            process_update_protocol_config(program_id, protocol_config, accounts)
+        }
+        LendingInstruction::SetConfigDeltaBounds { delta_bounds } => {
+            msg!("Instruction: Set Config Delta Bounds");
+            process_set_config_delta_bounds(program_id, delta_bounds, accounts, false)
+        }
+        LendingInstruction::CloseReserve => {
+            msg!("Instruction: Close Reserve");
+            process_close_reserve(program_id, accounts)
+        }
+        LendingInstruction::UpdateReserveOracles {
+            extra_oracle_pubkey,
+        } => {
+            msg!("Instruction: Update Reserve Oracles");
+            process_update_reserve_oracles(program_id, extra_oracle_pubkey, accounts, false)
+        }
     }
 }
 
@@ -232,18 +344,28 @@ fn process_init_lending_market(
     Ok(())
 }
 
+/// `queued` is true when executing a change queued with `QueueConfigChange`, in which case the
+/// owner signed the queueing instead.
 #[inline(never)] // avoid stack frame limit
+#[allow(clippy::too_many_arguments)]
 fn process_set_lending_market_owner_and_config(
     program_id: &Pubkey,
     new_owner: Pubkey,
     rate_limiter_config: RateLimiterConfig,
     whitelisted_liquidator: Option<Pubkey>,
     risk_authority: Pubkey,
+    obligation_max_outflow: u32,
+    obligation_outflow_window: u64,
+    global_allowed_borrow_value: u64,
+    global_unhealthy_borrow_value: u64,
     accounts: &[AccountInfo],
+    queued: bool,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
     let lending_market_info = next_account_info(account_info_iter)?;
     let market_change_authority_info = next_account_info(account_info_iter)?;
+    let config_timelock_info = next_account_info(account_info_iter).ok();
+    let market_roles_info = next_account_info(account_info_iter).ok();
 
     let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -251,13 +373,30 @@ fn process_set_lending_market_owner_and_config(
         return Err(LendingError::InvalidAccountOwner.into());
     }
 
-    if !market_change_authority_info.is_signer {
+    if !queued && !market_change_authority_info.is_signer {
         msg!("Lending market owner or risk authority provided must be a signer");
         return Err(LendingError::InvalidSigner.into());
     }
 
-    if market_change_authority_info.key == &lending_market.owner {
-        lending_market.owner = new_owner;
+    if queued && market_change_authority_info.key != &lending_market.owner {
+        msg!("Queued config change was queued by a previous lending market owner");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    let timelocked = !queued
+        && optional_config_timelock_delay(
+            program_id,
+            &lending_market,
+            lending_market_info,
+            config_timelock_info,
+        )? > 0;
+
+    if market_change_authority_info.key == &lending_market.owner && !timelocked {
+        if new_owner != lending_market.owner {
+            msg!("Transfer lending market ownership with ProposeMarketOwner and AcceptMarketOwner");
+            return Err(LendingError::InvalidMarketOwner.into());
+        }
+
         lending_market.risk_authority = risk_authority;
 
         if rate_limiter_config != lending_market.rate_limiter.config {
@@ -265,6 +404,14 @@ fn process_set_lending_market_owner_and_config(
         }
 
         lending_market.whitelisted_liquidator = whitelisted_liquidator;
+
+        validate_obligation_outflow_limit(obligation_max_outflow, obligation_outflow_window)?;
+        lending_market.obligation_max_outflow = obligation_max_outflow;
+        lending_market.obligation_outflow_window = obligation_outflow_window;
+
+        validate_global_borrow_values(global_allowed_borrow_value, global_unhealthy_borrow_value)?;
+        lending_market.global_allowed_borrow_value = global_allowed_borrow_value;
+        lending_market.global_unhealthy_borrow_value = global_unhealthy_borrow_value;
     } else if market_change_authority_info.key == &lending_market.risk_authority {
         // only can disable outflows
         if rate_limiter_config != lending_market.rate_limiter.config
@@ -273,9 +420,77 @@ fn process_set_lending_market_owner_and_config(
         {
             lending_market.rate_limiter = RateLimiter::new(rate_limiter_config, Clock::get()?.slot);
         }
+
+        // only can lower the global borrow values
+        let global_allowed_borrow_value = min(
+            global_allowed_borrow_value,
+            lending_market.global_allowed_borrow_value,
+        );
+        let global_unhealthy_borrow_value = min(
+            global_unhealthy_borrow_value,
+            lending_market.global_unhealthy_borrow_value,
+        );
+        validate_global_borrow_values(global_allowed_borrow_value, global_unhealthy_borrow_value)?;
+        lending_market.global_allowed_borrow_value = global_allowed_borrow_value;
+        lending_market.global_unhealthy_borrow_value = global_unhealthy_borrow_value;
+    } else if market_change_authority_info.key == &lending_market.owner {
+        msg!("Lending market has a config timelock, owner changes must be queued");
+        return Err(LendingError::ConfigChangeTimelocked.into());
     } else {
-        msg!("Signer must be the lending market owner or risk authority");
-        return Err(LendingError::InvalidMarketOwner.into());
+        let market_roles =
+            load_optional_market_roles(program_id, lending_market_info, market_roles_info)?;
+        let signer = market_change_authority_info.key;
+        if !market_roles.has_any_role(signer) {
+            msg!("Signer must be the lending market owner or risk authority");
+            return Err(LendingError::InvalidMarketOwner.into());
+        }
+
+        if new_owner != lending_market.owner
+            || risk_authority != lending_market.risk_authority
+            || whitelisted_liquidator != lending_market.whitelisted_liquidator
+        {
+            msg!(
+                "Only the lending market owner can change its owner, risk authority or liquidator"
+            );
+            return Err(LendingError::MissingMarketRole.into());
+        }
+
+        let obligation_outflow_limit_changed = obligation_max_outflow
+            != lending_market.obligation_max_outflow
+            || obligation_outflow_window != lending_market.obligation_outflow_window;
+        let limits_changed = obligation_outflow_limit_changed
+            || global_allowed_borrow_value != lending_market.global_allowed_borrow_value
+            || global_unhealthy_borrow_value != lending_market.global_unhealthy_borrow_value;
+        require_market_role(
+            &market_roles,
+            signer,
+            MarketRole::LimitsAdmin,
+            limits_changed,
+        )?;
+
+        // while timelocked, only the changes the risk authority could make are instant
+        if timelocked
+            && (obligation_outflow_limit_changed
+                || global_allowed_borrow_value > lending_market.global_allowed_borrow_value
+                || global_unhealthy_borrow_value > lending_market.global_unhealthy_borrow_value
+                || (rate_limiter_config != lending_market.rate_limiter.config
+                    && !disables_outflows(&rate_limiter_config)))
+        {
+            msg!("Lending market has a config timelock, only risk-reducing changes are instant");
+            return Err(LendingError::ConfigChangeTimelocked.into());
+        }
+
+        if rate_limiter_config != lending_market.rate_limiter.config {
+            require_rate_limiter_role(&market_roles, signer, &rate_limiter_config)?;
+            lending_market.rate_limiter = RateLimiter::new(rate_limiter_config, Clock::get()?.slot);
+        }
+
+        validate_obligation_outflow_limit(obligation_max_outflow, obligation_outflow_window)?;
+        lending_market.obligation_max_outflow = obligation_max_outflow;
+        lending_market.obligation_outflow_window = obligation_outflow_window;
+        validate_global_borrow_values(global_allowed_borrow_value, global_unhealthy_borrow_value)?;
+        lending_market.global_allowed_borrow_value = global_allowed_borrow_value;
+        lending_market.global_unhealthy_borrow_value = global_unhealthy_borrow_value;
     }
 
     LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
@@ -316,6 +531,15 @@ fn process_init_reserve(
     let rent_info = next_account_info(account_info_iter)?;
     let rent = &Rent::from_account_info(rent_info)?;
     let token_program_id = next_account_info(account_info_iter)?;
+    let extra_oracle_info = match config.extra_oracle_pubkey {
+        Some(_) => Some(next_account_info(account_info_iter)?),
+        None => None,
+    };
+    let underlying_oracle_info = match config.underlying_oracle_pubkey {
+        Some(_) => Some(next_account_info(account_info_iter)?),
+        None => None,
+    };
+    let protocol_config_info = next_account_info(account_info_iter)?;
 
     assert_rent_exempt(rent, reserve_info)?;
     let mut reserve = assert_uninitialized::<Reserve>(reserve_info)?;
@@ -354,6 +578,16 @@ fn process_init_reserve(
         msg!("Lending market owner provided must be a signer");
         return Err(LendingError::InvalidSigner.into());
     }
+    if let Some(protocol_config) = load_protocol_config(program_id, protocol_config_info)? {
+        if lending_market.owner != protocol_config.authority
+            && (config.protocol_liquidation_fee != protocol_config.default_protocol_liquidation_fee
+                || config.protocol_take_rate != protocol_config.default_protocol_take_rate)
+        {
+            msg!("Reserves on permissionless markets must start with the default protocol fees");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        protocol_config.validate_protocol_fees(&config)?;
+    }
     if *switchboard_feed_info.key == solend_program::NULL_PUBKEY
         && (*pyth_price_info.key == solend_program::NULL_PUBKEY
             || *pyth_product_info.key == solend_program::NULL_PUBKEY)
@@ -361,16 +595,32 @@ fn process_init_reserve(
         msg!("Both price oracles are null. At least one must be non-null");
         return Err(LendingError::InvalidOracleConfig.into());
     }
-    validate_pyth_keys(pyth_price_info)?;
-    validate_switchboard_keys(switchboard_feed_info)?;
+    let oracle_program_ids = market_oracle_program_ids(&lending_market);
+    validate_pyth_keys(pyth_price_info, &oracle_program_ids)?;
+    validate_switchboard_keys(switchboard_feed_info, &oracle_program_ids)?;
 
-    if let Some(extra_oracle_pubkey) = config.extra_oracle_pubkey {
-        let extra_oracle_info = next_account_info(account_info_iter)?;
-        validate_extra_oracle(extra_oracle_pubkey, extra_oracle_info)?;
+    if let (Some(extra_oracle_pubkey), Some(extra_oracle_info)) =
+        (config.extra_oracle_pubkey, extra_oracle_info)
+    {
+        validate_extra_oracle(extra_oracle_pubkey, extra_oracle_info, &oracle_program_ids)?;
     }
 
-    let (market_price, smoothed_market_price) =
-        get_price(Some(switchboard_feed_info), pyth_price_info, clock)?;
+    validate_underlying_oracle(
+        config.underlying_oracle_pubkey,
+        pyth_price_info,
+        underlying_oracle_info,
+        &oracle_program_ids,
+    )?;
+
+    let (market_price, smoothed_market_price) = get_price(
+        Some(switchboard_feed_info),
+        pyth_price_info,
+        underlying_oracle_info,
+        clock,
+        &oracle_program_ids,
+    )?;
+    let (market_price, smoothed_market_price, depegged) =
+        apply_peg_mode(&config, market_price, smoothed_market_price)?;
 
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
@@ -410,6 +660,7 @@ fn process_init_reserve(
         config,
         rate_limiter_config: RateLimiterConfig::default(),
     });
+    reserve.liquidity.depegged = depegged;
 
     let collateral_amount = reserve.deposit_liquidity(liquidity_amount)?;
     Reserve::pack(reserve, &mut reserve_info.data.borrow_mut())?;
@@ -478,6 +729,7 @@ fn process_init_reserve(
 fn validate_extra_oracle(
     extra_oracle_pubkey: Pubkey,
     extra_oracle_info: &AccountInfo<'_>,
+    oracle_program_ids: &OracleProgramIds,
 ) -> Result<(), ProgramError> {
     if extra_oracle_pubkey == solend_program::NULL_PUBKEY {
         msg!("Extra oracle cannot equal the null pubkey");
@@ -489,18 +741,100 @@ fn validate_extra_oracle(
         return Err(LendingError::InvalidOracleConfig.into());
     }
 
-    match get_oracle_type(extra_oracle_info)? {
+    match get_oracle_type(extra_oracle_info, oracle_program_ids)? {
+        OracleType::Pyth => {
+            validate_pyth_price_account_info(extra_oracle_info, &oracle_program_ids.pyth)?;
+        }
+        OracleType::PythPull => {
+            validate_pyth_pull_price_account_info(
+                extra_oracle_info,
+                &oracle_program_ids.pyth_pull,
+            )?;
+        }
+        OracleType::Switchboard => {
+            validate_switchboard_keys(extra_oracle_info, oracle_program_ids)?;
+        }
+        OracleType::SbOnDemand => {
+            validate_sb_on_demand_keys(
+                extra_oracle_info,
+                &oracle_program_ids.switchboard_on_demand,
+            )?;
+        }
+        OracleType::StakePool => {
+            msg!("Stake pool accounts can't be used as an extra oracle");
+            return Err(LendingError::InvalidOracleConfig.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// A stake pool primary oracle is priced off an underlying oracle, so the two must be configured
+/// together.
+fn validate_underlying_oracle(
+    underlying_oracle_pubkey: Option<Pubkey>,
+    pyth_price_info: &AccountInfo<'_>,
+    underlying_oracle_info: Option<&AccountInfo<'_>>,
+    oracle_program_ids: &OracleProgramIds,
+) -> Result<(), ProgramError> {
+    let is_stake_pool = matches!(
+        get_oracle_type(pyth_price_info, oracle_program_ids),
+        Ok(OracleType::StakePool)
+    );
+
+    let underlying_oracle_pubkey = match underlying_oracle_pubkey {
+        Some(underlying_oracle_pubkey) => underlying_oracle_pubkey,
+        None => {
+            if is_stake_pool {
+                msg!("Stake pool oracles require an underlying oracle");
+                return Err(LendingError::InvalidOracleConfig.into());
+            }
+            return Ok(());
+        }
+    };
+
+    if !is_stake_pool {
+        msg!("Underlying oracle can only be set when the primary oracle is a stake pool");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let underlying_oracle_info = match underlying_oracle_info {
+        Some(underlying_oracle_info) => underlying_oracle_info,
+        None => {
+            msg!("Underlying oracle account info missing");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    };
+
+    if underlying_oracle_info.key != &underlying_oracle_pubkey {
+        msg!(
+            "Underlying oracle provided does not match the underlying oracle pubkey in the config"
+        );
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    match get_oracle_type(underlying_oracle_info, oracle_program_ids)? {
         OracleType::Pyth => {
-            validate_pyth_price_account_info(extra_oracle_info)?;
+            validate_pyth_price_account_info(underlying_oracle_info, &oracle_program_ids.pyth)?;
         }
         OracleType::PythPull => {
-            validate_pyth_price_account_info(extra_oracle_info)?;
+            validate_pyth_pull_price_account_info(
+                underlying_oracle_info,
+                &oracle_program_ids.pyth_pull,
+            )?;
         }
         OracleType::Switchboard => {
-            validate_switchboard_keys(extra_oracle_info)?;
+            validate_switchboard_keys(underlying_oracle_info, oracle_program_ids)?;
         }
         OracleType::SbOnDemand => {
-            validate_sb_on_demand_keys(extra_oracle_info)?;
+            validate_sb_on_demand_keys(
+                underlying_oracle_info,
+                &oracle_program_ids.switchboard_on_demand,
+            )?;
+        }
+        OracleType::StakePool => {
+            msg!("Underlying oracle can't be a stake pool");
+            return Err(LendingError::InvalidOracleConfig.into());
         }
     }
 
@@ -515,6 +849,8 @@ fn process_refresh_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> Pro
     let clock = &Clock::get()?;
 
     let extra_oracle_account_info = next_account_info(account_info_iter).ok();
+    let underlying_oracle_account_info = next_account_info(account_info_iter).ok();
+    let lending_market_info = next_account_info(account_info_iter).ok();
     _refresh_reserve(
         program_id,
         reserve_info,
@@ -522,9 +858,12 @@ fn process_refresh_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> Pro
         Some(switchboard_feed_info),
         clock,
         extra_oracle_account_info,
+        underlying_oracle_account_info,
+        lending_market_info,
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 fn _refresh_reserve<'a>(
     program_id: &Pubkey,
     reserve_info: &AccountInfo<'a>,
@@ -532,6 +871,8 @@ fn _refresh_reserve<'a>(
     switchboard_feed_info: Option<&AccountInfo<'a>>,
     clock: &Clock,
     extra_oracle_account_info: Option<&AccountInfo<'a>>,
+    underlying_oracle_account_info: Option<&AccountInfo<'a>>,
+    lending_market_info: Option<&AccountInfo<'a>>,
 ) -> ProgramResult {
     let mut reserve = Box::new(Reserve::unpack(&reserve_info.data.borrow())?);
     if reserve_info.owner != program_id {
@@ -551,8 +892,56 @@ fn _refresh_reserve<'a>(
         return Err(LendingError::InvalidOracleConfig.into());
     }
 
-    let (market_price, smoothed_market_price) =
-        get_price(switchboard_feed_info, pyth_price_info, clock)?;
+    // the underlying oracle is passed after the extra oracle, which is optional itself
+    let underlying_oracle_account_info = match reserve.config.underlying_oracle_pubkey {
+        None => None,
+        Some(underlying_oracle_pubkey) => {
+            match [extra_oracle_account_info, underlying_oracle_account_info]
+                .iter()
+                .flatten()
+                .find(|info| info.key == &underlying_oracle_pubkey)
+            {
+                Some(underlying_oracle_account_info) => Some(*underlying_oracle_account_info),
+                None => {
+                    msg!("Reserve underlying oracle account info missing");
+                    return Err(LendingError::InvalidAccountInput.into());
+                }
+            }
+        }
+    };
+
+    // the lending market is optional and only needed when the market uses oracle programs other
+    // than the mainnet ones. It's passed after the extra and underlying oracles.
+    let oracle_program_ids = match [
+        extra_oracle_account_info,
+        underlying_oracle_account_info,
+        lending_market_info,
+    ]
+    .iter()
+    .flatten()
+    .find(|info| info.key == &reserve.lending_market)
+    {
+        Some(lending_market_info) => {
+            if lending_market_info.owner != program_id {
+                msg!("Lending market provided is not owned by the lending program");
+                return Err(LendingError::InvalidAccountOwner.into());
+            }
+            let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+            market_oracle_program_ids(&lending_market)
+        }
+        None => OracleProgramIds::default(),
+    };
+
+    let (market_price, smoothed_market_price) = get_price(
+        switchboard_feed_info,
+        pyth_price_info,
+        underlying_oracle_account_info,
+        clock,
+        &oracle_program_ids,
+    )?;
+    let (market_price, smoothed_market_price, depegged) =
+        apply_peg_mode(&reserve.config, market_price, smoothed_market_price)?;
+    reserve.liquidity.depegged = depegged;
 
     reserve.liquidity.market_price = market_price.try_mul(reserve.price_scale())?;
 
@@ -574,6 +963,7 @@ fn _refresh_reserve<'a>(
                 Some(get_single_price_unchecked(
                     extra_oracle_account_info,
                     clock,
+                    &oracle_program_ids,
                 )?)
             }
             None => {
@@ -670,11 +1060,12 @@ fn _deposit_reserve_liquidity<'a>(
     clock: &Clock,
     token_program_id: &AccountInfo<'a>,
 ) -> Result<u64, ProgramError> {
-    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_market_not_paused(&lending_market)?;
     if &lending_market.token_program_id != token_program_id.key {
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
@@ -733,6 +1124,13 @@ fn _deposit_reserve_liquidity<'a>(
     }
 
     let collateral_amount = reserve.deposit_liquidity(liquidity_amount)?;
+    credit_rate_limiters(
+        &mut lending_market,
+        lending_market_info,
+        &mut reserve,
+        clock.slot,
+        Decimal::from(liquidity_amount),
+    )?;
     reserve.last_update.mark_stale();
     Reserve::pack(*reserve, &mut reserve_info.data.borrow_mut())?;
 
@@ -822,6 +1220,7 @@ fn _redeem_reserve_collateral<'a>(
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_market_not_paused(&lending_market)?;
     if &lending_market.token_program_id != token_program_id.key {
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
@@ -968,12 +1367,62 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
     let obligation_info = next_account_info(account_info_iter)?;
     let clock = &Clock::get()?;
 
-    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
     if obligation_info.owner != program_id {
         msg!("Obligation provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
 
+    // the lending market comes after the reserves and is optional so existing clients keep
+    // working, in which case the default global borrow values apply
+    let reserve_count = min(
+        obligation.deposits.len() + obligation.borrows.len(),
+        accounts.len() - 1,
+    );
+    let (reserve_infos, remaining_infos) = accounts[1..].split_at(reserve_count);
+    let lending_market = match remaining_infos {
+        [] => None,
+        [lending_market_info] => {
+            if lending_market_info.owner != program_id {
+                msg!("Lending market provided is not owned by the lending program");
+                return Err(LendingError::InvalidAccountOwner.into());
+            }
+            if &obligation.lending_market != lending_market_info.key {
+                msg!("Obligation lending market does not match the lending market provided");
+                return Err(LendingError::InvalidAccountInput.into());
+            }
+            Some(LendingMarket::unpack(&lending_market_info.data.borrow())?)
+        }
+        _ => {
+            msg!("Too many obligation deposit or borrow reserves provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    };
+
+    _refresh_obligation(
+        program_id,
+        obligation,
+        obligation_info,
+        reserve_infos,
+        lending_market.as_ref(),
+        clock,
+    )
+}
+
+/// Refreshes `obligation` against `reserve_infos`, its deposit reserves followed by its borrow
+/// reserves, all refreshed and in order. The allowed and unhealthy borrow values are capped at the
+/// lending market's global borrow values, or the defaults without the lending market.
+#[inline(never)] // avoid stack frame limit
+fn _refresh_obligation(
+    program_id: &Pubkey,
+    mut obligation: Obligation,
+    obligation_info: &AccountInfo,
+    reserve_infos: &[AccountInfo],
+    lending_market: Option<&LendingMarket>,
+    clock: &Clock,
+) -> ProgramResult {
+    let account_info_iter = &mut reserve_infos.iter();
+
     let mut deposited_value = Decimal::zero();
     let mut borrowed_value = Decimal::zero(); // weighted borrow value wrt borrow weights
     let mut unweighted_borrowed_value = Decimal::zero();
@@ -1018,9 +1467,9 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
 
         let loan_to_value_rate = Rate::from_percent(deposit_reserve.config.loan_to_value_ratio);
         let liquidation_threshold_rate =
-            Rate::from_percent(deposit_reserve.config.liquidation_threshold);
+            Rate::from_percent(deposit_reserve.liquidation_threshold(clock.slot));
         let max_liquidation_threshold_rate =
-            Rate::from_percent(deposit_reserve.config.max_liquidation_threshold);
+            Rate::from_percent(deposit_reserve.max_liquidation_threshold(clock.slot));
 
         collateral.market_value = market_value;
         deposited_value = deposited_value.try_add(market_value)?;
@@ -1101,19 +1550,25 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
         unweighted_borrowed_value = unweighted_borrowed_value.try_add(market_value)?;
     }
 
-    if account_info_iter.next().is_some() {
-        msg!("Too many obligation deposit or borrow reserves provided");
-        return Err(LendingError::InvalidAccountInput.into());
-    }
-
     obligation.deposited_value = deposited_value;
     obligation.borrowed_value = borrowed_value;
     obligation.unweighted_borrowed_value = unweighted_borrowed_value;
     obligation.borrowed_value_upper_bound = borrowed_value_upper_bound;
     obligation.borrowing_isolated_asset = borrowing_isolated_asset;
 
-    let global_unhealthy_borrow_value = Decimal::from(70000000u64);
-    let global_allowed_borrow_value = Decimal::from(65000000u64);
+    let (global_allowed_borrow_value, global_unhealthy_borrow_value) = match lending_market {
+        Some(lending_market) => (
+            lending_market.global_allowed_borrow_value,
+            lending_market.global_unhealthy_borrow_value,
+        ),
+        None => (
+            DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE,
+            DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE,
+        ),
+    };
+    let global_allowed_borrow_value = Decimal::from(global_allowed_borrow_value);
+    let global_unhealthy_borrow_value = Decimal::from(global_unhealthy_borrow_value);
+    obligation.refreshed_without_lending_market = lending_market.is_none();
 
     obligation.allowed_borrow_value = min(allowed_borrow_value, global_allowed_borrow_value);
     obligation.unhealthy_borrow_value = min(unhealthy_borrow_value, global_unhealthy_borrow_value);
@@ -1122,7 +1577,7 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
 
     obligation.last_update.update_slot(clock.slot);
 
-    let (_, close_exceeded) = update_borrow_attribution_values(&mut obligation, &accounts[1..])?;
+    let (_, close_exceeded) = update_borrow_attribution_values(&mut obligation, reserve_infos)?;
     if close_exceeded.is_none() {
         obligation.closeable = false;
     }
@@ -1145,6 +1600,25 @@ fn process_refresh_obligation(program_id: &Pubkey, accounts: &[AccountInfo]) ->
     Ok(())
 }
 
+/// RefreshObligation caps the allowed and unhealthy borrow values at the default global borrow
+/// values when it isn't given the lending market. On a market with other global borrow values, an
+/// obligation refreshed that way can be over a lower cap or look unhealthy under a higher one.
+fn check_global_borrow_values(
+    obligation: &Obligation,
+    lending_market: &LendingMarket,
+) -> ProgramResult {
+    if obligation.refreshed_without_lending_market
+        && (lending_market.global_allowed_borrow_value != DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE
+            || lending_market.global_unhealthy_borrow_value
+                != DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE)
+    {
+        msg!("Obligation must be refreshed with its lending market account");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    Ok(())
+}
+
 /// This function updates the borrow attribution value on the ObligationCollateral and
 /// the reserve.
 ///
@@ -1267,6 +1741,7 @@ fn _deposit_obligation_collateral<'a>(
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_market_not_paused(&lending_market)?;
     if &lending_market.token_program_id != token_program_id.key {
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
@@ -1455,6 +1930,7 @@ fn _withdraw_obligation_collateral<'a>(
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_market_not_paused(&lending_market)?;
     if &lending_market.token_program_id != token_program_id.key {
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
@@ -1503,6 +1979,7 @@ fn _withdraw_obligation_collateral<'a>(
         msg!("Obligation is stale and must be refreshed in the current slot");
         return Err(LendingError::ObligationStale.into());
     }
+    check_global_borrow_values(&obligation, &lending_market)?;
 
     let (collateral, collateral_index) =
         obligation.find_collateral_in_deposits(*withdraw_reserve_info.key)?;
@@ -1524,17 +2001,20 @@ fn _withdraw_obligation_collateral<'a>(
         return Err(LendingError::InvalidMarketAuthority.into());
     }
 
+    // collateral withdrawn without being redeemed counts against the obligation's outflow limit
+    // too, otherwise it could be redeemed right after without the limit applying
+    let max_obligation_outflow_usd = obligation.remaining_outflow(&lending_market, clock.slot)?;
+
     // account for lending market and reserve rate limiter when withdrawing. this is needed to
     // support max withdraws.
     let max_outflow_collateral_amount = if account_for_rate_limiter {
         let max_outflow_usd = lending_market
             .rate_limiter
-            .clone() // remaining_outflow is a mutable call, but we don't have mutable access here
-            .remaining_outflow(clock.slot)?;
+            .projected_remaining_outflow(clock.slot)?;
 
         let max_lending_market_outflow_liquidity_amount = withdraw_reserve
             .usd_to_liquidity_amount_lower_bound(min(
-                max_outflow_usd,
+                min(max_outflow_usd, max_obligation_outflow_usd),
                 // min here bc this function can overflow if max_outflow_usd is u64::MAX
                 // the actual value doesn't matter too much as long as its sensible
                 obligation.deposited_value.try_mul(2)?,
@@ -1542,8 +2022,7 @@ fn _withdraw_obligation_collateral<'a>(
 
         let max_reserve_outflow_liquidity_amount = withdraw_reserve
             .rate_limiter
-            .clone()
-            .remaining_outflow(clock.slot)?;
+            .projected_remaining_outflow(clock.slot)?;
 
         let max_outflow_liquidity_amount = min(
             max_lending_market_outflow_liquidity_amount,
@@ -1554,6 +2033,14 @@ fn _withdraw_obligation_collateral<'a>(
             .collateral_exchange_rate()?
             .decimal_liquidity_to_collateral(max_outflow_liquidity_amount)?
             .try_floor_u64()?
+    } else if lending_market.has_obligation_outflow_limit() {
+        let max_obligation_outflow_liquidity_amount =
+            withdraw_reserve.usd_to_liquidity_amount_lower_bound(max_obligation_outflow_usd)?;
+
+        withdraw_reserve
+            .collateral_exchange_rate()?
+            .decimal_liquidity_to_collateral(max_obligation_outflow_liquidity_amount)?
+            .try_floor_u64()?
     } else {
         u64::MAX
     };
@@ -1569,11 +2056,18 @@ fn _withdraw_obligation_collateral<'a>(
         return Err(LendingError::WithdrawTooLarge.into());
     }
 
-    let withdraw_value = withdraw_reserve.market_value(
-        withdraw_reserve
-            .collateral_exchange_rate()?
-            .decimal_collateral_to_liquidity(Decimal::from(withdraw_amount))?,
-    )?;
+    let withdraw_liquidity_amount = withdraw_reserve
+        .collateral_exchange_rate()?
+        .decimal_collateral_to_liquidity(Decimal::from(withdraw_amount))?;
+    let withdraw_value = withdraw_reserve.market_value(withdraw_liquidity_amount)?;
+
+    obligation
+        .update_outflow(
+            &lending_market,
+            clock.slot,
+            withdraw_reserve.market_value_upper_bound(withdraw_liquidity_amount)?,
+        )
+        .inspect_err(|_| msg!("Obligation outflow limit exceeded! Please try again later."))?;
 
     // update relevant values before updating borrow attribution values
     obligation.deposited_value = obligation.deposited_value.saturating_sub(withdraw_value);
@@ -1634,11 +2128,56 @@ fn process_borrow_obligation_liquidity(
     let clock = &Clock::get()?;
     let token_program_id = next_account_info(account_info_iter)?;
 
+    _borrow_obligation_liquidity(
+        program_id,
+        liquidity_amount,
+        None,
+        source_liquidity_info,
+        destination_liquidity_info,
+        borrow_reserve_info,
+        borrow_reserve_liquidity_fee_receiver_info,
+        obligation_info,
+        lending_market_info,
+        lending_market_authority_info,
+        obligation_owner_info,
+        clock,
+        token_program_id,
+        &accounts[9..],
+    )?;
+
+    Ok(())
+}
+
+/// Borrows from a reserve against an obligation and returns the amount of liquidity received.
+/// `remaining_accounts` holds the obligation's deposit reserves followed by an optional host fee
+/// receiver.
+///
+/// When `max_borrow_value` is set, the borrow is also capped at that value and a borrow that rounds
+/// down to nothing is skipped instead of failing.
+#[allow(clippy::too_many_arguments)]
+#[inline(never)] // avoid stack frame limit
+fn _borrow_obligation_liquidity<'a>(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    max_borrow_value: Option<Decimal>,
+    source_liquidity_info: &AccountInfo<'a>,
+    destination_liquidity_info: &AccountInfo<'a>,
+    borrow_reserve_info: &AccountInfo<'a>,
+    borrow_reserve_liquidity_fee_receiver_info: &AccountInfo<'a>,
+    obligation_info: &AccountInfo<'a>,
+    lending_market_info: &AccountInfo<'a>,
+    lending_market_authority_info: &AccountInfo<'a>,
+    obligation_owner_info: &AccountInfo<'a>,
+    clock: &Clock,
+    token_program_id: &AccountInfo<'a>,
+    remaining_accounts: &[AccountInfo<'a>],
+) -> Result<u64, ProgramError> {
     let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_market_not_paused(&lending_market)?;
     if &lending_market.token_program_id != token_program_id.key {
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
@@ -1671,6 +2210,10 @@ fn process_borrow_obligation_liquidity(
         msg!("Borrow reserve is stale and must be refreshed in the current slot");
         return Err(LendingError::ReserveStale.into());
     }
+    if borrow_reserve.config.peg_mode == PegMode::HaltBorrows && borrow_reserve.liquidity.depegged {
+        msg!("Borrows are halted while the borrow reserve is depegged");
+        return Err(LendingError::ReserveDepegged.into());
+    }
     if liquidity_amount != u64::MAX
         && Decimal::from(liquidity_amount)
             .try_add(borrow_reserve.liquidity.borrowed_amount_wads)?
@@ -1702,6 +2245,7 @@ fn process_borrow_obligation_liquidity(
         msg!("Obligation is stale and must be refreshed in the current slot");
         return Err(LendingError::ObligationStale.into());
     }
+    check_global_borrow_values(&obligation, &lending_market)?;
     if obligation.deposits.is_empty() {
         msg!("Obligation has no deposits to borrow against");
         return Err(LendingError::ObligationDepositsEmpty.into());
@@ -1751,9 +2295,12 @@ fn process_borrow_obligation_liquidity(
         }
     };
 
-    let remaining_borrow_value = obligation
+    let mut remaining_borrow_value = obligation
         .remaining_borrow_value()
         .unwrap_or_else(|_| Decimal::zero());
+    if let Some(max_borrow_value) = max_borrow_value {
+        remaining_borrow_value = min(remaining_borrow_value, max_borrow_value);
+    }
     if remaining_borrow_value == Decimal::zero() {
         msg!("Remaining borrow value is zero");
         return Err(LendingError::BorrowTooLarge.into());
@@ -1770,7 +2317,10 @@ fn process_borrow_obligation_liquidity(
 
         min(
             borrow_reserve.usd_to_liquidity_amount_lower_bound(min(
-                max_outflow_usd,
+                min(
+                    max_outflow_usd,
+                    obligation.remaining_outflow(&lending_market, clock.slot)?,
+                ),
                 // min here bc this function can overflow if max_outflow_usd is u64::MAX
                 remaining_borrow_value,
             ))?,
@@ -1790,6 +2340,9 @@ fn process_borrow_obligation_liquidity(
     )?;
 
     if receive_amount == 0 {
+        if max_borrow_value.is_some() {
+            return Ok(0);
+        }
         msg!("Borrow amount is too small to receive liquidity after fees");
         return Err(LendingError::BorrowTooSmall.into());
     }
@@ -1816,6 +2369,14 @@ fn process_borrow_obligation_liquidity(
                 msg!("Reserve outflow limit exceeded! Please try again later");
                 err
             })?;
+
+        obligation
+            .update_outflow(
+                &lending_market,
+                clock.slot,
+                borrow_reserve.market_value_upper_bound(borrow_amount)?,
+            )
+            .inspect_err(|_| msg!("Obligation outflow limit exceeded! Please try again later"))?;
     }
 
     LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
@@ -1842,7 +2403,7 @@ fn process_borrow_obligation_liquidity(
     obligation_liquidity.borrow(borrow_amount)?;
     obligation.last_update.mark_stale();
 
-    let (open_exceeded, _) = update_borrow_attribution_values(&mut obligation, &accounts[9..])?;
+    let (open_exceeded, _) = update_borrow_attribution_values(&mut obligation, remaining_accounts)?;
     if let Some(reserve_pubkey) = open_exceeded {
         msg!(
             "Open borrow attribution limit exceeded for reserve {:?}",
@@ -1852,6 +2413,7 @@ fn process_borrow_obligation_liquidity(
     }
 
     // HACK: fast forward through the deposit reserve infos
+    let account_info_iter = &mut remaining_accounts.iter();
     for _ in 0..obligation.deposits.len() {
         next_account_info(account_info_iter)?;
     }
@@ -1895,49 +2457,296 @@ fn process_borrow_obligation_liquidity(
         token_program: token_program_id.clone(),
     })?;
 
-    Ok(())
+    Ok(receive_amount)
 }
 
 #[inline(never)] // avoid stack frame limit
-fn process_repay_obligation_liquidity(
+fn process_leverage_loop(
     program_id: &Pubkey,
     liquidity_amount: u64,
+    target_ltv: u8,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     if liquidity_amount == 0 {
         msg!("Liquidity amount provided cannot be zero");
         return Err(LendingError::InvalidAmount.into());
     }
+    if target_ltv >= 100 {
+        msg!("Target loan to value must be in range [0, 100)");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
     let account_info_iter = &mut accounts.iter();
     let source_liquidity_info = next_account_info(account_info_iter)?;
-    let destination_liquidity_info = next_account_info(account_info_iter)?;
-    let repay_reserve_info = next_account_info(account_info_iter)?;
+    let user_collateral_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+    let deposit_reserve_collateral_supply_info = next_account_info(account_info_iter)?;
+    let borrow_reserve_info = next_account_info(account_info_iter)?;
+    let borrow_reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let borrow_reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
     let obligation_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let obligation_owner_info = next_account_info(account_info_iter)?;
     let user_transfer_authority_info = next_account_info(account_info_iter)?;
-    let clock = &Clock::get()?;
     let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
 
-    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
-    if lending_market_info.owner != program_id {
-        msg!("Lending market provided is not owned by the lending program");
+    let deposit_reserve = Box::new(Reserve::unpack(&deposit_reserve_info.data.borrow())?);
+    if deposit_reserve_info.owner != program_id {
+        msg!("Deposit reserve provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
-    if &lending_market.token_program_id != token_program_id.key {
-        msg!("Lending market token program does not match the token program provided");
-        return Err(LendingError::InvalidTokenProgram.into());
+    if deposit_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Deposit reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
     }
-
-    _refresh_reserve_interest(program_id, repay_reserve_info, clock)?;
-    let mut repay_reserve = Box::new(Reserve::unpack(&repay_reserve_info.data.borrow())?);
-    if repay_reserve_info.owner != program_id {
-        msg!("Repay reserve provided is not owned by the lending program");
+    let borrow_reserve = Box::new(Reserve::unpack(&borrow_reserve_info.data.borrow())?);
+    if borrow_reserve_info.owner != program_id {
+        msg!("Borrow reserve provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
-    if &repay_reserve.lending_market != lending_market_info.key {
-        msg!("Repay reserve lending market does not match the lending market provided");
-        return Err(LendingError::InvalidAccountInput.into());
-    }
+    if borrow_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Borrow reserve is stale and must be refreshed in the current slot");
+        return Err(LendingError::ReserveStale.into());
+    }
+    if deposit_reserve_info.key == borrow_reserve_info.key {
+        msg!("Deposit reserve cannot be used as the borrow reserve");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if deposit_reserve.liquidity.mint_pubkey != borrow_reserve.liquidity.mint_pubkey {
+        msg!("Borrow reserve liquidity mint does not match the deposit reserve liquidity mint");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &obligation.lending_market != lending_market_info.key {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // the deposit reserves include the deposit reserve once it's added, the other borrow reserves
+    // are every borrow reserve but the borrow reserve
+    let mut deposit_reserve_count = obligation.deposits.len();
+    if !obligation
+        .deposits
+        .iter()
+        .any(|collateral| collateral.deposit_reserve == *deposit_reserve_info.key)
+    {
+        deposit_reserve_count += 1;
+    }
+    let other_borrow_reserve_count = obligation
+        .borrows
+        .iter()
+        .filter(|liquidity| liquidity.borrow_reserve != *borrow_reserve_info.key)
+        .count();
+    let remaining_accounts = &accounts[15..];
+    if remaining_accounts.len() < deposit_reserve_count + other_borrow_reserve_count {
+        msg!("Not enough obligation deposit or borrow reserves provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    let (deposit_reserve_infos, remaining_accounts) =
+        remaining_accounts.split_at(deposit_reserve_count);
+    let (other_borrow_reserve_infos, host_fee_receiver_infos) =
+        remaining_accounts.split_at(other_borrow_reserve_count);
+    let borrow_remaining_accounts = [deposit_reserve_infos, host_fee_receiver_infos].concat();
+
+    // every unit of weighted borrow value is deposited back as deposit_value_ratio units of
+    // deposited value, which raises the target borrow value by target_ltv of that.
+    let target_ltv_rate = Rate::from_percent(target_ltv);
+    let deposit_value_ratio = deposit_reserve.liquidity.market_price.try_div(
+        borrow_reserve
+            .liquidity
+            .market_price
+            .try_mul(borrow_reserve.borrow_weight())?,
+    )?;
+    let target_growth = deposit_value_ratio.try_mul(target_ltv_rate)?;
+    if target_growth >= Decimal::one() {
+        msg!("Target loan to value cannot be reached by looping these reserves");
+        return Err(LendingError::InvalidAmount.into());
+    }
+    let target_borrow_scale = Decimal::one().try_sub(target_growth)?;
+
+    let mut deposit_amount = liquidity_amount;
+    for iteration in 0..=MAX_LEVERAGE_LOOP_ITERATIONS {
+        _refresh_reserve_interest(program_id, deposit_reserve_info, clock)?;
+        let collateral_amount = _deposit_reserve_liquidity(
+            program_id,
+            deposit_amount,
+            source_liquidity_info,
+            user_collateral_info,
+            deposit_reserve_info,
+            deposit_reserve_liquidity_supply_info,
+            deposit_reserve_collateral_mint_info,
+            lending_market_info,
+            lending_market_authority_info,
+            user_transfer_authority_info,
+            clock,
+            token_program_id,
+        )?;
+        _refresh_reserve_interest(program_id, deposit_reserve_info, clock)?;
+        _deposit_obligation_collateral(
+            program_id,
+            collateral_amount,
+            user_collateral_info,
+            deposit_reserve_collateral_supply_info,
+            deposit_reserve_info,
+            obligation_info,
+            lending_market_info,
+            obligation_owner_info,
+            user_transfer_authority_info,
+            clock,
+            token_program_id,
+        )?;
+        // a full refresh, so every borrow sees the obligation RefreshObligation would produce
+        _refresh_reserve_interest(program_id, borrow_reserve_info, clock)?;
+        let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+        let reserve_infos = leverage_loop_reserve_infos(
+            &obligation,
+            deposit_reserve_infos,
+            borrow_reserve_info,
+            other_borrow_reserve_infos,
+        )?;
+        _refresh_obligation(
+            program_id,
+            obligation,
+            obligation_info,
+            &reserve_infos,
+            Some(&lending_market),
+            clock,
+        )?;
+
+        if iteration == MAX_LEVERAGE_LOOP_ITERATIONS {
+            break;
+        }
+
+        let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+        let target_borrowed_value = obligation.deposited_value.try_mul(target_ltv_rate)?;
+        if target_borrowed_value <= obligation.borrowed_value {
+            break;
+        }
+        let max_borrow_value = target_borrowed_value
+            .try_sub(obligation.borrowed_value)?
+            .try_div(target_borrow_scale)?;
+
+        _refresh_reserve_interest(program_id, borrow_reserve_info, clock)?;
+        deposit_amount = _borrow_obligation_liquidity(
+            program_id,
+            u64::MAX,
+            Some(max_borrow_value),
+            borrow_reserve_liquidity_supply_info,
+            source_liquidity_info,
+            borrow_reserve_info,
+            borrow_reserve_liquidity_fee_receiver_info,
+            obligation_info,
+            lending_market_info,
+            lending_market_authority_info,
+            obligation_owner_info,
+            clock,
+            token_program_id,
+            &borrow_remaining_accounts,
+        )?;
+        if deposit_amount == 0 {
+            break;
+        }
+    }
+
+    // mark everything as stale to make sure no weird bugs happen
+    for reserve_info in [deposit_reserve_info, borrow_reserve_info].iter() {
+        let mut reserve = Box::new(Reserve::unpack(&reserve_info.data.borrow())?);
+        reserve.last_update.mark_stale();
+        Reserve::pack(*reserve, &mut reserve_info.data.borrow_mut())?;
+    }
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    obligation.last_update.mark_stale();
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// The reserves RefreshObligation expects for `obligation` in a leverage loop: the deposit
+/// reserves followed by the borrow reserves in the order of the obligation's borrows, which
+/// changes as the loop borrows.
+fn leverage_loop_reserve_infos<'a>(
+    obligation: &Obligation,
+    deposit_reserve_infos: &[AccountInfo<'a>],
+    borrow_reserve_info: &AccountInfo<'a>,
+    other_borrow_reserve_infos: &[AccountInfo<'a>],
+) -> Result<Vec<AccountInfo<'a>>, ProgramError> {
+    let mut reserve_infos = deposit_reserve_infos.to_vec();
+    for liquidity in obligation.borrows.iter() {
+        let borrow_reserve_info = std::iter::once(borrow_reserve_info)
+            .chain(other_borrow_reserve_infos.iter())
+            .find(|reserve_info| reserve_info.key == &liquidity.borrow_reserve)
+            .ok_or_else(|| {
+                msg!(
+                    "Borrow reserve {} of the obligation was not provided",
+                    liquidity.borrow_reserve
+                );
+                LendingError::InvalidAccountInput
+            })?;
+        reserve_infos.push(borrow_reserve_info.clone());
+    }
+
+    Ok(reserve_infos)
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_repay_obligation_liquidity(
+    program_id: &Pubkey,
+    liquidity_amount: u64,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+    let account_info_iter = &mut accounts.iter();
+    let source_liquidity_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let repay_reserve_info = next_account_info(account_info_iter)?;
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+    let token_program_id = next_account_info(account_info_iter)?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.token_program_id != token_program_id.key {
+        msg!("Lending market token program does not match the token program provided");
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+
+    _refresh_reserve_interest(program_id, repay_reserve_info, clock)?;
+    let mut repay_reserve = Box::new(Reserve::unpack(&repay_reserve_info.data.borrow())?);
+    if repay_reserve_info.owner != program_id {
+        msg!("Repay reserve provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &repay_reserve.lending_market != lending_market_info.key {
+        msg!("Repay reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
     if &repay_reserve.liquidity.supply_pubkey == source_liquidity_info.key {
         msg!("Repay reserve liquidity supply cannot be used as the source liquidity provided");
         return Err(LendingError::InvalidAccountInput.into());
@@ -1982,6 +2791,13 @@ fn process_repay_obligation_liquidity(
     }
 
     repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+    credit_rate_limiters(
+        &mut lending_market,
+        lending_market_info,
+        &mut repay_reserve,
+        clock.slot,
+        Decimal::from(repay_amount),
+    )?;
     repay_reserve.last_update.mark_stale();
     Reserve::pack(*repay_reserve, &mut repay_reserve_info.data.borrow_mut())?;
 
@@ -2017,12 +2833,14 @@ fn _liquidate_obligation<'a>(
     user_transfer_authority_info: &AccountInfo<'a>,
     clock: &Clock,
     token_program_id: &AccountInfo<'a>,
+    batch: Option<&mut LiquidationBatch>,
 ) -> Result<(u64, Bonus), ProgramError> {
     let lending_market = Box::new(LendingMarket::unpack(&lending_market_info.data.borrow())?);
     if lending_market_info.owner != program_id {
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_market_not_paused(&lending_market)?;
     if &lending_market.token_program_id != token_program_id.key {
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
@@ -2095,6 +2913,7 @@ fn _liquidate_obligation<'a>(
         msg!("Obligation is stale and must be refreshed in the current slot");
         return Err(LendingError::ObligationStale.into());
     }
+    check_global_borrow_values(&obligation, &lending_market)?;
     if obligation.deposited_value == Decimal::zero() {
         msg!("Obligation deposited value is zero");
         return Err(LendingError::ObligationDepositsZero.into());
@@ -2104,7 +2923,11 @@ fn _liquidate_obligation<'a>(
         return Err(LendingError::ObligationBorrowsZero.into());
     }
 
-    if obligation.borrowed_value < obligation.unhealthy_borrow_value && !obligation.closeable {
+    // a batch liquidation checks the obligation's health once, before its first leg
+    let health = batch
+        .as_deref()
+        .map_or(&obligation, |batch| &batch.obligation);
+    if health.borrowed_value < health.unhealthy_borrow_value && !health.closeable {
         msg!("Obligation must be unhealthy or marked as closeable to be liquidated");
         return Err(LendingError::ObligationHealthy.into());
     }
@@ -2122,7 +2945,9 @@ fn _liquidate_obligation<'a>(
         msg!("Obligation borrow value is zero");
         return Err(LendingError::ObligationLiquidityEmpty.into());
     }
-    if liquidity_index != 0 {
+    // the legs of a batch share one health snapshot and liquidation value cap, so any borrow
+    // can be repaid
+    if batch.is_none() && liquidity_index != 0 {
         msg!("Obligation borrow is not the first liquidity in the borrows list");
         return Err(LendingError::InvalidAccountInput.into());
     }
@@ -2147,7 +2972,26 @@ fn _liquidate_obligation<'a>(
         return Err(LendingError::InvalidMarketAuthority.into());
     }
 
-    let bonus = withdraw_reserve.calculate_bonus(&obligation)?;
+    let bonus = withdraw_reserve.calculate_bonus(health)?;
+    let liquidity_amount = match batch.as_deref() {
+        Some(batch) => {
+            let max_batch_liquidation_amount = liquidity
+                .borrowed_amount_wads
+                .try_mul(
+                    batch
+                        .remaining_liquidation_value
+                        .try_div(liquidity.market_value)?
+                        .min(Decimal::one()),
+                )?
+                .try_floor_u64()?;
+            if max_batch_liquidation_amount == 0 {
+                msg!("Batch liquidation close factor is used up, skipping leg");
+                return Ok((0, bonus));
+            }
+            min(liquidity_amount, max_batch_liquidation_amount)
+        }
+        None => liquidity_amount,
+    };
     let CalculateLiquidationResult {
         settle_amount,
         repay_amount,
@@ -2169,6 +3013,13 @@ fn _liquidate_obligation<'a>(
         return Err(LendingError::LiquidationTooSmall.into());
     }
 
+    // liquidations aren't counted against the obligation's outflow limit, just like they aren't
+    // against the lending market and reserve rate limiters. an unhealthy obligation can always be
+    // liquidated, and its owner isn't throttled by outflows they didn't make.
+
+    let repay_borrow_weight = repay_reserve.borrow_weight();
+    let settle_value_upper_bound = repay_reserve.market_value_upper_bound(settle_amount)?;
+
     repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
     repay_reserve.last_update.mark_stale();
     Reserve::pack(*repay_reserve, &mut repay_reserve_info.data.borrow_mut())?;
@@ -2188,9 +3039,21 @@ fn _liquidate_obligation<'a>(
         )?;
     }
 
+    // a batch liquidation keeps the obligation fresh between legs and marks it stale at the end
+    match batch {
+        Some(batch) => batch.record_leg(
+            &mut obligation,
+            liquidity_index,
+            settle_amount,
+            repay_borrow_weight,
+            settle_value_upper_bound,
+            collateral_index,
+            withdraw_amount,
+        )?,
+        None => obligation.last_update.mark_stale(),
+    }
     obligation.repay(settle_amount, liquidity_index)?;
     obligation.withdraw(withdraw_amount, collateral_index)?;
-    obligation.last_update.mark_stale();
     Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
 
     spl_token_transfer(TokenTransferParams {
@@ -2258,8 +3121,46 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
         user_transfer_authority_info,
         clock,
         token_program_id,
+        None,
     )?;
 
+    _redeem_liquidated_collateral(
+        program_id,
+        withdrawn_collateral_amount,
+        &bonus,
+        destination_collateral_info,
+        destination_liquidity_info,
+        withdraw_reserve_info,
+        withdraw_reserve_collateral_mint_info,
+        withdraw_reserve_liquidity_supply_info,
+        withdraw_reserve_liquidity_fee_receiver_info,
+        lending_market_info,
+        lending_market_authority_info,
+        user_transfer_authority_info,
+        clock,
+        token_program_id,
+    )
+}
+
+/// Redeems the collateral seized by a liquidation, as far as the withdraw reserve has liquidity
+/// available, and pays the protocol's share of the bonus to the fee receiver
+#[allow(clippy::too_many_arguments)]
+fn _redeem_liquidated_collateral<'a>(
+    program_id: &Pubkey,
+    withdrawn_collateral_amount: u64,
+    bonus: &Bonus,
+    destination_collateral_info: &AccountInfo<'a>,
+    destination_liquidity_info: &AccountInfo<'a>,
+    withdraw_reserve_info: &AccountInfo<'a>,
+    withdraw_reserve_collateral_mint_info: &AccountInfo<'a>,
+    withdraw_reserve_liquidity_supply_info: &AccountInfo<'a>,
+    withdraw_reserve_liquidity_fee_receiver_info: &AccountInfo<'a>,
+    lending_market_info: &AccountInfo<'a>,
+    lending_market_authority_info: &AccountInfo<'a>,
+    user_transfer_authority_info: &AccountInfo<'a>,
+    clock: &Clock,
+    token_program_id: &AccountInfo<'a>,
+) -> ProgramResult {
     _refresh_reserve_interest(program_id, withdraw_reserve_info, clock)?;
     let withdraw_reserve = Box::new(Reserve::unpack(&withdraw_reserve_info.data.borrow())?);
     let collateral_exchange_rate = withdraw_reserve.collateral_exchange_rate()?;
@@ -2290,7 +3191,7 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
             return Err(LendingError::InvalidAccountInput.into());
         }
         let protocol_fee = withdraw_reserve
-            .calculate_protocol_liquidation_fee(withdraw_liquidity_amount, &bonus)?;
+            .calculate_protocol_liquidation_fee(withdraw_liquidity_amount, bonus)?;
 
         spl_token_transfer(TokenTransferParams {
             source: destination_liquidity_info.clone(),
@@ -2305,6 +3206,173 @@ fn process_liquidate_obligation_and_redeem_reserve_collateral(
     Ok(())
 }
 
+/// State carried between the legs of a batch liquidation
+struct LiquidationBatch {
+    /// Obligation as refreshed before the first leg, whose health decides eligibility and bonus
+    obligation: Obligation,
+    /// Borrowed value the remaining legs can still repay under the close factor
+    remaining_liquidation_value: Decimal,
+    /// Reserves used by earlier legs
+    reserves: Vec<Pubkey>,
+}
+
+impl LiquidationBatch {
+    /// Takes the value repaid and withdrawn by a leg off the obligation's cached market values and
+    /// the remaining close factor, so later legs price the obligation without a refresh. Must be
+    /// called before the repay and withdraw are applied to the obligation.
+    #[allow(clippy::too_many_arguments)]
+    fn record_leg(
+        &mut self,
+        obligation: &mut Obligation,
+        liquidity_index: usize,
+        settle_amount: Decimal,
+        borrow_weight: Decimal,
+        settle_value_upper_bound: Decimal,
+        collateral_index: usize,
+        withdraw_amount: u64,
+    ) -> ProgramResult {
+        let liquidity = &mut obligation.borrows[liquidity_index];
+        let repaid_value = liquidity
+            .market_value
+            .try_mul(settle_amount)?
+            .try_div(liquidity.borrowed_amount_wads)?;
+        liquidity.market_value = liquidity.market_value.saturating_sub(repaid_value);
+
+        let collateral = &mut obligation.deposits[collateral_index];
+        let withdrawn_value = collateral
+            .market_value
+            .try_mul(Decimal::from(withdraw_amount))?
+            .try_div(Decimal::from(collateral.deposited_amount))?;
+        collateral.market_value = collateral.market_value.saturating_sub(withdrawn_value);
+
+        obligation.deposited_value = obligation.deposited_value.saturating_sub(withdrawn_value);
+        obligation.unweighted_borrowed_value = obligation
+            .unweighted_borrowed_value
+            .saturating_sub(repaid_value);
+        obligation.borrowed_value = obligation
+            .borrowed_value
+            .saturating_sub(repaid_value.try_mul(borrow_weight)?);
+        obligation.borrowed_value_upper_bound = obligation
+            .borrowed_value_upper_bound
+            .saturating_sub(settle_value_upper_bound.try_mul(borrow_weight)?);
+
+        self.remaining_liquidation_value = self
+            .remaining_liquidation_value
+            .saturating_sub(repaid_value);
+        Ok(())
+    }
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_liquidate_obligation_batch(
+    program_id: &Pubkey,
+    liquidity_amounts: &[u64],
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if liquidity_amounts.is_empty() || liquidity_amounts.len() > MAX_LIQUIDATION_BATCH_LEGS {
+        msg!(
+            "Batch liquidation must have between 1 and {} legs",
+            MAX_LIQUIDATION_BATCH_LEGS
+        );
+        return Err(LendingError::InvalidAmount.into());
+    }
+    if liquidity_amounts.contains(&0) {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+    let obligation_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let user_transfer_authority_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    if obligation_info.owner != program_id {
+        msg!("Obligation provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(LendingError::ObligationStale.into());
+    }
+
+    // the lending market and obligation are validated against each other by every leg
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    check_global_borrow_values(&obligation, &lending_market)?;
+
+    let mut batch = LiquidationBatch {
+        remaining_liquidation_value: obligation.max_liquidation_value()?,
+        obligation,
+        reserves: vec![],
+    };
+
+    for liquidity_amount in liquidity_amounts {
+        let source_liquidity_info = next_account_info(account_info_iter)?;
+        let destination_collateral_info = next_account_info(account_info_iter)?;
+        let destination_liquidity_info = next_account_info(account_info_iter)?;
+        let repay_reserve_info = next_account_info(account_info_iter)?;
+        let repay_reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+        let withdraw_reserve_info = next_account_info(account_info_iter)?;
+        let withdraw_reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+        let withdraw_reserve_collateral_supply_info = next_account_info(account_info_iter)?;
+        let withdraw_reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+        let withdraw_reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
+
+        // reserves used by an earlier leg were fresh when it ran but have been marked stale since
+        for reserve_info in [repay_reserve_info, withdraw_reserve_info].iter() {
+            if batch.reserves.contains(reserve_info.key) {
+                _refresh_reserve_interest(program_id, reserve_info, clock)?;
+            }
+        }
+
+        let (withdrawn_collateral_amount, bonus) = _liquidate_obligation(
+            program_id,
+            *liquidity_amount,
+            source_liquidity_info,
+            destination_collateral_info,
+            repay_reserve_info,
+            repay_reserve_liquidity_supply_info,
+            withdraw_reserve_info,
+            withdraw_reserve_collateral_supply_info,
+            obligation_info,
+            lending_market_info,
+            lending_market_authority_info,
+            user_transfer_authority_info,
+            clock,
+            token_program_id,
+            Some(&mut batch),
+        )?;
+        batch.reserves.push(*repay_reserve_info.key);
+        batch.reserves.push(*withdraw_reserve_info.key);
+
+        _redeem_liquidated_collateral(
+            program_id,
+            withdrawn_collateral_amount,
+            &bonus,
+            destination_collateral_info,
+            destination_liquidity_info,
+            withdraw_reserve_info,
+            withdraw_reserve_collateral_mint_info,
+            withdraw_reserve_liquidity_supply_info,
+            withdraw_reserve_liquidity_fee_receiver_info,
+            lending_market_info,
+            lending_market_authority_info,
+            user_transfer_authority_info,
+            clock,
+            token_program_id,
+        )?;
+    }
+
+    let mut obligation = Obligation::unpack(&obligation_info.data.borrow())?;
+    obligation.last_update.mark_stale();
+    Obligation::pack(obligation, &mut obligation_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
 #[inline(never)] // avoid stack frame limit
 fn process_withdraw_obligation_collateral_and_redeem_reserve_liquidity(
     program_id: &Pubkey,
@@ -2360,14 +3428,25 @@ fn process_withdraw_obligation_collateral_and_redeem_reserve_liquidity(
     Ok(())
 }
 
+/// `queued` is true when executing a change queued with `QueueConfigChange`, in which case the
+/// owner signed the queueing instead.
 #[inline(never)] // avoid stack frame limit
 fn process_update_reserve_config(
     program_id: &Pubkey,
     config: ReserveConfig,
     rate_limiter_config: RateLimiterConfig,
+    liquidation_threshold_ramp_slots: u64,
     accounts: &[AccountInfo],
+    queued: bool,
 ) -> ProgramResult {
     validate_reserve_config(config)?;
+    if liquidation_threshold_ramp_slots > MAX_LIQUIDATION_THRESHOLD_RAMP_SLOTS {
+        msg!(
+            "Liquidation threshold ramp can't be longer than {} slots",
+            MAX_LIQUIDATION_THRESHOLD_RAMP_SLOTS
+        );
+        return Err(LendingError::InvalidConfig.into());
+    }
     let account_info_iter = &mut accounts.iter();
     let reserve_info = next_account_info(account_info_iter)?;
     let lending_market_info = next_account_info(account_info_iter)?;
@@ -2376,8 +3455,20 @@ fn process_update_reserve_config(
     let _pyth_product_info = next_account_info(account_info_iter)?;
     let pyth_price_info = next_account_info(account_info_iter)?;
     let switchboard_feed_info = next_account_info(account_info_iter)?;
+    let extra_oracle_info = match config.extra_oracle_pubkey {
+        Some(_) => Some(next_account_info(account_info_iter)?),
+        None => None,
+    };
+    let underlying_oracle_info = match config.underlying_oracle_pubkey {
+        Some(_) => Some(next_account_info(account_info_iter)?),
+        None => None,
+    };
+    let config_timelock_info = next_account_info(account_info_iter).ok();
+    let market_roles_info = next_account_info(account_info_iter).ok();
+    let protocol_config_info = next_account_info(account_info_iter).ok();
 
     let mut reserve = Box::new(Reserve::unpack(&reserve_info.data.borrow())?);
+    let previous_config = Box::new(reserve.config);
     if reserve_info.owner != program_id {
         msg!(
             "Reserve provided is not owned by the lending program {} != {}",
@@ -2401,8 +3492,24 @@ fn process_update_reserve_config(
         return Err(LendingError::InvalidAccountOwner.into());
     }
 
+    let market_roles =
+        load_optional_market_roles(program_id, lending_market_info, market_roles_info)?;
+    let protocol_config = load_optional_protocol_config(program_id, protocol_config_info)?;
+    // the solend market owner acts as the protocol authority until the protocol config is
+    // initialized, or when it's left out
+    let protocol_authority = match &protocol_config {
+        Some(protocol_config) => protocol_config.authority,
+        None => solend_market_owner::id(),
+    };
+    let signer_is_protocol_authority = &protocol_authority == signer_info.key;
+
+    // the fee admin of a market owned by the protocol authority can change its fees like the
+    // protocol authority
+    let protocol_fee_admin = protocol_authority == lending_market.owner
+        && market_roles.has_role(MarketRole::FeeAdmin, signer_info.key);
+
     // if it's a permissionless market
-    if &solend_market_owner::id() != signer_info.key {
+    if !signer_is_protocol_authority && !protocol_fee_admin {
         if reserve.config.protocol_liquidation_fee != config.protocol_liquidation_fee {
             msg!("permissionless markets can't edit protocol liquidation fees");
             return Err(LendingError::InvalidConfig.into());
@@ -2421,6 +3528,18 @@ fn process_update_reserve_config(
         }
     }
 
+    if reserve.config.protocol_liquidation_fee != config.protocol_liquidation_fee
+        || reserve.config.protocol_take_rate != config.protocol_take_rate
+    {
+        if protocol_config_info.is_none() {
+            msg!("Protocol config account must be provided to change protocol fees");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        if let Some(protocol_config) = &protocol_config {
+            protocol_config.validate_protocol_fees(&config)?;
+        }
+    }
+
     let authority_signer_seeds = &[
         lending_market_info.key.as_ref(),
         &[lending_market.bump_seed],
@@ -2435,24 +3554,67 @@ fn process_update_reserve_config(
         return Err(LendingError::InvalidMarketAuthority.into());
     }
 
-    if !signer_info.is_signer {
+    if !queued && !signer_info.is_signer {
         msg!("Lending market owner or risk authority provided must be a signer");
         return Err(LendingError::InvalidSigner.into());
     }
 
-    if signer_info.key == &lending_market.owner {
-        // if window duration or max outflow are different, then create a new rate limiter instance.
-        if rate_limiter_config != reserve.rate_limiter.config {
-            reserve.rate_limiter = RateLimiter::new(rate_limiter_config, Clock::get()?.slot);
+    if queued && signer_info.key != &lending_market.owner {
+        msg!("Queued config change was queued by a previous lending market owner");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    let timelocked = !queued
+        && optional_config_timelock_delay(
+            program_id,
+            &lending_market,
+            lending_market_info,
+            config_timelock_info,
+        )? > 0;
+
+    let signer_is_owner = signer_info.key == &lending_market.owner && !timelocked;
+    let signer_has_roles = signer_info.key != &lending_market.owner
+        && signer_info.key != &lending_market.risk_authority
+        && !signer_is_protocol_authority
+        && market_roles.has_any_role(signer_info.key);
+    if signer_has_roles {
+        require_reserve_config_roles(
+            &market_roles,
+            signer_info.key,
+            &reserve,
+            &config,
+            &rate_limiter_config,
+            pyth_price_info.key,
+            switchboard_feed_info.key,
+        )?;
+        // while timelocked, only the changes the risk authority could make are instant
+        if timelocked {
+            require_safer_reserve_config(
+                &reserve,
+                &config,
+                &rate_limiter_config,
+                pyth_price_info.key,
+                switchboard_feed_info.key,
+                LendingError::ConfigChangeTimelocked,
+            )?;
+        }
+    }
+
+    if signer_is_owner || signer_has_roles {
+        let oracle_program_ids = market_oracle_program_ids(&lending_market);
+
+        // if window duration or max outflow are different, then create a new rate limiter instance.
+        if rate_limiter_config != reserve.rate_limiter.config {
+            reserve.rate_limiter = RateLimiter::new(rate_limiter_config, Clock::get()?.slot);
         }
 
         if *pyth_price_info.key != reserve.liquidity.pyth_oracle_pubkey {
-            validate_pyth_keys(pyth_price_info)?;
+            validate_pyth_keys(pyth_price_info, &oracle_program_ids)?;
             reserve.liquidity.pyth_oracle_pubkey = *pyth_price_info.key;
         }
 
         if *switchboard_feed_info.key != reserve.liquidity.switchboard_oracle_pubkey {
-            validate_switchboard_keys(switchboard_feed_info)?;
+            validate_switchboard_keys(switchboard_feed_info, &oracle_program_ids)?;
             reserve.liquidity.switchboard_oracle_pubkey = *switchboard_feed_info.key;
         }
         if reserve.liquidity.switchboard_oracle_pubkey == solend_program::NULL_PUBKEY
@@ -2462,29 +3624,49 @@ fn process_update_reserve_config(
             return Err(LendingError::InvalidOracleConfig.into());
         }
 
-        if let Some(extra_oracle_pubkey) = config.extra_oracle_pubkey {
-            let extra_oracle_info = next_account_info(account_info_iter)?;
-            validate_extra_oracle(extra_oracle_pubkey, extra_oracle_info)?;
+        if let (Some(extra_oracle_pubkey), Some(extra_oracle_info)) =
+            (config.extra_oracle_pubkey, extra_oracle_info)
+        {
+            validate_extra_oracle(extra_oracle_pubkey, extra_oracle_info, &oracle_program_ids)?;
         }
 
-        reserve.config = config;
+        validate_underlying_oracle(
+            config.underlying_oracle_pubkey,
+            pyth_price_info,
+            underlying_oracle_info,
+            &oracle_program_ids,
+        )?;
+
+        reserve.set_config_with_liquidation_threshold_ramp(
+            config,
+            liquidation_threshold_ramp_slots,
+            Clock::get()?.slot,
+        );
     } else if signer_info.key == &lending_market.risk_authority {
+        require_safer_reserve_config(
+            &reserve,
+            &config,
+            &rate_limiter_config,
+            pyth_price_info.key,
+            switchboard_feed_info.key,
+            LendingError::RiskAuthorityChangeNotSafer,
+        )?;
+
         // only can disable outflows
         if rate_limiter_config.window_duration > 0 && rate_limiter_config.max_outflow == 0 {
             reserve.rate_limiter = RateLimiter::new(rate_limiter_config, Clock::get()?.slot);
         }
 
-        // only certain reserve config fields can be changed by the risk authority, and only in the
-        // safer direction for now
-        if config.borrow_limit < reserve.config.borrow_limit {
-            reserve.config.borrow_limit = config.borrow_limit;
-        }
-
-        if config.deposit_limit < reserve.config.deposit_limit {
-            reserve.config.deposit_limit = config.deposit_limit;
-        }
-    } else if *signer_info.key == solend_market_owner::id()
-    // 5ph has the ability to change the
+        reserve.set_config_with_liquidation_threshold_ramp(
+            config,
+            liquidation_threshold_ramp_slots,
+            Clock::get()?.slot,
+        );
+    } else if signer_info.key == &lending_market.owner {
+        msg!("Lending market has a config timelock, owner changes must be queued");
+        return Err(LendingError::ConfigChangeTimelocked.into());
+    } else if signer_is_protocol_authority
+    // the protocol authority has the ability to change the
     // fees on permissionless markets
     {
         reserve.config.fees = config.fees;
@@ -2496,6 +3678,144 @@ fn process_update_reserve_config(
         return Err(LendingError::InvalidSigner.into());
     }
 
+    if !queued {
+        // a lending market without a config timelock account has no config delta bounds either
+        if let Some(config_timelock_info) = config_timelock_info {
+            check_config_delta_bounds(
+                program_id,
+                lending_market_info,
+                config_timelock_info,
+                &previous_config,
+                &reserve.config,
+            )?;
+        }
+    }
+
+    reserve.last_update.mark_stale();
+    Reserve::pack(*reserve, &mut reserve_info.data.borrow_mut())?;
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_update_reserve_oracles(
+    program_id: &Pubkey,
+    extra_oracle_pubkey: Option<Pubkey>,
+    accounts: &[AccountInfo],
+    queued: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let signer_info = next_account_info(account_info_iter)?;
+    let pyth_price_info = next_account_info(account_info_iter)?;
+    let switchboard_feed_info = next_account_info(account_info_iter)?;
+    let config_timelock_info = next_account_info(account_info_iter)?;
+    let market_roles_info = next_account_info(account_info_iter)?;
+    let clock = &Clock::get()?;
+
+    let mut reserve = Box::new(Reserve::unpack(&reserve_info.data.borrow())?);
+    if reserve_info.owner != program_id {
+        msg!(
+            "Reserve provided is not owned by the lending program {} != {}",
+            &reserve_info.owner.to_string(),
+            &program_id.to_string(),
+        );
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = Box::new(LendingMarket::unpack(&lending_market_info.data.borrow())?);
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if !queued && !signer_info.is_signer {
+        msg!("Lending market owner or oracle admin provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    if queued && signer_info.key != &lending_market.owner {
+        msg!("Queued config change was queued by a previous lending market owner");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    let timelocked = !queued
+        && config_timelock_delay(program_id, lending_market_info, config_timelock_info)? > 0;
+    let market_roles = load_market_roles(program_id, lending_market_info, market_roles_info)?;
+    if signer_info.key != &lending_market.owner {
+        if !market_roles.has_any_role(signer_info.key) {
+            msg!("Signer must be the lending market owner or an oracle admin");
+            return Err(LendingError::InvalidSigner.into());
+        }
+        require_market_role(
+            &market_roles,
+            signer_info.key,
+            MarketRole::OracleAdmin,
+            true,
+        )?;
+    }
+    if timelocked {
+        msg!("Lending market has a config timelock, oracle changes must be queued");
+        return Err(LendingError::ConfigChangeTimelocked.into());
+    }
+
+    if pyth_price_info.key == &solend_program::NULL_PUBKEY
+        && switchboard_feed_info.key == &solend_program::NULL_PUBKEY
+    {
+        msg!("At least one price oracle must have a non-null pubkey");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let oracle_program_ids = market_oracle_program_ids(&lending_market);
+    validate_pyth_keys(pyth_price_info, &oracle_program_ids)?;
+    validate_switchboard_keys(switchboard_feed_info, &oracle_program_ids)?;
+
+    let extra_oracle_info = match extra_oracle_pubkey {
+        Some(extra_oracle_pubkey) => {
+            let extra_oracle_info = next_account_info(account_info_iter)?;
+            validate_extra_oracle(extra_oracle_pubkey, extra_oracle_info, &oracle_program_ids)?;
+            Some(extra_oracle_info)
+        }
+        None => None,
+    };
+
+    let underlying_oracle_info = match reserve.config.underlying_oracle_pubkey {
+        Some(_) => Some(next_account_info(account_info_iter)?),
+        None => None,
+    };
+    validate_underlying_oracle(
+        reserve.config.underlying_oracle_pubkey,
+        pyth_price_info,
+        underlying_oracle_info,
+        &oracle_program_ids,
+    )?;
+
+    // every oracle has to price on its own, so the secondary isn't just a fallback that has never
+    // been read
+    if pyth_price_info.key != &solend_program::NULL_PUBKEY {
+        get_price(
+            None,
+            pyth_price_info,
+            underlying_oracle_info,
+            clock,
+            &oracle_program_ids,
+        )?;
+    }
+    if switchboard_feed_info.key != &solend_program::NULL_PUBKEY {
+        get_single_price(switchboard_feed_info, clock, &oracle_program_ids)?;
+    }
+    if let Some(extra_oracle_info) = extra_oracle_info {
+        get_single_price_unchecked(extra_oracle_info, clock, &oracle_program_ids)?;
+    }
+
+    reserve.liquidity.pyth_oracle_pubkey = *pyth_price_info.key;
+    reserve.liquidity.switchboard_oracle_pubkey = *switchboard_feed_info.key;
+    reserve.config.extra_oracle_pubkey = extra_oracle_pubkey;
+
     reserve.last_update.mark_stale();
     Reserve::pack(*reserve, &mut reserve_info.data.borrow_mut())?;
     Ok(())
@@ -2629,6 +3949,7 @@ fn _flash_borrow_reserve_liquidity<'a>(
         msg!("Lending market provided is not owned by the lending program");
         return Err(LendingError::InvalidAccountOwner.into());
     }
+    assert_market_not_paused(&lending_market)?;
     if &lending_market.token_program_id != token_program_id.key {
         msg!("Lending market token program does not match the token program provided");
         return Err(LendingError::InvalidTokenProgram.into());
@@ -2684,10 +4005,13 @@ fn _flash_borrow_reserve_liquidity<'a>(
     // 1. Ensure the instruction is for this program
     // 2. Ensure the instruction can be unpacked into a LendingInstruction
     // 3. Ensure that the reserve for the repay matches the borrow
-    // 4. Ensure that there are no other flash instructions in the rest of the transaction
+    // 4. Ensure that there are no other flash instructions for this reserve in the rest of the
+    //    transaction. Flash instructions for other reserves are validated by their own borrow.
     // 5. Ensure that the repay amount matches the borrow amount
     //
-    // If all of these conditions are not met, the flash borrow fails.
+    // If all of these conditions are not met, the flash borrow fails. Earlier flash borrows from
+    // this reserve don't need to be checked here, since their own scan would have failed on this
+    // instruction.
     let mut i = current_index;
     let mut found_repay_ix = false;
 
@@ -2712,6 +4036,13 @@ fn _flash_borrow_reserve_liquidity<'a>(
                 liquidity_amount: repay_liquidity_amount,
                 borrow_instruction_index,
             } => {
+                // repays of flash borrows from other reserves
+                if (borrow_instruction_index as usize) != current_index
+                    && ixn.accounts[4].pubkey != *reserve_info.key
+                {
+                    continue;
+                }
+
                 if found_repay_ix {
                     msg!("Multiple flash repays not allowed");
                     return Err(LendingError::MultipleFlashBorrows.into());
@@ -2731,8 +4062,10 @@ fn _flash_borrow_reserve_liquidity<'a>(
 
                 found_repay_ix = true;
             }
-            LendingInstruction::FlashBorrowReserveLiquidity { .. } => {
-                msg!("Multiple flash borrows not allowed");
+            LendingInstruction::FlashBorrowReserveLiquidity { .. }
+                if ixn.accounts[2].pubkey == *reserve_info.key =>
+            {
+                msg!("Multiple flash borrows from the same reserve not allowed");
                 return Err(LendingError::MultipleFlashBorrows.into());
             }
             _ => (),
@@ -2776,6 +4109,7 @@ fn process_flash_repay_reserve_liquidity(
     let user_transfer_authority_info = next_account_info(account_info_iter)?;
     let sysvar_info = next_account_info(account_info_iter)?;
     let token_program_id = next_account_info(account_info_iter)?;
+    let flash_loan_allowlist_info = next_account_info(account_info_iter).ok();
 
     _flash_repay_reserve_liquidity(
         program_id,
@@ -2790,6 +4124,7 @@ fn process_flash_repay_reserve_liquidity(
         user_transfer_authority_info,
         sysvar_info,
         token_program_id,
+        flash_loan_allowlist_info,
     )?;
     Ok(())
 }
@@ -2808,6 +4143,7 @@ fn _flash_repay_reserve_liquidity<'a>(
     user_transfer_authority_info: &AccountInfo<'a>,
     sysvar_info: &AccountInfo<'a>,
     token_program_id: &AccountInfo<'a>,
+    flash_loan_allowlist_info: Option<&AccountInfo<'a>>,
 ) -> ProgramResult {
     let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
     if lending_market_info.owner != program_id {
@@ -2842,12 +4178,6 @@ fn _flash_repay_reserve_liquidity<'a>(
 
     let flash_loan_amount = liquidity_amount;
 
-    let flash_loan_amount_decimal = Decimal::from(flash_loan_amount);
-    let (origination_fee, host_fee) = reserve
-        .config
-        .fees
-        .calculate_flash_loan_fees(flash_loan_amount_decimal)?;
-
     // Make sure this isnt a cpi call
     let current_index = load_current_index_checked(sysvar_info)? as usize;
     if is_cpi_call(program_id, current_index, sysvar_info)? {
@@ -2897,6 +4227,25 @@ fn _flash_repay_reserve_liquidity<'a>(
         }
     };
 
+    let fee_discount_bps = match flash_loan_allowlist_info {
+        Some(flash_loan_allowlist_info) => flash_loan_fee_discount(
+            program_id,
+            lending_market_info,
+            user_transfer_authority_info,
+            flash_loan_allowlist_info,
+            sysvar_info,
+            borrow_instruction_index as usize,
+            current_index,
+        )?,
+        None => 0,
+    };
+
+    let flash_loan_amount_decimal = Decimal::from(flash_loan_amount);
+    let (origination_fee, host_fee) = reserve
+        .config
+        .fees
+        .calculate_flash_loan_fees(flash_loan_amount_decimal, fee_discount_bps)?;
+
     reserve
         .liquidity
         .repay(flash_loan_amount, flash_loan_amount_decimal)?;
@@ -2937,6 +4286,61 @@ fn _flash_repay_reserve_liquidity<'a>(
     Ok(())
 }
 
+/// Fee discount in bps for a flash loan repaid by the user transfer authority. The loan also gets
+/// the discount if every instruction between the flash borrow and the repay calls an allowlisted
+/// program, so the borrowed liquidity can only be used by those programs. An allowlist account
+/// that was never created grants no discount.
+fn flash_loan_fee_discount(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    user_transfer_authority_info: &AccountInfo,
+    flash_loan_allowlist_info: &AccountInfo,
+    sysvar_info: &AccountInfo,
+    borrow_instruction_index: usize,
+    repay_instruction_index: usize,
+) -> Result<u16, ProgramError> {
+    if flash_loan_allowlist_info.data_is_empty() {
+        return Ok(0);
+    }
+    if flash_loan_allowlist_info.owner != program_id {
+        msg!("Flash loan allowlist provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let allowlist_data = flash_loan_allowlist_info.try_borrow_data()?;
+    let allowlist = FlashLoanAllowlist::from_account_data(&allowlist_data)?;
+    let allowlist_key = Pubkey::create_program_address(
+        &[
+            lending_market_info.key.as_ref(),
+            b"FlashLoanAllowlist",
+            &[allowlist.bump_seed],
+        ],
+        program_id,
+    )?;
+    if allowlist_key != *flash_loan_allowlist_info.key {
+        msg!("Provided flash loan allowlist does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if user_transfer_authority_info.is_signer
+        && allowlist.fee_discount_for(user_transfer_authority_info.key) > 0
+    {
+        return Ok(allowlist.fee_discount_bps());
+    }
+
+    if borrow_instruction_index + 1 == repay_instruction_index {
+        return Ok(0);
+    }
+    for index in borrow_instruction_index + 1..repay_instruction_index {
+        let instruction = load_instruction_at_checked(index, sysvar_info)?;
+        if !allowlist.contains_program(&instruction.program_id) {
+            return Ok(0);
+        }
+    }
+
+    Ok(allowlist.fee_discount_bps())
+}
+
 fn process_forgive_debt(
     program_id: &Pubkey,
     liquidity_amount: u64,
@@ -3028,6 +4432,14 @@ fn process_forgive_debt(
     Ok(())
 }
 
+fn assert_market_not_paused(lending_market: &LendingMarket) -> ProgramResult {
+    if lending_market.paused {
+        msg!("Lending market is paused");
+        return Err(LendingError::MarketPaused.into());
+    }
+    Ok(())
+}
+
 fn assert_rent_exempt(rent: &Rent, account_info: &AccountInfo) -> ProgramResult {
     if !rent.is_exempt(account_info.lamports(), account_info.data_len()) {
         msg!(
@@ -3107,6 +4519,81 @@ fn process_update_market_metadata(
     Ok(())
 }
 
+fn process_update_flash_loan_allowlist(
+    program_id: &Pubkey,
+    allowlist: &FlashLoanAllowlist,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let allowlist_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program",);
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    if allowlist.fee_discount_bps() > MAX_FLASH_LOAN_FEE_DISCOUNT_BPS {
+        msg!("Flash loan fee discount must be in range [0, 10000] bps");
+        return Err(LendingError::InvalidConfig.into());
+    }
+
+    let allowlist_seeds = &[lending_market_info.key.as_ref(), b"FlashLoanAllowlist"];
+    let (allowlist_key, bump_seed) = Pubkey::find_program_address(allowlist_seeds, program_id);
+    if allowlist_key != *allowlist_info.key {
+        msg!("Provided flash loan allowlist does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if bump_seed != allowlist.bump_seed {
+        msg!("Provided bump seed does not match the expected derived bump seed");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    // initialize
+    if allowlist_info.data_is_empty() {
+        msg!("Creating flash loan allowlist account");
+
+        invoke_signed(
+            &create_account(
+                lending_market_owner_info.key,
+                allowlist_info.key,
+                Rent::get()?.minimum_balance(std::mem::size_of::<FlashLoanAllowlist>()),
+                std::mem::size_of::<FlashLoanAllowlist>() as u64,
+                program_id,
+            ),
+            &[lending_market_owner_info.clone(), allowlist_info.clone()],
+            &[&[
+                lending_market_info.key.as_ref(),
+                br"FlashLoanAllowlist",
+                &[bump_seed],
+            ]],
+        )?;
+    }
+
+    if allowlist_info.owner != program_id {
+        msg!("Flash loan allowlist provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let mut allowlist_account_data = allowlist_info.try_borrow_mut_data()?;
+    allowlist_account_data.copy_from_slice(bytes_of(allowlist));
+
+    Ok(())
+}
+
 /// process mark obligation as closable
 pub fn process_set_obligation_closeability_status(
     program_id: &Pubkey,
@@ -3187,60 +4674,1500 @@ pub fn process_set_obligation_closeability_status(
     Ok(())
 }
 
-fn assert_uninitialized<T: Pack + IsInitialized>(
-    account_info: &AccountInfo,
-) -> Result<T, ProgramError> {
-    let account: T = T::unpack_unchecked(&account_info.data.borrow())?;
-    if account.is_initialized() {
-        Err(LendingError::AlreadyInitialized.into())
-    } else {
-        Ok(account)
+fn process_set_config_timelock(
+    program_id: &Pubkey,
+    delay_slots: u64,
+    accounts: &[AccountInfo],
+    queued: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let config_timelock_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
     }
-}
 
-/// Unpacks a spl_token `Mint`.
-fn unpack_mint(data: &[u8]) -> Result<Mint, LendingError> {
-    Mint::unpack(data).map_err(|_| LendingError::InvalidTokenMint)
-}
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
 
-/// get_price tries to load the oracle price from pyth, and if it fails, uses switchboard.
-/// The first element in the returned tuple is the market price, and the second is the optional
-/// smoothed price (eg ema, twap).
-fn get_price(
-    secondary_price_account_info: Option<&AccountInfo>,
-    main_price_account_info: &AccountInfo,
-    clock: &Clock,
-) -> Result<(Decimal, Option<Decimal>), ProgramError> {
-    if let Ok(prices) = get_single_price(main_price_account_info, clock) {
-        return Ok((prices.0, prices.1));
+    if !queued && !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
     }
 
-    // if secondary was not passed in don't try to grab the price
-    if let Some(secondary_price_account_info_unwrapped) = secondary_price_account_info {
-        // TODO: add support for secondary smoothed prices. Probably need to add a new
-        // secondary account per reserve.
-        if let Ok(prices) = get_single_price(secondary_price_account_info_unwrapped, clock) {
-            return Ok((prices.0, prices.1));
-        }
+    let current_delay_slots =
+        config_timelock_delay(program_id, lending_market_info, config_timelock_info)?;
+    if !queued && delay_slots < current_delay_slots {
+        msg!("Lowering the config timelock delay must be queued");
+        return Err(LendingError::ConfigChangeTimelocked.into());
     }
 
-    Err(LendingError::InvalidOracleConfig.into())
+    let mut config_timelock = init_config_timelock_if_empty(
+        program_id,
+        lending_market_info,
+        lending_market_owner_info,
+        config_timelock_info,
+    )?;
+    config_timelock.delay_slots = delay_slots.to_le_bytes();
+
+    let mut config_timelock_account_data = config_timelock_info.try_borrow_mut_data()?;
+    config_timelock_account_data.copy_from_slice(bytes_of(&*config_timelock));
+
+    Ok(())
 }
 
-/// Issue a spl_token `InitializeAccount` instruction.
-#[inline(always)]
-fn spl_token_init_account(params: TokenInitializeAccountParams<'_>) -> ProgramResult {
-    let TokenInitializeAccountParams {
-        account,
-        mint,
-        owner,
-        rent,
-        token_program,
-    } = params;
-    let ix = spl_token::instruction::initialize_account(
-        token_program.key,
-        account.key,
-        mint.key,
+fn process_set_config_delta_bounds(
+    program_id: &Pubkey,
+    delta_bounds: Option<ConfigDeltaBounds>,
+    accounts: &[AccountInfo],
+    queued: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let config_timelock_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !queued && !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let mut config_timelock = init_config_timelock_if_empty(
+        program_id,
+        lending_market_info,
+        lending_market_owner_info,
+        config_timelock_info,
+    )?;
+
+    let loosened = match (config_timelock.delta_bounds(), delta_bounds) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(current), Some(new)) => !new.is_at_least_as_strict_as(&current),
+    };
+    if !queued && loosened && config_timelock.delay_slots() > 0 {
+        msg!("Loosening the config delta bounds must be queued");
+        return Err(LendingError::ConfigChangeTimelocked.into());
+    }
+
+    config_timelock.set_delta_bounds(delta_bounds);
+
+    let mut config_timelock_account_data = config_timelock_info.try_borrow_mut_data()?;
+    config_timelock_account_data.copy_from_slice(bytes_of(&*config_timelock));
+
+    Ok(())
+}
+
+/// Creates the lending market's config timelock account if it doesn't exist yet, and returns its
+/// contents
+fn init_config_timelock_if_empty<'a>(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo<'a>,
+    lending_market_owner_info: &AccountInfo<'a>,
+    config_timelock_info: &AccountInfo<'a>,
+) -> Result<Box<ConfigTimelock>, ProgramError> {
+    if let Some(config_timelock) =
+        load_config_timelock(program_id, lending_market_info, config_timelock_info)?
+    {
+        return Ok(config_timelock);
+    }
+
+    msg!("Creating config timelock account");
+
+    // instructions can only leave the config timelock account out while it doesn't exist
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    lending_market.has_config_timelock = true;
+    LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+
+    let config_timelock_seeds = &[lending_market_info.key.as_ref(), b"ConfigTimelock"];
+    let (_, bump_seed) = Pubkey::find_program_address(config_timelock_seeds, program_id);
+
+    invoke_signed(
+        &create_account(
+            lending_market_owner_info.key,
+            config_timelock_info.key,
+            Rent::get()?.minimum_balance(std::mem::size_of::<ConfigTimelock>()),
+            std::mem::size_of::<ConfigTimelock>() as u64,
+            program_id,
+        ),
+        &[
+            lending_market_owner_info.clone(),
+            config_timelock_info.clone(),
+        ],
+        &[&[
+            lending_market_info.key.as_ref(),
+            br"ConfigTimelock",
+            &[bump_seed],
+        ]],
+    )?;
+
+    Ok(Box::new(ConfigTimelock {
+        bump_seed,
+        ..ConfigTimelock::zeroed()
+    }))
+}
+
+fn process_queue_config_change(
+    program_id: &Pubkey,
+    change: &LendingInstruction,
+    change_data: &[u8],
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let config_timelock_info = next_account_info(account_info_iter)?;
+    let pending_config_change_info = next_account_info(account_info_iter)?;
+    let _system_program_info = next_account_info(account_info_iter)?;
+    let change_accounts = account_info_iter.as_slice();
+    let clock = Clock::get()?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    // the market roles padding doesn't fit in a pending config change, and is zeroed on execution
+    let change_data = match change {
+        LendingInstruction::UpdateMarketRoles => {
+            &change_data[..min(change_data.len(), QUEUED_UPDATE_MARKET_ROLES_DATA_LEN)]
+        }
+        _ => change_data,
+    };
+
+    if change_accounts.len() > MAX_QUEUED_CONFIG_CHANGE_ACCOUNTS
+        || change_data.len() > MAX_QUEUED_CONFIG_CHANGE_DATA_LEN
+    {
+        msg!("Config change is too large to be queued");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // the reserve or lending market the change applies to, and the account of the queued
+    // instruction the lending market owner signs with
+    let (target_index, change_market_index, change_owner_index) = match change {
+        LendingInstruction::UpdateReserveConfig { .. } => (0, 1, 3),
+        LendingInstruction::UpdateReserveOracles { .. } => (0, 1, 2),
+        _ => (0, 0, 1),
+    };
+    let (target_info, change_market_info, change_owner_info) = match (
+        change_accounts.get(target_index),
+        change_accounts.get(change_market_index),
+        change_accounts.get(change_owner_index),
+    ) {
+        (Some(target_info), Some(change_market_info), Some(change_owner_info)) => {
+            (target_info, change_market_info, change_owner_info)
+        }
+        _ => {
+            msg!("Config change is missing accounts");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    };
+
+    if change_market_info.key != lending_market_info.key {
+        msg!("Config change lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if change_owner_info.key != lending_market_owner_info.key {
+        msg!("Config change must be signed by the lending market owner queueing it");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if matches!(
+        change,
+        LendingInstruction::UpdateReserveConfig { .. }
+            | LendingInstruction::UpdateReserveOracles { .. }
+    ) {
+        let reserve = Reserve::unpack(&target_info.data.borrow())?;
+        if target_info.owner != program_id {
+            msg!("Reserve provided is not owned by the lending program");
+            return Err(LendingError::InvalidAccountOwner.into());
+        }
+        if &reserve.lending_market != lending_market_info.key {
+            msg!("Reserve lending market does not match the lending market provided");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    }
+
+    let delay_slots = config_timelock_delay(program_id, lending_market_info, config_timelock_info)?;
+
+    let pending_config_change_seeds = &[target_info.key.as_ref(), b"PendingConfigChange"];
+    let (pending_config_change_key, bump_seed) =
+        Pubkey::find_program_address(pending_config_change_seeds, program_id);
+    if pending_config_change_key != *pending_config_change_info.key {
+        msg!("Provided pending config change does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if !pending_config_change_info.data_is_empty() {
+        msg!("A config change is already queued for this account");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    invoke_signed(
+        &create_account(
+            lending_market_owner_info.key,
+            pending_config_change_info.key,
+            Rent::get()?.minimum_balance(std::mem::size_of::<PendingConfigChange>()),
+            std::mem::size_of::<PendingConfigChange>() as u64,
+            program_id,
+        ),
+        &[
+            lending_market_owner_info.clone(),
+            pending_config_change_info.clone(),
+        ],
+        &[&[
+            target_info.key.as_ref(),
+            br"PendingConfigChange",
+            &[bump_seed],
+        ]],
+    )?;
+
+    let earliest_execution_slot = clock
+        .slot
+        .checked_add(delay_slots)
+        .ok_or(LendingError::MathOverflow)?;
+
+    let mut pending_config_change = Box::new(PendingConfigChange::zeroed());
+    pending_config_change.bump_seed = bump_seed;
+    pending_config_change.lending_market = *lending_market_info.key;
+    pending_config_change.queued_by = *lending_market_owner_info.key;
+    pending_config_change.earliest_execution_slot = earliest_execution_slot.to_le_bytes();
+    pending_config_change.account_count = change_accounts.len() as u8;
+    for (pubkey, account_info) in pending_config_change
+        .accounts
+        .iter_mut()
+        .zip(change_accounts.iter())
+    {
+        *pubkey = *account_info.key;
+    }
+    pending_config_change.data_len = (change_data.len() as u16).to_le_bytes();
+    pending_config_change.data[..change_data.len()].copy_from_slice(change_data);
+
+    let mut pending_config_change_account_data =
+        pending_config_change_info.try_borrow_mut_data()?;
+    pending_config_change_account_data.copy_from_slice(bytes_of(&*pending_config_change));
+
+    Ok(())
+}
+
+fn process_execute_config_change(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let pending_config_change_info = next_account_info(account_info_iter)?;
+    let queued_by_info = next_account_info(account_info_iter)?;
+    let change_accounts = account_info_iter.as_slice();
+    let clock = Clock::get()?;
+
+    let pending_config_change =
+        load_pending_config_change(program_id, pending_config_change_info, queued_by_info)?;
+
+    if clock.slot < pending_config_change.earliest_execution_slot() {
+        msg!(
+            "Queued config change is executable from slot {}",
+            pending_config_change.earliest_execution_slot()
+        );
+        return Err(LendingError::ConfigChangeNotReady.into());
+    }
+
+    let expected_change_accounts = pending_config_change.accounts();
+    if change_accounts.len() != expected_change_accounts.len()
+        || change_accounts
+            .iter()
+            .zip(expected_change_accounts.iter())
+            .any(|(account_info, expected)| account_info.key != expected)
+    {
+        msg!("Accounts provided do not match the accounts of the queued config change");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    match LendingInstruction::unpack(pending_config_change.data())? {
+        LendingInstruction::UpdateReserveConfig {
+            config,
+            rate_limiter_config,
+            liquidation_threshold_ramp_slots,
+        } => process_update_reserve_config(
+            program_id,
+            config,
+            rate_limiter_config,
+            liquidation_threshold_ramp_slots,
+            change_accounts,
+            true,
+        )?,
+        LendingInstruction::SetLendingMarketOwnerAndConfig {
+            new_owner,
+            rate_limiter_config,
+            whitelisted_liquidator,
+            risk_authority,
+            obligation_max_outflow,
+            obligation_outflow_window,
+            global_allowed_borrow_value,
+            global_unhealthy_borrow_value,
+        } => process_set_lending_market_owner_and_config(
+            program_id,
+            new_owner,
+            rate_limiter_config,
+            whitelisted_liquidator,
+            risk_authority,
+            obligation_max_outflow,
+            obligation_outflow_window,
+            global_allowed_borrow_value,
+            global_unhealthy_borrow_value,
+            change_accounts,
+            true,
+        )?,
+        LendingInstruction::SetConfigTimelock { delay_slots } => {
+            process_set_config_timelock(program_id, delay_slots, change_accounts, true)?
+        }
+        LendingInstruction::SetConfigDeltaBounds { delta_bounds } => {
+            process_set_config_delta_bounds(program_id, delta_bounds, change_accounts, true)?
+        }
+        LendingInstruction::UpdateReserveOracles {
+            extra_oracle_pubkey,
+        } => {
+            process_update_reserve_oracles(program_id, extra_oracle_pubkey, change_accounts, true)?
+        }
+        LendingInstruction::UpdateMarketRoles => {
+            let queued_data = pending_config_change.data();
+            let mut data = [0u8; 1 + std::mem::size_of::<MarketRoles>()];
+            if queued_data.len() > data.len() {
+                msg!("Queued market roles change is too large");
+                return Err(LendingError::InvalidAccountInput.into());
+            }
+            data[..queued_data.len()].copy_from_slice(queued_data);
+            let roles = MarketRoles::new_from_bytes(&data)?;
+            process_update_market_roles(program_id, roles, change_accounts, true)?
+        }
+        _ => {
+            msg!("Queued instruction is not a config change");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    }
+
+    close_program_account(pending_config_change_info, queued_by_info)
+}
+
+fn process_cancel_config_change(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let signer_info = next_account_info(account_info_iter)?;
+    let pending_config_change_info = next_account_info(account_info_iter)?;
+    let queued_by_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &lending_market.owner != signer_info.key && &lending_market.risk_authority != signer_info.key
+    {
+        msg!("Signer must be the lending market owner or risk authority");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !signer_info.is_signer {
+        msg!("Lending market owner or risk authority provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let pending_config_change =
+        load_pending_config_change(program_id, pending_config_change_info, queued_by_info)?;
+    if &pending_config_change.lending_market != lending_market_info.key {
+        msg!("Queued config change lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    close_program_account(pending_config_change_info, queued_by_info)
+}
+
+/// Returns the delay of a lending market's config timelock, which is 0 if it was never set
+fn config_timelock_delay(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    config_timelock_info: &AccountInfo,
+) -> Result<u64, ProgramError> {
+    Ok(
+        load_config_timelock(program_id, lending_market_info, config_timelock_info)?
+            .map_or(0, |config_timelock| config_timelock.delay_slots()),
+    )
+}
+
+fn load_config_timelock(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    config_timelock_info: &AccountInfo,
+) -> Result<Option<Box<ConfigTimelock>>, ProgramError> {
+    let config_timelock_seeds = &[lending_market_info.key.as_ref(), b"ConfigTimelock"];
+    let (config_timelock_key, _) = Pubkey::find_program_address(config_timelock_seeds, program_id);
+    if config_timelock_key != *config_timelock_info.key {
+        msg!("Provided config timelock does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if config_timelock_info.data_is_empty() {
+        return Ok(None);
+    }
+
+    if config_timelock_info.owner != program_id {
+        msg!("Config timelock provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let config_timelock_data = config_timelock_info.try_borrow_data()?;
+    Ok(Some(Box::new(*ConfigTimelock::from_account_data(
+        &config_timelock_data,
+    )?)))
+}
+
+/// Fails if a reserve config change that wasn't queued exceeds the lending market's config delta
+/// bounds, and records the slot of the changes they apply to
+fn check_config_delta_bounds(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    config_timelock_info: &AccountInfo,
+    previous_config: &ReserveConfig,
+    config: &ReserveConfig,
+) -> ProgramResult {
+    let mut config_timelock =
+        match load_config_timelock(program_id, lending_market_info, config_timelock_info)? {
+            Some(config_timelock) => config_timelock,
+            None => return Ok(()),
+        };
+    let delta_bounds = match config_timelock.delta_bounds() {
+        Some(delta_bounds) => delta_bounds,
+        None => return Ok(()),
+    };
+
+    let slot = Clock::get()?.slot;
+    if delta_bounds.check(
+        previous_config,
+        config,
+        config_timelock.last_bounded_change_slot(),
+        slot,
+    )? {
+        config_timelock.last_bounded_change_slot = slot.to_le_bytes();
+        let mut config_timelock_account_data = config_timelock_info.try_borrow_mut_data()?;
+        config_timelock_account_data.copy_from_slice(bytes_of(&*config_timelock));
+    }
+
+    Ok(())
+}
+
+/// Delay of a config timelock account that instructions can leave out, which they can only do
+/// while the lending market doesn't have one
+fn optional_config_timelock_delay(
+    program_id: &Pubkey,
+    lending_market: &LendingMarket,
+    lending_market_info: &AccountInfo,
+    config_timelock_info: Option<&AccountInfo>,
+) -> Result<u64, ProgramError> {
+    match config_timelock_info {
+        Some(config_timelock_info) => {
+            config_timelock_delay(program_id, lending_market_info, config_timelock_info)
+        }
+        None if lending_market.has_config_timelock => {
+            msg!("Lending market has a config timelock, its account must be provided");
+            Err(LendingError::InvalidAccountInput.into())
+        }
+        None => Ok(0),
+    }
+}
+
+fn load_pending_config_change(
+    program_id: &Pubkey,
+    pending_config_change_info: &AccountInfo,
+    queued_by_info: &AccountInfo,
+) -> Result<Box<PendingConfigChange>, ProgramError> {
+    if pending_config_change_info.owner != program_id {
+        msg!("Pending config change provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let pending_config_change = Box::new(*PendingConfigChange::from_account_data(
+        &pending_config_change_info.try_borrow_data()?,
+    )?);
+    if pending_config_change.lending_market == Pubkey::default() {
+        msg!("No config change is queued");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if &pending_config_change.queued_by != queued_by_info.key {
+        msg!("Queued by account does not match the account that queued the config change");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    Ok(pending_config_change)
+}
+
+/// Refunds the rent of an account owned by the program to `destination_info` and clears it
+fn close_program_account(
+    account_info: &AccountInfo,
+    destination_info: &AccountInfo,
+) -> ProgramResult {
+    let lamports = account_info.lamports();
+    **destination_info.lamports.borrow_mut() = destination_info
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(LendingError::MathOverflow)?;
+    **account_info.lamports.borrow_mut() = 0;
+    account_info.data.borrow_mut().fill(0);
+
+    Ok(())
+}
+
+/// `queued` is true when executing a change queued with `QueueConfigChange`, in which case the
+/// owner signed the queueing instead.
+fn process_update_market_roles(
+    program_id: &Pubkey,
+    roles: &MarketRoles,
+    accounts: &[AccountInfo],
+    queued: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let market_roles_info = next_account_info(account_info_iter)?;
+    let _system_program_info = next_account_info(account_info_iter)?;
+    let config_timelock_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program",);
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !queued && !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    // creating the market roles account without assigning any role is still allowed
+    if !queued && config_timelock_delay(program_id, lending_market_info, config_timelock_info)? > 0
+    {
+        let current_roles = load_market_roles(program_id, lending_market_info, market_roles_info)?;
+        if MarketRole::ALL
+            .iter()
+            .any(|role| roles.holder(*role) != current_roles.holder(*role))
+        {
+            msg!("Lending market has a config timelock, role changes must be queued");
+            return Err(LendingError::ConfigChangeTimelocked.into());
+        }
+    }
+
+    // the lending market owner doesn't sign a queued change, so can't pay for the account
+    if queued && market_roles_info.data_is_empty() {
+        msg!("Market roles account must be created before role changes are queued");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let market_roles_seeds = &[lending_market_info.key.as_ref(), b"MarketRoles"];
+    let (market_roles_key, bump_seed) =
+        Pubkey::find_program_address(market_roles_seeds, program_id);
+    if market_roles_key != *market_roles_info.key {
+        msg!("Provided market roles does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if bump_seed != roles.bump_seed {
+        msg!("Provided bump seed does not match the expected derived bump seed");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    // initialize
+    if market_roles_info.data_is_empty() {
+        msg!("Creating market roles account");
+
+        invoke_signed(
+            &create_account(
+                lending_market_owner_info.key,
+                market_roles_info.key,
+                Rent::get()?.minimum_balance(std::mem::size_of::<MarketRoles>()),
+                std::mem::size_of::<MarketRoles>() as u64,
+                program_id,
+            ),
+            &[lending_market_owner_info.clone(), market_roles_info.clone()],
+            &[&[
+                lending_market_info.key.as_ref(),
+                br"MarketRoles",
+                &[bump_seed],
+            ]],
+        )?;
+    }
+
+    if market_roles_info.owner != program_id {
+        msg!("Market roles provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let mut market_roles_account_data = market_roles_info.try_borrow_mut_data()?;
+    market_roles_account_data.copy_from_slice(bytes_of(roles));
+
+    Ok(())
+}
+
+/// Returns the roles of a lending market, none of which are assigned if they were never set
+fn load_market_roles(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    market_roles_info: &AccountInfo,
+) -> Result<Box<MarketRoles>, ProgramError> {
+    let market_roles_seeds = &[lending_market_info.key.as_ref(), b"MarketRoles"];
+    let (market_roles_key, _) = Pubkey::find_program_address(market_roles_seeds, program_id);
+    if market_roles_key != *market_roles_info.key {
+        msg!("Provided market roles does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if market_roles_info.data_is_empty() {
+        return Ok(Box::new(MarketRoles::zeroed()));
+    }
+
+    if market_roles_info.owner != program_id {
+        msg!("Market roles provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let market_roles_data = market_roles_info.try_borrow_data()?;
+    Ok(Box::new(*MarketRoles::from_account_data(
+        &market_roles_data,
+    )?))
+}
+
+/// Market roles of an account instructions can leave out, in which case no role is assigned
+fn load_optional_market_roles(
+    program_id: &Pubkey,
+    lending_market_info: &AccountInfo,
+    market_roles_info: Option<&AccountInfo>,
+) -> Result<Box<MarketRoles>, ProgramError> {
+    match market_roles_info {
+        Some(market_roles_info) => {
+            load_market_roles(program_id, lending_market_info, market_roles_info)
+        }
+        None => Ok(Box::new(MarketRoles::zeroed())),
+    }
+}
+
+/// Fails unless `signer` holds `role`, if the fields it governs are `changed`
+fn require_market_role(
+    market_roles: &MarketRoles,
+    signer: &Pubkey,
+    role: MarketRole,
+    changed: bool,
+) -> ProgramResult {
+    if changed && !market_roles.has_role(role, signer) {
+        msg!("Signer must hold the {:?} role for this change", role);
+        return Err(LendingError::MissingMarketRole.into());
+    }
+    Ok(())
+}
+
+/// Disabling outflows is the pause guardian's, any other rate limiter change the limits admin's
+fn require_rate_limiter_role(
+    market_roles: &MarketRoles,
+    signer: &Pubkey,
+    rate_limiter_config: &RateLimiterConfig,
+) -> ProgramResult {
+    if disables_outflows(rate_limiter_config)
+        && market_roles.has_role(MarketRole::PauseGuardian, signer)
+    {
+        return Ok(());
+    }
+    require_market_role(market_roles, signer, MarketRole::LimitsAdmin, true)
+}
+
+/// Whether a rate limiter config blocks every outflow
+fn disables_outflows(rate_limiter_config: &RateLimiterConfig) -> bool {
+    rate_limiter_config.window_duration > 0 && rate_limiter_config.max_outflow == 0
+}
+
+/// Checks that `signer` holds the role of every group of reserve fields the update changes
+fn require_reserve_config_roles(
+    market_roles: &MarketRoles,
+    signer: &Pubkey,
+    reserve: &Reserve,
+    config: &ReserveConfig,
+    rate_limiter_config: &RateLimiterConfig,
+    pyth_price_pubkey: &Pubkey,
+    switchboard_feed_pubkey: &Pubkey,
+) -> ProgramResult {
+    let current = &reserve.config;
+
+    let oracles_changed = *pyth_price_pubkey != reserve.liquidity.pyth_oracle_pubkey
+        || *switchboard_feed_pubkey != reserve.liquidity.switchboard_oracle_pubkey
+        || config.extra_oracle_pubkey != current.extra_oracle_pubkey
+        || config.underlying_oracle_pubkey != current.underlying_oracle_pubkey
+        || config.scaled_price_offset_bps != current.scaled_price_offset_bps
+        || config.peg_mode != current.peg_mode
+        || config.peg_max_deviation_bps != current.peg_max_deviation_bps;
+    let fees_changed = config.fees != current.fees
+        || config.protocol_liquidation_fee != current.protocol_liquidation_fee
+        || config.protocol_take_rate != current.protocol_take_rate
+        || config.fee_receiver != current.fee_receiver;
+    let limits_changed = config.deposit_limit != current.deposit_limit
+        || config.borrow_limit != current.borrow_limit
+        || config.attributed_borrow_limit_open != current.attributed_borrow_limit_open
+        || config.attributed_borrow_limit_close != current.attributed_borrow_limit_close;
+    // every other field is the config admin's
+    let config_changed = ReserveConfig {
+        extra_oracle_pubkey: current.extra_oracle_pubkey,
+        underlying_oracle_pubkey: current.underlying_oracle_pubkey,
+        scaled_price_offset_bps: current.scaled_price_offset_bps,
+        peg_mode: current.peg_mode,
+        peg_max_deviation_bps: current.peg_max_deviation_bps,
+        fees: current.fees,
+        protocol_liquidation_fee: current.protocol_liquidation_fee,
+        protocol_take_rate: current.protocol_take_rate,
+        fee_receiver: current.fee_receiver,
+        deposit_limit: current.deposit_limit,
+        borrow_limit: current.borrow_limit,
+        attributed_borrow_limit_open: current.attributed_borrow_limit_open,
+        attributed_borrow_limit_close: current.attributed_borrow_limit_close,
+        ..*config
+    } != *current;
+
+    require_market_role(
+        market_roles,
+        signer,
+        MarketRole::OracleAdmin,
+        oracles_changed,
+    )?;
+    require_market_role(
+        market_roles,
+        signer,
+        MarketRole::ConfigAdmin,
+        config_changed,
+    )?;
+    require_market_role(market_roles, signer, MarketRole::FeeAdmin, fees_changed)?;
+    require_market_role(
+        market_roles,
+        signer,
+        MarketRole::LimitsAdmin,
+        limits_changed,
+    )?;
+    if *rate_limiter_config != reserve.rate_limiter.config {
+        require_rate_limiter_role(market_roles, signer, rate_limiter_config)?;
+    }
+
+    Ok(())
+}
+
+/// The risk authority, and role holders while the market is timelocked, can only make a reserve
+/// safer: lower its deposit, borrow and attributed open limits (zeroing the deposit and borrow
+/// limits makes the reserve reduce-only), lower its loan to value ratio, raise its borrow weight
+/// or disable its outflows. Any other change is rejected with `error` rather than dropped.
+fn require_safer_reserve_config(
+    reserve: &Reserve,
+    config: &ReserveConfig,
+    rate_limiter_config: &RateLimiterConfig,
+    pyth_price_pubkey: &Pubkey,
+    switchboard_feed_pubkey: &Pubkey,
+    error: LendingError,
+) -> ProgramResult {
+    let current = &reserve.config;
+
+    if config.deposit_limit > current.deposit_limit {
+        msg!("Only lowering the deposit limit is risk-reducing");
+        return Err(error.into());
+    }
+    if config.borrow_limit > current.borrow_limit {
+        msg!("Only lowering the borrow limit is risk-reducing");
+        return Err(error.into());
+    }
+    if config.attributed_borrow_limit_open > current.attributed_borrow_limit_open {
+        msg!("Only lowering the attributed borrow limit open is risk-reducing");
+        return Err(error.into());
+    }
+    if config.loan_to_value_ratio > current.loan_to_value_ratio {
+        msg!("Only lowering the loan to value ratio is risk-reducing");
+        return Err(error.into());
+    }
+    if config.added_borrow_weight_bps < current.added_borrow_weight_bps {
+        msg!("Only raising the added borrow weight is risk-reducing");
+        return Err(error.into());
+    }
+
+    if *rate_limiter_config != reserve.rate_limiter.config
+        && !disables_outflows(rate_limiter_config)
+    {
+        msg!("Only disabling outflows with the rate limiter is risk-reducing");
+        return Err(error.into());
+    }
+
+    if *pyth_price_pubkey != reserve.liquidity.pyth_oracle_pubkey
+        || *switchboard_feed_pubkey != reserve.liquidity.switchboard_oracle_pubkey
+    {
+        msg!("Changing the reserve oracles isn't risk-reducing");
+        return Err(error.into());
+    }
+
+    let other_fields_changed = ReserveConfig {
+        deposit_limit: current.deposit_limit,
+        borrow_limit: current.borrow_limit,
+        attributed_borrow_limit_open: current.attributed_borrow_limit_open,
+        loan_to_value_ratio: current.loan_to_value_ratio,
+        added_borrow_weight_bps: current.added_borrow_weight_bps,
+        ..*config
+    } != *current;
+    if other_fields_changed {
+        msg!("Only lowering limits and the loan to value ratio, or raising the added borrow weight, is risk-reducing");
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+fn process_propose_market_owner(
+    program_id: &Pubkey,
+    new_owner: Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let pending_market_owner_info = next_account_info(account_info_iter)?;
+
+    let lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let pending_market_owner_seeds = &[lending_market_info.key.as_ref(), b"PendingMarketOwner"];
+    let (pending_market_owner_key, bump_seed) =
+        Pubkey::find_program_address(pending_market_owner_seeds, program_id);
+    if pending_market_owner_key != *pending_market_owner_info.key {
+        msg!("Provided pending market owner does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // initialize
+    if pending_market_owner_info.data_is_empty() {
+        msg!("Creating pending market owner account");
+
+        invoke_signed(
+            &create_account(
+                lending_market_owner_info.key,
+                pending_market_owner_info.key,
+                Rent::get()?.minimum_balance(std::mem::size_of::<PendingMarketOwner>()),
+                std::mem::size_of::<PendingMarketOwner>() as u64,
+                program_id,
+            ),
+            &[
+                lending_market_owner_info.clone(),
+                pending_market_owner_info.clone(),
+            ],
+            &[&[
+                lending_market_info.key.as_ref(),
+                br"PendingMarketOwner",
+                &[bump_seed],
+            ]],
+        )?;
+    }
+
+    if pending_market_owner_info.owner != program_id {
+        msg!("Pending market owner provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let pending_market_owner = PendingMarketOwner {
+        bump_seed,
+        pending_owner: new_owner,
+        proposed_by: *lending_market_owner_info.key,
+        padding: [0; PENDING_MARKET_OWNER_PADDING_SIZE],
+    };
+    let mut pending_market_owner_account_data = pending_market_owner_info.try_borrow_mut_data()?;
+    pending_market_owner_account_data.copy_from_slice(bytes_of(&pending_market_owner));
+
+    Ok(())
+}
+
+fn process_accept_market_owner(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let new_owner_info = next_account_info(account_info_iter)?;
+    let pending_market_owner_info = next_account_info(account_info_iter)?;
+    let proposed_by_info = next_account_info(account_info_iter)?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let pending_market_owner_seeds = &[lending_market_info.key.as_ref(), b"PendingMarketOwner"];
+    let (pending_market_owner_key, _) =
+        Pubkey::find_program_address(pending_market_owner_seeds, program_id);
+    if pending_market_owner_key != *pending_market_owner_info.key {
+        msg!("Provided pending market owner does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if pending_market_owner_info.owner != program_id {
+        msg!("No lending market owner was proposed");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let pending_market_owner =
+        *PendingMarketOwner::from_account_data(&pending_market_owner_info.try_borrow_data()?)?;
+    if pending_market_owner.proposed_by != lending_market.owner
+        || &pending_market_owner.proposed_by != proposed_by_info.key
+    {
+        msg!("Lending market owner was proposed by a previous owner");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if &pending_market_owner.pending_owner != new_owner_info.key {
+        msg!("New owner does not match the proposed lending market owner");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    if !new_owner_info.is_signer {
+        msg!("Proposed lending market owner must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    lending_market.owner = *new_owner_info.key;
+    LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+
+    close_program_account(pending_market_owner_info, proposed_by_info)
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_set_market_paused(
+    program_id: &Pubkey,
+    paused: bool,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let signer_info = next_account_info(account_info_iter)?;
+    let market_roles_info = next_account_info(account_info_iter)?;
+
+    let mut lending_market = LendingMarket::unpack(&lending_market_info.data.borrow())?;
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    if !signer_info.is_signer {
+        msg!("Lending market owner, risk authority or pause guardian provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    if paused {
+        let market_roles = load_market_roles(program_id, lending_market_info, market_roles_info)?;
+        if signer_info.key != &lending_market.owner
+            && signer_info.key != &lending_market.risk_authority
+            && !market_roles.has_role(MarketRole::PauseGuardian, signer_info.key)
+        {
+            msg!("Signer must be the lending market owner, risk authority or pause guardian");
+            return Err(LendingError::InvalidSigner.into());
+        }
+    } else if signer_info.key != &lending_market.owner {
+        msg!("Only the lending market owner can unpause the lending market");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+
+    lending_market.paused = paused;
+    LendingMarket::pack(lending_market, &mut lending_market_info.data.borrow_mut())?;
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_init_protocol_config(
+    program_id: &Pubkey,
+    protocol_config: &ProtocolConfig,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let protocol_config_info = next_account_info(account_info_iter)?;
+    let upgrade_authority_info = next_account_info(account_info_iter)?;
+    let program_data_info = next_account_info(account_info_iter)?;
+
+    if program_upgrade_authority(program_id, program_data_info)?
+        != Some(*upgrade_authority_info.key)
+    {
+        msg!("Upgrade authority does not match the lending program's upgrade authority");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if !upgrade_authority_info.is_signer {
+        msg!("Upgrade authority provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+
+    let (protocol_config_key, bump_seed) =
+        Pubkey::find_program_address(&[b"ProtocolConfig"], program_id);
+    if protocol_config_key != *protocol_config_info.key {
+        msg!("Provided protocol config does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if bump_seed != protocol_config.bump_seed {
+        msg!("Provided bump seed does not match the expected derived bump seed");
+        return Err(LendingError::InvalidAmount.into());
+    }
+    if !protocol_config_info.data_is_empty() {
+        msg!("Protocol config is already initialized");
+        return Err(LendingError::AlreadyInitialized.into());
+    }
+
+    protocol_config.validate()?;
+
+    invoke_signed(
+        &create_account(
+            upgrade_authority_info.key,
+            protocol_config_info.key,
+            Rent::get()?.minimum_balance(std::mem::size_of::<ProtocolConfig>()),
+            std::mem::size_of::<ProtocolConfig>() as u64,
+            program_id,
+        ),
+        &[upgrade_authority_info.clone(), protocol_config_info.clone()],
+        &[&[br"ProtocolConfig", &[bump_seed]]],
+    )?;
+
+    let mut protocol_config_account_data = protocol_config_info.try_borrow_mut_data()?;
+    protocol_config_account_data.copy_from_slice(bytes_of(protocol_config));
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_update_protocol_config(
+    program_id: &Pubkey,
+    protocol_config: &ProtocolConfig,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let protocol_config_info = next_account_info(account_info_iter)?;
+    let protocol_authority_info = next_account_info(account_info_iter)?;
+
+    let current_protocol_config = match load_protocol_config(program_id, protocol_config_info)? {
+        Some(current_protocol_config) => current_protocol_config,
+        None => {
+            msg!("Protocol config is not initialized");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+    };
+
+    if &current_protocol_config.authority != protocol_authority_info.key {
+        msg!("Protocol authority does not match the protocol authority provided");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if !protocol_authority_info.is_signer {
+        msg!("Protocol authority provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if current_protocol_config.bump_seed != protocol_config.bump_seed {
+        msg!("Provided bump seed does not match the expected derived bump seed");
+        return Err(LendingError::InvalidAmount.into());
+    }
+
+    protocol_config.validate()?;
+
+    let mut protocol_config_account_data = protocol_config_info.try_borrow_mut_data()?;
+    protocol_config_account_data.copy_from_slice(bytes_of(protocol_config));
+
+    Ok(())
+}
+
+#[inline(never)] // avoid stack frame limit
+fn process_close_reserve(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+    let reserve_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_supply_info = next_account_info(account_info_iter)?;
+    let reserve_liquidity_fee_receiver_info = next_account_info(account_info_iter)?;
+    let reserve_collateral_mint_info = next_account_info(account_info_iter)?;
+    let reserve_collateral_supply_info = next_account_info(account_info_iter)?;
+    let lending_market_info = next_account_info(account_info_iter)?;
+    let lending_market_authority_info = next_account_info(account_info_iter)?;
+    let lending_market_owner_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+    let destination_liquidity_info = next_account_info(account_info_iter)?;
+    let token_program_id = next_account_info(account_info_iter)?;
+
+    let reserve = Box::new(Reserve::unpack(&reserve_info.data.borrow())?);
+    if reserve_info.owner != program_id {
+        msg!(
+            "Reserve provided is not owned by the lending program {} != {}",
+            &reserve_info.owner.to_string(),
+            &program_id.to_string(),
+        );
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &reserve.lending_market != lending_market_info.key {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.liquidity.supply_pubkey != reserve_liquidity_supply_info.key {
+        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.config.fee_receiver != reserve_liquidity_fee_receiver_info.key {
+        msg!("Reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.collateral.mint_pubkey != reserve_collateral_mint_info.key {
+        msg!("Reserve collateral mint does not match the reserve collateral mint provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+    if &reserve.collateral.supply_pubkey != reserve_collateral_supply_info.key {
+        msg!("Reserve collateral supply does not match the reserve collateral supply provided");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let lending_market = Box::new(LendingMarket::unpack(&lending_market_info.data.borrow())?);
+    if lending_market_info.owner != program_id {
+        msg!("Lending market provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+    if &lending_market.owner != lending_market_owner_info.key {
+        msg!("Lending market owner does not match the lending market owner provided");
+        return Err(LendingError::InvalidMarketOwner.into());
+    }
+    if !lending_market_owner_info.is_signer {
+        msg!("Lending market owner provided must be a signer");
+        return Err(LendingError::InvalidSigner.into());
+    }
+    if &lending_market.token_program_id != token_program_id.key {
+        msg!("Lending market token program does not match the token program provided");
+        return Err(LendingError::InvalidTokenProgram.into());
+    }
+    let authority_signer_seeds = &[
+        lending_market_info.key.as_ref(),
+        &[lending_market.bump_seed],
+    ];
+    let lending_market_authority_pubkey =
+        Pubkey::create_program_address(authority_signer_seeds, program_id)?;
+    if &lending_market_authority_pubkey != lending_market_authority_info.key {
+        msg!(
+            "Derived lending market authority does not match the lending market authority provided"
+        );
+        return Err(LendingError::InvalidMarketAuthority.into());
+    }
+
+    if reserve.liquidity.borrowed_amount_wads != Decimal::zero() {
+        msg!("Reserve still has outstanding borrows");
+        return Err(LendingError::ReserveNotEmpty.into());
+    }
+    let reserve_collateral_mint = unpack_mint(&reserve_collateral_mint_info.data.borrow())?;
+    if reserve_collateral_mint.supply != 0 || reserve.collateral.mint_total_supply != 0 {
+        msg!("Reserve collateral must be fully redeemed");
+        return Err(LendingError::ReserveNotEmpty.into());
+    }
+    // less than a token of protocol fees can't be redeemed and is swept with the supply below
+    if reserve
+        .liquidity
+        .accumulated_protocol_fees_wads
+        .try_floor_u64()?
+        != 0
+    {
+        msg!("Reserve protocol fees must be redeemed");
+        return Err(LendingError::ReserveNotEmpty.into());
+    }
+
+    let reserve_liquidity_supply =
+        unpack_token_account(&reserve_liquidity_supply_info.data.borrow())?;
+    if reserve_liquidity_supply.amount > 0 {
+        spl_token_transfer(TokenTransferParams {
+            source: reserve_liquidity_supply_info.clone(),
+            destination: reserve_liquidity_fee_receiver_info.clone(),
+            amount: reserve_liquidity_supply.amount,
+            authority: lending_market_authority_info.clone(),
+            authority_signer_seeds,
+            token_program: token_program_id.clone(),
+        })?;
+    }
+
+    let mut token_account_infos = vec![
+        reserve_liquidity_supply_info,
+        reserve_collateral_supply_info,
+    ];
+
+    // a fee receiver set up by InitReserve belongs to the lending market authority, so it can't be
+    // used once the reserve is gone
+    let reserve_liquidity_fee_receiver =
+        unpack_token_account(&reserve_liquidity_fee_receiver_info.data.borrow())?;
+    if reserve_liquidity_fee_receiver.owner == lending_market_authority_pubkey {
+        if reserve_liquidity_fee_receiver.amount > 0 {
+            spl_token_transfer(TokenTransferParams {
+                source: reserve_liquidity_fee_receiver_info.clone(),
+                destination: destination_liquidity_info.clone(),
+                amount: reserve_liquidity_fee_receiver.amount,
+                authority: lending_market_authority_info.clone(),
+                authority_signer_seeds,
+                token_program: token_program_id.clone(),
+            })?;
+        }
+        token_account_infos.push(reserve_liquidity_fee_receiver_info);
+    }
+
+    for token_account_info in token_account_infos {
+        spl_token_close_account(TokenCloseAccountParams {
+            account: token_account_info.clone(),
+            destination: destination_info.clone(),
+            authority: lending_market_authority_info.clone(),
+            authority_signer_seeds,
+            token_program: token_program_id.clone(),
+        })?;
+    }
+
+    close_program_account(reserve_info, destination_info)
+}
+
+/// Returns the program's protocol config from an account instructions can leave out, or None if
+/// it's left out or was never initialized
+fn load_optional_protocol_config(
+    program_id: &Pubkey,
+    protocol_config_info: Option<&AccountInfo>,
+) -> Result<Option<Box<ProtocolConfig>>, ProgramError> {
+    match protocol_config_info {
+        Some(protocol_config_info) => load_protocol_config(program_id, protocol_config_info),
+        None => Ok(None),
+    }
+}
+
+/// Returns the program's protocol config, or None if it was never initialized
+fn load_protocol_config(
+    program_id: &Pubkey,
+    protocol_config_info: &AccountInfo,
+) -> Result<Option<Box<ProtocolConfig>>, ProgramError> {
+    let (protocol_config_key, _) = Pubkey::find_program_address(&[b"ProtocolConfig"], program_id);
+    if protocol_config_key != *protocol_config_info.key {
+        msg!("Provided protocol config does not match the expected derived address");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    if protocol_config_info.data_is_empty() {
+        return Ok(None);
+    }
+
+    if protocol_config_info.owner != program_id {
+        msg!("Protocol config provided is not owned by the lending program");
+        return Err(LendingError::InvalidAccountOwner.into());
+    }
+
+    let protocol_config_data = protocol_config_info.try_borrow_data()?;
+    Ok(Some(Box::new(*ProtocolConfig::from_account_data(
+        &protocol_config_data,
+    )?)))
+}
+
+/// Returns the upgrade authority of the lending program, None if it's immutable
+fn program_upgrade_authority(
+    program_id: &Pubkey,
+    program_data_info: &AccountInfo,
+) -> Result<Option<Pubkey>, ProgramError> {
+    let (program_data_key, _) =
+        Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+    if program_data_key != *program_data_info.key
+        || program_data_info.owner != &bpf_loader_upgradeable::id()
+    {
+        msg!("Program data account provided is not the lending program's");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    // UpgradeableLoaderState::ProgramData is serialized as a u32 variant index of 3, the u64 slot
+    // it was last deployed at and an optional upgrade authority
+    let program_data = program_data_info.try_borrow_data()?;
+    match program_data.get(..UpgradeableLoaderState::size_of_programdata_metadata()) {
+        Some([3, 0, 0, 0, _, _, _, _, _, _, _, _, 0, ..]) => Ok(None),
+        Some([3, 0, 0, 0, _, _, _, _, _, _, _, _, 1, upgrade_authority @ ..]) => Ok(Some(
+            Pubkey::new_from_array(upgrade_authority.try_into().map_err(|_| {
+                msg!("Program data account upgrade authority cannot be unpacked");
+                LendingError::InvalidAccountInput
+            })?),
+        )),
+        _ => {
+            msg!("Program data account cannot be unpacked");
+            Err(LendingError::InvalidAccountInput.into())
+        }
+    }
+}
+
+fn assert_uninitialized<T: Pack + IsInitialized>(
+    account_info: &AccountInfo,
+) -> Result<T, ProgramError> {
+    let account: T = T::unpack_unchecked(&account_info.data.borrow())?;
+    if account.is_initialized() {
+        Err(LendingError::AlreadyInitialized.into())
+    } else {
+        Ok(account)
+    }
+}
+
+/// Unpacks a spl_token `Mint`.
+fn unpack_mint(data: &[u8]) -> Result<Mint, LendingError> {
+    Mint::unpack(data).map_err(|_| LendingError::InvalidTokenMint)
+}
+
+/// Unpacks a spl_token `Account`.
+fn unpack_token_account(data: &[u8]) -> Result<TokenAccount, LendingError> {
+    TokenAccount::unpack(data).map_err(|_| LendingError::InvalidTokenAccount)
+}
+
+/// Credits an inflow of liquidity to the reserve and market rate limiters that are in net flow
+/// mode. The lending market has to be passed as writable while its rate limiter is in net flow
+/// mode, so the credit can't be skipped.
+fn credit_rate_limiters(
+    lending_market: &mut LendingMarket,
+    lending_market_info: &AccountInfo,
+    reserve: &mut Reserve,
+    slot: u64,
+    liquidity_amount: Decimal,
+) -> ProgramResult {
+    reserve.rate_limiter.credit_inflow(slot, liquidity_amount)?;
+
+    if lending_market.rate_limiter.config.net_flow {
+        if !lending_market_info.is_writable {
+            msg!("Lending market account must be writable while its rate limiter is in net flow mode");
+            return Err(LendingError::InvalidAccountInput.into());
+        }
+        lending_market
+            .rate_limiter
+            .credit_inflow(slot, reserve.market_value_lower_bound(liquidity_amount)?)?;
+        LendingMarket::pack(
+            lending_market.clone(),
+            &mut lending_market_info.data.borrow_mut(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Oracle program ids accepted for reserves of the lending market
+fn market_oracle_program_ids(lending_market: &LendingMarket) -> OracleProgramIds {
+    OracleProgramIds::for_market(
+        &lending_market.oracle_program_id,
+        &lending_market.switchboard_oracle_program_id,
+    )
+}
+
+/// Replaces the oracle prices of pegged reserves with the peg price. The last element in the
+/// returned tuple is whether the reserve is depegged.
+fn apply_peg_mode(
+    config: &ReserveConfig,
+    market_price: Decimal,
+    smoothed_market_price: Option<Decimal>,
+) -> Result<(Decimal, Option<Decimal>, bool), ProgramError> {
+    if config.peg_mode == PegMode::Disabled {
+        return Ok((market_price, smoothed_market_price, false));
+    }
+
+    let pegged_price = get_pegged_price(
+        market_price,
+        smoothed_market_price,
+        config.peg_max_deviation_bps,
+    )?;
+    if pegged_price.depegged {
+        msg!(
+            "Oracle price {} is outside of the peg band, using the oracle price",
+            market_price
+        );
+    }
+
+    Ok((
+        pegged_price.price,
+        pegged_price.smoothed_price,
+        pegged_price.depegged,
+    ))
+}
+
+/// get_price tries to load the oracle price from pyth, and if it fails, uses switchboard.
+/// The first element in the returned tuple is the market price, and the second is the optional
+/// smoothed price (eg ema, twap). If an underlying price account is passed in, the main price
+/// account is a stake pool priced off the underlying.
+fn get_price(
+    secondary_price_account_info: Option<&AccountInfo>,
+    main_price_account_info: &AccountInfo,
+    underlying_price_account_info: Option<&AccountInfo>,
+    clock: &Clock,
+    oracle_program_ids: &OracleProgramIds,
+) -> Result<(Decimal, Option<Decimal>), ProgramError> {
+    let main_prices = match underlying_price_account_info {
+        Some(underlying_price_account_info) => get_stake_pool_price(
+            main_price_account_info,
+            underlying_price_account_info,
+            clock,
+            oracle_program_ids,
+        ),
+        None => get_single_price(main_price_account_info, clock, oracle_program_ids),
+    };
+    if let Ok(prices) = main_prices {
+        return Ok((prices.0, prices.1));
+    }
+
+    // if secondary was not passed in don't try to grab the price
+    if let Some(secondary_price_account_info_unwrapped) = secondary_price_account_info {
+        // TODO: add support for secondary smoothed prices. Probably need to add a new
+        // secondary account per reserve.
+        if let Ok(prices) = get_single_price(
+            secondary_price_account_info_unwrapped,
+            clock,
+            oracle_program_ids,
+        ) {
+            return Ok((prices.0, prices.1));
+        }
+    }
+
+    Err(LendingError::InvalidOracleConfig.into())
+}
+
+/// Issue a spl_token `InitializeAccount` instruction.
+#[inline(always)]
+fn spl_token_init_account(params: TokenInitializeAccountParams<'_>) -> ProgramResult {
+    let TokenInitializeAccountParams {
+        account,
+        mint,
+        owner,
+        rent,
+        token_program,
+    } = params;
+    let ix = spl_token::instruction::initialize_account(
+        token_program.key,
+        account.key,
+        mint.key,
         owner.key,
     )?;
     let result = invoke(&ix, &[account, mint, owner, rent, token_program]);
@@ -3360,6 +6287,30 @@ fn spl_token_burn(params: TokenBurnParams<'_, '_>) -> ProgramResult {
     result.map_err(|_| LendingError::TokenBurnFailed.into())
 }
 
+/// Issue a spl_token `CloseAccount` instruction.
+#[inline(always)]
+fn spl_token_close_account(params: TokenCloseAccountParams<'_, '_>) -> ProgramResult {
+    let TokenCloseAccountParams {
+        account,
+        destination,
+        authority,
+        token_program,
+        authority_signer_seeds,
+    } = params;
+    let result = invoke_optionally_signed(
+        &spl_token::instruction::close_account(
+            token_program.key,
+            account.key,
+            destination.key,
+            authority.key,
+            &[],
+        )?,
+        &[account, destination, authority, token_program],
+        authority_signer_seeds,
+    );
+    result.map_err(|_| LendingError::TokenCloseAccountFailed.into())
+}
+
 fn is_cpi_call(
     program_id: &Pubkey,
     current_index: usize,
@@ -3431,3 +6382,11 @@ struct TokenBurnParams<'a: 'b, 'b> {
     authority_signer_seeds: &'b [&'b [u8]],
     token_program: AccountInfo<'a>,
 }
+
+struct TokenCloseAccountParams<'a: 'b, 'b> {
+    account: AccountInfo<'a>,
+    destination: AccountInfo<'a>,
+    authority: AccountInfo<'a>,
+    authority_signer_seeds: &'b [&'b [u8]],
+    token_program: AccountInfo<'a>,
+}
@@ -2,7 +2,9 @@
 
 use crate::helpers::solend_program_test::*;
 use solana_program::pubkey::Pubkey;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
 use solana_sdk::signature::Signer;
+use solend_program::instruction::{borrow_obligation_liquidity, refresh_obligation};
 
 use solend_program::math::TryDiv;
 mod helpers;
@@ -353,6 +355,141 @@ async fn test_borrow_max() {
     assert_eq!(mint_supply_changes, HashSet::new());
 }
 
+#[tokio::test]
+async fn test_borrow_max_capped_by_global_allowed_borrow_value() {
+    let (
+        mut test,
+        lending_market,
+        _usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        _,
+        lending_market_owner,
+    ) = setup(&test_reserve_config()).await;
+
+    // $50 can be borrowed against the $100 of USDC deposited, but the market caps it at $20
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            lending_market.account.risk_authority,
+            lending_market.account.obligation_max_outflow,
+            lending_market.account.obligation_outflow_window,
+            20,
+            30,
+        )
+        .await
+        .unwrap();
+
+    let balance_checker = BalanceChecker::start(&mut test, &[&user]).await;
+
+    lending_market
+        .borrow_obligation_liquidity(&mut test, &wsol_reserve, &obligation, &user, None, u64::MAX)
+        .await
+        .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    let expected_balance_changes = HashSet::from([TokenBalanceChange {
+        token_account: user.get_account(&wsol_mint::id()).unwrap(),
+        mint: wsol_mint::id(),
+        diff: (2 * LAMPORTS_PER_SOL) as i128,
+    }]);
+    assert_eq!(balance_changes, expected_balance_changes);
+}
+
+#[tokio::test]
+async fn test_fail_refreshed_without_lending_market() {
+    let (
+        mut test,
+        lending_market,
+        _usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        _,
+        lending_market_owner,
+    ) = setup(&test_reserve_config()).await;
+
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            lending_market.account.risk_authority,
+            lending_market.account.obligation_max_outflow,
+            lending_market.account.obligation_outflow_window,
+            20,
+            30,
+        )
+        .await
+        .unwrap();
+
+    // refreshing with the lending market stores the capped values
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(
+        obligation.account.allowed_borrow_value,
+        Decimal::from(20u64)
+    );
+    assert_eq!(
+        obligation.account.unhealthy_borrow_value,
+        Decimal::from(30u64)
+    );
+    assert_eq!(
+        obligation.account.super_unhealthy_borrow_value,
+        Decimal::from(30u64)
+    );
+
+    // without it the default global borrow values apply, which are over the market's
+    test.advance_clock_by_slots(1).await;
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_000_000)];
+    instructions.extend(
+        lending_market
+            .build_refresh_instructions(&mut test, &obligation, Some(&wsol_reserve))
+            .await,
+    );
+    *instructions.last_mut().unwrap() = refresh_obligation(
+        solend_program::id(),
+        obligation.pubkey,
+        vec![obligation.account.deposits[0].deposit_reserve],
+    );
+    instructions.push(borrow_obligation_liquidity(
+        solend_program::id(),
+        LAMPORTS_PER_SOL,
+        wsol_reserve.account.liquidity.supply_pubkey,
+        user.get_account(&wsol_mint::id()).unwrap(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        lending_market.pubkey,
+        user.keypair.pubkey(),
+        vec![obligation.account.deposits[0].deposit_reserve],
+        None,
+    ));
+
+    let res = test
+        .process_transaction(&instructions, Some(&[&user.keypair]))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            (instructions.len() - 1) as u8,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
+
 #[tokio::test]
 async fn test_fail_borrow_over_reserve_borrow_limit() {
     let (mut test, lending_market, _, wsol_reserve, user, obligation, host_fee_receiver, _) =
@@ -411,6 +548,7 @@ async fn test_fail_reserve_borrow_rate_limit_exceeded() {
             RateLimiterConfig {
                 window_duration: 10,
                 max_outflow: LAMPORTS_PER_SOL,
+                net_flow: false,
             },
             None,
         )
@@ -548,6 +686,7 @@ async fn test_borrow_max_rate_limiter() {
             RateLimiterConfig {
                 window_duration: 20,
                 max_outflow: LAMPORTS_PER_SOL,
+                net_flow: false,
             },
             None,
         )
@@ -592,9 +731,14 @@ async fn test_borrow_max_rate_limiter() {
             RateLimiterConfig {
                 window_duration: 20,
                 max_outflow: 5, // $5
+                net_flow: false,
             },
             None,
             Pubkey::new_unique(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
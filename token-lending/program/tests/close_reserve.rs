@@ -0,0 +1,210 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::setup_world;
+use crate::solend_program_test::Info;
+use crate::solend_program_test::SolendProgramTest;
+use crate::solend_program_test::User;
+use helpers::*;
+use solana_program::program_pack::Pack;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::error::LendingError;
+use solend_program::instruction::init_reserve;
+use solend_program::state::{LendingMarket, Reserve, ReserveConfig};
+use solend_program::NULL_PUBKEY;
+use spl_token::state::{Account as Token, Mint};
+
+/// A wSOL reserve whose initial deposit's collateral is held by the lending market owner
+async fn setup() -> (SolendProgramTest, Info<LendingMarket>, Info<Reserve>, User) {
+    let (mut test, lending_market, _, _, mut lending_market_owner, _) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let reserve_keypair = Keypair::new();
+    let destination_collateral_pubkey = test
+        .create_account(Token::LEN, &spl_token::id(), None)
+        .await;
+    let reserve_liquidity_supply_pubkey = test
+        .create_account(Token::LEN, &spl_token::id(), None)
+        .await;
+    let reserve_pubkey = test
+        .create_account(Reserve::LEN, &solend_program::id(), Some(&reserve_keypair))
+        .await;
+    let reserve_liquidity_fee_receiver = test
+        .create_account(Token::LEN, &spl_token::id(), None)
+        .await;
+    let reserve_collateral_mint_pubkey =
+        test.create_account(Mint::LEN, &spl_token::id(), None).await;
+    let reserve_collateral_supply_pubkey = test
+        .create_account(Token::LEN, &spl_token::id(), None)
+        .await;
+
+    let oracle = test.mints.get(&wsol_mint::id()).unwrap().unwrap();
+    test.process_transaction(
+        &[init_reserve(
+            solend_program::id(),
+            1000,
+            ReserveConfig {
+                fee_receiver: reserve_liquidity_fee_receiver,
+                ..test_reserve_config()
+            },
+            lending_market_owner.get_account(&wsol_mint::id()).unwrap(),
+            destination_collateral_pubkey,
+            reserve_pubkey,
+            wsol_mint::id(),
+            reserve_liquidity_supply_pubkey,
+            reserve_collateral_mint_pubkey,
+            reserve_collateral_supply_pubkey,
+            oracle.pyth_product_pubkey,
+            oracle.pyth_price_pubkey,
+            NULL_PUBKEY,
+            lending_market.pubkey,
+            lending_market_owner.keypair.pubkey(),
+            lending_market_owner.keypair.pubkey(),
+        )],
+        Some(&[&lending_market_owner.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let destination_collateral = test
+        .load_account::<Token>(destination_collateral_pubkey)
+        .await;
+    lending_market_owner
+        .token_accounts
+        .push(destination_collateral);
+
+    let reserve = test.load_account::<Reserve>(reserve_pubkey).await;
+    (test, lending_market, reserve, lending_market_owner)
+}
+
+async fn account_exists(test: &mut SolendProgramTest, pubkey: Pubkey) -> bool {
+    test.context
+        .banks_client
+        .get_account(pubkey)
+        .await
+        .unwrap()
+        .is_some()
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, reserve, lending_market_owner) = setup().await;
+
+    lending_market
+        .redeem(&mut test, &reserve, &lending_market_owner, 1000)
+        .await
+        .unwrap();
+
+    // the fee receiver was set up by InitReserve, so it's owned by the lending market authority
+    lending_market_owner
+        .transfer(
+            &wsol_mint::id(),
+            reserve.account.config.fee_receiver,
+            100,
+            &mut test,
+        )
+        .await;
+
+    let fee_destination = User::new_with_balances(&mut test, &[(&wsol_mint::id(), 0)]).await;
+    let destination = Keypair::new().pubkey();
+    lending_market
+        .close_reserve(
+            &mut test,
+            &lending_market_owner,
+            &reserve,
+            destination,
+            fee_destination.get_account(&wsol_mint::id()).unwrap(),
+        )
+        .await
+        .unwrap();
+
+    for pubkey in [
+        reserve.pubkey,
+        reserve.account.liquidity.supply_pubkey,
+        reserve.account.collateral.supply_pubkey,
+        reserve.account.config.fee_receiver,
+    ] {
+        assert!(!account_exists(&mut test, pubkey).await);
+    }
+
+    // the collateral mint is left as is
+    assert!(account_exists(&mut test, reserve.account.collateral.mint_pubkey).await);
+
+    let destination_liquidity = test
+        .load_account::<Token>(fee_destination.get_account(&wsol_mint::id()).unwrap())
+        .await;
+    assert_eq!(destination_liquidity.account.amount, 100);
+
+    let rent = test.context.banks_client.get_rent().await.unwrap();
+    assert_eq!(
+        test.context
+            .banks_client
+            .get_balance(destination)
+            .await
+            .unwrap(),
+        rent.minimum_balance(Reserve::LEN) + 3 * rent.minimum_balance(Token::LEN)
+    );
+}
+
+#[tokio::test]
+async fn test_fail_collateral_outstanding() {
+    let (mut test, lending_market, reserve, lending_market_owner) = setup().await;
+
+    let res = lending_market
+        .close_reserve(
+            &mut test,
+            &lending_market_owner,
+            &reserve,
+            lending_market_owner.keypair.pubkey(),
+            lending_market_owner.get_account(&wsol_mint::id()).unwrap(),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ReserveNotEmpty as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_not_lending_market_owner() {
+    let (mut test, lending_market, reserve, lending_market_owner) = setup().await;
+
+    lending_market
+        .redeem(&mut test, &reserve, &lending_market_owner, 1000)
+        .await
+        .unwrap();
+
+    let rando = User::new_with_keypair(Keypair::new());
+    let res = lending_market
+        .close_reserve(
+            &mut test,
+            &rando,
+            &reserve,
+            rando.keypair.pubkey(),
+            lending_market_owner.get_account(&wsol_mint::id()).unwrap(),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+}
@@ -0,0 +1,624 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::setup_world;
+use crate::solend_program_test::Info;
+use crate::solend_program_test::SolendProgramTest;
+use crate::solend_program_test::User;
+use bytemuck::Zeroable;
+use helpers::*;
+use solana_program::instruction::Instruction;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::state::{
+    ConfigDeltaBounds, ConfigTimelock, LendingMarket, MarketRoles, Reserve, ReserveConfig,
+};
+use solend_program::NULL_PUBKEY;
+use solend_program::{
+    error::LendingError,
+    instruction::{
+        find_config_timelock_address, find_market_roles_address,
+        find_pending_config_change_address, set_config_timelock,
+        set_lending_market_owner_and_config, update_market_roles, update_reserve_config,
+    },
+};
+
+const DELAY_SLOTS: u64 = 100;
+
+/// A lending market with a config timelock, and a risk authority that isn't the owner
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    User,
+) {
+    let (mut test, lending_market, usdc_reserve, _, lending_market_owner, _) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let risk_authority = User::new_with_keypair(Keypair::new());
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            risk_authority.keypair.pubkey(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .set_config_timelock(&mut test, &lending_market_owner, DELAY_SLOTS)
+        .await
+        .unwrap();
+
+    let lending_market = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        risk_authority,
+    )
+}
+
+fn update_usdc_reserve_config(
+    test: &SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    usdc_reserve: &Info<Reserve>,
+    signer: Pubkey,
+    config: ReserveConfig,
+) -> Instruction {
+    let oracle = test.mints.get(&usdc_mint::id()).unwrap().unwrap();
+    update_reserve_config(
+        solend_program::id(),
+        config,
+        usdc_reserve.account.rate_limiter.config,
+        usdc_reserve.pubkey,
+        lending_market.pubkey,
+        signer,
+        oracle.pyth_product_pubkey,
+        oracle.pyth_price_pubkey,
+        oracle.switchboard_feed_pubkey.unwrap_or(NULL_PUBKEY),
+    )
+}
+
+async fn account_exists(test: &mut SolendProgramTest, pubkey: Pubkey) -> bool {
+    test.context
+        .banks_client
+        .get_account(pubkey)
+        .await
+        .unwrap()
+        .is_some()
+}
+
+#[tokio::test]
+async fn test_fail_owner_changes_timelocked() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, _) = setup().await;
+
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve,
+            usdc_reserve.account.config,
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ConfigChangeTimelocked as u32)
+        )
+    );
+
+    let res = lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            lending_market.account.risk_authority,
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ConfigChangeTimelocked as u32)
+        )
+    );
+
+    // lowering the delay has to be queued too
+    let res = lending_market
+        .set_config_timelock(&mut test, &lending_market_owner, 0)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ConfigChangeTimelocked as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_config_timelock_left_out() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, _) = setup().await;
+    assert!(lending_market.account.has_config_timelock);
+
+    let mut update_reserve_config = update_usdc_reserve_config(
+        &test,
+        &lending_market,
+        &usdc_reserve,
+        lending_market_owner.keypair.pubkey(),
+        usdc_reserve.account.config,
+    );
+    update_reserve_config.accounts.pop();
+
+    let mut set_market_config = set_lending_market_owner_and_config(
+        solend_program::id(),
+        lending_market.pubkey,
+        lending_market_owner.keypair.pubkey(),
+        lending_market_owner.keypair.pubkey(),
+        lending_market.account.rate_limiter.config,
+        None,
+        lending_market.account.risk_authority,
+        0,
+        0,
+        lending_market.account.global_allowed_borrow_value,
+        lending_market.account.global_unhealthy_borrow_value,
+    );
+    set_market_config.accounts.pop();
+
+    for instruction in [update_reserve_config, set_market_config] {
+        let res = test
+            .process_transaction(&[instruction], Some(&[&lending_market_owner.keypair]))
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            res,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+            )
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_queue_and_execute_reserve_config() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, _) = setup().await;
+
+    let new_config = ReserveConfig {
+        deposit_limit: 1_000,
+        ..usdc_reserve.account.config
+    };
+    let change = update_usdc_reserve_config(
+        &test,
+        &lending_market,
+        &usdc_reserve,
+        lending_market_owner.keypair.pubkey(),
+        new_config,
+    );
+
+    lending_market
+        .queue_config_change(&mut test, &lending_market_owner, &change)
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .execute_config_change(&mut test, lending_market_owner.keypair.pubkey(), &change)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ConfigChangeNotReady as u32)
+        )
+    );
+
+    test.advance_clock_by_slots(DELAY_SLOTS).await;
+
+    lending_market
+        .execute_config_change(&mut test, lending_market_owner.keypair.pubkey(), &change)
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config, new_config);
+
+    let pending_config_change =
+        find_pending_config_change_address(solend_program::id(), usdc_reserve.pubkey).0;
+    assert!(!account_exists(&mut test, pending_config_change).await);
+}
+
+#[tokio::test]
+async fn test_queue_and_execute_market_roles() {
+    let (mut test, lending_market, _, lending_market_owner, _) = setup().await;
+
+    // creating the market roles account without assigning any role doesn't have to be queued
+    lending_market
+        .update_market_roles(&mut test, &lending_market_owner, MarketRoles::zeroed())
+        .await
+        .unwrap();
+
+    let config_admin = Keypair::new().pubkey();
+    let roles = MarketRoles {
+        config_admin,
+        ..MarketRoles::zeroed()
+    };
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .update_market_roles(&mut test, &lending_market_owner, roles)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ConfigChangeTimelocked as u32)
+        )
+    );
+
+    let change = update_market_roles(
+        solend_program::id(),
+        roles,
+        lending_market.pubkey,
+        lending_market_owner.keypair.pubkey(),
+    );
+    lending_market
+        .queue_config_change(&mut test, &lending_market_owner, &change)
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(DELAY_SLOTS).await;
+
+    lending_market
+        .execute_config_change(&mut test, lending_market_owner.keypair.pubkey(), &change)
+        .await
+        .unwrap();
+
+    let market_roles = test
+        .load_zeroable_account::<MarketRoles>(
+            find_market_roles_address(solend_program::id(), lending_market.pubkey).0,
+        )
+        .await;
+    assert_eq!(market_roles.account.config_admin, config_admin);
+    assert_eq!(market_roles.account.oracle_admin, Pubkey::default());
+}
+
+#[tokio::test]
+async fn test_queue_and_execute_lower_delay() {
+    let (mut test, lending_market, _, lending_market_owner, _) = setup().await;
+
+    let change = set_config_timelock(
+        solend_program::id(),
+        0,
+        lending_market.pubkey,
+        lending_market_owner.keypair.pubkey(),
+    );
+    lending_market
+        .queue_config_change(&mut test, &lending_market_owner, &change)
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(DELAY_SLOTS).await;
+
+    lending_market
+        .execute_config_change(&mut test, lending_market_owner.keypair.pubkey(), &change)
+        .await
+        .unwrap();
+
+    let config_timelock = test
+        .load_zeroable_account::<ConfigTimelock>(
+            find_config_timelock_address(solend_program::id(), lending_market.pubkey).0,
+        )
+        .await;
+    assert_eq!(config_timelock.account.delay_slots(), 0);
+}
+
+#[tokio::test]
+async fn test_cancel_by_risk_authority() {
+    let (mut test, lending_market, _, lending_market_owner, risk_authority) = setup().await;
+
+    let change = set_lending_market_owner_and_config(
+        solend_program::id(),
+        lending_market.pubkey,
+        lending_market_owner.keypair.pubkey(),
+        lending_market_owner.keypair.pubkey(),
+        lending_market.account.rate_limiter.config,
+        None,
+        lending_market.account.risk_authority,
+        50_000,
+        10,
+        lending_market.account.global_allowed_borrow_value,
+        lending_market.account.global_unhealthy_borrow_value,
+    );
+    lending_market
+        .queue_config_change(&mut test, &lending_market_owner, &change)
+        .await
+        .unwrap();
+
+    // only one change can be queued per lending market
+    let lower_delay = set_config_timelock(
+        solend_program::id(),
+        0,
+        lending_market.pubkey,
+        lending_market_owner.keypair.pubkey(),
+    );
+    let res = lending_market
+        .queue_config_change(&mut test, &lending_market_owner, &lower_delay)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+
+    lending_market
+        .cancel_config_change(
+            &mut test,
+            &risk_authority,
+            lending_market_owner.keypair.pubkey(),
+            lending_market.pubkey,
+        )
+        .await
+        .unwrap();
+
+    let pending_config_change =
+        find_pending_config_change_address(solend_program::id(), lending_market.pubkey).0;
+    assert!(!account_exists(&mut test, pending_config_change).await);
+
+    test.advance_clock_by_slots(DELAY_SLOTS).await;
+
+    let res = lending_market
+        .execute_config_change(&mut test, lending_market_owner.keypair.pubkey(), &change)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidAccountOwner as u32)
+        )
+    );
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market_post.account, lending_market.account);
+}
+
+#[tokio::test]
+async fn test_risk_authority_changes_stay_instant() {
+    let (mut test, lending_market, usdc_reserve, _, risk_authority) = setup().await;
+
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &risk_authority,
+            &usdc_reserve,
+            ReserveConfig {
+                deposit_limit: 1_000,
+                borrow_limit: 500,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config.deposit_limit, 1_000);
+    assert_eq!(usdc_reserve_post.account.config.borrow_limit, 500);
+}
+
+fn exceeds_bounds(instruction_index: u8) -> TransactionError {
+    TransactionError::InstructionError(
+        instruction_index,
+        InstructionError::Custom(LendingError::ConfigChangeExceedsBounds as u32),
+    )
+}
+
+#[tokio::test]
+async fn test_config_delta_bounds() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, risk_authority) =
+        setup().await;
+
+    let delta_bounds = ConfigDeltaBounds {
+        max_loan_to_value_ratio_decrease: 5,
+        max_liquidation_threshold_decrease: 5,
+        max_added_borrow_weight_bps_increase: 0,
+        interval_slots: 50,
+    };
+
+    // tightening the bounds takes effect immediately
+    lending_market
+        .set_config_delta_bounds(&mut test, &lending_market_owner, Some(delta_bounds))
+        .await
+        .unwrap();
+
+    let config_timelock = test
+        .load_zeroable_account::<ConfigTimelock>(
+            find_config_timelock_address(solend_program::id(), lending_market.pubkey).0,
+        )
+        .await;
+    assert_eq!(config_timelock.account.delta_bounds(), Some(delta_bounds));
+    assert_eq!(config_timelock.account.delay_slots(), DELAY_SLOTS);
+
+    let loan_to_value_ratio = usdc_reserve.account.config.loan_to_value_ratio;
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &risk_authority,
+            &usdc_reserve,
+            ReserveConfig {
+                loan_to_value_ratio: loan_to_value_ratio - 10,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, exceeds_bounds(1));
+
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &risk_authority,
+            &usdc_reserve,
+            ReserveConfig {
+                loan_to_value_ratio: loan_to_value_ratio - 5,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // the next bounded change has to wait for the interval
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &risk_authority,
+            &usdc_reserve_post,
+            ReserveConfig {
+                loan_to_value_ratio: loan_to_value_ratio - 10,
+                ..usdc_reserve_post.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, exceeds_bounds(1));
+
+    test.advance_clock_by_slots(50).await;
+
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &risk_authority,
+            &usdc_reserve_post,
+            ReserveConfig {
+                loan_to_value_ratio: loan_to_value_ratio - 10,
+                ..usdc_reserve_post.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // loosening them has to be queued
+    let res = lending_market
+        .set_config_delta_bounds(&mut test, &lending_market_owner, None)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ConfigChangeTimelocked as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_queued_changes_bypass_delta_bounds() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, _) = setup().await;
+
+    lending_market
+        .set_config_delta_bounds(
+            &mut test,
+            &lending_market_owner,
+            Some(ConfigDeltaBounds::default()),
+        )
+        .await
+        .unwrap();
+
+    let new_config = ReserveConfig {
+        loan_to_value_ratio: 20,
+        liquidation_threshold: 25,
+        ..usdc_reserve.account.config
+    };
+    let change = update_usdc_reserve_config(
+        &test,
+        &lending_market,
+        &usdc_reserve,
+        lending_market_owner.keypair.pubkey(),
+        new_config,
+    );
+    lending_market
+        .queue_config_change(&mut test, &lending_market_owner, &change)
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(DELAY_SLOTS).await;
+
+    lending_market
+        .execute_config_change(&mut test, lending_market_owner.keypair.pubkey(), &change)
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config, new_config);
+}
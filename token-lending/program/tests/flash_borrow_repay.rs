@@ -4,6 +4,8 @@ mod helpers;
 
 use std::collections::HashSet;
 
+use bytemuck::Zeroable;
+
 use helpers::*;
 
 use flash_loan_proxy::proxy_program;
@@ -11,8 +13,11 @@ use helpers::solend_program_test::{
     setup_world, BalanceChecker, Info, SolendProgramTest, TokenBalanceChange, User,
 };
 use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
 use solana_program::sysvar;
 use solana_program_test::*;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
 use solana_sdk::{
     instruction::InstructionError,
     pubkey::Pubkey,
@@ -20,7 +25,7 @@ use solana_sdk::{
     transaction::TransactionError,
 };
 use solend_program::instruction::LendingInstruction;
-use solend_program::state::LastUpdate;
+use solend_program::state::{FlashLoanAllowlist, LastUpdate};
 use solend_program::{
     error::LendingError,
     instruction::{flash_borrow_reserve_liquidity, flash_repay_reserve_liquidity},
@@ -152,6 +157,266 @@ async fn test_success() {
     );
 }
 
+#[tokio::test]
+async fn test_success_allowlisted_signer() {
+    let (mut test, lending_market, usdc_reserve, user, host_fee_receiver, lending_market_owner) =
+        setup(&ReserveConfig {
+            deposit_limit: u64::MAX,
+            fees: ReserveFees {
+                borrow_fee_wad: 100_000_000_000,
+                host_fee_percentage: 20,
+                flash_loan_fee_wad: 3_000_000_000_000_000,
+            },
+            ..test_reserve_config()
+        })
+        .await;
+
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let mut allowlist = FlashLoanAllowlist::zeroed();
+    allowlist.fee_discount_bps = 5_000u16.to_le_bytes();
+    allowlist.signers[0] = user.keypair.pubkey();
+    lending_market
+        .update_flash_loan_allowlist(&mut test, &lending_market_owner, allowlist)
+        .await
+        .unwrap();
+
+    let balance_checker =
+        BalanceChecker::start(&mut test, &[&usdc_reserve, &user, &host_fee_receiver]).await;
+
+    // half of the 0.3% fee is waived
+    const FLASH_LOAN_AMOUNT: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const FEE_AMOUNT: u64 = 1_500_000;
+    const HOST_FEE_AMOUNT: u64 = 300_000;
+    test.process_transaction(
+        &[
+            flash_borrow_reserve_liquidity(
+                solend_program::id(),
+                FLASH_LOAN_AMOUNT,
+                usdc_reserve.account.liquidity.supply_pubkey,
+                user.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.pubkey,
+                lending_market.pubkey,
+            ),
+            flash_repay_reserve_liquidity(
+                solend_program::id(),
+                FLASH_LOAN_AMOUNT,
+                0,
+                user.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.account.liquidity.supply_pubkey,
+                usdc_reserve.account.config.fee_receiver,
+                host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.pubkey,
+                lending_market.pubkey,
+                user.keypair.pubkey(),
+            ),
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (balance_changes, mint_supply_changes) =
+        balance_checker.find_balance_changes(&mut test).await;
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: user.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: -(FEE_AMOUNT as i128),
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.config.fee_receiver,
+            mint: usdc_mint::id(),
+            diff: (FEE_AMOUNT - HOST_FEE_AMOUNT) as i128,
+        },
+        TokenBalanceChange {
+            token_account: host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: HOST_FEE_AMOUNT as i128,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+    assert_eq!(mint_supply_changes, HashSet::new());
+
+    // once the signer is removed from the allowlist it pays the full fee
+    allowlist.signers[0] = Pubkey::default();
+    lending_market
+        .update_flash_loan_allowlist(&mut test, &lending_market_owner, allowlist)
+        .await
+        .unwrap();
+
+    let balance_checker =
+        BalanceChecker::start(&mut test, &[&usdc_reserve, &user, &host_fee_receiver]).await;
+    test.process_transaction(
+        &[
+            flash_borrow_reserve_liquidity(
+                solend_program::id(),
+                2 * FLASH_LOAN_AMOUNT,
+                usdc_reserve.account.liquidity.supply_pubkey,
+                user.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.pubkey,
+                lending_market.pubkey,
+            ),
+            flash_repay_reserve_liquidity(
+                solend_program::id(),
+                2 * FLASH_LOAN_AMOUNT,
+                0,
+                user.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.account.liquidity.supply_pubkey,
+                usdc_reserve.account.config.fee_receiver,
+                host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.pubkey,
+                lending_market.pubkey,
+                user.keypair.pubkey(),
+            ),
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: user.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: -6_000_000,
+        },
+        TokenBalanceChange {
+            token_account: usdc_reserve.account.config.fee_receiver,
+            mint: usdc_mint::id(),
+            diff: 4_800_000,
+        },
+        TokenBalanceChange {
+            token_account: host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: 1_200_000,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+}
+
+#[tokio::test]
+async fn test_success_allowlisted_program() {
+    let (mut test, lending_market, usdc_reserve, user, host_fee_receiver, lending_market_owner) =
+        setup(&ReserveConfig {
+            deposit_limit: u64::MAX,
+            fees: ReserveFees {
+                borrow_fee_wad: 100_000_000_000,
+                host_fee_percentage: 20,
+                flash_loan_fee_wad: 3_000_000_000_000_000,
+            },
+            ..test_reserve_config()
+        })
+        .await;
+
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let mut allowlist = FlashLoanAllowlist::zeroed();
+    allowlist.fee_discount_bps = 5_000u16.to_le_bytes();
+    allowlist.programs[0] = spl_token::id();
+    lending_market
+        .update_flash_loan_allowlist(&mut test, &lending_market_owner, allowlist)
+        .await
+        .unwrap();
+
+    const FLASH_LOAN_AMOUNT: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    let flash_loan_instructions = |between: Vec<Instruction>| {
+        let mut instructions = vec![flash_borrow_reserve_liquidity(
+            solend_program::id(),
+            FLASH_LOAN_AMOUNT,
+            usdc_reserve.account.liquidity.supply_pubkey,
+            user.get_account(&usdc_mint::id()).unwrap(),
+            usdc_reserve.pubkey,
+            lending_market.pubkey,
+        )];
+        instructions.extend(between);
+        instructions.push(flash_repay_reserve_liquidity(
+            solend_program::id(),
+            FLASH_LOAN_AMOUNT,
+            0,
+            user.get_account(&usdc_mint::id()).unwrap(),
+            usdc_reserve.account.liquidity.supply_pubkey,
+            usdc_reserve.account.config.fee_receiver,
+            host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+            usdc_reserve.pubkey,
+            lending_market.pubkey,
+            user.keypair.pubkey(),
+        ));
+        instructions
+    };
+    let approve_instruction = approve(
+        &spl_token::id(),
+        &user.get_account(&usdc_mint::id()).unwrap(),
+        &host_fee_receiver.keypair.pubkey(),
+        &user.keypair.pubkey(),
+        &[],
+        1,
+    )
+    .unwrap();
+
+    // every instruction between the borrow and the repay calls the allowlisted token program, so
+    // half of the 0.3% fee is waived
+    let balance_checker = BalanceChecker::start(&mut test, &[&user]).await;
+    test.process_transaction(
+        &flash_loan_instructions(vec![approve_instruction.clone()]),
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    assert_eq!(
+        balance_changes,
+        HashSet::from([TokenBalanceChange {
+            token_account: user.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: -1_500_000,
+        }])
+    );
+
+    // a call to any other program in between means the full fee is paid
+    test.advance_clock_by_slots(1).await;
+    let balance_checker = BalanceChecker::start(&mut test, &[&user]).await;
+    test.process_transaction(
+        &flash_loan_instructions(vec![
+            approve_instruction,
+            ComputeBudgetInstruction::set_compute_unit_price(1),
+        ]),
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    assert_eq!(
+        balance_changes,
+        HashSet::from([TokenBalanceChange {
+            token_account: user.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: -3_000_000,
+        }])
+    );
+}
+
 #[tokio::test]
 async fn test_fail_disable_flash_loans() {
     let (mut test, lending_market, usdc_reserve, user, host_fee_receiver, _) =
@@ -411,6 +676,249 @@ async fn test_fail_only_one_flash_ix_pair_per_tx() {
     );
 }
 
+async fn setup_two_reserves() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<Reserve>,
+    User,
+    User,
+) {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, _, user) = setup_world(
+        &ReserveConfig {
+            deposit_limit: u64::MAX,
+            borrow_limit: u64::MAX,
+            ..test_reserve_config()
+        },
+        &ReserveConfig {
+            deposit_limit: u64::MAX,
+            borrow_limit: u64::MAX,
+            ..test_reserve_config()
+        },
+    )
+    .await;
+
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 100_000_000_000)
+        .await
+        .expect("This should succeed");
+
+    let usdc_reserve = test.load_account(usdc_reserve.pubkey).await;
+    let wsol_reserve = test.load_account(wsol_reserve.pubkey).await;
+
+    let host_fee_receiver =
+        User::new_with_balances(&mut test, &[(&usdc_mint::id(), 0), (&wsol_mint::id(), 0)]).await;
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        host_fee_receiver,
+    )
+}
+
+#[tokio::test]
+async fn test_success_multiple_reserves() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, user, host_fee_receiver) =
+        setup_two_reserves().await;
+
+    const USDC_FLASH_LOAN_AMOUNT: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const WSOL_FLASH_LOAN_AMOUNT: u64 = LAMPORTS_TO_SOL / 2;
+    test.process_transaction(
+        &[
+            flash_borrow_reserve_liquidity(
+                solend_program::id(),
+                USDC_FLASH_LOAN_AMOUNT,
+                usdc_reserve.account.liquidity.supply_pubkey,
+                user.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.pubkey,
+                lending_market.pubkey,
+            ),
+            flash_borrow_reserve_liquidity(
+                solend_program::id(),
+                WSOL_FLASH_LOAN_AMOUNT,
+                wsol_reserve.account.liquidity.supply_pubkey,
+                user.get_account(&wsol_mint::id()).unwrap(),
+                wsol_reserve.pubkey,
+                lending_market.pubkey,
+            ),
+            flash_repay_reserve_liquidity(
+                solend_program::id(),
+                WSOL_FLASH_LOAN_AMOUNT,
+                1,
+                user.get_account(&wsol_mint::id()).unwrap(),
+                wsol_reserve.account.liquidity.supply_pubkey,
+                wsol_reserve.account.config.fee_receiver,
+                host_fee_receiver.get_account(&wsol_mint::id()).unwrap(),
+                wsol_reserve.pubkey,
+                lending_market.pubkey,
+                user.keypair.pubkey(),
+            ),
+            flash_repay_reserve_liquidity(
+                solend_program::id(),
+                USDC_FLASH_LOAN_AMOUNT,
+                0,
+                user.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.account.liquidity.supply_pubkey,
+                usdc_reserve.account.config.fee_receiver,
+                host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+                usdc_reserve.pubkey,
+                lending_market.pubkey,
+                user.keypair.pubkey(),
+            ),
+        ],
+        Some(&[&user.keypair]),
+    )
+    .await
+    .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account,
+        Reserve {
+            last_update: LastUpdate {
+                slot: 1000,
+                stale: true
+            },
+            ..usdc_reserve.account
+        }
+    );
+
+    let wsol_reserve_post = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    assert_eq!(
+        wsol_reserve_post.account,
+        Reserve {
+            last_update: LastUpdate {
+                slot: 1000,
+                stale: true
+            },
+            ..wsol_reserve.account
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_fail_multiple_reserves_missing_repay() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, user, host_fee_receiver) =
+        setup_two_reserves().await;
+
+    const USDC_FLASH_LOAN_AMOUNT: u64 = 1_000 * FRACTIONAL_TO_USDC;
+    const WSOL_FLASH_LOAN_AMOUNT: u64 = LAMPORTS_TO_SOL / 2;
+    let res = test
+        .process_transaction(
+            &[
+                flash_borrow_reserve_liquidity(
+                    solend_program::id(),
+                    USDC_FLASH_LOAN_AMOUNT,
+                    usdc_reserve.account.liquidity.supply_pubkey,
+                    user.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+                flash_borrow_reserve_liquidity(
+                    solend_program::id(),
+                    WSOL_FLASH_LOAN_AMOUNT,
+                    wsol_reserve.account.liquidity.supply_pubkey,
+                    user.get_account(&wsol_mint::id()).unwrap(),
+                    wsol_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+                flash_repay_reserve_liquidity(
+                    solend_program::id(),
+                    USDC_FLASH_LOAN_AMOUNT,
+                    0,
+                    user.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.account.liquidity.supply_pubkey,
+                    usdc_reserve.account.config.fee_receiver,
+                    host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.pubkey,
+                    lending_market.pubkey,
+                    user.keypair.pubkey(),
+                ),
+            ],
+            Some(&[&user.keypair]),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::NoFlashRepayFound as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_multiple_reserves_swapped_repays() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, user, host_fee_receiver) =
+        setup_two_reserves().await;
+
+    const FLASH_LOAN_AMOUNT: u64 = 1_000;
+    let res = test
+        .process_transaction(
+            &[
+                flash_borrow_reserve_liquidity(
+                    solend_program::id(),
+                    FLASH_LOAN_AMOUNT,
+                    usdc_reserve.account.liquidity.supply_pubkey,
+                    user.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+                flash_borrow_reserve_liquidity(
+                    solend_program::id(),
+                    FLASH_LOAN_AMOUNT,
+                    wsol_reserve.account.liquidity.supply_pubkey,
+                    user.get_account(&wsol_mint::id()).unwrap(),
+                    wsol_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+                // each repay points to the other reserve's borrow
+                flash_repay_reserve_liquidity(
+                    solend_program::id(),
+                    FLASH_LOAN_AMOUNT,
+                    1,
+                    user.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.account.liquidity.supply_pubkey,
+                    usdc_reserve.account.config.fee_receiver,
+                    host_fee_receiver.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.pubkey,
+                    lending_market.pubkey,
+                    user.keypair.pubkey(),
+                ),
+                flash_repay_reserve_liquidity(
+                    solend_program::id(),
+                    FLASH_LOAN_AMOUNT,
+                    0,
+                    user.get_account(&wsol_mint::id()).unwrap(),
+                    wsol_reserve.account.liquidity.supply_pubkey,
+                    wsol_reserve.account.config.fee_receiver,
+                    host_fee_receiver.get_account(&wsol_mint::id()).unwrap(),
+                    wsol_reserve.pubkey,
+                    lending_market.pubkey,
+                    user.keypair.pubkey(),
+                ),
+            ],
+            Some(&[&user.keypair]),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidFlashRepay as u32)
+        )
+    );
+}
+
 #[tokio::test]
 async fn test_fail_invalid_repay_ix() {
     let (mut test, lending_market, usdc_reserve, user, host_fee_receiver, _) =
@@ -17,7 +17,7 @@ use solana_sdk::{
     account::Account,
     signature::{Keypair, Signer},
 };
-use solend_program::state::{ReserveConfig, ReserveFees, ReserveType};
+use solend_program::state::{PegMode, ReserveConfig, ReserveFees, ReserveType};
 
 use spl_token::state::Mint;
 
@@ -59,6 +59,9 @@ pub fn reserve_config_no_fees() -> ReserveConfig {
         extra_oracle_pubkey: None,
         attributed_borrow_limit_open: u64::MAX,
         attributed_borrow_limit_close: u64::MAX,
+        underlying_oracle_pubkey: None,
+        peg_mode: PegMode::Disabled,
+        peg_max_deviation_bps: 0,
     }
 }
 
@@ -91,6 +94,9 @@ pub fn test_reserve_config() -> ReserveConfig {
         extra_oracle_pubkey: None,
         attributed_borrow_limit_open: u64::MAX,
         attributed_borrow_limit_close: u64::MAX,
+        underlying_oracle_pubkey: None,
+        peg_mode: PegMode::Disabled,
+        peg_max_deviation_bps: 0,
     }
 }
 
@@ -3,6 +3,7 @@ use oracles::switchboard_on_demand_mainnet;
 
 use oracles::pyth_mainnet;
 use oracles::pyth_pull_mainnet;
+use oracles::spl_stake_pool_mainnet;
 use oracles::switchboard_v2_mainnet;
 use pyth_solana_receiver_sdk::price_update::PriceUpdateV2;
 use solend_sdk::instruction::*;
@@ -20,10 +21,14 @@ use super::{
 use crate::helpers::*;
 use solana_program::native_token::LAMPORTS_PER_SOL;
 use solend_program::state::RateLimiterConfig;
-use solend_sdk::{instruction::update_reserve_config, NULL_PUBKEY};
+use solend_sdk::{
+    instruction::{update_reserve_config, update_reserve_config_with_liquidation_threshold_ramp},
+    NULL_PUBKEY,
+};
 
 use pyth_sdk_solana::state::PROD_ACCT_SIZE;
 use solana_program::{
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
     clock::Clock,
     instruction::Instruction,
     program_pack::{IsInitialized, Pack},
@@ -32,6 +37,7 @@ use solana_program::{
     system_instruction, sysvar,
 };
 use solana_sdk::{
+    account::AccountSharedData,
     compute_budget::ComputeBudgetInstruction,
     signature::{Keypair, Signer},
     system_instruction::create_account,
@@ -472,6 +478,78 @@ impl SolendProgramTest {
         pyth_price_pubkey
     }
 
+    /// Creates the lending program's program data account with the given upgrade authority, since
+    /// the program isn't deployed with the upgradeable loader in tests.
+    pub async fn set_upgrade_authority(&mut self, upgrade_authority: Pubkey) {
+        let data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: Some(upgrade_authority),
+        })
+        .unwrap();
+
+        let mut account = AccountSharedData::new(
+            self.rent.minimum_balance(data.len()),
+            data.len(),
+            &bpf_loader_upgradeable::id(),
+        );
+        account.set_data(data);
+
+        self.context
+            .set_account(&find_program_data_address(solend_program::id()).0, &account);
+    }
+
+    pub async fn init_protocol_config(
+        &mut self,
+        upgrade_authority: &User,
+        protocol_config: ProtocolConfig,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [init_protocol_config(
+            solend_program::id(),
+            protocol_config,
+            upgrade_authority.keypair.pubkey(),
+        )];
+
+        self.process_transaction(&instructions, Some(&[&upgrade_authority.keypair]))
+            .await
+    }
+
+    pub async fn update_protocol_config(
+        &mut self,
+        protocol_authority: &User,
+        protocol_config: ProtocolConfig,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [update_protocol_config(
+            solend_program::id(),
+            protocol_config,
+            protocol_authority.keypair.pubkey(),
+        )];
+
+        self.process_transaction(&instructions, Some(&[&protocol_authority.keypair]))
+            .await
+    }
+
+    /// Creates an spl stake pool account with the given exchange rate. Only the fields read by
+    /// the stake pool oracle are filled in.
+    pub async fn init_stake_pool(&mut self, total_lamports: u64, pool_token_supply: u64) -> Pubkey {
+        const STAKE_POOL_LEN: usize = 611;
+        let mut data = vec![0u8; STAKE_POOL_LEN];
+        data[0] = 1; // AccountType::StakePool
+        data[258..266].copy_from_slice(&total_lamports.to_le_bytes());
+        data[266..274].copy_from_slice(&pool_token_supply.to_le_bytes());
+
+        let mut account = AccountSharedData::new(
+            self.rent.minimum_balance(STAKE_POOL_LEN),
+            STAKE_POOL_LEN,
+            &spl_stake_pool_mainnet::id(),
+        );
+        account.set_data(data);
+
+        let stake_pool_pubkey = Pubkey::new_unique();
+        self.context.set_account(&stake_pool_pubkey, &account);
+
+        stake_pool_pubkey
+    }
+
     pub async fn set_price(&mut self, mint: &Pubkey, price: &PriceArgs) {
         let oracle = self.mints.get(mint).unwrap().unwrap();
         self.process_transaction(
@@ -870,6 +948,29 @@ impl Info<LendingMarket> {
         config: ReserveConfig,
         rate_limiter_config: RateLimiterConfig,
         oracle: Option<&Oracle>,
+    ) -> Result<(), BanksClientError> {
+        self.update_reserve_config_with_liquidation_threshold_ramp(
+            test,
+            signer,
+            reserve,
+            config,
+            rate_limiter_config,
+            0,
+            oracle,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update_reserve_config_with_liquidation_threshold_ramp(
+        &self,
+        test: &mut SolendProgramTest,
+        signer: &User, // lending market owner or risk authority
+        reserve: &Info<Reserve>,
+        config: ReserveConfig,
+        rate_limiter_config: RateLimiterConfig,
+        liquidation_threshold_ramp_slots: u64,
+        oracle: Option<&Oracle>,
     ) -> Result<(), BanksClientError> {
         let default_oracle = test
             .mints
@@ -879,11 +980,12 @@ impl Info<LendingMarket> {
         let oracle = oracle.unwrap_or(&default_oracle);
 
         let instructions = [
-            ComputeBudgetInstruction::set_compute_unit_limit(30_000),
-            update_reserve_config(
+            ComputeBudgetInstruction::set_compute_unit_limit(60_000),
+            update_reserve_config_with_liquidation_threshold_ramp(
                 solend_program::id(),
                 config,
                 rate_limiter_config,
+                liquidation_threshold_ramp_slots,
                 reserve.pubkey,
                 self.pubkey,
                 signer.keypair.pubkey(),
@@ -897,6 +999,30 @@ impl Info<LendingMarket> {
             .await
     }
 
+    pub async fn update_reserve_oracles(
+        &self,
+        test: &mut SolendProgramTest,
+        signer: &User, // lending market owner or oracle admin
+        reserve: &Info<Reserve>,
+        pyth_price_pubkey: Pubkey,
+        switchboard_feed_pubkey: Pubkey,
+        extra_oracle_pubkey: Option<Pubkey>,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [update_reserve_oracles(
+            solend_program::id(),
+            extra_oracle_pubkey,
+            reserve.account.config.underlying_oracle_pubkey,
+            reserve.pubkey,
+            self.pubkey,
+            signer.keypair.pubkey(),
+            pyth_price_pubkey,
+            switchboard_feed_pubkey,
+        )];
+
+        test.process_transaction(&instructions, Some(&[&signer.keypair]))
+            .await
+    }
+
     pub async fn deposit_reserve_liquidity_and_obligation_collateral(
         &self,
         test: &mut SolendProgramTest,
@@ -946,6 +1072,8 @@ impl Info<LendingMarket> {
                 reserve.account.liquidity.pyth_oracle_pubkey,
                 reserve.account.liquidity.switchboard_oracle_pubkey,
                 reserve.account.config.extra_oracle_pubkey,
+                reserve.account.config.underlying_oracle_pubkey,
+                None,
             ),
             redeem_reserve_collateral(
                 solend_program::id(),
@@ -1042,6 +1170,8 @@ impl Info<LendingMarket> {
                     reserve.account.liquidity.pyth_oracle_pubkey,
                     reserve.account.liquidity.switchboard_oracle_pubkey,
                     reserve.account.config.extra_oracle_pubkey,
+                    reserve.account.config.underlying_oracle_pubkey,
+                    None,
                 ),
             ],
             None,
@@ -1088,6 +1218,8 @@ impl Info<LendingMarket> {
                     reserve.account.liquidity.pyth_oracle_pubkey,
                     reserve.account.liquidity.switchboard_oracle_pubkey,
                     reserve.account.config.extra_oracle_pubkey,
+                    reserve.account.config.underlying_oracle_pubkey,
+                    None,
                 )
             })
             .collect();
@@ -1105,10 +1237,11 @@ impl Info<LendingMarket> {
             r
         };
 
-        instructions.push(refresh_obligation(
+        instructions.push(refresh_obligation_with_lending_market(
             solend_program::id(),
             obligation.pubkey,
             reserve_pubkeys,
+            self.pubkey,
         ));
 
         instructions
@@ -1181,6 +1314,73 @@ impl Info<LendingMarket> {
             .await
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub async fn leverage_loop(
+        &self,
+        test: &mut SolendProgramTest,
+        deposit_reserve: &Info<Reserve>,
+        borrow_reserve: &Info<Reserve>,
+        obligation: &Info<Obligation>,
+        user: &User,
+        liquidity_amount: u64,
+        target_ltv: u8,
+    ) -> Result<(), BanksClientError> {
+        let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+
+        self.refresh_reserve(test, deposit_reserve).await.unwrap();
+        let refresh_ixs = self
+            .build_refresh_instructions(test, &obligation, Some(borrow_reserve))
+            .await;
+        test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+        let mut collateral_reserves: Vec<Pubkey> = obligation
+            .account
+            .deposits
+            .iter()
+            .map(|d| d.deposit_reserve)
+            .collect();
+        if !collateral_reserves.contains(&deposit_reserve.pubkey) {
+            collateral_reserves.push(deposit_reserve.pubkey);
+        }
+        let other_borrow_reserves: Vec<Pubkey> = obligation
+            .account
+            .borrows
+            .iter()
+            .map(|b| b.borrow_reserve)
+            .filter(|reserve| *reserve != borrow_reserve.pubkey)
+            .collect();
+
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(1_000_000),
+            leverage_loop(
+                solend_program::id(),
+                liquidity_amount,
+                target_ltv,
+                user.get_account(&deposit_reserve.account.liquidity.mint_pubkey)
+                    .unwrap(),
+                user.get_account(&deposit_reserve.account.collateral.mint_pubkey)
+                    .unwrap(),
+                deposit_reserve.pubkey,
+                deposit_reserve.account.liquidity.supply_pubkey,
+                deposit_reserve.account.collateral.mint_pubkey,
+                deposit_reserve.account.collateral.supply_pubkey,
+                borrow_reserve.pubkey,
+                borrow_reserve.account.liquidity.supply_pubkey,
+                borrow_reserve.account.config.fee_receiver,
+                obligation.pubkey,
+                self.pubkey,
+                user.keypair.pubkey(),
+                user.keypair.pubkey(),
+                collateral_reserves,
+                other_borrow_reserves,
+                None,
+            ),
+        ];
+
+        test.process_transaction(&instructions, Some(&[&user.keypair]))
+            .await
+    }
+
     pub async fn repay_obligation_liquidity(
         &self,
         test: &mut SolendProgramTest,
@@ -1221,6 +1421,8 @@ impl Info<LendingMarket> {
                 reserve.account.liquidity.pyth_oracle_pubkey,
                 reserve.account.liquidity.switchboard_oracle_pubkey,
                 reserve.account.config.extra_oracle_pubkey,
+                reserve.account.config.underlying_oracle_pubkey,
+                None,
             ),
             redeem_fees(
                 solend_program::id(),
@@ -1234,6 +1436,31 @@ impl Info<LendingMarket> {
         test.process_transaction(&instructions, None).await
     }
 
+    pub async fn close_reserve(
+        &self,
+        test: &mut SolendProgramTest,
+        lending_market_owner: &User,
+        reserve: &Info<Reserve>,
+        destination: Pubkey,
+        destination_liquidity: Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [close_reserve(
+            solend_program::id(),
+            reserve.pubkey,
+            reserve.account.liquidity.supply_pubkey,
+            reserve.account.config.fee_receiver,
+            reserve.account.collateral.mint_pubkey,
+            reserve.account.collateral.supply_pubkey,
+            self.pubkey,
+            lending_market_owner.keypair.pubkey(),
+            destination,
+            destination_liquidity,
+        )];
+
+        test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
+            .await
+    }
+
     pub async fn liquidate_obligation_and_redeem_reserve_collateral(
         &self,
         test: &mut SolendProgramTest,
@@ -1277,6 +1504,76 @@ impl Info<LendingMarket> {
         .await
     }
 
+    /// Liquidates an obligation in one instruction, each leg being a (repay reserve, withdraw
+    /// reserve, liquidity amount) triple
+    pub async fn liquidate_obligation_batch(
+        &self,
+        test: &mut SolendProgramTest,
+        legs: &[(&Info<Reserve>, &Info<Reserve>, u64)],
+        obligation: &Info<Obligation>,
+        user: &User,
+    ) -> Result<(), BanksClientError> {
+        let refresh_ixs = self
+            .build_refresh_instructions(test, obligation, None)
+            .await;
+        test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+        let legs = legs
+            .iter()
+            .map(
+                |(repay_reserve, withdraw_reserve, liquidity_amount)| LiquidationBatchLeg {
+                    liquidity_amount: *liquidity_amount,
+                    source_liquidity_pubkey: user
+                        .get_account(&repay_reserve.account.liquidity.mint_pubkey)
+                        .unwrap(),
+                    destination_collateral_pubkey: user
+                        .get_account(&withdraw_reserve.account.collateral.mint_pubkey)
+                        .unwrap(),
+                    destination_liquidity_pubkey: user
+                        .get_account(&withdraw_reserve.account.liquidity.mint_pubkey)
+                        .unwrap(),
+                    repay_reserve_pubkey: repay_reserve.pubkey,
+                    repay_reserve_liquidity_supply_pubkey: repay_reserve
+                        .account
+                        .liquidity
+                        .supply_pubkey,
+                    withdraw_reserve_pubkey: withdraw_reserve.pubkey,
+                    withdraw_reserve_collateral_mint_pubkey: withdraw_reserve
+                        .account
+                        .collateral
+                        .mint_pubkey,
+                    withdraw_reserve_collateral_supply_pubkey: withdraw_reserve
+                        .account
+                        .collateral
+                        .supply_pubkey,
+                    withdraw_reserve_liquidity_supply_pubkey: withdraw_reserve
+                        .account
+                        .liquidity
+                        .supply_pubkey,
+                    withdraw_reserve_liquidity_fee_receiver_pubkey: withdraw_reserve
+                        .account
+                        .config
+                        .fee_receiver,
+                },
+            )
+            .collect();
+
+        test.process_transaction(
+            &[
+                ComputeBudgetInstruction::set_compute_unit_limit(400_000),
+                liquidate_obligation_batch(
+                    solend_program::id(),
+                    obligation.pubkey,
+                    self.pubkey,
+                    user.keypair.pubkey(),
+                    legs,
+                ),
+            ],
+            Some(&[&user.keypair]),
+        )
+        .await
+    }
+
     pub async fn liquidate_obligation(
         &self,
         test: &mut SolendProgramTest,
@@ -1403,6 +1700,10 @@ impl Info<LendingMarket> {
         config: RateLimiterConfig,
         whitelisted_liquidator: Option<Pubkey>,
         risk_authority: Pubkey,
+        obligation_max_outflow: u32,
+        obligation_outflow_window: u64,
+        global_allowed_borrow_value: u64,
+        global_unhealthy_borrow_value: u64,
     ) -> Result<(), BanksClientError> {
         let instructions = [set_lending_market_owner_and_config(
             solend_program::id(),
@@ -1412,6 +1713,10 @@ impl Info<LendingMarket> {
             config,
             whitelisted_liquidator,
             risk_authority,
+            obligation_max_outflow,
+            obligation_outflow_window,
+            global_allowed_borrow_value,
+            global_unhealthy_borrow_value,
         )];
 
         test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
@@ -1461,6 +1766,179 @@ impl Info<LendingMarket> {
         test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
             .await
     }
+
+    pub async fn update_flash_loan_allowlist(
+        &self,
+        test: &mut SolendProgramTest,
+        lending_market_owner: &User,
+        allowlist: FlashLoanAllowlist,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [update_flash_loan_allowlist(
+            solend_program::id(),
+            allowlist,
+            self.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )];
+
+        test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
+            .await
+    }
+
+    pub async fn propose_market_owner(
+        &self,
+        test: &mut SolendProgramTest,
+        lending_market_owner: &User,
+        new_owner: Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [propose_market_owner(
+            solend_program::id(),
+            new_owner,
+            self.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )];
+
+        test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
+            .await
+    }
+
+    pub async fn accept_market_owner(
+        &self,
+        test: &mut SolendProgramTest,
+        new_owner: &User,
+        proposed_by: Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [accept_market_owner(
+            solend_program::id(),
+            self.pubkey,
+            new_owner.keypair.pubkey(),
+            proposed_by,
+        )];
+
+        test.process_transaction(&instructions, Some(&[&new_owner.keypair]))
+            .await
+    }
+
+    pub async fn set_market_paused(
+        &self,
+        test: &mut SolendProgramTest,
+        signer: &User,
+        paused: bool,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [set_market_paused(
+            solend_program::id(),
+            paused,
+            self.pubkey,
+            signer.keypair.pubkey(),
+        )];
+
+        test.process_transaction(&instructions, Some(&[&signer.keypair]))
+            .await
+    }
+
+    pub async fn update_market_roles(
+        &self,
+        test: &mut SolendProgramTest,
+        lending_market_owner: &User,
+        roles: MarketRoles,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [update_market_roles(
+            solend_program::id(),
+            roles,
+            self.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )];
+
+        test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
+            .await
+    }
+
+    pub async fn set_config_timelock(
+        &self,
+        test: &mut SolendProgramTest,
+        lending_market_owner: &User,
+        delay_slots: u64,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [set_config_timelock(
+            solend_program::id(),
+            delay_slots,
+            self.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )];
+
+        test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
+            .await
+    }
+
+    pub async fn set_config_delta_bounds(
+        &self,
+        test: &mut SolendProgramTest,
+        lending_market_owner: &User,
+        delta_bounds: Option<ConfigDeltaBounds>,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [set_config_delta_bounds(
+            solend_program::id(),
+            delta_bounds,
+            self.pubkey,
+            lending_market_owner.keypair.pubkey(),
+        )];
+
+        test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
+            .await
+    }
+
+    /// Queues `change`, an instruction the lending market owner would otherwise sign
+    pub async fn queue_config_change(
+        &self,
+        test: &mut SolendProgramTest,
+        lending_market_owner: &User,
+        change: &Instruction,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+            queue_config_change(
+                solend_program::id(),
+                self.pubkey,
+                lending_market_owner.keypair.pubkey(),
+                change,
+            ),
+        ];
+
+        test.process_transaction(&instructions, Some(&[&lending_market_owner.keypair]))
+            .await
+    }
+
+    pub async fn execute_config_change(
+        &self,
+        test: &mut SolendProgramTest,
+        queued_by: Pubkey,
+        change: &Instruction,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_limit(100_000),
+            execute_config_change(solend_program::id(), self.pubkey, queued_by, change),
+        ];
+
+        test.process_transaction(&instructions, None).await
+    }
+
+    pub async fn cancel_config_change(
+        &self,
+        test: &mut SolendProgramTest,
+        signer: &User, // lending market owner or risk authority
+        queued_by: Pubkey,
+        target: Pubkey,
+    ) -> Result<(), BanksClientError> {
+        let instructions = [cancel_config_change(
+            solend_program::id(),
+            self.pubkey,
+            signer.keypair.pubkey(),
+            queued_by,
+            target,
+        )];
+
+        test.process_transaction(&instructions, Some(&[&signer.keypair]))
+            .await
+    }
 }
 
 /// Track token balance changes across transactions.
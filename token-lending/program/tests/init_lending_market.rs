@@ -12,7 +12,10 @@ use solana_sdk::signer::Signer;
 use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
 use solend_program::instruction::init_lending_market;
-use solend_program::state::{LendingMarket, RateLimiter, PROGRAM_VERSION};
+use solend_program::state::{
+    LendingMarket, RateLimiter, DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE,
+    DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE, PROGRAM_VERSION,
+};
 
 #[tokio::test]
 async fn test_success() {
@@ -38,6 +41,12 @@ async fn test_success() {
             rate_limiter: RateLimiter::default(),
             whitelisted_liquidator: None,
             risk_authority: lending_market_owner.keypair.pubkey(),
+            obligation_max_outflow: 0,
+            obligation_outflow_window: 0,
+            global_allowed_borrow_value: DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE,
+            global_unhealthy_borrow_value: DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE,
+            paused: false,
+            has_config_timelock: false,
         }
     );
 }
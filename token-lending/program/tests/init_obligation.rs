@@ -52,6 +52,10 @@ async fn test_success() {
             super_unhealthy_borrow_value: Decimal::zero(),
             borrowing_isolated_asset: false,
             closeable: false,
+            refreshed_without_lending_market: false,
+            outflow_window_start: 0,
+            outflow_prev_value: 0,
+            outflow_cur_value: 0,
         }
     );
 }
@@ -172,7 +172,8 @@ async fn test_success() {
                 accumulated_protocol_fees_wads: Decimal::zero(),
                 market_price: Decimal::from(10u64),
                 smoothed_market_price: Decimal::from(10u64),
-                extra_market_price: None
+                extra_market_price: None,
+                depegged: false
             },
             collateral: ReserveCollateral {
                 mint_pubkey: reserve_collateral_mint_pubkey,
@@ -182,6 +183,7 @@ async fn test_success() {
             config: reserve_config,
             rate_limiter: RateLimiter::new(RateLimiterConfig::default(), 1001),
             attributed_borrow_value: Decimal::zero(),
+            liquidation_threshold_ramp: None,
         }
     );
 }
@@ -0,0 +1,429 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use std::collections::HashSet;
+
+use helpers::solend_program_test::{
+    setup_world, BalanceChecker, Info, SolendProgramTest, TokenBalanceChange, User,
+};
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::{
+    instruction::InstructionError, signature::Keypair, transaction::TransactionError,
+};
+use solend_program::{
+    error::LendingError,
+    instruction::{deposit_reserve_liquidity, leverage_loop},
+    math::{Decimal, TryAdd, TryDiv},
+    state::{LendingMarket, Obligation, Reserve, ReserveConfig},
+};
+
+/// Sets up a SOL reserve to borrow from and a second reserve for the same mint, standing in for an
+/// LST reserve that accepts SOL, to deposit into.
+async fn setup(
+    deposit_reserve_config: &ReserveConfig,
+    borrow_reserve_config: &ReserveConfig,
+) -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<Reserve>,
+    Info<Reserve>,
+    Info<Obligation>,
+    User,
+) {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, lending_market_owner, _) =
+        setup_world(&test_reserve_config(), borrow_reserve_config).await;
+
+    let deposit_reserve = test
+        .init_reserve(
+            &lending_market,
+            &lending_market_owner,
+            &wsol_mint::id(),
+            deposit_reserve_config,
+            &Keypair::new(),
+            LAMPORTS_TO_SOL,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let lender = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&wsol_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+    lending_market
+        .deposit(&mut test, &wsol_reserve, &lender, 100 * LAMPORTS_TO_SOL)
+        .await
+        .unwrap();
+
+    let user = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 10 * LAMPORTS_TO_SOL),
+            (&deposit_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 20 * FRACTIONAL_TO_USDC),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    let obligation = lending_market
+        .init_obligation(&mut test, Keypair::new(), &user)
+        .await
+        .unwrap();
+
+    let wsol_reserve = test.load_account(wsol_reserve.pubkey).await;
+
+    (
+        test,
+        lending_market,
+        deposit_reserve,
+        wsol_reserve,
+        usdc_reserve,
+        obligation,
+        user,
+    )
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (mut test, lending_market, deposit_reserve, borrow_reserve, _, obligation, user) = setup(
+        &ReserveConfig {
+            loan_to_value_ratio: 75,
+            liquidation_threshold: 80,
+            max_liquidation_threshold: 85,
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    let balance_checker = BalanceChecker::start(&mut test, &[&user]).await;
+
+    // deposit 1 SOL and loop to a 50% LTV. the first borrow is capped at 75% of the 1 SOL
+    // deposit, the second tops up the remaining 0.25 SOL.
+    lending_market
+        .leverage_loop(
+            &mut test,
+            &deposit_reserve,
+            &borrow_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+            50,
+        )
+        .await
+        .unwrap();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    let expected_balance_changes = HashSet::from([TokenBalanceChange {
+        token_account: user.get_account(&wsol_mint::id()).unwrap(),
+        mint: wsol_mint::id(),
+        diff: -(LAMPORTS_TO_SOL as i128),
+    }]);
+    assert_eq!(balance_changes, expected_balance_changes);
+
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+
+    assert_eq!(obligation_post.account.deposits.len(), 1);
+    assert_eq!(
+        obligation_post.account.deposits[0].deposit_reserve,
+        deposit_reserve.pubkey
+    );
+    assert_eq!(
+        obligation_post.account.deposits[0].deposited_amount,
+        2 * LAMPORTS_TO_SOL
+    );
+
+    assert_eq!(obligation_post.account.borrows.len(), 1);
+    assert_eq!(
+        obligation_post.account.borrows[0].borrow_reserve,
+        borrow_reserve.pubkey
+    );
+    assert_eq!(
+        obligation_post.account.borrows[0].borrowed_amount_wads,
+        Decimal::from(LAMPORTS_TO_SOL)
+    );
+
+    assert_eq!(
+        obligation_post.account.deposited_value,
+        Decimal::from(20u64)
+    );
+    assert_eq!(obligation_post.account.borrowed_value, Decimal::from(10u64));
+}
+
+#[tokio::test]
+async fn test_success_borrow_weight() {
+    let (mut test, lending_market, deposit_reserve, borrow_reserve, _, obligation, user) = setup(
+        &test_reserve_config(),
+        &ReserveConfig {
+            added_borrow_weight_bps: 10_000,
+            ..test_reserve_config()
+        },
+    )
+    .await;
+
+    // with a borrow weight of 2, every 0.1 SOL borrowed counts as $2 of borrowed value, so a 40%
+    // LTV on 1.25 SOL of deposits ($12.5) is reached after borrowing 0.25 SOL.
+    lending_market
+        .leverage_loop(
+            &mut test,
+            &deposit_reserve,
+            &borrow_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+            40,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+
+    assert_eq!(
+        obligation_post.account.deposits[0].deposited_amount,
+        5 * LAMPORTS_TO_SOL / 4
+    );
+    assert_eq!(
+        obligation_post.account.borrows[0].borrowed_amount_wads,
+        Decimal::from(LAMPORTS_TO_SOL / 4)
+    );
+    assert_eq!(
+        obligation_post.account.deposited_value,
+        Decimal::from(25u64).try_div(2u64).unwrap()
+    );
+    assert_eq!(obligation_post.account.borrowed_value, Decimal::from(5u64));
+    assert!(
+        obligation_post.account.borrowed_value_upper_bound
+            <= obligation_post.account.allowed_borrow_value
+    );
+}
+
+#[tokio::test]
+async fn test_success_capped_by_allowed_borrow_value() {
+    let (mut test, lending_market, deposit_reserve, borrow_reserve, _, obligation, user) = setup(
+        &ReserveConfig {
+            loan_to_value_ratio: 20,
+            ..test_reserve_config()
+        },
+        &ReserveConfig {
+            added_borrow_weight_bps: 10_000,
+            ..test_reserve_config()
+        },
+    )
+    .await;
+
+    // a 40% target is out of reach with a 20% LTV reserve, so every round borrows up to the
+    // allowed borrow value and the loop settles just below it.
+    lending_market
+        .leverage_loop(
+            &mut test,
+            &deposit_reserve,
+            &borrow_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+            40,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+
+    assert!(
+        obligation_post.account.borrowed_value_upper_bound
+            <= obligation_post.account.allowed_borrow_value
+    );
+    // more than the first round's $2 was borrowed
+    assert!(obligation_post.account.borrowed_value > Decimal::from(2u64));
+    assert!(
+        obligation_post.account.loan_to_value().unwrap()
+            < Decimal::from_percent(20)
+                .try_add(Decimal::from_percent(1))
+                .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_fail_target_ltv_too_high() {
+    let (mut test, lending_market, deposit_reserve, borrow_reserve, _, obligation, user) =
+        setup(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = lending_market
+        .leverage_loop(
+            &mut test,
+            &deposit_reserve,
+            &borrow_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+            100,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidAmount as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_mismatched_mints() {
+    let (mut test, lending_market, deposit_reserve, _, usdc_reserve, obligation, user) =
+        setup(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = lending_market
+        .leverage_loop(
+            &mut test,
+            &deposit_reserve,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+            50,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_same_reserve() {
+    let (mut test, lending_market, deposit_reserve, _, _, obligation, user) =
+        setup(&test_reserve_config(), &test_reserve_config()).await;
+
+    let res = lending_market
+        .leverage_loop(
+            &mut test,
+            &deposit_reserve,
+            &deposit_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_TO_SOL,
+            50,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_other_deposit_reserve_stale() {
+    let (mut test, lending_market, deposit_reserve, borrow_reserve, usdc_reserve, obligation, user) =
+        setup(&test_reserve_config(), &test_reserve_config()).await;
+
+    lending_market
+        .deposit_reserve_liquidity_and_obligation_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            10 * FRACTIONAL_TO_USDC,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .refresh_reserve(&mut test, &borrow_reserve)
+        .await
+        .unwrap();
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_000_000)];
+    instructions.extend(
+        lending_market
+            .build_refresh_instructions(&mut test, &obligation, Some(&deposit_reserve))
+            .await,
+    );
+    // depositing into the USDC reserve leaves it stale for the rest of the transaction
+    instructions.push(deposit_reserve_liquidity(
+        solend_program::id(),
+        FRACTIONAL_TO_USDC,
+        user.get_account(&usdc_mint::id()).unwrap(),
+        user.get_account(&usdc_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        usdc_reserve.pubkey,
+        usdc_reserve.account.liquidity.supply_pubkey,
+        usdc_reserve.account.collateral.mint_pubkey,
+        lending_market.pubkey,
+        user.keypair.pubkey(),
+    ));
+    let leverage_loop_index = instructions.len() as u8;
+    instructions.push(leverage_loop(
+        solend_program::id(),
+        LAMPORTS_TO_SOL,
+        20,
+        user.get_account(&wsol_mint::id()).unwrap(),
+        user.get_account(&deposit_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        deposit_reserve.pubkey,
+        deposit_reserve.account.liquidity.supply_pubkey,
+        deposit_reserve.account.collateral.mint_pubkey,
+        deposit_reserve.account.collateral.supply_pubkey,
+        borrow_reserve.pubkey,
+        borrow_reserve.account.liquidity.supply_pubkey,
+        borrow_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        lending_market.pubkey,
+        user.keypair.pubkey(),
+        user.keypair.pubkey(),
+        vec![usdc_reserve.pubkey, deposit_reserve.pubkey],
+        vec![],
+        None,
+    ));
+
+    let res = test
+        .process_transaction(&instructions, Some(&[&user.keypair]))
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            leverage_loop_index,
+            InstructionError::Custom(LendingError::ReserveStale as u32)
+        )
+    );
+}
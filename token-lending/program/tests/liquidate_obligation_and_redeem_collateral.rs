@@ -4,19 +4,26 @@ use crate::solend_program_test::custom_scenario;
 use crate::solend_program_test::MintSupplyChange;
 use crate::solend_program_test::ObligationArgs;
 use crate::solend_program_test::ReserveArgs;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
 use solana_sdk::instruction::InstructionError;
 use solana_sdk::native_token::LAMPORTS_PER_SOL;
 use solana_sdk::signer::Signer;
 use solana_sdk::transaction::TransactionError;
 use solend_program::error::LendingError;
+use solend_program::instruction::{
+    liquidate_obligation_and_redeem_reserve_collateral, refresh_obligation,
+};
 use solend_program::math::TrySub;
 use solend_program::state::LastUpdate;
 use solend_program::state::ObligationCollateral;
 use solend_program::state::ObligationLiquidity;
 use solend_program::state::ReserveConfig;
 use solend_program::state::ReserveFees;
+use solend_sdk::state::preview_liquidation;
 use solend_sdk::state::Bonus;
+use solend_sdk::state::LiquidationPreview;
 use solend_sdk::NULL_PUBKEY;
+use spl_token::state::Account as Token;
 mod helpers;
 
 use crate::solend_program_test::scenario_1;
@@ -24,6 +31,7 @@ use crate::solend_program_test::BalanceChecker;
 use crate::solend_program_test::PriceArgs;
 use crate::solend_program_test::TokenBalanceChange;
 use crate::solend_program_test::User;
+use crate::solend_program_test::{Info, SolendProgramTest};
 use helpers::*;
 use solana_program_test::*;
 use solana_sdk::signature::Keypair;
@@ -35,7 +43,7 @@ use solend_program::state::ReserveCollateral;
 use solend_program::state::ReserveLiquidity;
 use solend_program::state::LIQUIDATION_CLOSE_FACTOR;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[tokio::test]
 async fn test_success_new() {
@@ -288,6 +296,10 @@ async fn test_whitelisting_liquidator() {
             lending_market.account.rate_limiter.config,
             Some(whitelisted_liquidator.keypair.pubkey()),
             NULL_PUBKEY,
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
@@ -340,6 +352,137 @@ async fn test_whitelisting_liquidator() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_fail_refreshed_without_lending_market_over_default_caps() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        _user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            lending_market.account.risk_authority,
+            0,
+            0,
+            90_000_000,
+            100_000_000,
+        )
+        .await
+        .unwrap();
+
+    // the 100k USDC deposited are worth $200M, so the unhealthy borrow value is $110M before the
+    // market's $100M cap. the 10 SOL borrowed are worth $80M, which keeps the obligation healthy.
+    test.set_price(
+        &usdc_mint::id(),
+        &PriceArgs {
+            price: 2000,
+            conf: 0,
+            expo: 0,
+            ema_price: 2000,
+            ema_conf: 0,
+        },
+    )
+    .await;
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 8_000_000,
+            conf: 0,
+            expo: 0,
+            ema_price: 8_000_000,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let err = lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ObligationHealthy as u32)
+        )
+    );
+
+    // refreshed without the lending market, the unhealthy borrow value is capped at the default
+    // $70M instead, under the $80M borrowed
+    test.advance_clock_by_slots(1).await;
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_000_000)];
+    instructions.extend(
+        lending_market
+            .build_refresh_instructions(&mut test, &obligation, None)
+            .await,
+    );
+    *instructions.last_mut().unwrap() = refresh_obligation(
+        solend_program::id(),
+        obligation.pubkey,
+        vec![usdc_reserve.pubkey, wsol_reserve.pubkey],
+    );
+    instructions.push(liquidate_obligation_and_redeem_reserve_collateral(
+        solend_program::id(),
+        u64::MAX,
+        liquidator.get_account(&wsol_mint::id()).unwrap(),
+        liquidator
+            .get_account(&usdc_reserve.account.collateral.mint_pubkey)
+            .unwrap(),
+        liquidator.get_account(&usdc_mint::id()).unwrap(),
+        wsol_reserve.pubkey,
+        wsol_reserve.account.liquidity.supply_pubkey,
+        usdc_reserve.pubkey,
+        usdc_reserve.account.collateral.mint_pubkey,
+        usdc_reserve.account.collateral.supply_pubkey,
+        usdc_reserve.account.liquidity.supply_pubkey,
+        usdc_reserve.account.config.fee_receiver,
+        obligation.pubkey,
+        lending_market.pubkey,
+        liquidator.keypair.pubkey(),
+    ));
+
+    let err = test
+        .process_transaction(&instructions, Some(&[&liquidator.keypair]))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            (instructions.len() - 1) as u8,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
+
 #[tokio::test]
 async fn test_success_insufficient_liquidity() {
     let (mut test, lending_market, usdc_reserve, wsol_reserve, user, obligation, _) = scenario_1(
@@ -820,3 +963,437 @@ async fn test_liquidate_closeable_obligation() {
         }])
     );
 }
+
+/// Refreshes the obligation, previews liquidating it for as much as possible and checks the
+/// liquidation does exactly what the preview says
+async fn liquidate_matching_preview(
+    test: &mut SolendProgramTest,
+    lending_market: &Info<LendingMarket>,
+    repay_reserve: &Info<Reserve>,
+    withdraw_reserve: &Info<Reserve>,
+    obligation: &Info<Obligation>,
+    liquidator: &User,
+) -> LiquidationPreview {
+    // refresh in this slot so the preview sees the same accounts the liquidation does. the
+    // compute budget instruction keeps this apart from the liquidation helper's own refresh.
+    let mut refresh_ixs = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_000_000)];
+    refresh_ixs.extend(
+        lending_market
+            .build_refresh_instructions(test, obligation, None)
+            .await,
+    );
+    test.process_transaction(&refresh_ixs, None).await.unwrap();
+
+    let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+    let repay_reserve = test.load_account::<Reserve>(repay_reserve.pubkey).await;
+    let withdraw_reserve = test.load_account::<Reserve>(withdraw_reserve.pubkey).await;
+    let preview = preview_liquidation(
+        &obligation.account,
+        &repay_reserve.pubkey,
+        &repay_reserve.account,
+        &withdraw_reserve.pubkey,
+        &withdraw_reserve.account,
+        u64::MAX,
+    )
+    .unwrap();
+
+    let balance_checker = BalanceChecker::start(test, &[liquidator]).await;
+    let fee_receiver_balance = test
+        .load_account::<Token>(withdraw_reserve.account.config.fee_receiver)
+        .await
+        .account
+        .amount;
+
+    lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            test,
+            &repay_reserve,
+            &withdraw_reserve,
+            &obligation,
+            liquidator,
+            u64::MAX,
+        )
+        .await
+        .unwrap();
+
+    // the repay and withdraw liquidity accounts are the same one when both reserves are
+    let mut expected_diffs = HashMap::new();
+    for (mint, diff) in [
+        (
+            repay_reserve.account.liquidity.mint_pubkey,
+            -(preview.repay_amount as i128),
+        ),
+        (
+            withdraw_reserve.account.liquidity.mint_pubkey,
+            (preview.redeemed_liquidity_amount - preview.protocol_fee) as i128,
+        ),
+        (
+            withdraw_reserve.account.collateral.mint_pubkey,
+            (preview.withdraw_amount - preview.redeemed_collateral_amount) as i128,
+        ),
+    ] {
+        *expected_diffs.entry(mint).or_insert(0) += diff;
+    }
+    let expected_balance_changes: HashSet<TokenBalanceChange> = expected_diffs
+        .into_iter()
+        .filter(|(_, diff)| *diff != 0)
+        .map(|(mint, diff)| TokenBalanceChange {
+            token_account: liquidator.get_account(&mint).unwrap(),
+            mint,
+            diff,
+        })
+        .collect();
+
+    let (balance_changes, _) = balance_checker.find_balance_changes(test).await;
+    assert_eq!(balance_changes, expected_balance_changes);
+    assert_eq!(
+        test.load_account::<Token>(withdraw_reserve.account.config.fee_receiver)
+            .await
+            .account
+            .amount
+            - fee_receiver_balance,
+        preview.protocol_fee
+    );
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let (collateral, _) = obligation
+        .account
+        .find_collateral_in_deposits(withdraw_reserve.pubkey)
+        .unwrap();
+    let (collateral_post, _) = obligation_post
+        .account
+        .find_collateral_in_deposits(withdraw_reserve.pubkey)
+        .unwrap();
+    assert_eq!(
+        collateral_post.deposited_amount,
+        collateral.deposited_amount - preview.withdraw_amount
+    );
+    let (liquidity, _) = obligation
+        .account
+        .find_liquidity_in_borrows(repay_reserve.pubkey)
+        .unwrap();
+    let (liquidity_post, _) = obligation_post
+        .account
+        .find_liquidity_in_borrows(repay_reserve.pubkey)
+        .unwrap();
+    assert_eq!(
+        liquidity_post.borrowed_amount_wads,
+        liquidity
+            .borrowed_amount_wads
+            .try_sub(preview.settle_amount)
+            .unwrap()
+    );
+
+    preview
+}
+
+#[tokio::test]
+async fn test_matches_preview() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, _user, obligation, _) = scenario_1(
+        &ReserveConfig {
+            protocol_liquidation_fee: 30,
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5600,
+            conf: 0,
+            expo: 0,
+            ema_price: 5600,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let preview = liquidate_matching_preview(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &usdc_reserve,
+        &obligation,
+        &liquidator,
+    )
+    .await;
+
+    assert_eq!(preview.redeemed_collateral_amount, preview.withdraw_amount);
+    assert!(preview.protocol_fee > 0);
+    assert!(preview.profit() > Decimal::zero());
+}
+
+#[tokio::test]
+async fn test_matches_preview_same_reserve() {
+    let (mut test, lending_market, reserves, obligations, _users, lending_market_owner) =
+        custom_scenario(
+            &[ReserveArgs {
+                mint: usdc_mint::id(),
+                config: ReserveConfig {
+                    optimal_borrow_rate: 0,
+                    max_borrow_rate: 0,
+                    ..test_reserve_config()
+                },
+                liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                price: PriceArgs {
+                    price: 10,
+                    conf: 0,
+                    expo: -1,
+                    ema_price: 10,
+                    ema_conf: 1,
+                },
+            }],
+            &[ObligationArgs {
+                deposits: vec![(usdc_mint::id(), 100 * FRACTIONAL_TO_USDC)],
+                borrows: vec![(usdc_mint::id(), 10 * FRACTIONAL_TO_USDC)],
+            }],
+        )
+        .await;
+    let usdc_reserve = &reserves[0];
+
+    // a borrow weight of 11 makes the $10 borrow worth more than the $55 unhealthy borrow value
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            usdc_reserve,
+            ReserveConfig {
+                added_borrow_weight_bps: 100_000,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&usdc_mint::id(), 100 * FRACTIONAL_TO_USDC),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    let preview = liquidate_matching_preview(
+        &mut test,
+        &lending_market,
+        usdc_reserve,
+        usdc_reserve,
+        &obligations[0],
+        &liquidator,
+    )
+    .await;
+
+    assert!(preview.repay_amount > 0);
+    assert_eq!(preview.redeemed_collateral_amount, preview.withdraw_amount);
+}
+
+#[tokio::test]
+async fn test_matches_preview_partially_redeemable() {
+    let (mut test, lending_market, usdc_reserve, wsol_reserve, _user, obligation, _) = scenario_1(
+        &ReserveConfig {
+            optimal_borrow_rate: 0,
+            max_borrow_rate: 0,
+            fees: ReserveFees::default(),
+            ..test_reserve_config()
+        },
+        &test_reserve_config(),
+    )
+    .await;
+
+    // someone borrows most of the USDC so only part of the seized cUSDC can be redeemed
+    {
+        let usdc_borrower = User::new_with_balances(
+            &mut test,
+            &[
+                (&usdc_mint::id(), 0),
+                (&wsol_mint::id(), 20_000 * LAMPORTS_TO_SOL),
+                (&wsol_reserve.account.collateral.mint_pubkey, 0),
+            ],
+        )
+        .await;
+
+        let obligation = lending_market
+            .init_obligation(&mut test, Keypair::new(), &usdc_borrower)
+            .await
+            .unwrap();
+
+        lending_market
+            .deposit_reserve_liquidity_and_obligation_collateral(
+                &mut test,
+                &wsol_reserve,
+                &obligation,
+                &usdc_borrower,
+                20_000 * LAMPORTS_TO_SOL,
+            )
+            .await
+            .unwrap();
+
+        let obligation = test.load_account::<Obligation>(obligation.pubkey).await;
+        lending_market
+            .borrow_obligation_liquidity(
+                &mut test,
+                &usdc_reserve,
+                &obligation,
+                &usdc_borrower,
+                usdc_borrower.get_account(&usdc_mint::id()),
+                u64::MAX,
+            )
+            .await
+            .unwrap()
+    }
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 5500,
+            conf: 0,
+            expo: 0,
+            ema_price: 5500,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let preview = liquidate_matching_preview(
+        &mut test,
+        &lending_market,
+        &wsol_reserve,
+        &usdc_reserve,
+        &obligation,
+        &liquidator,
+    )
+    .await;
+
+    assert!(preview.redeemed_collateral_amount > 0);
+    assert!(preview.redeemed_collateral_amount < preview.withdraw_amount);
+}
+
+#[tokio::test]
+async fn test_matches_preview_closeable() {
+    let (mut test, lending_market, reserves, obligations, _users, lending_market_owner) =
+        custom_scenario(
+            &[
+                ReserveArgs {
+                    mint: usdc_mint::id(),
+                    config: ReserveConfig {
+                        liquidation_bonus: 5,
+                        max_liquidation_bonus: 10,
+                        protocol_liquidation_fee: 1,
+                        ..reserve_config_no_fees()
+                    },
+                    liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                    price: PriceArgs {
+                        price: 10,
+                        conf: 0,
+                        expo: -1,
+                        ema_price: 10,
+                        ema_conf: 1,
+                    },
+                },
+                ReserveArgs {
+                    mint: wsol_mint::id(),
+                    config: reserve_config_no_fees(),
+                    liquidity_amount: LAMPORTS_PER_SOL,
+                    price: PriceArgs {
+                        price: 10,
+                        conf: 0,
+                        expo: 0,
+                        ema_price: 10,
+                        ema_conf: 0,
+                    },
+                },
+            ],
+            &[ObligationArgs {
+                deposits: vec![(usdc_mint::id(), 20 * FRACTIONAL_TO_USDC)],
+                borrows: vec![(wsol_mint::id(), LAMPORTS_PER_SOL)],
+            }],
+        )
+        .await;
+
+    let usdc_reserve = reserves
+        .iter()
+        .find(|r| r.account.liquidity.mint_pubkey == usdc_mint::id())
+        .unwrap();
+    let wsol_reserve = reserves
+        .iter()
+        .find(|r| r.account.liquidity.mint_pubkey == wsol_mint::id())
+        .unwrap();
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdc_mint::id(), 0),
+        ],
+    )
+    .await;
+
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            usdc_reserve,
+            ReserveConfig {
+                attributed_borrow_limit_open: 1,
+                attributed_borrow_limit_close: 1,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .set_obligation_closeability_status(
+            &mut test,
+            &obligations[0],
+            usdc_reserve,
+            &lending_market_owner,
+            true,
+        )
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+
+    // the obligation is healthy, it's only liquidatable because it's closeable
+    let preview = liquidate_matching_preview(
+        &mut test,
+        &lending_market,
+        wsol_reserve,
+        usdc_reserve,
+        &obligations[0],
+        &liquidator,
+    )
+    .await;
+
+    assert_eq!(preview.repay_amount, LAMPORTS_PER_SOL / 5);
+    assert_eq!(preview.protocol_fee, 1);
+}
@@ -0,0 +1,377 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use std::collections::HashSet;
+
+use helpers::solend_program_test::{
+    custom_scenario, BalanceChecker, Info, ObligationArgs, PriceArgs, ReserveArgs,
+    SolendProgramTest, TokenBalanceChange, User,
+};
+use helpers::*;
+use solana_program_test::*;
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+use solend_program::{
+    error::LendingError,
+    math::Decimal,
+    state::{LendingMarket, Obligation, Reserve, ReserveConfig},
+};
+
+fn price(price: i64) -> PriceArgs {
+    PriceArgs {
+        price,
+        conf: 0,
+        expo: 0,
+        ema_price: price,
+        ema_conf: 0,
+    }
+}
+
+/// An obligation with 100 USDC and 100 USDT deposited and 9 SOL borrowed at $10, plus a
+/// liquidator holding 100 SOL.
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    Info<Reserve>,
+    Info<Reserve>,
+    Info<Obligation>,
+    User,
+) {
+    let reserve_config = ReserveConfig {
+        optimal_borrow_rate: 0,
+        max_borrow_rate: 0,
+        ..test_reserve_config()
+    };
+    let (mut test, lending_market, reserves, obligations, _, _) = custom_scenario(
+        &[
+            ReserveArgs {
+                mint: usdc_mint::id(),
+                config: reserve_config,
+                liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+                price: price(1),
+            },
+            ReserveArgs {
+                mint: usdt_mint::id(),
+                config: reserve_config,
+                liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+                price: price(1),
+            },
+            ReserveArgs {
+                mint: wsol_mint::id(),
+                config: reserve_config,
+                liquidity_amount: 100 * LAMPORTS_TO_SOL,
+                price: price(10),
+            },
+        ],
+        &[ObligationArgs {
+            deposits: vec![
+                (usdc_mint::id(), 100 * FRACTIONAL_TO_USDC),
+                (usdt_mint::id(), 100 * FRACTIONAL_TO_USDC),
+            ],
+            borrows: vec![(wsol_mint::id(), 9 * LAMPORTS_TO_SOL)],
+        }],
+    )
+    .await;
+
+    let find_reserve = |mint| {
+        reserves
+            .iter()
+            .find(|r| r.account.liquidity.mint_pubkey == mint)
+            .unwrap()
+            .clone()
+    };
+    let usdc_reserve = find_reserve(usdc_mint::id());
+    let usdt_reserve = find_reserve(usdt_mint::id());
+    let wsol_reserve = find_reserve(wsol_mint::id());
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdc_mint::id(), 0),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+            (&usdt_mint::id(), 0),
+            (&usdt_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        usdt_reserve,
+        wsol_reserve,
+        obligations[0].clone(),
+        liquidator,
+    )
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        usdt_reserve,
+        wsol_reserve,
+        obligation,
+        liquidator,
+    ) = setup().await;
+
+    // SOL doubles to $20, so $180 is borrowed against $200 of deposits and the obligation is
+    // unhealthy. the close factor allows 20% of $180 = $36 (1.8 SOL) to be repaid in total.
+    test.set_price(&wsol_mint::id(), &price(20)).await;
+
+    let balance_checker = BalanceChecker::start(&mut test, &[&liquidator]).await;
+
+    // the first leg repays 1 SOL for USDC, the second one is capped at the 0.8 SOL left under the
+    // close factor rather than 20% of the $160 still borrowed after the first leg.
+    lending_market
+        .liquidate_obligation_batch(
+            &mut test,
+            &[
+                (&wsol_reserve, &usdc_reserve, LAMPORTS_TO_SOL),
+                (&wsol_reserve, &usdt_reserve, u64::MAX),
+            ],
+            &obligation,
+            &liquidator,
+        )
+        .await
+        .unwrap();
+
+    // every leg seizes the repaid value plus a 5% bonus, 1% of which goes to the protocol:
+    // - $20 repaid for 21 USDC, 0.2 of it as the protocol fee
+    // - $16 repaid for 16.8 USDT, 0.16 of it as the protocol fee
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: liquidator.get_account(&wsol_mint::id()).unwrap(),
+            mint: wsol_mint::id(),
+            diff: -((18 * LAMPORTS_TO_SOL / 10) as i128),
+        },
+        TokenBalanceChange {
+            token_account: liquidator.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: 20_800_000,
+        },
+        TokenBalanceChange {
+            token_account: liquidator.get_account(&usdt_mint::id()).unwrap(),
+            mint: usdt_mint::id(),
+            diff: 16_640_000,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert!(obligation_post.account.last_update.stale);
+    assert_eq!(
+        obligation_post.account.borrows[0].borrowed_amount_wads,
+        Decimal::from(72 * LAMPORTS_TO_SOL / 10)
+    );
+    assert_eq!(
+        obligation_post.account.borrowed_value,
+        Decimal::from(144u64)
+    );
+
+    let deposited_amount = |reserve: &Info<Reserve>| {
+        obligation_post
+            .account
+            .deposits
+            .iter()
+            .find(|d| d.deposit_reserve == reserve.pubkey)
+            .unwrap()
+            .deposited_amount
+    };
+    assert_eq!(deposited_amount(&usdc_reserve), 79 * FRACTIONAL_TO_USDC);
+    assert_eq!(deposited_amount(&usdt_reserve), 83_200_000);
+}
+
+#[tokio::test]
+async fn test_success_skips_legs_after_close_factor() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        usdt_reserve,
+        wsol_reserve,
+        obligation,
+        liquidator,
+    ) = setup().await;
+
+    test.set_price(&wsol_mint::id(), &price(20)).await;
+
+    let balance_checker = BalanceChecker::start(&mut test, &[&liquidator]).await;
+
+    // the first leg uses up the whole close factor, so the second one is skipped
+    lending_market
+        .liquidate_obligation_batch(
+            &mut test,
+            &[
+                (&wsol_reserve, &usdc_reserve, u64::MAX),
+                (&wsol_reserve, &usdt_reserve, u64::MAX),
+            ],
+            &obligation,
+            &liquidator,
+        )
+        .await
+        .unwrap();
+
+    // $36 repaid for 37.8 USDC, 0.36 of it as the protocol fee
+    let (balance_changes, _) = balance_checker.find_balance_changes(&mut test).await;
+    let expected_balance_changes = HashSet::from([
+        TokenBalanceChange {
+            token_account: liquidator.get_account(&wsol_mint::id()).unwrap(),
+            mint: wsol_mint::id(),
+            diff: -((18 * LAMPORTS_TO_SOL / 10) as i128),
+        },
+        TokenBalanceChange {
+            token_account: liquidator.get_account(&usdc_mint::id()).unwrap(),
+            mint: usdc_mint::id(),
+            diff: 37_440_000,
+        },
+    ]);
+    assert_eq!(balance_changes, expected_balance_changes);
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(
+        obligation_post.account.borrows[0].borrowed_amount_wads,
+        Decimal::from(72 * LAMPORTS_TO_SOL / 10)
+    );
+}
+
+#[tokio::test]
+async fn test_fail_healthy() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        usdt_reserve,
+        wsol_reserve,
+        obligation,
+        liquidator,
+    ) = setup().await;
+
+    let res = lending_market
+        .liquidate_obligation_batch(
+            &mut test,
+            &[
+                (&wsol_reserve, &usdc_reserve, LAMPORTS_TO_SOL),
+                (&wsol_reserve, &usdt_reserve, LAMPORTS_TO_SOL),
+            ],
+            &obligation,
+            &liquidator,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ObligationHealthy as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_success_borrows_on_different_reserves() {
+    let reserve_config = ReserveConfig {
+        optimal_borrow_rate: 0,
+        max_borrow_rate: 0,
+        ..test_reserve_config()
+    };
+    let (mut test, lending_market, reserves, obligations, _, _) = custom_scenario(
+        &[
+            ReserveArgs {
+                mint: usdc_mint::id(),
+                config: reserve_config,
+                liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+                price: price(1),
+            },
+            ReserveArgs {
+                mint: usdt_mint::id(),
+                config: reserve_config,
+                liquidity_amount: 1_000 * FRACTIONAL_TO_USDC,
+                price: price(1),
+            },
+            ReserveArgs {
+                mint: wsol_mint::id(),
+                config: reserve_config,
+                liquidity_amount: 100 * LAMPORTS_TO_SOL,
+                price: price(10),
+            },
+        ],
+        &[ObligationArgs {
+            deposits: vec![(usdc_mint::id(), 200 * FRACTIONAL_TO_USDC)],
+            borrows: vec![
+                (wsol_mint::id(), 45 * LAMPORTS_TO_SOL / 10),
+                (usdt_mint::id(), 45 * FRACTIONAL_TO_USDC),
+            ],
+        }],
+    )
+    .await;
+
+    let find_reserve = |mint| {
+        reserves
+            .iter()
+            .find(|r| r.account.liquidity.mint_pubkey == mint)
+            .unwrap()
+            .clone()
+    };
+    let usdc_reserve = find_reserve(usdc_mint::id());
+    let usdt_reserve = find_reserve(usdt_mint::id());
+    let wsol_reserve = find_reserve(wsol_mint::id());
+    let obligation = obligations[0].clone();
+
+    let liquidator = User::new_with_balances(
+        &mut test,
+        &[
+            (&wsol_mint::id(), 100 * LAMPORTS_TO_SOL),
+            (&usdt_mint::id(), 100 * FRACTIONAL_TO_USDC),
+            (&usdc_mint::id(), 0),
+            (&usdc_reserve.account.collateral.mint_pubkey, 0),
+        ],
+    )
+    .await;
+
+    // SOL doubles to $20, so $135 is borrowed against $200 of deposits and the obligation is
+    // unhealthy. the close factor allows 20% of $135 = $27 to be repaid in total.
+    test.set_price(&wsol_mint::id(), &price(20)).await;
+
+    // the first leg repays the USDT borrow, which isn't the first one in the borrows list, and
+    // the second one is capped at the $17 left under the close factor.
+    lending_market
+        .liquidate_obligation_batch(
+            &mut test,
+            &[
+                (&usdt_reserve, &usdc_reserve, 10 * FRACTIONAL_TO_USDC),
+                (&wsol_reserve, &usdc_reserve, u64::MAX),
+            ],
+            &obligation,
+            &liquidator,
+        )
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    let borrowed_amount = |reserve: &Info<Reserve>| {
+        obligation_post
+            .account
+            .borrows
+            .iter()
+            .find(|b| b.borrow_reserve == reserve.pubkey)
+            .unwrap()
+            .borrowed_amount_wads
+    };
+    assert_eq!(
+        borrowed_amount(&usdt_reserve),
+        Decimal::from(35 * FRACTIONAL_TO_USDC)
+    );
+    assert_eq!(
+        borrowed_amount(&wsol_reserve),
+        Decimal::from(365 * LAMPORTS_TO_SOL / 100)
+    );
+}
@@ -74,6 +74,10 @@ async fn test_mark_obligation_as_closeable_success() {
             lending_market.account.rate_limiter.config,
             lending_market.account.whitelisted_liquidator,
             risk_authority.keypair.pubkey(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
@@ -189,6 +193,10 @@ async fn invalid_signer() {
             lending_market.account.rate_limiter.config,
             lending_market.account.whitelisted_liquidator,
             risk_authority.keypair.pubkey(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
@@ -0,0 +1,254 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::scenario_1;
+use crate::solend_program_test::User;
+use bytemuck::Zeroable;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::error::LendingError;
+use solend_program::instruction::{flash_borrow_reserve_liquidity, flash_repay_reserve_liquidity};
+use solend_program::state::{LendingMarket, MarketRoles};
+
+fn assert_market_paused(res: TransactionError) {
+    match res {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, LendingError::MarketPaused as u32)
+        }
+        _ => panic!("Unexpected error: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn test_paused_market() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        lending_market_owner,
+    ) = scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    lending_market
+        .set_market_paused(&mut test, &lending_market_owner, true)
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(
+        lending_market_post.account,
+        LendingMarket {
+            paused: true,
+            ..lending_market.account
+        }
+    );
+
+    let res = lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 1_000_000)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_market_paused(res);
+
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            None,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_market_paused(res);
+
+    // the helpers send the same refresh transaction, which can only be processed once per slot
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .withdraw_obligation_collateral(&mut test, &usdc_reserve, &obligation, &user, 1_000_000)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_market_paused(res);
+
+    test.advance_clock_by_slots(1).await;
+    let res = lending_market
+        .liquidate_obligation_and_redeem_reserve_collateral(
+            &mut test,
+            &wsol_reserve,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_market_paused(res);
+
+    let res = test
+        .process_transaction(
+            &[
+                flash_borrow_reserve_liquidity(
+                    solend_program::id(),
+                    1_000_000,
+                    usdc_reserve.account.liquidity.supply_pubkey,
+                    user.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.pubkey,
+                    lending_market.pubkey,
+                ),
+                flash_repay_reserve_liquidity(
+                    solend_program::id(),
+                    1_000_000,
+                    0,
+                    user.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.account.liquidity.supply_pubkey,
+                    usdc_reserve.account.config.fee_receiver,
+                    user.get_account(&usdc_mint::id()).unwrap(),
+                    usdc_reserve.pubkey,
+                    lending_market.pubkey,
+                    user.keypair.pubkey(),
+                ),
+            ],
+            Some(&[&user.keypair]),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_market_paused(res);
+
+    // repays and refreshes are still allowed
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .refresh_obligation(&mut test, &obligation)
+        .await
+        .unwrap();
+    lending_market
+        .repay_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .set_market_paused(&mut test, &lending_market_owner, false)
+        .await
+        .unwrap();
+
+    lending_market
+        .deposit(&mut test, &usdc_reserve, &user, 2_000_000)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_pause_authorities() {
+    let (mut test, lending_market, _, _, user, _, lending_market_owner) =
+        scenario_1(&test_reserve_config(), &test_reserve_config()).await;
+
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let risk_authority = User::new_with_keypair(Keypair::new());
+    let pause_guardian = User::new_with_keypair(Keypair::new());
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            risk_authority.keypair.pubkey(),
+            lending_market.account.obligation_max_outflow,
+            lending_market.account.obligation_outflow_window,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap();
+    lending_market
+        .update_market_roles(
+            &mut test,
+            &lending_market_owner,
+            MarketRoles {
+                pause_guardian: pause_guardian.keypair.pubkey(),
+                ..MarketRoles::zeroed()
+            },
+        )
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .set_market_paused(&mut test, &user, true)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidSigner as u32)
+        )
+    );
+
+    lending_market
+        .set_market_paused(&mut test, &pause_guardian, true)
+        .await
+        .unwrap();
+    lending_market
+        .set_market_paused(&mut test, &risk_authority, true)
+        .await
+        .unwrap();
+
+    // only the owner can unpause
+    for signer in [&risk_authority, &pause_guardian] {
+        let res = lending_market
+            .set_market_paused(&mut test, signer, false)
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            res,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+            )
+        );
+    }
+
+    lending_market
+        .set_market_paused(&mut test, &lending_market_owner, false)
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert!(!lending_market_post.account.paused);
+}
@@ -0,0 +1,493 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::setup_world;
+use crate::solend_program_test::Info;
+use crate::solend_program_test::SolendProgramTest;
+use crate::solend_program_test::User;
+use bytemuck::Zeroable;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::error::LendingError;
+use solend_program::instruction::set_lending_market_owner_and_config;
+use solend_program::state::{
+    LendingMarket, MarketRoles, RateLimiterConfig, Reserve, ReserveConfig,
+};
+
+struct Roles {
+    config_admin: User,
+    oracle_admin: User,
+    fee_admin: User,
+    pause_guardian: User,
+    limits_admin: User,
+}
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    Roles,
+) {
+    let (mut test, lending_market, usdc_reserve, _, lending_market_owner, _) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let roles = Roles {
+        config_admin: User::new_with_keypair(Keypair::new()),
+        oracle_admin: User::new_with_keypair(Keypair::new()),
+        fee_admin: User::new_with_keypair(Keypair::new()),
+        pause_guardian: User::new_with_keypair(Keypair::new()),
+        limits_admin: User::new_with_keypair(Keypair::new()),
+    };
+
+    let market_roles = MarketRoles {
+        config_admin: roles.config_admin.keypair.pubkey(),
+        oracle_admin: roles.oracle_admin.keypair.pubkey(),
+        fee_admin: roles.fee_admin.keypair.pubkey(),
+        pause_guardian: roles.pause_guardian.keypair.pubkey(),
+        limits_admin: roles.limits_admin.keypair.pubkey(),
+        ..MarketRoles::zeroed()
+    };
+    lending_market
+        .update_market_roles(&mut test, &lending_market_owner, market_roles)
+        .await
+        .unwrap();
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        roles,
+    )
+}
+
+fn missing_market_role(instruction_index: u8) -> TransactionError {
+    TransactionError::InstructionError(
+        instruction_index,
+        InstructionError::Custom(LendingError::MissingMarketRole as u32),
+    )
+}
+
+#[tokio::test]
+async fn test_limits_admin() {
+    let (mut test, lending_market, usdc_reserve, _, roles) = setup().await;
+
+    let new_config = ReserveConfig {
+        deposit_limit: 1_000,
+        borrow_limit: 500,
+        ..usdc_reserve.account.config
+    };
+    let new_rate_limiter_config = RateLimiterConfig {
+        window_duration: 10,
+        max_outflow: 100,
+        net_flow: false,
+    };
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.limits_admin,
+            &usdc_reserve,
+            new_config,
+            new_rate_limiter_config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config, new_config);
+    assert_eq!(
+        usdc_reserve_post.account.rate_limiter.config,
+        new_rate_limiter_config
+    );
+
+    // the loan to value ratio is the config admin's
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.limits_admin,
+            &usdc_reserve_post,
+            ReserveConfig {
+                loan_to_value_ratio: 40,
+                ..new_config
+            },
+            new_rate_limiter_config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, missing_market_role(1));
+
+    // market limits can be changed too, but not the owner or risk authority
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &roles.limits_admin,
+            &lending_market.account.owner,
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+            50_000,
+            10,
+            10_000_000,
+            20_000_000,
+        )
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(
+        lending_market_post.account,
+        LendingMarket {
+            obligation_max_outflow: 50_000,
+            obligation_outflow_window: 10,
+            global_allowed_borrow_value: 10_000_000,
+            global_unhealthy_borrow_value: 20_000_000,
+            ..lending_market.account
+        }
+    );
+
+    let res = lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &roles.limits_admin,
+            &roles.limits_admin.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+            50_000,
+            10,
+            10_000_000,
+            20_000_000,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, missing_market_role(0));
+}
+
+#[tokio::test]
+async fn test_fail_market_roles_left_out() {
+    let (mut test, lending_market, _, _, roles) = setup().await;
+
+    let mut instruction = set_lending_market_owner_and_config(
+        solend_program::id(),
+        lending_market.pubkey,
+        roles.limits_admin.keypair.pubkey(),
+        lending_market.account.owner,
+        lending_market.account.rate_limiter.config,
+        lending_market.account.whitelisted_liquidator,
+        lending_market.account.risk_authority,
+        50_000,
+        10,
+        lending_market.account.global_allowed_borrow_value,
+        lending_market.account.global_unhealthy_borrow_value,
+    );
+    // without the market roles account no role is assigned
+    instruction.accounts.pop();
+
+    let res = test
+        .process_transaction(&[instruction], Some(&[&roles.limits_admin.keypair]))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_config_admin() {
+    let (mut test, lending_market, usdc_reserve, _, roles) = setup().await;
+
+    let new_config = ReserveConfig {
+        loan_to_value_ratio: 40,
+        liquidation_threshold: 50,
+        ..usdc_reserve.account.config
+    };
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.config_admin,
+            &usdc_reserve,
+            new_config,
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config, new_config);
+
+    // deposit limits are the limits admin's
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.config_admin,
+            &usdc_reserve_post,
+            ReserveConfig {
+                deposit_limit: 1_000,
+                ..new_config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, missing_market_role(1));
+
+    // the oracle admin can't change the config
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.oracle_admin,
+            &usdc_reserve_post,
+            ReserveConfig {
+                loan_to_value_ratio: 30,
+                ..new_config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, missing_market_role(1));
+}
+
+#[tokio::test]
+async fn test_roles_timelocked() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, roles) = setup().await;
+
+    lending_market
+        .set_config_timelock(&mut test, &lending_market_owner, 100)
+        .await
+        .unwrap();
+
+    let config_change_timelocked = |index| {
+        TransactionError::InstructionError(
+            index,
+            InstructionError::Custom(LendingError::ConfigChangeTimelocked as u32),
+        )
+    };
+
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.config_admin,
+            &usdc_reserve,
+            ReserveConfig {
+                loan_to_value_ratio: 52,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, config_change_timelocked(1));
+
+    // risk-reducing changes stay instant
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.config_admin,
+            &usdc_reserve,
+            ReserveConfig {
+                loan_to_value_ratio: 40,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.limits_admin,
+            &usdc_reserve,
+            ReserveConfig {
+                deposit_limit: 1_000,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.limits_admin,
+            &usdc_reserve,
+            ReserveConfig {
+                deposit_limit: 2_000,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, config_change_timelocked(1));
+
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &roles.limits_admin,
+            &lending_market.account.owner,
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+            lending_market.account.obligation_max_outflow,
+            lending_market.account.obligation_outflow_window,
+            lending_market.account.global_allowed_borrow_value - 1,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &roles.limits_admin,
+            &lending_market.account.owner,
+            lending_market.account.rate_limiter.config,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+            lending_market.account.obligation_max_outflow,
+            lending_market.account.obligation_outflow_window,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value + 1,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, config_change_timelocked(0));
+}
+
+#[tokio::test]
+async fn test_pause_guardian() {
+    let (mut test, lending_market, usdc_reserve, _, roles) = setup().await;
+
+    let paused = RateLimiterConfig {
+        window_duration: 1,
+        max_outflow: 0,
+        net_flow: false,
+    };
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.pause_guardian,
+            &usdc_reserve,
+            usdc_reserve.account.config,
+            paused,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.rate_limiter.config, paused);
+
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &roles.pause_guardian,
+            &lending_market.account.owner,
+            paused,
+            lending_market.account.whitelisted_liquidator,
+            lending_market.account.risk_authority,
+            lending_market.account.obligation_max_outflow,
+            lending_market.account.obligation_outflow_window,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market_post.account.rate_limiter.config, paused);
+
+    // unpausing is the limits admin's
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.pause_guardian,
+            &usdc_reserve_post,
+            usdc_reserve.account.config,
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, missing_market_role(1));
+}
+
+#[tokio::test]
+async fn test_fee_admin_of_permissionless_market() {
+    let (mut test, lending_market, usdc_reserve, _, roles) = setup().await;
+
+    // only markets owned by the protocol let their fee admin change fees
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &roles.fee_admin,
+            &usdc_reserve,
+            ReserveConfig {
+                protocol_take_rate: 10,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidConfig as u32)
+        )
+    );
+}
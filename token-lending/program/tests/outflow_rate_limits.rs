@@ -10,6 +10,8 @@ mod helpers;
 
 use helpers::solend_program_test::{setup_world, Info, SolendProgramTest, User};
 use solend_sdk::error::LendingError;
+use solend_sdk::instruction::repay_obligation_liquidity;
+use solend_sdk::math::Decimal;
 
 use solend_sdk::state::{LendingMarket, RateLimiterConfig, Reserve, ReserveConfig};
 
@@ -129,9 +131,14 @@ async fn test_outflow_reserve() {
             RateLimiterConfig {
                 window_duration: 10,
                 max_outflow: 10,
+                net_flow: false,
             },
             None,
             lending_market.account.risk_authority,
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
@@ -213,3 +220,422 @@ async fn test_outflow_reserve() {
         test.advance_clock_by_slots(1).await;
     }
 }
+
+#[tokio::test]
+async fn test_outflow_obligation() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        host_fee_receiver,
+        lending_market_owner,
+        wsol_depositor,
+    ) = setup(&ReserveConfig {
+        ..test_reserve_config()
+    })
+    .await;
+
+    // ie, within 10 slots, the maximum outflow from a single obligation is $10. the lending
+    // market rate limiter keeps its own 1 slot window
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            lending_market.account.risk_authority,
+            10,
+            10,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let obligation_post = test.load_account::<Obligation>(obligation.pubkey).await;
+    assert_eq!(obligation_post.account.outflow_cur_value, 10);
+
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            1,
+        )
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
+        )
+    );
+
+    let res = lending_market
+        .withdraw_obligation_collateral_and_redeem_reserve_collateral(
+            &mut test,
+            &usdc_reserve,
+            &obligation,
+            &user,
+            1,
+        )
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
+        )
+    );
+
+    // withdrawing collateral without redeeming it counts as well
+    let res = lending_market
+        .withdraw_obligation_collateral(&mut test, &usdc_reserve, &obligation, &user, 1)
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::WithdrawTooLarge as u32)
+        )
+    );
+
+    // outflows that don't come from the obligation aren't affected
+    lending_market
+        .redeem(&mut test, &wsol_reserve, &wsol_depositor, LAMPORTS_PER_SOL)
+        .await
+        .unwrap();
+
+    // the obligation can borrow again once its borrow has slid out of the window
+    test.advance_clock_by_slots(20).await;
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            1,
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_net_flow_outflow_market() {
+    let (
+        mut test,
+        lending_market,
+        _usdc_reserve,
+        wsol_reserve,
+        user,
+        obligation,
+        host_fee_receiver,
+        lending_market_owner,
+        _wsol_depositor,
+    ) = setup(&ReserveConfig {
+        ..test_reserve_config()
+    })
+    .await;
+
+    // ie, within 10 slots, the maximum net outflow is $10
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 10,
+                net_flow: true,
+            },
+            None,
+            lending_market.account.risk_authority,
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap();
+
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            1,
+        )
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
+        )
+    );
+
+    // repaying $5 frees up $5 of outflow in the same window
+    let wsol_reserve = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    lending_market
+        .repay_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            LAMPORTS_PER_SOL / 2,
+        )
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(
+        lending_market_post
+            .account
+            .rate_limiter
+            .clone()
+            .remaining_outflow(test.get_clock().await.slot)
+            .unwrap(),
+        Decimal::from(5u64)
+    );
+
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            LAMPORTS_PER_SOL / 2,
+        )
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            1,
+        )
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
+        )
+    );
+
+    // the market rate limiter can't be skipped by passing the market as read only
+    let wsol_reserve = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    let mut instruction = repay_obligation_liquidity(
+        solend_program::id(),
+        LAMPORTS_PER_SOL / 2,
+        user.get_account(&wsol_mint::id()).unwrap(),
+        wsol_reserve.account.liquidity.supply_pubkey,
+        wsol_reserve.pubkey,
+        obligation.pubkey,
+        lending_market.pubkey,
+        user.keypair.pubkey(),
+    );
+    instruction.accounts[4].is_writable = false;
+
+    let res = test
+        .process_transaction(&[instruction], Some(&[&user.keypair]))
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_net_flow_outflow_reserve() {
+    let (
+        mut test,
+        lending_market,
+        _usdc_reserve,
+        wsol_reserve,
+        mut user,
+        obligation,
+        host_fee_receiver,
+        lending_market_owner,
+        _wsol_depositor,
+    ) = setup(&ReserveConfig {
+        ..test_reserve_config()
+    })
+    .await;
+
+    // ie, within 10 slots, the maximum net outflow is 1 SOL
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            &wsol_reserve,
+            wsol_reserve.account.config,
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: LAMPORTS_PER_SOL,
+                net_flow: true,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+    let wsol_reserve = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            LAMPORTS_PER_SOL,
+        )
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            1,
+        )
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
+        )
+    );
+
+    // depositing half a SOL frees up half a SOL of outflow in the same window
+    user.create_token_account(&wsol_reserve.account.collateral.mint_pubkey, &mut test)
+        .await;
+    lending_market
+        .refresh_reserve(&mut test, &wsol_reserve)
+        .await
+        .unwrap();
+    lending_market
+        .deposit(&mut test, &wsol_reserve, &user, LAMPORTS_PER_SOL / 2)
+        .await
+        .unwrap();
+
+    let wsol_reserve_post = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    assert_eq!(
+        wsol_reserve_post
+            .account
+            .rate_limiter
+            .clone()
+            .remaining_outflow(test.get_clock().await.slot)
+            .unwrap(),
+        Decimal::from(LAMPORTS_PER_SOL / 2)
+    );
+
+    lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            LAMPORTS_PER_SOL / 2,
+        )
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &wsol_reserve,
+            &obligation,
+            &user,
+            host_fee_receiver.get_account(&wsol_mint::id()),
+            1,
+        )
+        .await
+        .err()
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::OutflowRateLimitExceeded as u32)
+        )
+    );
+}
@@ -0,0 +1,328 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::setup_world;
+use crate::solend_program_test::Info;
+use crate::solend_program_test::SolendProgramTest;
+use crate::solend_program_test::User;
+use bytemuck::Zeroable;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::error::LendingError;
+use solend_program::instruction::{find_protocol_config_address, update_reserve_config};
+use solend_program::state::{LendingMarket, ProtocolConfig, Reserve, ReserveConfig};
+use solend_program::NULL_PUBKEY;
+
+fn protocol_config(authority: &User) -> ProtocolConfig {
+    ProtocolConfig {
+        authority: authority.keypair.pubkey(),
+        default_protocol_liquidation_fee: 20,
+        default_protocol_take_rate: 5,
+        min_protocol_liquidation_fee: 0,
+        max_protocol_liquidation_fee: 30,
+        min_protocol_take_rate: 0,
+        max_protocol_take_rate: 20,
+        ..ProtocolConfig::zeroed()
+    }
+}
+
+fn invalid_config(instruction_index: u8) -> TransactionError {
+    TransactionError::InstructionError(
+        instruction_index,
+        InstructionError::Custom(LendingError::InvalidConfig as u32),
+    )
+}
+
+/// The upgrade authority pays for the protocol config account
+async fn setup_upgrade_authority(test: &mut SolendProgramTest) -> User {
+    let upgrade_authority = User::new_with_keypair(Keypair::new());
+    test.set_upgrade_authority(upgrade_authority.keypair.pubkey())
+        .await;
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &upgrade_authority.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    upgrade_authority
+}
+
+/// A lending market owned by someone other than the protocol authority, with an initialized
+/// protocol config
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    User,
+) {
+    let (mut test, lending_market, usdc_reserve, _, lending_market_owner, _) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let upgrade_authority = setup_upgrade_authority(&mut test).await;
+
+    let protocol_authority = User::new_with_keypair(Keypair::new());
+    test.init_protocol_config(&upgrade_authority, protocol_config(&protocol_authority))
+        .await
+        .unwrap();
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        protocol_authority,
+    )
+}
+
+#[tokio::test]
+async fn test_init_and_update_protocol_config() {
+    let (mut test, _, _, _, _) = setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    let upgrade_authority = setup_upgrade_authority(&mut test).await;
+
+    let protocol_authority = User::new_with_keypair(Keypair::new());
+    let rando = User::new_with_keypair(Keypair::new());
+
+    // only the upgrade authority can initialize it
+    let res = test
+        .init_protocol_config(&rando, protocol_config(&protocol_authority))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidSigner as u32)
+        )
+    );
+
+    test.init_protocol_config(&upgrade_authority, protocol_config(&protocol_authority))
+        .await
+        .unwrap();
+
+    let protocol_config_pubkey = find_protocol_config_address(solend_program::id()).0;
+    let protocol_config_post = test
+        .load_zeroable_account::<ProtocolConfig>(protocol_config_pubkey)
+        .await;
+    assert_eq!(
+        protocol_config_post.account,
+        ProtocolConfig {
+            bump_seed: protocol_config_post.account.bump_seed,
+            ..protocol_config(&protocol_authority)
+        }
+    );
+
+    // it can only be initialized once
+    let res = test
+        .init_protocol_config(&upgrade_authority, protocol_config(&rando))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::AlreadyInitialized as u32)
+        )
+    );
+
+    // only the protocol authority can update it
+    let new_protocol_authority = User::new_with_keypair(Keypair::new());
+    let new_protocol_config = ProtocolConfig {
+        max_protocol_take_rate: 10,
+        ..protocol_config(&new_protocol_authority)
+    };
+    let res = test
+        .update_protocol_config(&rando, new_protocol_config)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidSigner as u32)
+        )
+    );
+
+    test.update_protocol_config(&protocol_authority, new_protocol_config)
+        .await
+        .unwrap();
+
+    let protocol_config_post = test
+        .load_zeroable_account::<ProtocolConfig>(protocol_config_pubkey)
+        .await;
+    assert_eq!(
+        protocol_config_post.account,
+        ProtocolConfig {
+            bump_seed: protocol_config_post.account.bump_seed,
+            ..new_protocol_config
+        }
+    );
+
+    // the defaults have to be within the bounds
+    let res = test
+        .update_protocol_config(
+            &new_protocol_authority,
+            ProtocolConfig {
+                max_protocol_take_rate: 4,
+                ..new_protocol_config
+            },
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, invalid_config(0));
+}
+
+#[tokio::test]
+async fn test_protocol_authority_changes_protocol_fees() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, protocol_authority) =
+        setup().await;
+
+    // the owner of a permissionless market can't change protocol fees
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve,
+            ReserveConfig {
+                protocol_take_rate: 10,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, invalid_config(1));
+
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &protocol_authority,
+            &usdc_reserve,
+            ReserveConfig {
+                protocol_take_rate: 10,
+                ..usdc_reserve.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config.protocol_take_rate, 10);
+
+    // but only within the protocol config's bounds
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &protocol_authority,
+            &usdc_reserve_post,
+            ReserveConfig {
+                protocol_take_rate: 21,
+                ..usdc_reserve_post.account.config
+            },
+            usdc_reserve.account.rate_limiter.config,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, invalid_config(1));
+}
+
+#[tokio::test]
+async fn test_protocol_fee_changes_need_protocol_config() {
+    let (mut test, lending_market, usdc_reserve, _, protocol_authority) = setup().await;
+
+    let oracle = test.mints.get(&usdc_mint::id()).unwrap().unwrap();
+    let mut instruction = update_reserve_config(
+        solend_program::id(),
+        ReserveConfig {
+            protocol_take_rate: 21,
+            ..usdc_reserve.account.config
+        },
+        usdc_reserve.account.rate_limiter.config,
+        usdc_reserve.pubkey,
+        lending_market.pubkey,
+        protocol_authority.keypair.pubkey(),
+        oracle.pyth_product_pubkey,
+        oracle.pyth_price_pubkey,
+        oracle.switchboard_feed_pubkey.unwrap_or(NULL_PUBKEY),
+    );
+    // leaving out the protocol config can't skip its bounds
+    instruction.accounts.pop();
+
+    let res = test
+        .process_transaction(&[instruction], Some(&[&protocol_authority.keypair]))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidAccountInput as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_init_reserve_default_protocol_fees() {
+    let (mut test, lending_market, _, lending_market_owner, _) = setup().await;
+
+    // reserves on permissionless markets can't start with other protocol fees
+    let res = test
+        .init_reserve(
+            &lending_market,
+            &lending_market_owner,
+            &wsol_mint::id(),
+            &test_reserve_config(),
+            &Keypair::new(),
+            1000,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(res, invalid_config(1));
+
+    let reserve_config = ReserveConfig {
+        protocol_liquidation_fee: 20,
+        protocol_take_rate: 5,
+        ..test_reserve_config()
+    };
+    let wsol_reserve = test
+        .init_reserve(
+            &lending_market,
+            &lending_market_owner,
+            &wsol_mint::id(),
+            &reserve_config,
+            &Keypair::new(),
+            1000,
+            None,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(wsol_reserve.account.config, reserve_config);
+}
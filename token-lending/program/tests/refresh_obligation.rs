@@ -7,11 +7,15 @@ use crate::solend_program_test::ObligationArgs;
 use crate::solend_program_test::PriceArgs;
 use crate::solend_program_test::ReserveArgs;
 use helpers::AddPacked;
+use solana_sdk::instruction::InstructionError;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::TransactionError;
+use solend_program::error::LendingError;
 use solend_program::instruction::refresh_obligation;
 use solend_program::processor::process_instruction;
 
 use solend_program::state::ObligationCollateral;
+use solend_program::state::{LiquidationThresholdRamp, MAX_LIQUIDATION_THRESHOLD_RAMP_SLOTS};
 use solend_sdk::state::PROGRAM_VERSION;
 use std::collections::HashSet;
 
@@ -582,3 +586,122 @@ async fn test_normalize_obligation() {
         }]
     );
 }
+
+#[tokio::test]
+async fn test_liquidation_threshold_ramp() {
+    let (mut test, lending_market, reserves, obligations, _users, lending_market_owner) =
+        custom_scenario(
+            &[ReserveArgs {
+                mint: usdc_mint::id(),
+                config: test_reserve_config(),
+                liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                price: PriceArgs {
+                    price: 10,
+                    conf: 0,
+                    expo: -1,
+                    ema_price: 10,
+                    ema_conf: 1,
+                },
+            }],
+            &[ObligationArgs {
+                deposits: vec![(usdc_mint::id(), 100 * FRACTIONAL_TO_USDC)],
+                borrows: vec![],
+            }],
+        )
+        .await;
+
+    let usdc_reserve = &reserves[0];
+    let new_config = ReserveConfig {
+        loan_to_value_ratio: 40,
+        liquidation_threshold: 45,
+        max_liquidation_threshold: 45,
+        ..usdc_reserve.account.config
+    };
+
+    // ramps can't last forever
+    let res = lending_market
+        .update_reserve_config_with_liquidation_threshold_ramp(
+            &mut test,
+            &lending_market_owner,
+            usdc_reserve,
+            new_config,
+            usdc_reserve.account.rate_limiter.config,
+            MAX_LIQUIDATION_THRESHOLD_RAMP_SLOTS + 1,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidConfig as u32)
+        )
+    );
+
+    let start_slot = test.get_clock().await.slot;
+    lending_market
+        .update_reserve_config_with_liquidation_threshold_ramp(
+            &mut test,
+            &lending_market_owner,
+            usdc_reserve,
+            new_config,
+            usdc_reserve.account.rate_limiter.config,
+            100,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(usdc_reserve_post.account.config, new_config);
+    assert_eq!(
+        usdc_reserve_post.account.liquidation_threshold_ramp,
+        Some(LiquidationThresholdRamp {
+            start_liquidation_threshold: 55,
+            start_max_liquidation_threshold: 65,
+            start_slot,
+            end_slot: start_slot + 100,
+        })
+    );
+
+    // halfway through the ramp the thresholds are halfway down, the ltv applies right away
+    test.advance_clock_by_slots(50).await;
+    lending_market
+        .refresh_obligation(&mut test, &obligations[0])
+        .await
+        .unwrap();
+
+    let obligation = test.load_account::<Obligation>(obligations[0].pubkey).await;
+    assert_eq!(obligation.account.deposited_value, Decimal::from(100u64));
+    assert_eq!(
+        obligation.account.allowed_borrow_value,
+        Decimal::from(40u64)
+    );
+    assert_eq!(
+        obligation.account.unhealthy_borrow_value,
+        Decimal::from(50u64)
+    );
+    assert_eq!(
+        obligation.account.super_unhealthy_borrow_value,
+        Decimal::from(55u64)
+    );
+
+    // once it's over the config's thresholds apply
+    test.advance_clock_by_slots(50).await;
+    lending_market
+        .refresh_obligation(&mut test, &obligations[0])
+        .await
+        .unwrap();
+
+    let obligation = test.load_account::<Obligation>(obligations[0].pubkey).await;
+    assert_eq!(
+        obligation.account.unhealthy_borrow_value,
+        Decimal::from(45u64)
+    );
+    assert_eq!(
+        obligation.account.super_unhealthy_borrow_value,
+        Decimal::from(45u64)
+    );
+}
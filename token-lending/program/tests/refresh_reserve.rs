@@ -6,6 +6,7 @@ use crate::solend_program_test::custom_scenario;
 use crate::solend_program_test::setup_world;
 use crate::solend_program_test::BalanceChecker;
 use crate::solend_program_test::Info;
+use crate::solend_program_test::ObligationArgs;
 use crate::solend_program_test::Oracle;
 use crate::solend_program_test::PriceArgs;
 use crate::solend_program_test::ReserveArgs;
@@ -20,6 +21,7 @@ use solana_sdk::{signature::Keypair, transaction::TransactionError};
 use solend_program::state::LastUpdate;
 use solend_program::state::LendingMarket;
 use solend_program::state::Obligation;
+use solend_program::state::PegMode;
 use solend_program::state::Reserve;
 use solend_program::state::ReserveConfig;
 use solend_program::state::ReserveFees;
@@ -955,3 +957,232 @@ async fn test_switchboard_pull_oracle() {
         ),
     );
 }
+
+#[tokio::test]
+async fn test_stake_pool_oracle() {
+    let (mut test, lending_market, reserves, _obligations, _users, lending_market_owner) =
+        custom_scenario(
+            &[ReserveArgs {
+                mint: msol_mint::id(),
+                config: test_reserve_config(),
+                liquidity_amount: 1000,
+                price: PriceArgs {
+                    price: 10,
+                    conf: 0,
+                    expo: 0,
+                    ema_price: 10,
+                    ema_conf: 0,
+                },
+            }],
+            &[],
+        )
+        .await;
+
+    let msol_reserve = &reserves[0];
+
+    let wsol_pyth_feed = test.init_pyth_feed(&wsol_mint::id()).await;
+    test.set_price(
+        &wsol_mint::id(),
+        &PriceArgs {
+            price: 20,
+            conf: 0,
+            expo: 0,
+            ema_price: 10,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    // 1 pool token = 1.1 SOL
+    let stake_pool = test
+        .init_stake_pool(11 * LAMPORTS_PER_SOL, 10 * LAMPORTS_PER_SOL)
+        .await;
+    let stake_pool_oracle = Oracle {
+        pyth_product_pubkey: NULL_PUBKEY,
+        pyth_price_pubkey: stake_pool,
+        switchboard_feed_pubkey: None,
+    };
+
+    // a stake pool can't be used without an underlying oracle
+    let err = lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            msol_reserve,
+            msol_reserve.account.config,
+            msol_reserve.account.rate_limiter.config,
+            Some(&stake_pool_oracle),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
+        )
+    );
+
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &lending_market_owner,
+            msol_reserve,
+            ReserveConfig {
+                underlying_oracle_pubkey: Some(wsol_pyth_feed),
+                ..msol_reserve.account.config
+            },
+            msol_reserve.account.rate_limiter.config,
+            Some(&stake_pool_oracle),
+        )
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(1).await;
+
+    let msol_reserve = test.load_account::<Reserve>(reserves[0].pubkey).await;
+    lending_market
+        .refresh_reserve(&mut test, &msol_reserve)
+        .await
+        .unwrap();
+
+    let msol_reserve_post = test.load_account::<Reserve>(reserves[0].pubkey).await;
+    assert_eq!(
+        msol_reserve_post.account.liquidity.market_price,
+        Decimal::from(22u64)
+    );
+    assert_eq!(
+        msol_reserve_post.account.liquidity.smoothed_market_price,
+        Decimal::from(11u64)
+    );
+
+    // staleness comes from the underlying oracle
+    test.advance_clock_by_slots(1000).await;
+    let msol_reserve = test.load_account::<Reserve>(reserves[0].pubkey).await;
+    let err = lending_market
+        .refresh_reserve(&mut test, &msol_reserve)
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_pegged_oracle() {
+    let (mut test, lending_market, reserves, obligations, mut users, _lending_market_owner) =
+        custom_scenario(
+            &[
+                ReserveArgs {
+                    mint: usdc_mint::id(),
+                    config: ReserveConfig {
+                        peg_mode: PegMode::HaltBorrows,
+                        peg_max_deviation_bps: 50,
+                        ..test_reserve_config()
+                    },
+                    liquidity_amount: 100_000 * FRACTIONAL_TO_USDC,
+                    price: PriceArgs {
+                        price: 1003,
+                        conf: 0,
+                        expo: -3,
+                        ema_price: 998,
+                        ema_conf: 0,
+                    },
+                },
+                ReserveArgs {
+                    mint: wsol_mint::id(),
+                    config: test_reserve_config(),
+                    liquidity_amount: 100 * LAMPORTS_PER_SOL,
+                    price: PriceArgs {
+                        price: 10,
+                        conf: 0,
+                        expo: 0,
+                        ema_price: 10,
+                        ema_conf: 0,
+                    },
+                },
+            ],
+            &[ObligationArgs {
+                deposits: vec![(wsol_mint::id(), 10 * LAMPORTS_PER_SOL)],
+                borrows: vec![],
+            }],
+        )
+        .await;
+
+    test.advance_clock_by_slots(1).await;
+
+    let usdc_reserve = test.load_account::<Reserve>(reserves[0].pubkey).await;
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve)
+        .await
+        .unwrap();
+
+    // within the peg band, the peg price is used
+    let usdc_reserve = test.load_account::<Reserve>(reserves[0].pubkey).await;
+    assert_eq!(usdc_reserve.account.liquidity.market_price, Decimal::one());
+    assert_eq!(
+        usdc_reserve.account.liquidity.smoothed_market_price,
+        Decimal::one()
+    );
+    assert!(!usdc_reserve.account.liquidity.depegged);
+
+    test.set_price(
+        &usdc_mint::id(),
+        &PriceArgs {
+            price: 990,
+            conf: 0,
+            expo: -3,
+            ema_price: 998,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    test.advance_clock_by_slots(1).await;
+
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve)
+        .await
+        .unwrap();
+
+    // outside of the band, the oracle price is used
+    let usdc_reserve = test.load_account::<Reserve>(reserves[0].pubkey).await;
+    assert_eq!(
+        usdc_reserve.account.liquidity.market_price,
+        Decimal::from_percent(99)
+    );
+    assert_eq!(
+        usdc_reserve.account.liquidity.smoothed_market_price,
+        Decimal::from_bps(9_980)
+    );
+    assert!(usdc_reserve.account.liquidity.depegged);
+
+    users[0]
+        .create_token_account(&usdc_mint::id(), &mut test)
+        .await;
+    let err = lending_market
+        .borrow_obligation_liquidity(
+            &mut test,
+            &usdc_reserve,
+            &obligations[0],
+            &users[0],
+            None,
+            FRACTIONAL_TO_USDC,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::ReserveDepegged as u32)
+        )
+    );
+}
@@ -8,6 +8,8 @@ use crate::solend_program_test::SolendProgramTest;
 use crate::solend_program_test::User;
 use helpers::*;
 use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
 use solana_program_test::*;
 use solana_sdk::{
     instruction::InstructionError,
@@ -19,23 +21,37 @@ use solend_program::state::LendingMarket;
 use solend_program::state::RateLimiterConfig;
 use solend_sdk::state::RateLimiter;
 
-use solend_program::{error::LendingError, instruction::LendingInstruction};
+use solend_program::{
+    error::LendingError,
+    instruction::{find_pending_market_owner_address, LendingInstruction},
+};
 
 async fn setup() -> (SolendProgramTest, Info<LendingMarket>, User) {
-    let (test, lending_market, _usdc_reserve, _, lending_market_owner, _user) =
+    let (mut test, lending_market, _usdc_reserve, _, lending_market_owner, _user) =
         setup_world(&test_reserve_config(), &test_reserve_config()).await;
 
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
     (test, lending_market, lending_market_owner)
 }
 
 #[tokio::test]
 async fn test_success() {
     let (mut test, lending_market, lending_market_owner) = setup().await;
-    let new_owner = Keypair::new();
     let new_risk_authority = Keypair::new();
     let new_config = RateLimiterConfig {
         max_outflow: 100,
         window_duration: 5,
+        net_flow: false,
     };
 
     let whitelisted_liquidator = Pubkey::new_unique();
@@ -43,10 +59,14 @@ async fn test_success() {
         .set_lending_market_owner_and_config(
             &mut test,
             &lending_market_owner,
-            &new_owner.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
             new_config,
             Some(whitelisted_liquidator),
             new_risk_authority.pubkey(),
+            50_000,
+            10,
+            100_000_000,
+            120_000_000,
         )
         .await
         .unwrap();
@@ -58,10 +78,252 @@ async fn test_success() {
     assert_eq!(
         lending_market_post.account,
         LendingMarket {
-            owner: new_owner.pubkey(),
             rate_limiter: RateLimiter::new(new_config, 1000),
             whitelisted_liquidator: Some(whitelisted_liquidator),
-            ..lending_market_post.account
+            risk_authority: new_risk_authority.pubkey(),
+            obligation_max_outflow: 50_000,
+            obligation_outflow_window: 10,
+            global_allowed_borrow_value: 100_000_000,
+            global_unhealthy_borrow_value: 120_000_000,
+            ..lending_market.account
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_fail_one_step_owner_transfer() {
+    let (mut test, lending_market, lending_market_owner) = setup().await;
+    let new_owner = Keypair::new();
+
+    let res = lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &new_owner.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            lending_market.account.risk_authority,
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_propose_and_accept_owner() {
+    let (mut test, lending_market, lending_market_owner) = setup().await;
+    let mistyped_owner = User::new_with_keypair(Keypair::new());
+    let new_owner = User::new_with_keypair(Keypair::new());
+
+    lending_market
+        .propose_market_owner(
+            &mut test,
+            &lending_market_owner,
+            mistyped_owner.keypair.pubkey(),
+        )
+        .await
+        .unwrap();
+
+    // proposing again replaces the proposed owner
+    lending_market
+        .propose_market_owner(&mut test, &lending_market_owner, new_owner.keypair.pubkey())
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(lending_market_post.account, lending_market.account);
+
+    let res = lending_market
+        .accept_market_owner(
+            &mut test,
+            &mistyped_owner,
+            lending_market_owner.keypair.pubkey(),
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+
+    lending_market
+        .accept_market_owner(&mut test, &new_owner, lending_market_owner.keypair.pubkey())
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(
+        lending_market_post.account,
+        LendingMarket {
+            owner: new_owner.keypair.pubkey(),
+            ..lending_market.account
+        }
+    );
+
+    let pending_market_owner = test
+        .context
+        .banks_client
+        .get_account(
+            find_pending_market_owner_address(solend_program::id(), lending_market.pubkey).0,
+        )
+        .await
+        .unwrap();
+    assert!(pending_market_owner.is_none());
+}
+
+#[tokio::test]
+async fn test_fail_propose_owner_not_owner() {
+    let (mut test, lending_market, _lending_market_owner) = setup().await;
+    let invalid_owner = User::new_with_keypair(Keypair::new());
+
+    let res = lending_market
+        .propose_market_owner(&mut test, &invalid_owner, invalid_owner.keypair.pubkey())
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidMarketOwner as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_fail_invalid_global_borrow_values() {
+    let (mut test, lending_market, lending_market_owner) = setup().await;
+
+    for (global_allowed_borrow_value, global_unhealthy_borrow_value) in [
+        (0, 70_000_000),
+        (65_000_001, 70_000_000),
+        (70_000_000, 65_000_000),
+    ] {
+        let res = lending_market
+            .set_lending_market_owner_and_config(
+                &mut test,
+                &lending_market_owner,
+                &lending_market_owner.keypair.pubkey(),
+                lending_market.account.rate_limiter.config,
+                None,
+                lending_market.account.risk_authority,
+                0,
+                0,
+                global_allowed_borrow_value,
+                global_unhealthy_borrow_value,
+            )
+            .await
+            .unwrap_err()
+            .unwrap();
+
+        assert_eq!(
+            res,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(LendingError::InvalidConfig as u32)
+            )
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_fail_obligation_outflow_limit_without_window() {
+    let (mut test, lending_market, lending_market_owner) = setup().await;
+
+    let res = lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            lending_market.account.risk_authority,
+            50_000,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidConfig as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_risk_authority_can_only_lower_global_borrow_values() {
+    let (mut test, lending_market, lending_market_owner) = setup().await;
+
+    let risk_authority = User::new_with_keypair(Keypair::new());
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &lending_market_owner,
+            &lending_market_owner.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            risk_authority.keypair.pubkey(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
+        )
+        .await
+        .unwrap();
+
+    // the allowed borrow value is lowered, the raise of the unhealthy borrow value is ignored
+    lending_market
+        .set_lending_market_owner_and_config(
+            &mut test,
+            &risk_authority,
+            &risk_authority.keypair.pubkey(),
+            lending_market.account.rate_limiter.config,
+            None,
+            risk_authority.keypair.pubkey(),
+            0,
+            0,
+            10_000_000,
+            100_000_000,
+        )
+        .await
+        .unwrap();
+
+    let lending_market_post = test
+        .load_account::<LendingMarket>(lending_market.pubkey)
+        .await;
+    assert_eq!(
+        lending_market_post.account,
+        LendingMarket {
+            risk_authority: risk_authority.keypair.pubkey(),
+            global_allowed_borrow_value: 10_000_000,
+            ..lending_market.account
         }
     );
 }
@@ -81,6 +343,10 @@ async fn test_risk_authority_can_set_only_rate_limiter() {
             lending_market.account.rate_limiter.config,
             None,
             risk_authority.pubkey(),
+            50_000,
+            10,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
@@ -90,6 +356,7 @@ async fn test_risk_authority_can_set_only_rate_limiter() {
     let new_rate_limiter_config = RateLimiterConfig {
         max_outflow: 0,
         window_duration: 1,
+        net_flow: false,
     };
 
     let lending_market = test
@@ -108,6 +375,10 @@ async fn test_risk_authority_can_set_only_rate_limiter() {
                 rate_limiter_config: new_rate_limiter_config,
                 whitelisted_liquidator: None,
                 risk_authority: new_owner.pubkey(),
+                obligation_max_outflow: 50_000,
+                obligation_outflow_window: 10,
+                global_allowed_borrow_value: lending_market.account.global_allowed_borrow_value,
+                global_unhealthy_borrow_value: lending_market.account.global_unhealthy_borrow_value,
             }
             .pack(),
         }],
@@ -144,6 +415,10 @@ async fn test_invalid_owner() {
             RateLimiterConfig::default(),
             None,
             new_risk_authority.pubkey(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap_err()
@@ -176,6 +451,12 @@ async fn test_owner_not_signer() {
                     rate_limiter_config: RateLimiterConfig::default(),
                     whitelisted_liquidator: None,
                     risk_authority: new_risk_authority.pubkey(),
+                    obligation_max_outflow: 0,
+                    obligation_outflow_window: 0,
+                    global_allowed_borrow_value: lending_market.account.global_allowed_borrow_value,
+                    global_unhealthy_borrow_value: lending_market
+                        .account
+                        .global_unhealthy_borrow_value,
                 }
                 .pack(),
             }],
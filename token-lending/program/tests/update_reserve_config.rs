@@ -62,6 +62,7 @@ async fn test_update_reserve_config_owner() {
     let new_rate_limiter_config = RateLimiterConfig {
         window_duration: 50,
         max_outflow: 100,
+        net_flow: false,
     };
 
     lending_market
@@ -116,23 +117,27 @@ async fn test_update_reserve_config_risk_authority() {
             lending_market.account.rate_limiter.config,
             lending_market.account.whitelisted_liquidator,
             risk_authority.keypair.pubkey(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
 
+    // every change here makes the reserve safer
     let new_reserve_config = ReserveConfig {
-        borrow_limit: 20, // this should get updated on the reserve (safer than previous
-        // value)
-        deposit_limit: 10001, // this should NOT get updated on the reserve (prev limit was
-        // safer)
-        liquidation_threshold: 60, // this should NOT get updated (risk authority can't change
-        // this)
+        borrow_limit: 20,
+        loan_to_value_ratio: 40,
+        added_borrow_weight_bps: 100,
+        attributed_borrow_limit_open: 1_000,
         ..wsol_reserve.account.config
     };
 
     let new_rate_limiter_config = RateLimiterConfig {
         window_duration: 50,
         max_outflow: 0,
+        net_flow: false,
     };
 
     lending_market
@@ -151,14 +156,93 @@ async fn test_update_reserve_config_risk_authority() {
     assert_eq!(
         wsol_reserve_post.account,
         Reserve {
-            config: ReserveConfig {
-                borrow_limit: 20,
-                ..wsol_reserve.account.config
-            },
+            config: new_reserve_config,
             rate_limiter: RateLimiter::new(new_rate_limiter_config, 1000),
             ..wsol_reserve.account
         }
     );
+
+    // unsafe changes are rejected instead of ignored
+    for unsafe_config in [
+        ReserveConfig {
+            deposit_limit: 10001,
+            ..new_reserve_config
+        },
+        ReserveConfig {
+            loan_to_value_ratio: 45,
+            ..new_reserve_config
+        },
+        ReserveConfig {
+            added_borrow_weight_bps: 50,
+            ..new_reserve_config
+        },
+        ReserveConfig {
+            liquidation_threshold: 60,
+            ..new_reserve_config
+        },
+    ] {
+        let res = lending_market
+            .update_reserve_config(
+                &mut test,
+                &risk_authority,
+                &wsol_reserve_post,
+                unsafe_config,
+                new_rate_limiter_config,
+                None,
+            )
+            .await
+            .unwrap_err()
+            .unwrap();
+        assert_eq!(
+            res,
+            TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(LendingError::RiskAuthorityChangeNotSafer as u32)
+            )
+        );
+    }
+
+    // re-enabling outflows is unsafe too
+    let res = lending_market
+        .update_reserve_config(
+            &mut test,
+            &risk_authority,
+            &wsol_reserve_post,
+            new_reserve_config,
+            RateLimiterConfig::default(),
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            1,
+            InstructionError::Custom(LendingError::RiskAuthorityChangeNotSafer as u32)
+        )
+    );
+
+    // zeroing the deposit and borrow limits makes the reserve reduce-only
+    let reduce_only_config = ReserveConfig {
+        deposit_limit: 0,
+        borrow_limit: 0,
+        ..new_reserve_config
+    };
+    lending_market
+        .update_reserve_config(
+            &mut test,
+            &risk_authority,
+            &wsol_reserve_post,
+            reduce_only_config,
+            new_rate_limiter_config,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let wsol_reserve_post = test.load_account::<Reserve>(wsol_reserve.pubkey).await;
+    assert_eq!(wsol_reserve_post.account.config, reduce_only_config);
 }
 
 #[tokio::test]
@@ -186,6 +270,7 @@ async fn test_update_invalid_oracle_config() {
     let new_rate_limiter_config = RateLimiterConfig {
         window_duration: 50,
         max_outflow: 100,
+        net_flow: false,
     };
 
     let switchboard_pubkey = test.init_switchboard_feed(&wsol_mint::id()).await;
@@ -308,6 +393,10 @@ async fn test_update_reserve_config_invalid_signers() {
             lending_market.account.rate_limiter.config,
             lending_market.account.whitelisted_liquidator,
             risk_authority.keypair.pubkey(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
@@ -321,6 +410,7 @@ async fn test_update_reserve_config_invalid_signers() {
     let new_rate_limiter_config = RateLimiterConfig {
         window_duration: 50,
         max_outflow: 0,
+        net_flow: false,
     };
 
     // case 1: try to update with a random user
@@ -410,6 +500,7 @@ pub fn malicious_update_reserve_config(
         data: LendingInstruction::UpdateReserveConfig {
             config,
             rate_limiter_config,
+            liquidation_threshold_ramp_slots: 0,
         }
         .pack(),
     }
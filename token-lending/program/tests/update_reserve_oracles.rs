@@ -0,0 +1,357 @@
+#![cfg(feature = "test-bpf")]
+
+mod helpers;
+
+use crate::solend_program_test::setup_world;
+use crate::solend_program_test::Info;
+use crate::solend_program_test::PriceArgs;
+use crate::solend_program_test::SolendProgramTest;
+use crate::solend_program_test::SwitchboardPriceArgs;
+use crate::solend_program_test::User;
+use bytemuck::Zeroable;
+use helpers::*;
+use solana_program::native_token::LAMPORTS_PER_SOL;
+use solana_program::system_instruction::transfer;
+use solana_program_test::*;
+use solana_sdk::{
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::TransactionError,
+};
+use solend_program::error::LendingError;
+use solend_program::instruction::update_reserve_oracles;
+use solend_program::state::{LendingMarket, MarketRoles, Reserve, ReserveConfig};
+use solend_program::NULL_PUBKEY;
+
+async fn setup() -> (
+    SolendProgramTest,
+    Info<LendingMarket>,
+    Info<Reserve>,
+    User,
+    Pubkey,
+    Pubkey,
+) {
+    let (mut test, lending_market, usdc_reserve, _, lending_market_owner, _) =
+        setup_world(&test_reserve_config(), &test_reserve_config()).await;
+
+    // the lending market owner pays for queued config changes
+    test.process_transaction(
+        &[transfer(
+            &test.context.payer.pubkey(),
+            &lending_market_owner.keypair.pubkey(),
+            LAMPORTS_PER_SOL,
+        )],
+        None,
+    )
+    .await
+    .unwrap();
+
+    let pyth_price_pubkey = test.init_pyth_feed(&usdt_mint::id()).await;
+    test.set_price(
+        &usdt_mint::id(),
+        &PriceArgs {
+            price: 1,
+            conf: 0,
+            expo: 0,
+            ema_price: 1,
+            ema_conf: 0,
+        },
+    )
+    .await;
+
+    let switchboard_feed_pubkey = test.init_switchboard_feed(&usdt_mint::id()).await;
+    test.set_switchboard_price(&usdt_mint::id(), SwitchboardPriceArgs { price: 1, expo: 0 })
+        .await;
+
+    (
+        test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+    )
+}
+
+#[tokio::test]
+async fn test_success() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+    ) = setup().await;
+    let extra_oracle_pubkey = test
+        .mints
+        .get(&wsol_mint::id())
+        .unwrap()
+        .unwrap()
+        .pyth_price_pubkey;
+
+    lending_market
+        .update_reserve_oracles(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve,
+            pyth_price_pubkey,
+            switchboard_feed_pubkey,
+            Some(extra_oracle_pubkey),
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.pyth_oracle_pubkey,
+        pyth_price_pubkey
+    );
+    assert_eq!(
+        usdc_reserve_post
+            .account
+            .liquidity
+            .switchboard_oracle_pubkey,
+        switchboard_feed_pubkey
+    );
+    // nothing else in the config changes
+    assert_eq!(
+        usdc_reserve_post.account.config,
+        ReserveConfig {
+            extra_oracle_pubkey: Some(extra_oracle_pubkey),
+            ..usdc_reserve.account.config
+        }
+    );
+
+    test.advance_clock_by_slots(1).await;
+    lending_market
+        .refresh_reserve(&mut test, &usdc_reserve_post)
+        .await
+        .unwrap();
+
+    // removing the secondary and extra oracles
+    lending_market
+        .update_reserve_oracles(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve_post,
+            pyth_price_pubkey,
+            NULL_PUBKEY,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post
+            .account
+            .liquidity
+            .switchboard_oracle_pubkey,
+        NULL_PUBKEY
+    );
+    assert_eq!(usdc_reserve_post.account.config.extra_oracle_pubkey, None);
+}
+
+#[tokio::test]
+async fn test_fail_feed_without_price() {
+    let (mut test, lending_market, usdc_reserve, lending_market_owner, _, switchboard_feed_pubkey) =
+        setup().await;
+
+    let unpriced_pyth_price_pubkey = test.init_pyth_feed(&bonk_mint::id()).await;
+
+    let res = lending_market
+        .update_reserve_oracles(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve,
+            unpriced_pyth_price_pubkey,
+            switchboard_feed_pubkey,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidOracleConfig as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_signers() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+    ) = setup().await;
+
+    let rando = User::new_with_keypair(Keypair::new());
+    let res = lending_market
+        .update_reserve_oracles(
+            &mut test,
+            &rando,
+            &usdc_reserve,
+            pyth_price_pubkey,
+            switchboard_feed_pubkey,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::InvalidSigner as u32)
+        )
+    );
+
+    let oracle_admin = User::new_with_keypair(Keypair::new());
+    let fee_admin = User::new_with_keypair(Keypair::new());
+    lending_market
+        .update_market_roles(
+            &mut test,
+            &lending_market_owner,
+            MarketRoles {
+                oracle_admin: oracle_admin.keypair.pubkey(),
+                fee_admin: fee_admin.keypair.pubkey(),
+                ..MarketRoles::zeroed()
+            },
+        )
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .update_reserve_oracles(
+            &mut test,
+            &fee_admin,
+            &usdc_reserve,
+            pyth_price_pubkey,
+            switchboard_feed_pubkey,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::MissingMarketRole as u32)
+        )
+    );
+
+    lending_market
+        .update_reserve_oracles(
+            &mut test,
+            &oracle_admin,
+            &usdc_reserve,
+            pyth_price_pubkey,
+            switchboard_feed_pubkey,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.pyth_oracle_pubkey,
+        pyth_price_pubkey
+    );
+}
+
+#[tokio::test]
+async fn test_config_timelock() {
+    let (
+        mut test,
+        lending_market,
+        usdc_reserve,
+        lending_market_owner,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+    ) = setup().await;
+
+    lending_market
+        .set_config_timelock(&mut test, &lending_market_owner, 100)
+        .await
+        .unwrap();
+
+    let res = lending_market
+        .update_reserve_oracles(
+            &mut test,
+            &lending_market_owner,
+            &usdc_reserve,
+            pyth_price_pubkey,
+            switchboard_feed_pubkey,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        res,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(LendingError::ConfigChangeTimelocked as u32)
+        )
+    );
+
+    let change = update_reserve_oracles(
+        solend_program::id(),
+        None,
+        None,
+        usdc_reserve.pubkey,
+        lending_market.pubkey,
+        lending_market_owner.keypair.pubkey(),
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+    );
+    lending_market
+        .queue_config_change(&mut test, &lending_market_owner, &change)
+        .await
+        .unwrap();
+
+    test.advance_clock_by_slots(100).await;
+
+    // the new feeds are read when the change executes
+    test.set_price(
+        &usdt_mint::id(),
+        &PriceArgs {
+            price: 1,
+            conf: 0,
+            expo: 0,
+            ema_price: 1,
+            ema_conf: 0,
+        },
+    )
+    .await;
+    test.set_switchboard_price(&usdt_mint::id(), SwitchboardPriceArgs { price: 1, expo: 0 })
+        .await;
+
+    lending_market
+        .execute_config_change(&mut test, lending_market_owner.keypair.pubkey(), &change)
+        .await
+        .unwrap();
+
+    let usdc_reserve_post = test.load_account::<Reserve>(usdc_reserve.pubkey).await;
+    assert_eq!(
+        usdc_reserve_post.account.liquidity.pyth_oracle_pubkey,
+        pyth_price_pubkey
+    );
+    assert_eq!(
+        usdc_reserve_post
+            .account
+            .liquidity
+            .switchboard_oracle_pubkey,
+        switchboard_feed_pubkey
+    );
+}
@@ -205,6 +205,7 @@ async fn test_withdraw_max_rate_limiter() {
             RateLimiterConfig {
                 window_duration: 20,
                 max_outflow: 20 * LAMPORTS_PER_SOL,
+                net_flow: false,
             },
             None,
         )
@@ -248,9 +249,14 @@ async fn test_withdraw_max_rate_limiter() {
             RateLimiterConfig {
                 window_duration: 20,
                 max_outflow: 50, // $50
+                net_flow: false,
             },
             None,
             Pubkey::new_unique(),
+            0,
+            0,
+            lending_market.account.global_allowed_borrow_value,
+            lending_market.account.global_unhealthy_borrow_value,
         )
         .await
         .unwrap();
@@ -183,8 +183,8 @@ pub enum LendingError {
     /// No cpi flash repays allowed
     #[error("No cpi flash repays allowed")]
     FlashRepayCpi,
-    /// Multiple flash borrows not allowed in the same transaction
-    #[error("Multiple flash borrows not allowed in the same transaction")]
+    /// Multiple flash borrows from the same reserve not allowed in the same transaction
+    #[error("Multiple flash borrows from the same reserve not allowed in the same transaction")]
     MultipleFlashBorrows,
     /// Flash loans are disabled for this reserve
     #[error("Flash loans are disabled for this reserve")]
@@ -209,6 +209,33 @@ pub enum LendingError {
     /// Borrow Attribution Limit Not Exceeded
     #[error("Borrow Attribution Limit Not Exceeded")]
     BorrowAttributionLimitNotExceeded,
+    /// Borrows are halted while a pegged reserve is depegged
+    #[error("Reserve is depegged")]
+    ReserveDepegged,
+    /// The lending market has a config timelock, so the change has to be queued
+    #[error("Config change must be queued")]
+    ConfigChangeTimelocked,
+    /// A queued config change was executed before its earliest execution slot
+    #[error("Queued config change is not executable yet")]
+    ConfigChangeNotReady,
+    /// The signer doesn't hold the market role that governs a field it tried to change
+    #[error("Signer is missing the market role for this change")]
+    MissingMarketRole,
+    /// The risk authority tried to change a reserve config field in an unsafe direction
+    #[error("Risk authority can only make a reserve config safer")]
+    RiskAuthorityChangeNotSafer,
+    /// The lending market is paused
+    #[error("Lending market is paused")]
+    MarketPaused,
+    /// A reserve config change that wasn't queued exceeds the market's config delta bounds
+    #[error("Reserve config change exceeds the market's config delta bounds")]
+    ConfigChangeExceedsBounds,
+    /// The reserve still has borrows, collateral or unredeemed protocol fees
+    #[error("Reserve must have no borrows, collateral or protocol fees left to be closed")]
+    ReserveNotEmpty,
+    /// Token close account failed
+    #[error("Token close account failed")]
+    TokenCloseAccountFailed,
 }
 
 impl From<LendingError> for ProgramError {
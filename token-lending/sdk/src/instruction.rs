@@ -1,6 +1,9 @@
 //! Instruction types
 
-use crate::state::{LendingMarketMetadata, ReserveType};
+use crate::state::{
+    ConfigDeltaBounds, FlashLoanAllowlist, LendingMarketMetadata, MarketRoles, PegMode,
+    ProtocolConfig, ReserveType,
+};
 use crate::{
     error::LendingError,
     state::{RateLimiterConfig, ReserveConfig, ReserveFees},
@@ -11,6 +14,7 @@ use std::convert::TryFrom;
 use num_traits::FromPrimitive;
 use solana_program::system_program;
 use solana_program::{
+    bpf_loader_upgradeable,
     instruction::{AccountMeta, Instruction},
     msg,
     program_error::ProgramError,
@@ -19,6 +23,12 @@ use solana_program::{
 };
 use std::{convert::TryInto, mem::size_of};
 
+/// Maximum number of legs in a `LiquidateObligationBatch` instruction
+pub const MAX_LIQUIDATION_BATCH_LEGS: usize = 6;
+
+/// Number of accounts each leg of a `LiquidateObligationBatch` instruction takes
+pub const LIQUIDATION_BATCH_LEG_ACCOUNTS: usize = 10;
+
 /// Instructions supported by the lending program.
 #[derive(Clone, Debug, PartialEq, Eq)]
 // #[allow(clippy::large_enum_variant)]
@@ -48,8 +58,15 @@ pub enum LendingInstruction {
     ///
     ///   0. `[writable]` Lending market account.
     ///   1. `[signer]` Current owner.
+    ///   2. `[]` Config timelock account (optional).
+    ///             Must be a pda with seeds [lending_market, "ConfigTimelock"], may be uninitialized.
+    ///             Can only be left out while the lending market doesn't have one.
+    ///   3. `[]` Market roles account (optional).
+    ///             Must be a pda with seeds [lending_market, "MarketRoles"], may be uninitialized.
+    ///             No role is assigned if it's left out.
     SetLendingMarketOwnerAndConfig {
-        /// The new owner
+        /// Must be the current owner unless the risk authority or a market role signs, ownership
+        /// is transferred with `ProposeMarketOwner` and `AcceptMarketOwner`
         new_owner: Pubkey,
         /// The new config
         rate_limiter_config: RateLimiterConfig,
@@ -57,6 +74,20 @@ pub enum LendingInstruction {
         whitelisted_liquidator: Option<Pubkey>,
         /// The risk authority
         risk_authority: Pubkey,
+        /// Max value of borrows and withdraws out of a single obligation per obligation outflow
+        /// window, in whole units of the quote currency. 0 disables the per obligation limit.
+        /// Liquidations aren't limited or counted
+        obligation_max_outflow: u32,
+        /// Length in slots of the sliding window obligation outflows are limited in. Can only be 0
+        /// while the per obligation limit is disabled. Like the max outflow, it has to be a u8
+        /// times a power of ten
+        obligation_outflow_window: u64,
+        /// Cap on the allowed borrow value of every obligation, in whole units of the quote
+        /// currency. The risk authority can only lower it
+        global_allowed_borrow_value: u64,
+        /// Cap on the unhealthy borrow value of every obligation, in whole units of the quote
+        /// currency. The risk authority can only lower it
+        global_unhealthy_borrow_value: u64,
     },
 
     // 2
@@ -75,7 +106,8 @@ pub enum LendingInstruction {
     ///   7 `[writable]` Reserve collateral token supply - uninitialized.
     ///   8. `[]` Pyth product account.
     ///   9. `[]` Pyth price account.
-    ///             This will be used as the reserve liquidity oracle account.
+    ///             This will be used as the reserve liquidity oracle account. For stake pool
+    ///             reserves this is the stake pool account.
     ///   10. `[]` Switchboard price feed account. used as a backup oracle
     ///   11 `[]` Lending market account.
     ///   12 `[]` Derived lending market authority.
@@ -84,6 +116,12 @@ pub enum LendingInstruction {
     ///   15 `[]` Clock sysvar (optional, will be removed soon).
     ///   16 `[]` Rent sysvar.
     ///   17 `[]` Token program id.
+    ///   .. `[]` Extra oracle account, if config.extra_oracle_pubkey is set.
+    ///   .. `[]` Underlying oracle account, if config.underlying_oracle_pubkey is set.
+    ///   .. `[]` Protocol config account.
+    ///             Must be a pda with seeds ["ProtocolConfig"], may be uninitialized.
+    ///             Reserves on markets not owned by its authority must start with its default
+    ///             protocol fees.
     InitReserve {
         /// Initial amount of liquidity to deposit into the new reserve
         liquidity_amount: u64,
@@ -101,7 +139,10 @@ pub enum LendingInstruction {
     ///             Must be the Pyth price account specified at InitReserve.
     ///   2. `[]` Switchboard Reserve liquidity oracle account.
     ///             Must be the Switchboard price feed account specified at InitReserve.
-    ///   3. `[]` Clock sysvar (optional, will be removed soon).
+    ///   3. `[]` Extra oracle account, if the reserve has one.
+    ///   4. `[]` Underlying oracle account, if the reserve is priced off a stake pool.
+    ///   5. `[]` Lending market account, if the market uses oracle programs other than the
+    ///             mainnet ones.
     RefreshReserve,
 
     // 4
@@ -116,7 +157,7 @@ pub enum LendingInstruction {
     ///   2. `[writable]` Reserve account.
     ///   3. `[writable]` Reserve liquidity supply SPL Token account.
     ///   4. `[writable]` Reserve collateral SPL Token mint.
-    ///   5. `[]` Lending market account.
+    ///   5. `[writable]` Lending market account. Must be writable if its rate limiter is in net flow mode.
     ///   6. `[]` Derived lending market authority.
     ///   7. `[signer]` User transfer authority ($authority).
     ///   8. `[]` Clock sysvar (optional, will be removed soon).
@@ -171,6 +212,9 @@ pub enum LendingInstruction {
     ///   1. `[]` Clock sysvar (optional, will be removed soon).
     ///   .. `[writable]` Collateral deposit reserve accounts - refreshed, all, in order.
     ///   .. `[]` Liquidity borrow reserve accounts - refreshed, all, in order.
+    ///   .. `[]` Lending market account (optional).
+    ///             The default global borrow values apply if it isn't provided, and borrows,
+    ///             withdraws and liquidations fail if the market configures other ones.
     RefreshObligation,
 
     // 8
@@ -250,7 +294,7 @@ pub enum LendingInstruction {
     ///   1. `[writable]` Destination repay reserve liquidity supply SPL Token account.
     ///   2. `[writable]` Repay reserve account - refreshed.
     ///   3. `[writable]` Obligation account - refreshed.
-    ///   4. `[]` Lending market account.
+    ///   4. `[writable]` Lending market account. Must be writable if its rate limiter is in net flow mode.
     ///   5. `[signer]` User transfer authority ($authority).
     ///   6. `[]` Clock sysvar (optional, will be removed soon).
     ///   7. `[]` Token program id.
@@ -338,7 +382,7 @@ pub enum LendingInstruction {
     ///   2. `[writable]` Reserve account.
     ///   3. `[writable]` Reserve liquidity supply SPL Token account.
     ///   4. `[writable]` Reserve collateral SPL Token mint.
-    ///   5. `[]` Lending market account.
+    ///   5. `[writable]` Lending market account. Must be writable if its rate limiter is in net flow mode.
     ///   6. `[]` Derived lending market authority.
     ///   7. `[writable]` Destination deposit reserve collateral supply SPL Token account.
     ///   8. `[writable]` Obligation account.
@@ -389,11 +433,27 @@ pub enum LendingInstruction {
     ///   5 `[]` Pyth product key.
     ///   6 `[]` Pyth price key.
     ///   7 `[]` Switchboard key.
+    ///   .. `[]` Extra oracle key, if the config has one.
+    ///   .. `[]` Underlying oracle key, if the config has one.
+    ///   .. `[writable]` Config timelock account (optional).
+    ///             Must be a pda with seeds [lending_market, "ConfigTimelock"], may be uninitialized.
+    ///             Changes that aren't queued have to stay within its config delta bounds. Can
+    ///             only be left out while the lending market doesn't have one.
+    ///   .. `[]` Market roles account (optional).
+    ///             Must be a pda with seeds [lending_market, "MarketRoles"], may be uninitialized.
+    ///             No role is assigned if it's left out.
+    ///   .. `[]` Protocol config account (optional).
+    ///             Must be a pda with seeds ["ProtocolConfig"], may be uninitialized.
+    ///             The solend market owner acts as its authority until it's initialized, or if
+    ///             it's left out. Protocol fees can't be changed without it.
     UpdateReserveConfig {
         /// Reserve config to update to
         config: ReserveConfig,
         /// Rate limiter config
         rate_limiter_config: RateLimiterConfig,
+        /// Number of slots over which lowered liquidation thresholds take effect, 0 applies
+        /// them immediately
+        liquidation_threshold_ramp_slots: u64,
     },
 
     // 17
@@ -436,7 +496,9 @@ pub enum LendingInstruction {
     RedeemFees,
 
     // 19
-    /// Flash borrow reserve liquidity
+    /// Flash borrow reserve liquidity. A transaction can flash borrow from several reserves, but
+    /// only once from each, and every flash borrow needs exactly one matching flash repay later in
+    /// the transaction.
     //
     /// Accounts expected by this instruction:
     ///
@@ -469,6 +531,10 @@ pub enum LendingInstruction {
     ///   6. `[signer]` User transfer authority ($authority).
     ///   7. `[]` Instructions sysvar.
     ///   8. `[]` Token program id.
+    ///   9. `[]` Flash loan allowlist account (optional).
+    ///             Must be a pda with seeds [lending_market, "FlashLoanAllowlist"].
+    ///             The flash loan fee is discounted if $authority is on the allowlist, or if
+    ///             every instruction between the flash borrow and this one calls a program on it.
     FlashRepayReserveLiquidity {
         /// Amount of liquidity to flash repay
         liquidity_amount: u64,
@@ -512,6 +578,303 @@ pub enum LendingInstruction {
         /// Obligation is closable
         closeable: bool,
     },
+
+    // 24
+    /// UpdateFlashLoanAllowlist
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[]` Lending market account.
+    /// 1. `[signer]` Lending market owner.
+    /// 2. `[writable]` Flash loan allowlist account.
+    /// Must be a pda with seeds [lending_market, "FlashLoanAllowlist"]
+    /// 3. `[]` System program
+    UpdateFlashLoanAllowlist,
+
+    // 25
+    /// Deposit liquidity into a reserve as obligation collateral, then repeatedly borrow the same
+    /// token from another reserve and deposit it back until the obligation reaches a target loan
+    /// to value. Every borrow is capped by the obligation's allowed borrow value, the borrow
+    /// reserve's borrow limit and the rate limiters, so the loop can stop short of the target.
+    /// Requires a refreshed obligation and reserves.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Source liquidity token account.
+    ///                     $authority can transfer $liquidity_amount.
+    ///                     Receives each borrow before it is deposited again.
+    ///   1. `[writable]` User collateral token account.
+    ///                     Minted by deposit reserve collateral mint.
+    ///   2. `[writable]` Deposit reserve account - refreshed.
+    ///   3. `[writable]` Deposit reserve liquidity supply SPL Token account.
+    ///   4. `[writable]` Deposit reserve collateral SPL Token mint.
+    ///   5. `[writable]` Deposit reserve collateral supply SPL Token account.
+    ///   6. `[writable]` Borrow reserve account - refreshed.
+    ///                     Must have the same liquidity mint as the deposit reserve.
+    ///   7. `[writable]` Borrow reserve liquidity supply SPL Token account.
+    ///   8. `[writable]` Borrow reserve liquidity fee receiver account.
+    ///   9. `[writable]` Obligation account - refreshed.
+    ///   10 `[writable]` Lending market account.
+    ///   11 `[]` Derived lending market authority.
+    ///   12 `[signer]` Obligation owner.
+    ///   13 `[signer]` User transfer authority ($authority).
+    ///   14 `[]` Token program id.
+    ///   15.. `[writable]` Obligation deposit reserves, in order, once the deposit reserve has
+    ///                     been added.
+    ///   .. `[writable]` Obligation borrow reserves other than the borrow reserve, in order.
+    ///                     The obligation is refreshed against all of them after every deposit.
+    ///   .. `[optional, writable]` Host fee receiver account.
+    LeverageLoop {
+        /// Amount of liquidity to deposit before borrowing
+        liquidity_amount: u64,
+        /// Target loan to value of the obligation (borrowed value / deposited value), in percent
+        target_ltv: u8,
+    },
+
+    // 26
+    /// Liquidate an unhealthy obligation across several (repay reserve, withdraw reserve) legs in
+    /// one instruction, redeeming the collateral seized by each leg. The obligation's health is
+    /// checked once, before the first leg, and the close factor applies to the borrowed value
+    /// repaid by all legs together: once it is used up the remaining legs are skipped. As with
+    /// `LiquidateObligationAndRedeemReserveCollateral`, each leg must repay the obligation's first
+    /// borrow, which moves on to the next borrow once it is fully repaid. Requires a refreshed
+    /// obligation and reserves.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Obligation account - refreshed.
+    ///   1. `[]` Lending market account.
+    ///   2. `[]` Derived lending market authority.
+    ///   3. `[signer]` User transfer authority ($authority).
+    ///   4. `[]` Token program id.
+    ///
+    ///   Then for each leg:
+    ///
+    ///   0. `[writable]` Source liquidity token account.
+    ///                     Minted by repay reserve liquidity mint.
+    ///                     $authority can transfer the leg's liquidity amount.
+    ///   1. `[writable]` Destination collateral token account.
+    ///                     Minted by withdraw reserve collateral mint.
+    ///   2. `[writable]` Destination liquidity token account.
+    ///   3. `[writable]` Repay reserve account - refreshed.
+    ///   4. `[writable]` Repay reserve liquidity supply SPL Token account.
+    ///   5. `[writable]` Withdraw reserve account - refreshed.
+    ///   6. `[writable]` Withdraw reserve collateral SPL Token mint.
+    ///   7. `[writable]` Withdraw reserve collateral supply SPL Token account.
+    ///   8. `[writable]` Withdraw reserve liquidity supply SPL Token account.
+    ///   9. `[writable]` Withdraw reserve liquidity fee receiver account.
+    LiquidateObligationBatch {
+        /// Amount of liquidity to repay in each leg - u64::MAX for up to 100% of the borrowed
+        /// amount
+        liquidity_amounts: Vec<u64>,
+    },
+
+    // 27
+    /// Sets the delay the lending market owner's `UpdateReserveConfig`, `UpdateReserveOracles`,
+    /// `SetLendingMarketOwnerAndConfig` and `UpdateMarketRoles` changes have to be queued for.
+    /// Raising the delay takes effect immediately, lowering it has to be queued itself.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[writable, signer]` Lending market owner.
+    ///   2. `[writable]` Config timelock account.
+    ///             Must be a pda with seeds [lending_market, "ConfigTimelock"].
+    ///   3. `[]` System program
+    SetConfigTimelock {
+        /// Slots between queueing a config change and executing it
+        delay_slots: u64,
+    },
+
+    // 28
+    /// Queues an `UpdateReserveConfig`, `UpdateReserveOracles`, `SetLendingMarketOwnerAndConfig`,
+    /// `SetConfigTimelock`, `SetConfigDeltaBounds` or `UpdateMarketRoles` instruction signed by the
+    /// lending market owner, to be executed with `ExecuteConfigChange`
+    /// once the market's config timelock delay has passed. Only one change can be queued per
+    /// reserve, and one per lending market.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable, signer]` Lending market owner.
+    ///   2. `[]` Config timelock account.
+    ///             Must be a pda with seeds [lending_market, "ConfigTimelock"], may be uninitialized.
+    ///   3. `[writable]` Pending config change account - uninitialized.
+    ///             Must be a pda with seeds [target, "PendingConfigChange"], where the target is
+    ///             the reserve for `UpdateReserveConfig` and `UpdateReserveOracles` and the
+    ///             lending market otherwise.
+    ///   4. `[]` System program
+    ///   5.. `[]` Accounts of the queued instruction, in order.
+    QueueConfigChange {
+        /// Instruction to execute
+        change: Box<LendingInstruction>,
+    },
+
+    // 29
+    /// Executes a queued config change once its earliest execution slot is reached. Can be called
+    /// by anyone.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Pending config change account.
+    ///   1. `[writable]` Lending market owner that queued the change, refunded the rent.
+    ///   2.. Accounts of the queued instruction, in order. None of them have to sign.
+    ExecuteConfigChange,
+
+    // 30
+    /// Cancels a queued config change.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[signer]` Lending market owner or risk authority.
+    ///   2. `[writable]` Pending config change account.
+    ///   3. `[writable]` Lending market owner that queued the change, refunded the rent.
+    CancelConfigChange,
+
+    // 31
+    /// UpdateMarketRoles. While the lending market has a config timelock, role changes must be
+    /// queued with `QueueConfigChange`, once the market roles account exists.
+    ///
+    /// Accounts expected by this instruction:
+    /// 0. `[]` Lending market account.
+    /// 1. `[writable, signer]` Lending market owner.
+    /// 2. `[writable]` Market roles account.
+    /// Must be a pda with seeds [lending_market, "MarketRoles"]
+    /// 3. `[]` System program
+    /// 4. `[]` Config timelock account.
+    /// Must be a pda with seeds [lending_market, "ConfigTimelock"], may be uninitialized.
+    UpdateMarketRoles,
+
+    // 32
+    /// Proposes a new lending market owner, replacing any earlier proposal. Ownership is only
+    /// transferred once the proposed owner signs `AcceptMarketOwner`.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[]` Lending market account.
+    ///   1. `[writable, signer]` Lending market owner.
+    ///   2. `[writable]` Pending market owner account.
+    ///             Must be a pda with seeds [lending_market, "PendingMarketOwner"].
+    ///   3. `[]` System program
+    ProposeMarketOwner {
+        /// Proposed owner
+        new_owner: Pubkey,
+    },
+
+    // 33
+    /// Makes the proposed lending market owner the owner.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[signer]` Proposed lending market owner.
+    ///   2. `[writable]` Pending market owner account.
+    ///             Must be a pda with seeds [lending_market, "PendingMarketOwner"].
+    ///   3. `[writable]` Lending market owner that proposed the transfer, refunded the rent.
+    AcceptMarketOwner,
+
+    // 34
+    /// Pauses or unpauses a lending market. While it's paused, deposits, borrows, withdraws,
+    /// flash borrows and liquidations fail with `MarketPaused`. The lending market owner, risk
+    /// authority and pause guardian can pause it, only the owner can unpause it.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[signer]` Lending market owner, risk authority or pause guardian.
+    ///   2. `[]` Market roles account.
+    ///             Must be a pda with seeds [lending_market, "MarketRoles"], may be uninitialized.
+    SetMarketPaused {
+        /// Whether the lending market is paused
+        paused: bool,
+    },
+
+    // 35
+    /// Initializes the program's protocol config. Can only be done once, by the lending program's
+    /// upgrade authority.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Protocol config account - uninitialized.
+    ///             Must be a pda with seeds ["ProtocolConfig"].
+    ///   1. `[writable, signer]` Lending program upgrade authority.
+    ///   2. `[]` Lending program data account.
+    ///   3. `[]` System program
+    InitProtocolConfig,
+
+    // 36
+    /// Updates the program's protocol config, including its protocol authority.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Protocol config account.
+    ///             Must be a pda with seeds ["ProtocolConfig"].
+    ///   1. `[signer]` Protocol authority.
+    UpdateProtocolConfig,
+
+    // 37
+    /// Sets or removes the bounds on reserve config changes that aren't queued. Tightening the
+    /// bounds takes effect immediately, loosening or removing them has to be queued.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Lending market account.
+    ///   1. `[writable, signer]` Lending market owner.
+    ///   2. `[writable]` Config timelock account.
+    ///             Must be a pda with seeds [lending_market, "ConfigTimelock"].
+    ///   3. `[]` System program
+    SetConfigDeltaBounds {
+        /// Bounds to enforce, None to remove them
+        delta_bounds: Option<ConfigDeltaBounds>,
+    },
+
+    // 38
+    /// Closes a reserve that has no borrows, no collateral and no unredeemed protocol fees left,
+    /// refunding the rent of the reserve account and its liquidity and collateral supplies. Any
+    /// tokens left in the liquidity supply are sent to the fee receiver. If the fee receiver is
+    /// owned by the derived lending market authority, its tokens are sent to the destination
+    /// liquidity account and it's closed too. The collateral mint can't be closed by the token
+    /// program and is left as is.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[writable]` Reserve liquidity supply SPL Token account.
+    ///   2. `[writable]` Reserve liquidity fee receiver.
+    ///   3. `[]` Reserve collateral SPL Token mint.
+    ///   4. `[writable]` Reserve collateral supply SPL Token account.
+    ///   5. `[]` Lending market account.
+    ///   6. `[]` Derived lending market authority.
+    ///   7. `[signer]` Lending market owner.
+    ///   8. `[writable]` Rent destination.
+    ///   9. `[writable]` Destination liquidity token account.
+    ///                     Minted by reserve liquidity mint.
+    ///   10 `[]` Token program id.
+    CloseReserve,
+
+    // 39
+    /// Rotates a reserve's price oracles without resending its config. The primary and secondary
+    /// oracles are taken from the accounts and the extra oracle from the instruction data. Every
+    /// oracle the reserve ends up with has to return a price.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` Reserve account.
+    ///   1. `[]` Lending market account.
+    ///   2. `[signer]` Lending market owner, or a holder of the oracle admin market role.
+    ///   3. `[]` Pyth price key, or the null pubkey.
+    ///   4. `[]` Switchboard key, or the null pubkey.
+    ///   5. `[]` Config timelock account.
+    ///             Must be a pda with seeds [lending_market, "ConfigTimelock"], may be uninitialized.
+    ///             The change has to be queued if the lending market has a config timelock.
+    ///   6. `[]` Market roles account.
+    ///             Must be a pda with seeds [lending_market, "MarketRoles"], may be uninitialized.
+    ///   7. `[]` Extra oracle key, if one is set.
+    ///   8. `[]` Underlying oracle key, if the reserve config has one.
+    UpdateReserveOracles {
+        /// Extra oracle to set, None to remove it
+        extra_oracle_pubkey: Option<Pubkey>,
+    },
 }
 
 impl LendingInstruction {
@@ -533,6 +896,11 @@ impl LendingInstruction {
                 let (new_owner, rest) = Self::unpack_pubkey(rest)?;
                 let (window_duration, rest) = Self::unpack_u64(rest)?;
                 let (max_outflow, rest) = Self::unpack_u64(rest)?;
+                let (net_flow, rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (false, rest),
+                    (1, rest) => (true, rest),
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
                 let (whitelisted_liquidator, rest) = match Self::unpack_u8(rest)? {
                     (0, rest) => (None, rest),
                     (1, rest) => {
@@ -542,15 +910,24 @@ impl LendingInstruction {
                     _ => return Err(LendingError::InstructionUnpackError.into()),
                 };
 
-                let (risk_authority, _rest) = Self::unpack_pubkey(rest)?;
+                let (risk_authority, rest) = Self::unpack_pubkey(rest)?;
+                let (obligation_max_outflow, rest) = Self::unpack_u32(rest)?;
+                let (obligation_outflow_window, rest) = Self::unpack_u64(rest)?;
+                let (global_allowed_borrow_value, rest) = Self::unpack_u64(rest)?;
+                let (global_unhealthy_borrow_value, _rest) = Self::unpack_u64(rest)?;
                 Self::SetLendingMarketOwnerAndConfig {
                     new_owner,
                     rate_limiter_config: RateLimiterConfig {
                         window_duration,
                         max_outflow,
+                        net_flow,
                     },
                     whitelisted_liquidator,
                     risk_authority,
+                    obligation_max_outflow,
+                    obligation_outflow_window,
+                    global_allowed_borrow_value,
+                    global_unhealthy_borrow_value,
                 }
             }
             2 => {
@@ -586,7 +963,17 @@ impl LendingInstruction {
                     _ => return Err(LendingError::InstructionUnpackError.into()),
                 };
                 let (attributed_borrow_limit_open, rest) = Self::unpack_u64(rest)?;
-                let (attributed_borrow_limit_close, _rest) = Self::unpack_u64(rest)?;
+                let (attributed_borrow_limit_close, rest) = Self::unpack_u64(rest)?;
+                let (underlying_oracle_pubkey, rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (None, rest),
+                    (1, rest) => {
+                        let (pubkey, rest) = Self::unpack_pubkey(rest)?;
+                        (Some(pubkey), rest)
+                    }
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                let (peg_mode, rest) = Self::unpack_u8(rest)?;
+                let (peg_max_deviation_bps, _rest) = Self::unpack_u16(rest)?;
                 Self::InitReserve {
                     liquidity_amount,
                     config: ReserveConfig {
@@ -617,6 +1004,10 @@ impl LendingInstruction {
                         extra_oracle_pubkey,
                         attributed_borrow_limit_open,
                         attributed_borrow_limit_close,
+                        underlying_oracle_pubkey,
+                        peg_mode: PegMode::from_u8(peg_mode)
+                            .ok_or(LendingError::InstructionUnpackError)?,
+                        peg_max_deviation_bps,
                     },
                 }
             }
@@ -696,8 +1087,24 @@ impl LendingInstruction {
                 };
                 let (attributed_borrow_limit_open, rest) = Self::unpack_u64(rest)?;
                 let (attributed_borrow_limit_close, rest) = Self::unpack_u64(rest)?;
+                let (underlying_oracle_pubkey, rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (None, rest),
+                    (1, rest) => {
+                        let (pubkey, rest) = Self::unpack_pubkey(rest)?;
+                        (Some(pubkey), rest)
+                    }
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                let (peg_mode, rest) = Self::unpack_u8(rest)?;
+                let (peg_max_deviation_bps, rest) = Self::unpack_u16(rest)?;
                 let (window_duration, rest) = Self::unpack_u64(rest)?;
-                let (max_outflow, _rest) = Self::unpack_u64(rest)?;
+                let (max_outflow, rest) = Self::unpack_u64(rest)?;
+                let (net_flow, rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (false, rest),
+                    (1, rest) => (true, rest),
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                let (liquidation_threshold_ramp_slots, _rest) = Self::unpack_u64(rest)?;
 
                 Self::UpdateReserveConfig {
                     config: ReserveConfig {
@@ -728,11 +1135,17 @@ impl LendingInstruction {
                         extra_oracle_pubkey,
                         attributed_borrow_limit_open,
                         attributed_borrow_limit_close,
+                        underlying_oracle_pubkey,
+                        peg_mode: PegMode::from_u8(peg_mode)
+                            .ok_or(LendingError::InstructionUnpackError)?,
+                        peg_max_deviation_bps,
                     },
                     rate_limiter_config: RateLimiterConfig {
                         window_duration,
                         max_outflow,
+                        net_flow,
                     },
+                    liquidation_threshold_ramp_slots,
                 }
             }
             17 => {
@@ -766,6 +1179,101 @@ impl LendingInstruction {
 
                 Self::SetObligationCloseabilityStatus { closeable }
             }
+            24 => Self::UpdateFlashLoanAllowlist,
+            25 => {
+                let (liquidity_amount, rest) = Self::unpack_u64(rest)?;
+                let (target_ltv, _rest) = Self::unpack_u8(rest)?;
+                Self::LeverageLoop {
+                    liquidity_amount,
+                    target_ltv,
+                }
+            }
+            26 => {
+                let (leg_count, mut rest) = Self::unpack_u8(rest)?;
+                let mut liquidity_amounts = Vec::with_capacity(leg_count as usize);
+                for _ in 0..leg_count {
+                    let (liquidity_amount, next) = Self::unpack_u64(rest)?;
+                    liquidity_amounts.push(liquidity_amount);
+                    rest = next;
+                }
+                Self::LiquidateObligationBatch { liquidity_amounts }
+            }
+            27 => {
+                let (delay_slots, _rest) = Self::unpack_u64(rest)?;
+                Self::SetConfigTimelock { delay_slots }
+            }
+            28 => {
+                let change = Self::unpack(rest)?;
+                match change {
+                    Self::UpdateReserveConfig { .. }
+                    | Self::SetLendingMarketOwnerAndConfig { .. }
+                    | Self::SetConfigTimelock { .. }
+                    | Self::SetConfigDeltaBounds { .. }
+                    | Self::UpdateReserveOracles { .. }
+                    | Self::UpdateMarketRoles => {}
+                    _ => {
+                        msg!("Instruction cannot be queued as a config change");
+                        return Err(LendingError::InstructionUnpackError.into());
+                    }
+                }
+                Self::QueueConfigChange {
+                    change: Box::new(change),
+                }
+            }
+            29 => Self::ExecuteConfigChange,
+            30 => Self::CancelConfigChange,
+            31 => Self::UpdateMarketRoles,
+            32 => {
+                let (new_owner, _rest) = Self::unpack_pubkey(rest)?;
+                Self::ProposeMarketOwner { new_owner }
+            }
+            33 => Self::AcceptMarketOwner,
+            34 => {
+                let (paused, _rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (false, rest),
+                    (1, rest) => (true, rest),
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                Self::SetMarketPaused { paused }
+            }
+            35 => Self::InitProtocolConfig,
+            36 => Self::UpdateProtocolConfig,
+            37 => {
+                let (delta_bounds, _rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (None, rest),
+                    (1, rest) => {
+                        let (max_loan_to_value_ratio_decrease, rest) = Self::unpack_u8(rest)?;
+                        let (max_liquidation_threshold_decrease, rest) = Self::unpack_u8(rest)?;
+                        let (max_added_borrow_weight_bps_increase, rest) = Self::unpack_u64(rest)?;
+                        let (interval_slots, rest) = Self::unpack_u64(rest)?;
+                        (
+                            Some(ConfigDeltaBounds {
+                                max_loan_to_value_ratio_decrease,
+                                max_liquidation_threshold_decrease,
+                                max_added_borrow_weight_bps_increase,
+                                interval_slots,
+                            }),
+                            rest,
+                        )
+                    }
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                Self::SetConfigDeltaBounds { delta_bounds }
+            }
+            38 => Self::CloseReserve,
+            39 => {
+                let (extra_oracle_pubkey, _rest) = match Self::unpack_u8(rest)? {
+                    (0, rest) => (None, rest),
+                    (1, rest) => {
+                        let (pubkey, rest) = Self::unpack_pubkey(rest)?;
+                        (Some(pubkey), rest)
+                    }
+                    _ => return Err(LendingError::InstructionUnpackError.into()),
+                };
+                Self::UpdateReserveOracles {
+                    extra_oracle_pubkey,
+                }
+            }
             _ => {
                 msg!("Instruction cannot be unpacked");
                 return Err(LendingError::InstructionUnpackError.into());
@@ -801,6 +1309,34 @@ impl LendingInstruction {
         Ok((value, rest))
     }
 
+    fn unpack_u32(input: &[u8]) -> Result<(u32, &[u8]), ProgramError> {
+        if input.len() < 4 {
+            msg!("u32 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(4);
+        let value = bytes
+            .get(..4)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u32::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
+    fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+        if input.len() < 2 {
+            msg!("u16 cannot be unpacked");
+            return Err(LendingError::InstructionUnpackError.into());
+        }
+        let (bytes, rest) = input.split_at(2);
+        let value = bytes
+            .get(..2)
+            .and_then(|slice| slice.try_into().ok())
+            .map(u16::from_le_bytes)
+            .ok_or(LendingError::InstructionUnpackError)?;
+        Ok((value, rest))
+    }
+
     fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
         if input.is_empty() {
             msg!("u8 cannot be unpacked");
@@ -856,11 +1392,16 @@ impl LendingInstruction {
                 rate_limiter_config: config,
                 whitelisted_liquidator,
                 risk_authority,
+                obligation_max_outflow,
+                obligation_outflow_window,
+                global_allowed_borrow_value,
+                global_unhealthy_borrow_value,
             } => {
                 buf.push(1);
                 buf.extend_from_slice(new_owner.as_ref());
                 buf.extend_from_slice(&config.window_duration.to_le_bytes());
                 buf.extend_from_slice(&config.max_outflow.to_le_bytes());
+                buf.push(config.net_flow as u8);
                 match whitelisted_liquidator {
                     Some(liquidator) => {
                         buf.push(1);
@@ -871,6 +1412,10 @@ impl LendingInstruction {
                     }
                 };
                 buf.extend_from_slice(risk_authority.as_ref());
+                buf.extend_from_slice(&obligation_max_outflow.to_le_bytes());
+                buf.extend_from_slice(&obligation_outflow_window.to_le_bytes());
+                buf.extend_from_slice(&global_allowed_borrow_value.to_le_bytes());
+                buf.extend_from_slice(&global_unhealthy_borrow_value.to_le_bytes());
             }
             Self::InitReserve {
                 liquidity_amount,
@@ -904,6 +1449,9 @@ impl LendingInstruction {
                         extra_oracle_pubkey,
                         attributed_borrow_limit_open,
                         attributed_borrow_limit_close,
+                        underlying_oracle_pubkey,
+                        peg_mode,
+                        peg_max_deviation_bps,
                     },
             } => {
                 buf.push(2);
@@ -941,6 +1489,17 @@ impl LendingInstruction {
                 };
                 buf.extend_from_slice(&attributed_borrow_limit_open.to_le_bytes());
                 buf.extend_from_slice(&attributed_borrow_limit_close.to_le_bytes());
+                match underlying_oracle_pubkey {
+                    Some(pubkey) => {
+                        buf.push(1);
+                        buf.extend_from_slice(pubkey.as_ref());
+                    }
+                    None => {
+                        buf.push(0);
+                    }
+                };
+                buf.extend_from_slice(&(peg_mode as u8).to_le_bytes());
+                buf.extend_from_slice(&peg_max_deviation_bps.to_le_bytes());
             }
             Self::RefreshReserve => {
                 buf.push(3);
@@ -994,6 +1553,7 @@ impl LendingInstruction {
             Self::UpdateReserveConfig {
                 config,
                 rate_limiter_config,
+                liquidation_threshold_ramp_slots,
             } => {
                 buf.push(16);
                 buf.extend_from_slice(&config.optimal_utilization_rate.to_le_bytes());
@@ -1029,8 +1589,21 @@ impl LendingInstruction {
                 };
                 buf.extend_from_slice(&config.attributed_borrow_limit_open.to_le_bytes());
                 buf.extend_from_slice(&config.attributed_borrow_limit_close.to_le_bytes());
+                match config.underlying_oracle_pubkey {
+                    Some(pubkey) => {
+                        buf.push(1);
+                        buf.extend_from_slice(pubkey.as_ref());
+                    }
+                    None => {
+                        buf.push(0);
+                    }
+                };
+                buf.extend_from_slice(&(config.peg_mode as u8).to_le_bytes());
+                buf.extend_from_slice(&config.peg_max_deviation_bps.to_le_bytes());
                 buf.extend_from_slice(&rate_limiter_config.window_duration.to_le_bytes());
                 buf.extend_from_slice(&rate_limiter_config.max_outflow.to_le_bytes());
+                buf.push(rate_limiter_config.net_flow as u8);
+                buf.extend_from_slice(&liquidation_threshold_ramp_slots.to_le_bytes());
             }
             Self::LiquidateObligationAndRedeemReserveCollateral { liquidity_amount } => {
                 buf.push(17);
@@ -1061,6 +1634,88 @@ impl LendingInstruction {
                 buf.push(23);
                 buf.extend_from_slice(&(closeable as u8).to_le_bytes());
             }
+            Self::UpdateFlashLoanAllowlist => {}
+            Self::LeverageLoop {
+                liquidity_amount,
+                target_ltv,
+            } => {
+                buf.push(25);
+                buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+                buf.extend_from_slice(&target_ltv.to_le_bytes());
+            }
+            Self::LiquidateObligationBatch {
+                ref liquidity_amounts,
+            } => {
+                buf.push(26);
+                buf.push(liquidity_amounts.len() as u8);
+                for liquidity_amount in liquidity_amounts {
+                    buf.extend_from_slice(&liquidity_amount.to_le_bytes());
+                }
+            }
+            Self::SetConfigTimelock { delay_slots } => {
+                buf.push(27);
+                buf.extend_from_slice(&delay_slots.to_le_bytes());
+            }
+            Self::QueueConfigChange { ref change } => {
+                buf.push(28);
+                buf.extend_from_slice(&change.pack());
+            }
+            Self::ExecuteConfigChange => {
+                buf.push(29);
+            }
+            Self::CancelConfigChange => {
+                buf.push(30);
+            }
+            Self::UpdateMarketRoles => {}
+            Self::ProposeMarketOwner { new_owner } => {
+                buf.push(32);
+                buf.extend_from_slice(new_owner.as_ref());
+            }
+            Self::AcceptMarketOwner => {
+                buf.push(33);
+            }
+            Self::SetMarketPaused { paused } => {
+                buf.push(34);
+                buf.extend_from_slice(&(paused as u8).to_le_bytes());
+            }
+            Self::InitProtocolConfig => {}
+            Self::UpdateProtocolConfig => {}
+            Self::SetConfigDeltaBounds { delta_bounds } => {
+                buf.push(37);
+                match delta_bounds {
+                    Some(delta_bounds) => {
+                        buf.push(1);
+                        buf.push(delta_bounds.max_loan_to_value_ratio_decrease);
+                        buf.push(delta_bounds.max_liquidation_threshold_decrease);
+                        buf.extend_from_slice(
+                            &delta_bounds
+                                .max_added_borrow_weight_bps_increase
+                                .to_le_bytes(),
+                        );
+                        buf.extend_from_slice(&delta_bounds.interval_slots.to_le_bytes());
+                    }
+                    None => {
+                        buf.push(0);
+                    }
+                }
+            }
+            Self::CloseReserve => {
+                buf.push(38);
+            }
+            Self::UpdateReserveOracles {
+                extra_oracle_pubkey,
+            } => {
+                buf.push(39);
+                match extra_oracle_pubkey {
+                    Some(pubkey) => {
+                        buf.push(1);
+                        buf.extend_from_slice(pubkey.as_ref());
+                    }
+                    None => {
+                        buf.push(0);
+                    }
+                };
+            }
         }
         buf
     }
@@ -1093,6 +1748,7 @@ pub fn init_lending_market(
 }
 
 /// Creates a 'SetLendingMarketOwner' instruction.
+#[allow(clippy::too_many_arguments)]
 pub fn set_lending_market_owner_and_config(
     program_id: Pubkey,
     lending_market_pubkey: Pubkey,
@@ -1101,18 +1757,34 @@ pub fn set_lending_market_owner_and_config(
     rate_limiter_config: RateLimiterConfig,
     whitelisted_liquidator: Option<Pubkey>,
     risk_authority: Pubkey,
+    obligation_max_outflow: u32,
+    obligation_outflow_window: u64,
+    global_allowed_borrow_value: u64,
+    global_unhealthy_borrow_value: u64,
 ) -> Instruction {
     Instruction {
         program_id,
         accounts: vec![
             AccountMeta::new(lending_market_pubkey, false),
             AccountMeta::new_readonly(lending_market_owner, true),
+            AccountMeta::new_readonly(
+                find_config_timelock_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
+            AccountMeta::new_readonly(
+                find_market_roles_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
         ],
         data: LendingInstruction::SetLendingMarketOwnerAndConfig {
             new_owner,
             rate_limiter_config,
             whitelisted_liquidator,
             risk_authority,
+            obligation_max_outflow,
+            obligation_outflow_window,
+            global_allowed_borrow_value,
+            global_unhealthy_borrow_value,
         }
         .pack(),
     }
@@ -1166,6 +1838,15 @@ pub fn init_reserve(
         accounts.push(AccountMeta::new_readonly(extra_oracle_pubkey, false));
     }
 
+    if let Some(underlying_oracle_pubkey) = config.underlying_oracle_pubkey {
+        accounts.push(AccountMeta::new_readonly(underlying_oracle_pubkey, false));
+    }
+
+    accounts.push(AccountMeta::new_readonly(
+        find_protocol_config_address(program_id).0,
+        false,
+    ));
+
     Instruction {
         program_id,
         accounts,
@@ -1184,6 +1865,8 @@ pub fn refresh_reserve(
     reserve_liquidity_pyth_oracle_pubkey: Pubkey,
     reserve_liquidity_switchboard_oracle_pubkey: Pubkey,
     extra_oracle_pubkey: Option<Pubkey>,
+    underlying_oracle_pubkey: Option<Pubkey>,
+    lending_market_pubkey: Option<Pubkey>,
 ) -> Instruction {
     let mut accounts = vec![
         AccountMeta::new(reserve_pubkey, false),
@@ -1195,6 +1878,14 @@ pub fn refresh_reserve(
         accounts.push(AccountMeta::new_readonly(extra_oracle_pubkey, false));
     }
 
+    if let Some(underlying_oracle_pubkey) = underlying_oracle_pubkey {
+        accounts.push(AccountMeta::new_readonly(underlying_oracle_pubkey, false));
+    }
+
+    if let Some(lending_market_pubkey) = lending_market_pubkey {
+        accounts.push(AccountMeta::new_readonly(lending_market_pubkey, false));
+    }
+
     Instruction {
         program_id,
         accounts,
@@ -1227,7 +1918,7 @@ pub fn deposit_reserve_liquidity(
             AccountMeta::new(reserve_pubkey, false),
             AccountMeta::new(reserve_liquidity_supply_pubkey, false),
             AccountMeta::new(reserve_collateral_mint_pubkey, false),
-            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_pubkey, false),
             AccountMeta::new_readonly(lending_market_authority_pubkey, false),
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(spl_token::id(), false),
@@ -1311,6 +2002,21 @@ pub fn refresh_obligation(
     }
 }
 
+/// Creates a 'RefreshObligation' instruction that caps the obligation at the lending market's
+/// global borrow values.
+pub fn refresh_obligation_with_lending_market(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    reserve_pubkeys: Vec<Pubkey>,
+    lending_market_pubkey: Pubkey,
+) -> Instruction {
+    let mut instruction = refresh_obligation(program_id, obligation_pubkey, reserve_pubkeys);
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(lending_market_pubkey, false));
+    instruction
+}
+
 /// Creates a 'DepositObligationCollateral' instruction.
 #[allow(clippy::too_many_arguments)]
 pub fn deposit_obligation_collateral(
@@ -1370,7 +2076,7 @@ pub fn deposit_reserve_liquidity_and_obligation_collateral(
             AccountMeta::new(reserve_pubkey, false),
             AccountMeta::new(reserve_liquidity_supply_pubkey, false),
             AccountMeta::new(reserve_collateral_mint_pubkey, false),
-            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_pubkey, false),
             AccountMeta::new_readonly(lending_market_authority_pubkey, false),
             AccountMeta::new(destination_deposit_collateral_pubkey, false),
             AccountMeta::new(obligation_pubkey, false),
@@ -1545,7 +2251,7 @@ pub fn repay_obligation_liquidity(
             AccountMeta::new(destination_liquidity_pubkey, false),
             AccountMeta::new(repay_reserve_pubkey, false),
             AccountMeta::new(obligation_pubkey, false),
-            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_pubkey, false),
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(spl_token::id(), false),
         ],
@@ -1604,12 +2310,41 @@ pub fn update_reserve_config(
     pyth_price_pubkey: Pubkey,
     switchboard_feed_pubkey: Pubkey,
 ) -> Instruction {
-    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
-        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
-        &program_id,
-    );
-    let mut accounts = vec![
-        AccountMeta::new(reserve_pubkey, false),
+    update_reserve_config_with_liquidation_threshold_ramp(
+        program_id,
+        config,
+        rate_limiter_config,
+        0,
+        reserve_pubkey,
+        lending_market_pubkey,
+        lending_market_owner_pubkey,
+        pyth_product_pubkey,
+        pyth_price_pubkey,
+        switchboard_feed_pubkey,
+    )
+}
+
+/// Creates an 'UpdateReserveConfig' instruction that ramps lowered liquidation thresholds down
+/// over `liquidation_threshold_ramp_slots` slots.
+#[allow(clippy::too_many_arguments)]
+pub fn update_reserve_config_with_liquidation_threshold_ramp(
+    program_id: Pubkey,
+    config: ReserveConfig,
+    rate_limiter_config: RateLimiterConfig,
+    liquidation_threshold_ramp_slots: u64,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+    pyth_product_pubkey: Pubkey,
+    pyth_price_pubkey: Pubkey,
+    switchboard_feed_pubkey: Pubkey,
+) -> Instruction {
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    let mut accounts = vec![
+        AccountMeta::new(reserve_pubkey, false),
         AccountMeta::new_readonly(lending_market_pubkey, false),
         AccountMeta::new_readonly(lending_market_authority_pubkey, false),
         AccountMeta::new_readonly(lending_market_owner_pubkey, true),
@@ -1622,12 +2357,30 @@ pub fn update_reserve_config(
         accounts.push(AccountMeta::new_readonly(extra_oracle_pubkey, false));
     }
 
+    if let Some(underlying_oracle_pubkey) = config.underlying_oracle_pubkey {
+        accounts.push(AccountMeta::new_readonly(underlying_oracle_pubkey, false));
+    }
+
+    accounts.push(AccountMeta::new(
+        find_config_timelock_address(program_id, lending_market_pubkey).0,
+        false,
+    ));
+    accounts.push(AccountMeta::new_readonly(
+        find_market_roles_address(program_id, lending_market_pubkey).0,
+        false,
+    ));
+    accounts.push(AccountMeta::new_readonly(
+        find_protocol_config_address(program_id).0,
+        false,
+    ));
+
     Instruction {
         program_id,
         accounts,
         data: LendingInstruction::UpdateReserveConfig {
             config,
             rate_limiter_config,
+            liquidation_threshold_ramp_slots,
         }
         .pack(),
     }
@@ -1753,6 +2506,13 @@ pub fn flash_repay_reserve_liquidity(
     lending_market_pubkey: Pubkey,
     user_transfer_authority_pubkey: Pubkey,
 ) -> Instruction {
+    let (flash_loan_allowlist_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            b"FlashLoanAllowlist",
+        ],
+        &program_id,
+    );
     Instruction {
         program_id,
         accounts: vec![
@@ -1765,6 +2525,7 @@ pub fn flash_repay_reserve_liquidity(
             AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
             AccountMeta::new_readonly(sysvar::instructions::id(), false),
             AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(flash_loan_allowlist_pubkey, false),
         ],
         data: LendingInstruction::FlashRepayReserveLiquidity {
             liquidity_amount,
@@ -1828,6 +2589,173 @@ pub fn update_market_metadata(
     }
 }
 
+/// Creates a `UpdateFlashLoanAllowlist` instruction
+pub fn update_flash_loan_allowlist(
+    program_id: Pubkey,
+    mut allowlist: FlashLoanAllowlist,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+) -> Instruction {
+    let (flash_loan_allowlist_pubkey, bump_seed) = Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            b"FlashLoanAllowlist",
+        ],
+        &program_id,
+    );
+
+    allowlist.bump_seed = bump_seed;
+
+    let mut data = [0u8; 1 + std::mem::size_of::<FlashLoanAllowlist>()];
+    data[0] = 24;
+    data[1..].copy_from_slice(bytes_of(&allowlist));
+
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_owner, true),
+            AccountMeta::new(flash_loan_allowlist_pubkey, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: data.to_vec(),
+    }
+}
+
+/// Creates a `LeverageLoop` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn leverage_loop(
+    program_id: Pubkey,
+    liquidity_amount: u64,
+    target_ltv: u8,
+    source_liquidity_pubkey: Pubkey,
+    user_collateral_pubkey: Pubkey,
+    deposit_reserve_pubkey: Pubkey,
+    deposit_reserve_liquidity_supply_pubkey: Pubkey,
+    deposit_reserve_collateral_mint_pubkey: Pubkey,
+    deposit_reserve_collateral_supply_pubkey: Pubkey,
+    borrow_reserve_pubkey: Pubkey,
+    borrow_reserve_liquidity_supply_pubkey: Pubkey,
+    borrow_reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    obligation_owner_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+    collateral_reserves: Vec<Pubkey>,
+    other_borrow_reserves: Vec<Pubkey>,
+    host_fee_receiver_pubkey: Option<Pubkey>,
+) -> Instruction {
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    let mut accounts = vec![
+        AccountMeta::new(source_liquidity_pubkey, false),
+        AccountMeta::new(user_collateral_pubkey, false),
+        AccountMeta::new(deposit_reserve_pubkey, false),
+        AccountMeta::new(deposit_reserve_liquidity_supply_pubkey, false),
+        AccountMeta::new(deposit_reserve_collateral_mint_pubkey, false),
+        AccountMeta::new(deposit_reserve_collateral_supply_pubkey, false),
+        AccountMeta::new(borrow_reserve_pubkey, false),
+        AccountMeta::new(borrow_reserve_liquidity_supply_pubkey, false),
+        AccountMeta::new(borrow_reserve_liquidity_fee_receiver_pubkey, false),
+        AccountMeta::new(obligation_pubkey, false),
+        AccountMeta::new(lending_market_pubkey, false),
+        AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+        AccountMeta::new_readonly(obligation_owner_pubkey, true),
+        AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    for collateral_reserve in collateral_reserves {
+        accounts.push(AccountMeta::new(collateral_reserve, false));
+    }
+    for borrow_reserve in other_borrow_reserves {
+        accounts.push(AccountMeta::new(borrow_reserve, false));
+    }
+
+    if let Some(host_fee_receiver_pubkey) = host_fee_receiver_pubkey {
+        accounts.push(AccountMeta::new(host_fee_receiver_pubkey, false));
+    }
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::LeverageLoop {
+            liquidity_amount,
+            target_ltv,
+        }
+        .pack(),
+    }
+}
+
+/// Accounts and amount for one leg of a `LiquidateObligationBatch` instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiquidationBatchLeg {
+    /// Amount of liquidity to repay - u64::MAX for up to 100% of the borrowed amount
+    pub liquidity_amount: u64,
+    /// Source liquidity token account
+    pub source_liquidity_pubkey: Pubkey,
+    /// Destination collateral token account
+    pub destination_collateral_pubkey: Pubkey,
+    /// Destination liquidity token account
+    pub destination_liquidity_pubkey: Pubkey,
+    /// Repay reserve
+    pub repay_reserve_pubkey: Pubkey,
+    /// Repay reserve liquidity supply
+    pub repay_reserve_liquidity_supply_pubkey: Pubkey,
+    /// Withdraw reserve
+    pub withdraw_reserve_pubkey: Pubkey,
+    /// Withdraw reserve collateral mint
+    pub withdraw_reserve_collateral_mint_pubkey: Pubkey,
+    /// Withdraw reserve collateral supply
+    pub withdraw_reserve_collateral_supply_pubkey: Pubkey,
+    /// Withdraw reserve liquidity supply
+    pub withdraw_reserve_liquidity_supply_pubkey: Pubkey,
+    /// Withdraw reserve liquidity fee receiver
+    pub withdraw_reserve_liquidity_fee_receiver_pubkey: Pubkey,
+}
+
+/// Creates a `LiquidateObligationBatch` instruction
+pub fn liquidate_obligation_batch(
+    program_id: Pubkey,
+    obligation_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    user_transfer_authority_pubkey: Pubkey,
+    legs: Vec<LiquidationBatchLeg>,
+) -> Instruction {
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    let mut accounts = vec![
+        AccountMeta::new(obligation_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+        AccountMeta::new_readonly(user_transfer_authority_pubkey, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ];
+    let mut liquidity_amounts = Vec::with_capacity(legs.len());
+    for leg in legs {
+        accounts.extend([
+            AccountMeta::new(leg.source_liquidity_pubkey, false),
+            AccountMeta::new(leg.destination_collateral_pubkey, false),
+            AccountMeta::new(leg.destination_liquidity_pubkey, false),
+            AccountMeta::new(leg.repay_reserve_pubkey, false),
+            AccountMeta::new(leg.repay_reserve_liquidity_supply_pubkey, false),
+            AccountMeta::new(leg.withdraw_reserve_pubkey, false),
+            AccountMeta::new(leg.withdraw_reserve_collateral_mint_pubkey, false),
+            AccountMeta::new(leg.withdraw_reserve_collateral_supply_pubkey, false),
+            AccountMeta::new(leg.withdraw_reserve_liquidity_supply_pubkey, false),
+            AccountMeta::new(leg.withdraw_reserve_liquidity_fee_receiver_pubkey, false),
+        ]);
+        liquidity_amounts.push(leg.liquidity_amount);
+    }
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::LiquidateObligationBatch { liquidity_amounts }.pack(),
+    }
+}
+
 /// Creates a `MarkObligationAsClosable` instruction
 pub fn set_obligation_closeability_status(
     program_id: Pubkey,
@@ -1849,9 +2777,449 @@ pub fn set_obligation_closeability_status(
     }
 }
 
+/// Finds the config timelock account of a lending market
+pub fn find_config_timelock_address(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            b"ConfigTimelock",
+        ],
+        &program_id,
+    )
+}
+
+/// Finds the pending config change account of a reserve or lending market
+pub fn find_pending_config_change_address(
+    program_id: Pubkey,
+    target_pubkey: Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &target_pubkey.to_bytes()[..PUBKEY_BYTES],
+            b"PendingConfigChange",
+        ],
+        &program_id,
+    )
+}
+
+/// Reserve or lending market a config change is queued for
+fn config_change_target(lending_market_pubkey: Pubkey, change: &Instruction) -> Pubkey {
+    match change.data.first() {
+        Some(16) | Some(39) => change.accounts[0].pubkey,
+        _ => lending_market_pubkey,
+    }
+}
+
+/// Creates a `SetConfigTimelock` instruction
+pub fn set_config_timelock(
+    program_id: Pubkey,
+    delay_slots: u64,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_owner, true),
+            AccountMeta::new(
+                find_config_timelock_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetConfigTimelock { delay_slots }.pack(),
+    }
+}
+
+/// Creates a `SetConfigDeltaBounds` instruction
+pub fn set_config_delta_bounds(
+    program_id: Pubkey,
+    delta_bounds: Option<ConfigDeltaBounds>,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_owner, true),
+            AccountMeta::new(
+                find_config_timelock_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::SetConfigDeltaBounds { delta_bounds }.pack(),
+    }
+}
+
+/// Creates a `QueueConfigChange` instruction. `change` is the `UpdateReserveConfig`,
+/// `UpdateReserveOracles`, `SetLendingMarketOwnerAndConfig`, `SetConfigTimelock`,
+/// `SetConfigDeltaBounds` or `UpdateMarketRoles` instruction the lending market owner would
+/// otherwise send.
+pub fn queue_config_change(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+    change: &Instruction,
+) -> Instruction {
+    let target_pubkey = config_change_target(lending_market_pubkey, change);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new(lending_market_owner, true),
+        AccountMeta::new_readonly(
+            find_config_timelock_address(program_id, lending_market_pubkey).0,
+            false,
+        ),
+        AccountMeta::new(
+            find_pending_config_change_address(program_id, target_pubkey).0,
+            false,
+        ),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(
+        change
+            .accounts
+            .iter()
+            .map(|account| AccountMeta::new_readonly(account.pubkey, false)),
+    );
+
+    let mut data = vec![28];
+    data.extend_from_slice(&change.data);
+    Instruction {
+        program_id,
+        accounts,
+        data,
+    }
+}
+
+/// Creates an `ExecuteConfigChange` instruction for a change queued with `queue_config_change`
+pub fn execute_config_change(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+    queued_by: Pubkey,
+    change: &Instruction,
+) -> Instruction {
+    let target_pubkey = config_change_target(lending_market_pubkey, change);
+    let mut accounts = vec![
+        AccountMeta::new(
+            find_pending_config_change_address(program_id, target_pubkey).0,
+            false,
+        ),
+        AccountMeta::new(queued_by, false),
+    ];
+    accounts.extend(change.accounts.iter().map(|account| AccountMeta {
+        pubkey: account.pubkey,
+        is_signer: false,
+        is_writable: account.is_writable,
+    }));
+
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::ExecuteConfigChange.pack(),
+    }
+}
+
+/// Creates a `CancelConfigChange` instruction. The target is the reserve of a queued
+/// `UpdateReserveConfig` or `UpdateReserveOracles` and the lending market otherwise.
+pub fn cancel_config_change(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+    signer: Pubkey,
+    queued_by: Pubkey,
+    target_pubkey: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new(
+                find_pending_config_change_address(program_id, target_pubkey).0,
+                false,
+            ),
+            AccountMeta::new(queued_by, false),
+        ],
+        data: LendingInstruction::CancelConfigChange.pack(),
+    }
+}
+
+/// Finds the market roles account of a lending market
+pub fn find_market_roles_address(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            b"MarketRoles",
+        ],
+        &program_id,
+    )
+}
+
+/// Creates a `UpdateMarketRoles` instruction
+pub fn update_market_roles(
+    program_id: Pubkey,
+    mut roles: MarketRoles,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+) -> Instruction {
+    let (market_roles_pubkey, bump_seed) =
+        find_market_roles_address(program_id, lending_market_pubkey);
+
+    roles.bump_seed = bump_seed;
+
+    let mut data = [0u8; 1 + std::mem::size_of::<MarketRoles>()];
+    data[0] = 31;
+    data[1..].copy_from_slice(bytes_of(&roles));
+
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_owner, true),
+            AccountMeta::new(market_roles_pubkey, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(
+                find_config_timelock_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
+        ],
+        data: data.to_vec(),
+    }
+}
+
+/// Finds the pending market owner account of a lending market
+pub fn find_pending_market_owner_address(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            &lending_market_pubkey.to_bytes()[..PUBKEY_BYTES],
+            b"PendingMarketOwner",
+        ],
+        &program_id,
+    )
+}
+
+/// Creates a `ProposeMarketOwner` instruction
+pub fn propose_market_owner(
+    program_id: Pubkey,
+    new_owner: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new(lending_market_owner, true),
+            AccountMeta::new(
+                find_pending_market_owner_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: LendingInstruction::ProposeMarketOwner { new_owner }.pack(),
+    }
+}
+
+/// Creates an `AcceptMarketOwner` instruction
+pub fn accept_market_owner(
+    program_id: Pubkey,
+    lending_market_pubkey: Pubkey,
+    new_owner: Pubkey,
+    proposed_by: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new_readonly(new_owner, true),
+            AccountMeta::new(
+                find_pending_market_owner_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
+            AccountMeta::new(proposed_by, false),
+        ],
+        data: LendingInstruction::AcceptMarketOwner.pack(),
+    }
+}
+
+/// Creates a `SetMarketPaused` instruction
+pub fn set_market_paused(
+    program_id: Pubkey,
+    paused: bool,
+    lending_market_pubkey: Pubkey,
+    signer: Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(lending_market_pubkey, false),
+            AccountMeta::new_readonly(signer, true),
+            AccountMeta::new_readonly(
+                find_market_roles_address(program_id, lending_market_pubkey).0,
+                false,
+            ),
+        ],
+        data: LendingInstruction::SetMarketPaused { paused }.pack(),
+    }
+}
+
+/// Finds the protocol config account of the lending program
+pub fn find_protocol_config_address(program_id: Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"ProtocolConfig"], &program_id)
+}
+
+/// Finds the program data account of an upgradeable program
+pub fn find_program_data_address(program_id: Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id())
+}
+
+/// Creates an `InitProtocolConfig` instruction
+pub fn init_protocol_config(
+    program_id: Pubkey,
+    mut protocol_config: ProtocolConfig,
+    upgrade_authority: Pubkey,
+) -> Instruction {
+    let (protocol_config_pubkey, bump_seed) = find_protocol_config_address(program_id);
+    protocol_config.bump_seed = bump_seed;
+
+    let mut data = [0u8; 1 + std::mem::size_of::<ProtocolConfig>()];
+    data[0] = 35;
+    data[1..].copy_from_slice(bytes_of(&protocol_config));
+
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(protocol_config_pubkey, false),
+            AccountMeta::new(upgrade_authority, true),
+            AccountMeta::new_readonly(find_program_data_address(program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: data.to_vec(),
+    }
+}
+
+/// Creates an `UpdateProtocolConfig` instruction
+pub fn update_protocol_config(
+    program_id: Pubkey,
+    mut protocol_config: ProtocolConfig,
+    protocol_authority: Pubkey,
+) -> Instruction {
+    let (protocol_config_pubkey, bump_seed) = find_protocol_config_address(program_id);
+    protocol_config.bump_seed = bump_seed;
+
+    let mut data = [0u8; 1 + std::mem::size_of::<ProtocolConfig>()];
+    data[0] = 36;
+    data[1..].copy_from_slice(bytes_of(&protocol_config));
+
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(protocol_config_pubkey, false),
+            AccountMeta::new_readonly(protocol_authority, true),
+        ],
+        data: data.to_vec(),
+    }
+}
+
+/// Creates a `CloseReserve` instruction
+#[allow(clippy::too_many_arguments)]
+pub fn close_reserve(
+    program_id: Pubkey,
+    reserve_pubkey: Pubkey,
+    reserve_liquidity_supply_pubkey: Pubkey,
+    reserve_liquidity_fee_receiver_pubkey: Pubkey,
+    reserve_collateral_mint_pubkey: Pubkey,
+    reserve_collateral_supply_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    lending_market_owner_pubkey: Pubkey,
+    destination_pubkey: Pubkey,
+    destination_liquidity_pubkey: Pubkey,
+) -> Instruction {
+    let (lending_market_authority_pubkey, _bump_seed) = Pubkey::find_program_address(
+        &[&lending_market_pubkey.to_bytes()[..PUBKEY_BYTES]],
+        &program_id,
+    );
+    Instruction {
+        program_id,
+        accounts: vec![
+            AccountMeta::new(reserve_pubkey, false),
+            AccountMeta::new(reserve_liquidity_supply_pubkey, false),
+            AccountMeta::new(reserve_liquidity_fee_receiver_pubkey, false),
+            AccountMeta::new_readonly(reserve_collateral_mint_pubkey, false),
+            AccountMeta::new(reserve_collateral_supply_pubkey, false),
+            AccountMeta::new_readonly(lending_market_pubkey, false),
+            AccountMeta::new_readonly(lending_market_authority_pubkey, false),
+            AccountMeta::new_readonly(lending_market_owner_pubkey, true),
+            AccountMeta::new(destination_pubkey, false),
+            AccountMeta::new(destination_liquidity_pubkey, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: LendingInstruction::CloseReserve.pack(),
+    }
+}
+
+/// Creates an `UpdateReserveOracles` instruction. `underlying_oracle_pubkey` is the reserve
+/// config's underlying oracle, which the new pyth price key is priced off if it's a stake pool.
+#[allow(clippy::too_many_arguments)]
+pub fn update_reserve_oracles(
+    program_id: Pubkey,
+    extra_oracle_pubkey: Option<Pubkey>,
+    underlying_oracle_pubkey: Option<Pubkey>,
+    reserve_pubkey: Pubkey,
+    lending_market_pubkey: Pubkey,
+    signer: Pubkey,
+    pyth_price_pubkey: Pubkey,
+    switchboard_feed_pubkey: Pubkey,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(reserve_pubkey, false),
+        AccountMeta::new_readonly(lending_market_pubkey, false),
+        AccountMeta::new_readonly(signer, true),
+        AccountMeta::new_readonly(pyth_price_pubkey, false),
+        AccountMeta::new_readonly(switchboard_feed_pubkey, false),
+        AccountMeta::new_readonly(
+            find_config_timelock_address(program_id, lending_market_pubkey).0,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            find_market_roles_address(program_id, lending_market_pubkey).0,
+            false,
+        ),
+    ];
+
+    if let Some(extra_oracle_pubkey) = extra_oracle_pubkey {
+        accounts.push(AccountMeta::new_readonly(extra_oracle_pubkey, false));
+    }
+
+    if let Some(underlying_oracle_pubkey) = underlying_oracle_pubkey {
+        accounts.push(AccountMeta::new_readonly(underlying_oracle_pubkey, false));
+    }
+
+    Instruction {
+        program_id,
+        accounts,
+        data: LendingInstruction::UpdateReserveOracles {
+            extra_oracle_pubkey,
+        }
+        .pack(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::state::MAX_QUEUED_CONFIG_CHANGE_DATA_LEN;
     use rand::Rng;
 
     #[test]
@@ -1877,6 +3245,7 @@ mod test {
                     rate_limiter_config: RateLimiterConfig {
                         window_duration: rng.gen::<u64>(),
                         max_outflow: rng.gen::<u64>(),
+                        net_flow: rng.gen(),
                     },
                     whitelisted_liquidator: if rng.gen_bool(0.5) {
                         None
@@ -1884,6 +3253,10 @@ mod test {
                         Some(Pubkey::new_unique())
                     },
                     risk_authority: Pubkey::new_unique(),
+                    obligation_max_outflow: rng.gen::<u32>(),
+                    obligation_outflow_window: rng.gen::<u64>(),
+                    global_allowed_borrow_value: rng.gen::<u64>(),
+                    global_unhealthy_borrow_value: rng.gen::<u64>(),
                 };
 
                 let packed = instruction.pack();
@@ -1926,6 +3299,13 @@ mod test {
                         },
                         attributed_borrow_limit_open: rng.gen(),
                         attributed_borrow_limit_close: rng.gen(),
+                        underlying_oracle_pubkey: if rng.gen_bool(0.5) {
+                            Some(Pubkey::new_unique())
+                        } else {
+                            None
+                        },
+                        peg_mode: PegMode::from_u8(rng.gen::<u8>() % 3).unwrap(),
+                        peg_max_deviation_bps: rng.gen(),
                     },
                 };
 
@@ -2094,16 +3474,34 @@ mod test {
                         },
                         attributed_borrow_limit_open: rng.gen(),
                         attributed_borrow_limit_close: rng.gen(),
+                        underlying_oracle_pubkey: if rng.gen_bool(0.5) {
+                            Some(Pubkey::new_unique())
+                        } else {
+                            None
+                        },
+                        peg_mode: PegMode::from_u8(rng.gen::<u8>() % 3).unwrap(),
+                        peg_max_deviation_bps: rng.gen(),
                     },
                     rate_limiter_config: RateLimiterConfig {
                         window_duration: rng.gen::<u64>(),
                         max_outflow: rng.gen::<u64>(),
+                        net_flow: rng.gen(),
                     },
+                    liquidation_threshold_ramp_slots: rng.gen(),
                 };
 
                 let packed = instruction.pack();
                 let unpacked = LendingInstruction::unpack(&packed).unwrap();
                 assert_eq!(instruction, unpacked);
+
+                // has to fit in a pending config change account
+                assert!(packed.len() <= MAX_QUEUED_CONFIG_CHANGE_DATA_LEN);
+                let instruction = LendingInstruction::QueueConfigChange {
+                    change: Box::new(instruction),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
             }
 
             // liquidate obligation and redeem reserve collateral
@@ -2171,6 +3569,157 @@ mod test {
                 let unpacked = LendingInstruction::unpack(&packed).unwrap();
                 assert_eq!(instruction, unpacked);
             }
+
+            // LeverageLoop
+            {
+                let instruction = LendingInstruction::LeverageLoop {
+                    liquidity_amount: rng.gen(),
+                    target_ltv: rng.gen(),
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // LiquidateObligationBatch
+            {
+                let leg_count = rng.gen_range(0..=MAX_LIQUIDATION_BATCH_LEGS);
+                let instruction = LendingInstruction::LiquidateObligationBatch {
+                    liquidity_amounts: (0..leg_count).map(|_| rng.gen()).collect(),
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // SetConfigTimelock
+            {
+                let instruction = LendingInstruction::SetConfigTimelock {
+                    delay_slots: rng.gen(),
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+
+                let instruction = LendingInstruction::QueueConfigChange {
+                    change: Box::new(instruction),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // ExecuteConfigChange
+            {
+                let instruction = LendingInstruction::ExecuteConfigChange;
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // CancelConfigChange
+            {
+                let instruction = LendingInstruction::CancelConfigChange;
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // ProposeMarketOwner
+            {
+                let instruction = LendingInstruction::ProposeMarketOwner {
+                    new_owner: Pubkey::new_unique(),
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // AcceptMarketOwner
+            {
+                let instruction = LendingInstruction::AcceptMarketOwner;
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // SetMarketPaused
+            {
+                let instruction = LendingInstruction::SetMarketPaused { paused: rng.gen() };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // SetConfigDeltaBounds
+            {
+                let instruction = LendingInstruction::SetConfigDeltaBounds {
+                    delta_bounds: if rng.gen_bool(0.5) {
+                        Some(ConfigDeltaBounds {
+                            max_loan_to_value_ratio_decrease: rng.gen(),
+                            max_liquidation_threshold_decrease: rng.gen(),
+                            max_added_borrow_weight_bps_increase: rng.gen(),
+                            interval_slots: rng.gen(),
+                        })
+                    } else {
+                        None
+                    },
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // CloseReserve
+            {
+                let instruction = LendingInstruction::CloseReserve;
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
+
+            // UpdateReserveOracles
+            {
+                let instruction = LendingInstruction::UpdateReserveOracles {
+                    extra_oracle_pubkey: if rng.gen_bool(0.5) {
+                        Some(Pubkey::new_unique())
+                    } else {
+                        None
+                    },
+                };
+
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+
+                let instruction = LendingInstruction::QueueConfigChange {
+                    change: Box::new(instruction),
+                };
+                let packed = instruction.pack();
+                let unpacked = LendingInstruction::unpack(&packed).unwrap();
+                assert_eq!(instruction, unpacked);
+            }
         }
     }
+
+    #[test]
+    fn queue_config_change_rejects_other_instructions() {
+        let instruction = LendingInstruction::QueueConfigChange {
+            change: Box::new(LendingInstruction::RedeemFees {}),
+        };
+        assert_eq!(
+            LendingInstruction::unpack(&instruction.pack()),
+            Err(LendingError::InstructionUnpackError.into())
+        );
+    }
 }
@@ -0,0 +1,395 @@
+use super::*;
+
+use crate::error::LendingError;
+use bytemuck::checked::try_from_bytes;
+use bytemuck::{Pod, Zeroable};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use static_assertions::assert_eq_size;
+use std::cmp::max;
+
+/// Maximum number of accounts of a queued config change instruction
+pub const MAX_QUEUED_CONFIG_CHANGE_ACCOUNTS: usize = 13;
+
+/// Maximum data length of a queued config change instruction
+pub const MAX_QUEUED_CONFIG_CHANGE_DATA_LEN: usize = 256;
+
+/// padding size
+pub const CONFIG_TIMELOCK_PADDING_SIZE: usize = 37;
+
+/// padding size
+pub const PENDING_CONFIG_CHANGE_PADDING_SIZE: usize = 64;
+
+/// Delay the lending market owner's reserve and market config changes have to be queued for,
+/// and the bounds on reserve config changes that aren't queued.
+/// Must be a pda with seeds [lending_market, "ConfigTimelock"]. A market without one has no delay
+/// and no bounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct ConfigTimelock {
+    /// Bump seed
+    pub bump_seed: u8,
+    /// Slots between queueing a config change and executing it (little endian u64)
+    pub delay_slots: [u8; 8],
+    /// Whether the config delta bounds below are enforced, 0 or 1
+    pub delta_bounds_enabled: u8,
+    /// See `ConfigDeltaBounds::max_loan_to_value_ratio_decrease`
+    pub max_loan_to_value_ratio_decrease: u8,
+    /// See `ConfigDeltaBounds::max_liquidation_threshold_decrease`
+    pub max_liquidation_threshold_decrease: u8,
+    /// See `ConfigDeltaBounds::max_added_borrow_weight_bps_increase` (little endian u64)
+    pub max_added_borrow_weight_bps_increase: [u8; 8],
+    /// See `ConfigDeltaBounds::interval_slots` (little endian u64)
+    pub delta_bounds_interval_slots: [u8; 8],
+    /// Slot of the last reserve config change the bounds applied to (little endian u64)
+    pub last_bounded_change_slot: [u8; 8],
+    /// Padding
+    pub padding: [u8; CONFIG_TIMELOCK_PADDING_SIZE],
+}
+
+/// Bounds on how much a reserve config change that isn't queued can move the parameters
+/// obligations' health depends on. The lending market owner can only exceed them by queueing
+/// the change behind the config timelock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConfigDeltaBounds {
+    /// Most the loan to value ratio can be lowered by in one change, in percentage points
+    pub max_loan_to_value_ratio_decrease: u8,
+    /// Most the liquidation threshold and max liquidation threshold can be lowered by in one
+    /// change, in percentage points
+    pub max_liquidation_threshold_decrease: u8,
+    /// Most the added borrow weight can be raised by in one change, in bps
+    pub max_added_borrow_weight_bps_increase: u64,
+    /// Slots that have to pass between two bounded changes to the lending market's reserves
+    pub interval_slots: u64,
+}
+
+impl ConfigDeltaBounds {
+    /// Whether no change allowed by these bounds would be rejected by `other`
+    pub fn is_at_least_as_strict_as(&self, other: &ConfigDeltaBounds) -> bool {
+        self.max_loan_to_value_ratio_decrease <= other.max_loan_to_value_ratio_decrease
+            && self.max_liquidation_threshold_decrease <= other.max_liquidation_threshold_decrease
+            && self.max_added_borrow_weight_bps_increase
+                <= other.max_added_borrow_weight_bps_increase
+            && self.interval_slots >= other.interval_slots
+    }
+
+    /// Fails if a reserve config change from `previous` to `config` exceeds the bounds. Returns
+    /// whether the bounds applied to the change.
+    pub fn check(
+        &self,
+        previous: &ReserveConfig,
+        config: &ReserveConfig,
+        last_bounded_change_slot: u64,
+        slot: u64,
+    ) -> Result<bool, ProgramError> {
+        let loan_to_value_ratio_decrease = previous
+            .loan_to_value_ratio
+            .saturating_sub(config.loan_to_value_ratio);
+        let liquidation_threshold_decrease = max(
+            previous
+                .liquidation_threshold
+                .saturating_sub(config.liquidation_threshold),
+            previous
+                .max_liquidation_threshold
+                .saturating_sub(config.max_liquidation_threshold),
+        );
+        let added_borrow_weight_bps_increase = config
+            .added_borrow_weight_bps
+            .saturating_sub(previous.added_borrow_weight_bps);
+
+        if loan_to_value_ratio_decrease == 0
+            && liquidation_threshold_decrease == 0
+            && added_borrow_weight_bps_increase == 0
+        {
+            return Ok(false);
+        }
+
+        if loan_to_value_ratio_decrease > self.max_loan_to_value_ratio_decrease {
+            msg!(
+                "Loan to value ratio can be lowered by at most {} points without queueing the change",
+                self.max_loan_to_value_ratio_decrease
+            );
+            return Err(LendingError::ConfigChangeExceedsBounds.into());
+        }
+        if liquidation_threshold_decrease > self.max_liquidation_threshold_decrease {
+            msg!(
+                "Liquidation thresholds can be lowered by at most {} points without queueing the change",
+                self.max_liquidation_threshold_decrease
+            );
+            return Err(LendingError::ConfigChangeExceedsBounds.into());
+        }
+        if added_borrow_weight_bps_increase > self.max_added_borrow_weight_bps_increase {
+            msg!(
+                "Added borrow weight can be raised by at most {} bps without queueing the change",
+                self.max_added_borrow_weight_bps_increase
+            );
+            return Err(LendingError::ConfigChangeExceedsBounds.into());
+        }
+        if last_bounded_change_slot != 0
+            && slot < last_bounded_change_slot.saturating_add(self.interval_slots)
+        {
+            msg!(
+                "Bounded reserve config changes can be made again from slot {}",
+                last_bounded_change_slot.saturating_add(self.interval_slots)
+            );
+            return Err(LendingError::ConfigChangeExceedsBounds.into());
+        }
+
+        Ok(true)
+    }
+}
+
+impl ConfigTimelock {
+    /// Create a ConfigTimelock reference from account data
+    pub fn from_account_data(data: &[u8]) -> Result<&ConfigTimelock, ProgramError> {
+        try_from_bytes::<ConfigTimelock>(data).map_err(|_| {
+            msg!("Failed to deserialize ConfigTimelock account");
+            LendingError::InvalidAccountInput.into()
+        })
+    }
+
+    /// Slots between queueing a config change and executing it
+    pub fn delay_slots(&self) -> u64 {
+        u64::from_le_bytes(self.delay_slots)
+    }
+
+    /// Bounds on reserve config changes that aren't queued, if enforced
+    pub fn delta_bounds(&self) -> Option<ConfigDeltaBounds> {
+        if self.delta_bounds_enabled == 0 {
+            return None;
+        }
+
+        Some(ConfigDeltaBounds {
+            max_loan_to_value_ratio_decrease: self.max_loan_to_value_ratio_decrease,
+            max_liquidation_threshold_decrease: self.max_liquidation_threshold_decrease,
+            max_added_borrow_weight_bps_increase: u64::from_le_bytes(
+                self.max_added_borrow_weight_bps_increase,
+            ),
+            interval_slots: u64::from_le_bytes(self.delta_bounds_interval_slots),
+        })
+    }
+
+    /// Sets or removes the bounds on reserve config changes that aren't queued
+    pub fn set_delta_bounds(&mut self, delta_bounds: Option<ConfigDeltaBounds>) {
+        let bounds = delta_bounds.unwrap_or_default();
+        self.delta_bounds_enabled = delta_bounds.is_some() as u8;
+        self.max_loan_to_value_ratio_decrease = bounds.max_loan_to_value_ratio_decrease;
+        self.max_liquidation_threshold_decrease = bounds.max_liquidation_threshold_decrease;
+        self.max_added_borrow_weight_bps_increase =
+            bounds.max_added_borrow_weight_bps_increase.to_le_bytes();
+        self.delta_bounds_interval_slots = bounds.interval_slots.to_le_bytes();
+    }
+
+    /// Slot of the last reserve config change the bounds applied to
+    pub fn last_bounded_change_slot(&self) -> u64 {
+        u64::from_le_bytes(self.last_bounded_change_slot)
+    }
+}
+
+unsafe impl Zeroable for ConfigTimelock {}
+unsafe impl Pod for ConfigTimelock {}
+
+assert_eq_size!(
+    ConfigTimelock,
+    [u8; 1 + 8 + 1 + 1 + 1 + 8 + 8 + 8 + CONFIG_TIMELOCK_PADDING_SIZE]
+);
+
+/// A config change queued by the lending market owner, executable by anyone once
+/// `earliest_execution_slot` is reached. Must be a pda with seeds [target, "PendingConfigChange"],
+/// where the target is the reserve for `UpdateReserveConfig` and the lending market otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct PendingConfigChange {
+    /// Bump seed
+    pub bump_seed: u8,
+    /// Lending market the change applies to
+    pub lending_market: Pubkey,
+    /// Lending market owner that queued the change, refunded the rent when it's executed or
+    /// cancelled
+    pub queued_by: Pubkey,
+    /// First slot the change can be executed in (little endian u64)
+    pub earliest_execution_slot: [u8; 8],
+    /// Number of used entries in `accounts`
+    pub account_count: u8,
+    /// Accounts the queued instruction has to be executed with, in order
+    pub accounts: [Pubkey; MAX_QUEUED_CONFIG_CHANGE_ACCOUNTS],
+    /// Length of `data` used (little endian u16)
+    pub data_len: [u8; 2],
+    /// Packed `LendingInstruction` to execute
+    pub data: [u8; MAX_QUEUED_CONFIG_CHANGE_DATA_LEN],
+    /// Padding
+    pub padding: [u8; PENDING_CONFIG_CHANGE_PADDING_SIZE],
+}
+
+impl PendingConfigChange {
+    /// Create a PendingConfigChange reference from account data
+    pub fn from_account_data(data: &[u8]) -> Result<&PendingConfigChange, ProgramError> {
+        try_from_bytes::<PendingConfigChange>(data).map_err(|_| {
+            msg!("Failed to deserialize PendingConfigChange account");
+            LendingError::InvalidAccountInput.into()
+        })
+    }
+
+    /// First slot the change can be executed in
+    pub fn earliest_execution_slot(&self) -> u64 {
+        u64::from_le_bytes(self.earliest_execution_slot)
+    }
+
+    /// Accounts the queued instruction has to be executed with
+    pub fn accounts(&self) -> &[Pubkey] {
+        &self.accounts[..self.account_count as usize]
+    }
+
+    /// Packed `LendingInstruction` to execute
+    pub fn data(&self) -> &[u8] {
+        &self.data[..u16::from_le_bytes(self.data_len) as usize]
+    }
+}
+
+unsafe impl Zeroable for PendingConfigChange {}
+unsafe impl Pod for PendingConfigChange {}
+
+assert_eq_size!(
+    PendingConfigChange,
+    [u8; 1
+        + 32
+        + 32
+        + 8
+        + 1
+        + MAX_QUEUED_CONFIG_CHANGE_ACCOUNTS * 32
+        + 2
+        + MAX_QUEUED_CONFIG_CHANGE_DATA_LEN
+        + PENDING_CONFIG_CHANGE_PADDING_SIZE],
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn delta_bounds() -> ConfigDeltaBounds {
+        ConfigDeltaBounds {
+            max_loan_to_value_ratio_decrease: 5,
+            max_liquidation_threshold_decrease: 5,
+            max_added_borrow_weight_bps_increase: 1_000,
+            interval_slots: 100,
+        }
+    }
+
+    fn reserve_config() -> ReserveConfig {
+        ReserveConfig {
+            loan_to_value_ratio: 50,
+            liquidation_threshold: 55,
+            max_liquidation_threshold: 65,
+            ..ReserveConfig::default()
+        }
+    }
+
+    #[test]
+    fn check_delta_bounds() {
+        let delta_bounds = delta_bounds();
+        let previous = reserve_config();
+
+        // raising collateral parameters isn't bounded
+        assert_eq!(
+            delta_bounds.check(
+                &previous,
+                &ReserveConfig {
+                    loan_to_value_ratio: 60,
+                    liquidation_threshold: 70,
+                    max_liquidation_threshold: 80,
+                    ..previous
+                },
+                1_000,
+                1_001
+            ),
+            Ok(false)
+        );
+
+        assert_eq!(
+            delta_bounds.check(
+                &previous,
+                &ReserveConfig {
+                    loan_to_value_ratio: 45,
+                    liquidation_threshold: 50,
+                    added_borrow_weight_bps: 1_000,
+                    ..previous
+                },
+                0,
+                1_000
+            ),
+            Ok(true)
+        );
+
+        for config in [
+            ReserveConfig {
+                loan_to_value_ratio: 44,
+                ..previous
+            },
+            ReserveConfig {
+                liquidation_threshold: 49,
+                ..previous
+            },
+            ReserveConfig {
+                max_liquidation_threshold: 59,
+                ..previous
+            },
+            ReserveConfig {
+                added_borrow_weight_bps: 1_001,
+                ..previous
+            },
+        ] {
+            assert_eq!(
+                delta_bounds.check(&previous, &config, 0, 1_000),
+                Err(LendingError::ConfigChangeExceedsBounds.into())
+            );
+        }
+
+        // one bounded change per interval
+        let config = ReserveConfig {
+            loan_to_value_ratio: 49,
+            ..previous
+        };
+        assert_eq!(
+            delta_bounds.check(&previous, &config, 1_000, 1_099),
+            Err(LendingError::ConfigChangeExceedsBounds.into())
+        );
+        assert_eq!(
+            delta_bounds.check(&previous, &config, 1_000, 1_100),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn delta_bounds_strictness() {
+        let delta_bounds = delta_bounds();
+        assert!(delta_bounds.is_at_least_as_strict_as(&delta_bounds));
+        assert!(ConfigDeltaBounds {
+            max_loan_to_value_ratio_decrease: 0,
+            interval_slots: 200,
+            ..delta_bounds
+        }
+        .is_at_least_as_strict_as(&delta_bounds));
+        assert!(!ConfigDeltaBounds {
+            max_liquidation_threshold_decrease: 6,
+            ..delta_bounds
+        }
+        .is_at_least_as_strict_as(&delta_bounds));
+        assert!(!ConfigDeltaBounds {
+            interval_slots: 99,
+            ..delta_bounds
+        }
+        .is_at_least_as_strict_as(&delta_bounds));
+    }
+
+    #[test]
+    fn delta_bounds_round_trip() {
+        let mut config_timelock = ConfigTimelock::zeroed();
+        assert_eq!(config_timelock.delta_bounds(), None);
+
+        config_timelock.set_delta_bounds(Some(delta_bounds()));
+        assert_eq!(config_timelock.delta_bounds(), Some(delta_bounds()));
+
+        config_timelock.set_delta_bounds(None);
+        assert_eq!(config_timelock, ConfigTimelock::zeroed());
+    }
+}
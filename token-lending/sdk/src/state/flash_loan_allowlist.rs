@@ -0,0 +1,120 @@
+use super::*;
+
+use crate::error::LendingError;
+use bytemuck::checked::try_from_bytes;
+use bytemuck::{Pod, Zeroable};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use static_assertions::{assert_eq_size, const_assert};
+
+/// Maximum number of signers on a flash loan allowlist
+pub const MAX_FLASH_LOAN_ALLOWLIST_SIGNERS: usize = 16;
+
+/// Maximum number of programs on a flash loan allowlist
+pub const MAX_FLASH_LOAN_ALLOWLIST_PROGRAMS: usize = 8;
+
+/// Flash loan fee discount that waives the whole fee
+pub const MAX_FLASH_LOAN_FEE_DISCOUNT_BPS: u16 = 10_000;
+
+/// padding size
+pub const FLASH_LOAN_ALLOWLIST_PADDING_SIZE: usize = 64;
+
+/// Signers and programs whose flash loans pay a discounted fee on a lending market
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FlashLoanAllowlist {
+    /// Bump seed
+    pub bump_seed: u8,
+    /// Share of the reserve flash loan fee waived, in basis points (little endian u16)
+    pub fee_discount_bps: [u8; 2],
+    /// Flash repay transfer authorities eligible for the discount, unused slots are the
+    /// default pubkey
+    pub signers: [Pubkey; MAX_FLASH_LOAN_ALLOWLIST_SIGNERS],
+    /// Programs eligible for the discount when every instruction between a flash borrow and its
+    /// repay calls one of them, unused slots are the default pubkey
+    pub programs: [Pubkey; MAX_FLASH_LOAN_ALLOWLIST_PROGRAMS],
+    /// Padding
+    pub padding: [u8; FLASH_LOAN_ALLOWLIST_PADDING_SIZE],
+}
+
+impl FlashLoanAllowlist {
+    /// Create a FlashLoanAllowlist reference from instruction data
+    pub fn new_from_bytes(data: &[u8]) -> Result<&FlashLoanAllowlist, ProgramError> {
+        try_from_bytes::<FlashLoanAllowlist>(&data[1..]).map_err(|_| {
+            msg!("Failed to deserialize FlashLoanAllowlist");
+            LendingError::InstructionUnpackError.into()
+        })
+    }
+
+    /// Create a FlashLoanAllowlist reference from account data
+    pub fn from_account_data(data: &[u8]) -> Result<&FlashLoanAllowlist, ProgramError> {
+        try_from_bytes::<FlashLoanAllowlist>(data).map_err(|_| {
+            msg!("Failed to deserialize FlashLoanAllowlist account");
+            LendingError::InvalidAccountInput.into()
+        })
+    }
+
+    /// Fee discount in basis points
+    pub fn fee_discount_bps(&self) -> u16 {
+        u16::from_le_bytes(self.fee_discount_bps)
+    }
+
+    /// Fee discount in basis points that applies to a flash loan repaid by `signer`
+    pub fn fee_discount_for(&self, signer: &Pubkey) -> u16 {
+        if *signer != Pubkey::default() && self.signers.contains(signer) {
+            self.fee_discount_bps()
+        } else {
+            0
+        }
+    }
+
+    /// Whether `program_id` is on the allowlist
+    pub fn contains_program(&self, program_id: &Pubkey) -> bool {
+        *program_id != Pubkey::default() && self.programs.contains(program_id)
+    }
+}
+
+unsafe impl Zeroable for FlashLoanAllowlist {}
+unsafe impl Pod for FlashLoanAllowlist {}
+
+assert_eq_size!(
+    FlashLoanAllowlist,
+    [u8; 1
+        + 2
+        + MAX_FLASH_LOAN_ALLOWLIST_SIGNERS * 32
+        + MAX_FLASH_LOAN_ALLOWLIST_PROGRAMS * 32
+        + FLASH_LOAN_ALLOWLIST_PADDING_SIZE],
+);
+
+// transaction size limit check
+const_assert!(std::mem::size_of::<FlashLoanAllowlist>() <= 850);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fee_discount_for() {
+        let signer = Pubkey::new_unique();
+        let mut allowlist = FlashLoanAllowlist::zeroed();
+        allowlist.fee_discount_bps = 2_500u16.to_le_bytes();
+        allowlist.signers[3] = signer;
+
+        assert_eq!(allowlist.fee_discount_bps(), 2_500);
+        assert_eq!(allowlist.fee_discount_for(&signer), 2_500);
+        assert_eq!(allowlist.fee_discount_for(&Pubkey::new_unique()), 0);
+        assert_eq!(allowlist.fee_discount_for(&Pubkey::default()), 0);
+
+        let program_id = Pubkey::new_unique();
+        allowlist.programs[7] = program_id;
+        assert!(allowlist.contains_program(&program_id));
+        assert!(!allowlist.contains_program(&signer));
+        assert!(!allowlist.contains_program(&Pubkey::default()));
+
+        let data = bytemuck::bytes_of(&allowlist);
+        assert_eq!(
+            FlashLoanAllowlist::from_account_data(data).unwrap(),
+            &allowlist
+        );
+    }
+}
@@ -1,12 +1,20 @@
 use super::*;
+use crate::error::LendingError;
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
 use solana_program::{
+    entrypoint::ProgramResult,
     msg,
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::{Pubkey, PUBKEY_BYTES},
 };
 
+/// Default cap on an obligation's allowed borrow value, in whole units of the quote currency
+pub const DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE: u64 = 65_000_000;
+
+/// Default cap on an obligation's unhealthy borrow values, in whole units of the quote currency
+pub const DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE: u64 = 70_000_000;
+
 /// Lending market state
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct LendingMarket {
@@ -31,6 +39,25 @@ pub struct LendingMarket {
     pub whitelisted_liquidator: Option<Pubkey>,
     /// risk authority (additional pubkey used for setting params)
     pub risk_authority: Pubkey,
+    /// Max value of borrows and withdraws out of a single obligation per obligation outflow
+    /// window, in whole units of the quote currency. 0 disables the per obligation limit.
+    /// Liquidations aren't limited or counted
+    pub obligation_max_outflow: u32,
+    /// Length in slots of the sliding window obligation outflows are limited in. Only 0 while the
+    /// per obligation limit is disabled
+    pub obligation_outflow_window: u64,
+    /// Cap on the allowed borrow value of every obligation in this market, in whole units of the
+    /// quote currency
+    pub global_allowed_borrow_value: u64,
+    /// Cap on the unhealthy and super unhealthy borrow values of every obligation in this market,
+    /// in whole units of the quote currency
+    pub global_unhealthy_borrow_value: u64,
+    /// Whether deposits, borrows, withdraws, flash borrows and liquidations are paused. Repays
+    /// and refreshes are always allowed
+    pub paused: bool,
+    /// Whether the lending market's config timelock account exists, which instructions that can
+    /// leave it out then have to provide
+    pub has_config_timelock: bool,
 }
 
 impl LendingMarket {
@@ -53,6 +80,94 @@ impl LendingMarket {
         self.rate_limiter = RateLimiter::default();
         self.whitelisted_liquidator = None;
         self.risk_authority = params.owner;
+        self.obligation_max_outflow = 0;
+        self.obligation_outflow_window = 0;
+        self.global_allowed_borrow_value = DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE;
+        self.global_unhealthy_borrow_value = DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE;
+        self.paused = false;
+        self.has_config_timelock = false;
+    }
+
+    /// Whether borrows and withdraws out of a single obligation are limited
+    pub fn has_obligation_outflow_limit(&self) -> bool {
+        self.obligation_max_outflow != 0 && self.obligation_outflow_window != 0
+    }
+}
+
+/// Validates the global borrow values of a lending market. Both have to be nonzero, fit in the
+/// compact encoding they're stored in, and the allowed borrow value can't exceed the unhealthy one.
+pub fn validate_global_borrow_values(
+    global_allowed_borrow_value: u64,
+    global_unhealthy_borrow_value: u64,
+) -> ProgramResult {
+    if encode_compact_value(global_allowed_borrow_value).is_none() {
+        msg!("Global allowed borrow value must be a nonzero u8 times a power of ten");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if encode_compact_value(global_unhealthy_borrow_value).is_none() {
+        msg!("Global unhealthy borrow value must be a nonzero u8 times a power of ten");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if global_allowed_borrow_value > global_unhealthy_borrow_value {
+        msg!("Global allowed borrow value must be <= global unhealthy borrow value");
+        return Err(LendingError::InvalidConfig.into());
+    }
+
+    Ok(())
+}
+
+/// Validates the per obligation outflow limit of a lending market. Both values have to be 0 or fit
+/// in the compact encoding they're stored in, and an enabled limit needs a window.
+pub fn validate_obligation_outflow_limit(
+    obligation_max_outflow: u32,
+    obligation_outflow_window: u64,
+) -> ProgramResult {
+    if obligation_max_outflow != 0 && encode_compact_value(obligation_max_outflow as u64).is_none()
+    {
+        msg!("Obligation max outflow must be 0 or a nonzero u8 times a power of ten");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if obligation_outflow_window != 0 && encode_compact_value(obligation_outflow_window).is_none() {
+        msg!("Obligation outflow window must be 0 or a nonzero u8 times a power of ten");
+        return Err(LendingError::InvalidConfig.into());
+    }
+    if obligation_max_outflow != 0 && obligation_outflow_window == 0 {
+        msg!("Obligation outflow window must be nonzero while obligation outflows are limited");
+        return Err(LendingError::InvalidConfig.into());
+    }
+
+    Ok(())
+}
+
+/// The global borrow values and the per obligation outflow limit are stored as a u8 mantissa and
+/// a power of ten between 0 and 15, eg 65_000_000 is stored as (65, 6). Returns None for values
+/// that can't be stored that way, including 0.
+fn encode_compact_value(value: u64) -> Option<(u8, u8)> {
+    let mut mantissa = value;
+    let mut exponent = 0;
+    while mantissa > u8::MAX as u64 {
+        // dropping a nonzero digit would lose precision
+        let last_digit = mantissa % 10;
+        if last_digit != 0 {
+            return None;
+        }
+        mantissa /= 10;
+        exponent += 1;
+    }
+
+    if mantissa == 0 || exponent > 15 {
+        return None;
+    }
+    Some((mantissa as u8, exponent))
+}
+
+/// Inverse of encode_compact_value. A mantissa of 0 means the value was never set, in which case the
+/// default is returned.
+fn decode_compact_value(mantissa: u8, exponent: u8, default: u64) -> u64 {
+    if mantissa == 0 {
+        default
+    } else {
+        mantissa as u64 * 10u64.pow(exponent as u32)
     }
 }
 
@@ -98,6 +213,13 @@ impl Pack for LendingMarket {
             rate_limiter,
             whitelisted_liquidator,
             risk_authority,
+            flags,
+            obligation_max_outflow_mantissa,
+            obligation_outflow_window_mantissa,
+            obligation_outflow_exponents,
+            global_allowed_borrow_value_mantissa,
+            global_unhealthy_borrow_value_mantissa,
+            global_borrow_value_exponents,
             _padding,
         ) = mut_array_refs![
             output,
@@ -111,7 +233,14 @@ impl Pack for LendingMarket {
             RATE_LIMITER_LEN,
             PUBKEY_BYTES,
             PUBKEY_BYTES,
-            8
+            1,
+            1,
+            1,
+            1,
+            1,
+            1,
+            1,
+            1
         ];
 
         *version = self.version.to_le_bytes();
@@ -131,6 +260,30 @@ impl Pack for LendingMarket {
             }
         }
         risk_authority.copy_from_slice(self.risk_authority.as_ref());
+        // the rate limiter's net flow is bit 0 of the flags, whether the market has a config
+        // timelock account is bit 1 and whether the market is paused is bit 2
+        *flags = [u8::from(self.rate_limiter.config.net_flow)
+            | (u8::from(self.has_config_timelock) << 1)
+            | (u8::from(self.paused) << 2)];
+
+        // values that don't fit are rejected by validate_obligation_outflow_limit and
+        // validate_global_borrow_values before they're set, so falling back to 0 (disabled or the
+        // default) only happens for values that are 0
+        let (max_outflow_mantissa, max_outflow_exponent) =
+            encode_compact_value(self.obligation_max_outflow as u64).unwrap_or((0, 0));
+        let (window_mantissa, window_exponent) =
+            encode_compact_value(self.obligation_outflow_window).unwrap_or((0, 0));
+        *obligation_max_outflow_mantissa = max_outflow_mantissa.to_le_bytes();
+        *obligation_outflow_window_mantissa = window_mantissa.to_le_bytes();
+        *obligation_outflow_exponents = [(max_outflow_exponent << 4) | window_exponent];
+
+        let (allowed_mantissa, allowed_exponent) =
+            encode_compact_value(self.global_allowed_borrow_value).unwrap_or((0, 0));
+        let (unhealthy_mantissa, unhealthy_exponent) =
+            encode_compact_value(self.global_unhealthy_borrow_value).unwrap_or((0, 0));
+        *global_allowed_borrow_value_mantissa = allowed_mantissa.to_le_bytes();
+        *global_unhealthy_borrow_value_mantissa = unhealthy_mantissa.to_le_bytes();
+        *global_borrow_value_exponents = [(allowed_exponent << 4) | unhealthy_exponent];
     }
 
     /// Unpacks a byte buffer into a [LendingMarketInfo](struct.LendingMarketInfo.html)
@@ -148,6 +301,13 @@ impl Pack for LendingMarket {
             rate_limiter,
             whitelisted_liquidator,
             risk_authority,
+            flags,
+            obligation_max_outflow_mantissa,
+            obligation_outflow_window_mantissa,
+            obligation_outflow_exponents,
+            global_allowed_borrow_value_mantissa,
+            global_unhealthy_borrow_value_mantissa,
+            global_borrow_value_exponents,
             _padding,
         ) = array_refs![
             input,
@@ -161,7 +321,14 @@ impl Pack for LendingMarket {
             RATE_LIMITER_LEN,
             PUBKEY_BYTES,
             PUBKEY_BYTES,
-            8
+            1,
+            1,
+            1,
+            1,
+            1,
+            1,
+            1,
+            1
         ];
 
         let version = u8::from_le_bytes(*version);
@@ -170,6 +337,14 @@ impl Pack for LendingMarket {
             return Err(ProgramError::InvalidAccountData);
         }
 
+        let mut rate_limiter = RateLimiter::unpack_from_slice(rate_limiter)?;
+        let flags = flags[0];
+        if flags > 0b111 {
+            msg!("Lending market flags cannot be unpacked");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        rate_limiter.config.net_flow = flags & 0b01 != 0;
+
         let owner_pubkey = Pubkey::new_from_array(*owner);
         Ok(Self {
             version,
@@ -179,7 +354,7 @@ impl Pack for LendingMarket {
             token_program_id: Pubkey::new_from_array(*token_program_id),
             oracle_program_id: Pubkey::new_from_array(*oracle_program_id),
             switchboard_oracle_program_id: Pubkey::new_from_array(*switchboard_oracle_program_id),
-            rate_limiter: RateLimiter::unpack_from_slice(rate_limiter)?,
+            rate_limiter,
             whitelisted_liquidator: if whitelisted_liquidator == &[0u8; 32] {
                 None
             } else {
@@ -193,6 +368,32 @@ impl Pack for LendingMarket {
             } else {
                 Pubkey::new_from_array(*risk_authority)
             },
+            // the obligation max outflow is validated to fit in a u32 before it's set
+            obligation_max_outflow: decode_compact_value(
+                obligation_max_outflow_mantissa[0],
+                obligation_outflow_exponents[0] >> 4,
+                0,
+            )
+            .min(u32::MAX as u64) as u32,
+            obligation_outflow_window: decode_compact_value(
+                obligation_outflow_window_mantissa[0],
+                obligation_outflow_exponents[0] & 0x0f,
+                0,
+            ),
+            // lending markets created before the global borrow values were configurable have
+            // zeroes here and get the defaults
+            global_allowed_borrow_value: decode_compact_value(
+                global_allowed_borrow_value_mantissa[0],
+                global_borrow_value_exponents[0] >> 4,
+                DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE,
+            ),
+            global_unhealthy_borrow_value: decode_compact_value(
+                global_unhealthy_borrow_value_mantissa[0],
+                global_borrow_value_exponents[0] & 0x0f,
+                DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE,
+            ),
+            paused: flags & 0b100 != 0,
+            has_config_timelock: flags & 0b010 != 0,
         })
     }
 }
@@ -220,6 +421,15 @@ mod test {
                 Some(Pubkey::new_unique())
             },
             risk_authority: Pubkey::new_unique(),
+            obligation_max_outflow: rng.gen_range(0..=u8::MAX as u32)
+                * 10u32.pow(rng.gen_range(0..=7)),
+            obligation_outflow_window: rng.gen_range(0..=u8::MAX as u64)
+                * 10u64.pow(rng.gen_range(0..=15)),
+            global_allowed_borrow_value: rng.gen_range(1..=u8::MAX as u64),
+            global_unhealthy_borrow_value: rng.gen_range(1..=u8::MAX as u64)
+                * 10u64.pow(rng.gen_range(0..=15)),
+            paused: rng.gen(),
+            has_config_timelock: rng.gen(),
         };
 
         let mut packed = vec![0u8; LendingMarket::LEN];
@@ -227,4 +437,72 @@ mod test {
         let unpacked = LendingMarket::unpack_from_slice(&packed).unwrap();
         assert_eq!(unpacked, lending_market);
     }
+
+    #[test]
+    fn global_borrow_values() {
+        assert_eq!(encode_compact_value(65_000_000), Some((65, 6)));
+        assert_eq!(encode_compact_value(255), Some((255, 0)));
+        assert_eq!(encode_compact_value(2_560), None);
+        assert_eq!(encode_compact_value(0), None);
+        assert_eq!(encode_compact_value(65_000_001), None);
+        assert_eq!(encode_compact_value(10u64.pow(17)), Some((100, 15)));
+        assert_eq!(encode_compact_value(10u64.pow(18)), None);
+
+        assert_eq!(
+            validate_global_borrow_values(
+                DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE,
+                DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            validate_global_borrow_values(2_000_000, 1_000_000),
+            Err(LendingError::InvalidConfig.into())
+        );
+        assert_eq!(
+            validate_global_borrow_values(1_000_000, 1_234_567),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+
+    #[test]
+    fn obligation_outflow_limit() {
+        assert_eq!(validate_obligation_outflow_limit(0, 0), Ok(()));
+        assert_eq!(
+            validate_obligation_outflow_limit(5_000_000, 216_000),
+            Ok(())
+        );
+        assert_eq!(
+            validate_obligation_outflow_limit(5_000_000, 0),
+            Err(LendingError::InvalidConfig.into())
+        );
+        assert_eq!(
+            validate_obligation_outflow_limit(1_234_567, 216_000),
+            Err(LendingError::InvalidConfig.into())
+        );
+        assert_eq!(
+            validate_obligation_outflow_limit(5_000_000, 216_001),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+
+    #[test]
+    fn unpack_defaults_global_borrow_values() {
+        let lending_market = LendingMarket {
+            version: PROGRAM_VERSION,
+            ..LendingMarket::default()
+        };
+
+        let mut packed = vec![0u8; LendingMarket::LEN];
+        LendingMarket::pack(lending_market, &mut packed).unwrap();
+        let unpacked = LendingMarket::unpack_from_slice(&packed).unwrap();
+        assert_eq!(
+            unpacked.global_allowed_borrow_value,
+            DEFAULT_GLOBAL_ALLOWED_BORROW_VALUE
+        );
+        assert_eq!(
+            unpacked.global_unhealthy_borrow_value,
+            DEFAULT_GLOBAL_UNHEALTHY_BORROW_VALUE
+        );
+    }
 }
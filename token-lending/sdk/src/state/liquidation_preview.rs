@@ -0,0 +1,221 @@
+use super::*;
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, SaturatingSub, TryAdd},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::cmp::min;
+
+/// Outcome of a `LiquidateObligationAndRedeemReserveCollateral` instruction, computed off-chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiquidationPreview {
+    /// Bonus applied to the seized collateral
+    pub bonus: Bonus,
+    /// Amount of borrowed liquidity settled on the obligation
+    pub settle_amount: Decimal,
+    /// Amount of liquidity transferred from the liquidator to the repay reserve
+    pub repay_amount: u64,
+    /// Amount of collateral (cTokens) seized from the obligation
+    pub withdraw_amount: u64,
+    /// Amount of seized collateral redeemed for liquidity. Collateral the withdraw reserve
+    /// doesn't have the liquidity to redeem stays with the liquidator as cTokens.
+    pub redeemed_collateral_amount: u64,
+    /// Amount of liquidity received for the redeemed collateral, protocol fee included
+    pub redeemed_liquidity_amount: u64,
+    /// Amount of the redeemed liquidity paid to the withdraw reserve fee receiver
+    pub protocol_fee: u64,
+    /// Quote currency value of the liquidity repaid
+    pub repay_value: Decimal,
+    /// Quote currency value of what the liquidator keeps: the redeemed liquidity net of the
+    /// protocol fee, plus any collateral left unredeemed
+    pub received_value: Decimal,
+}
+
+impl LiquidationPreview {
+    /// Liquidator profit in the quote currency, zero if the liquidation loses money
+    pub fn profit(&self) -> Decimal {
+        self.received_value.saturating_sub(self.repay_value)
+    }
+}
+
+/// Computes what `LiquidateObligationAndRedeemReserveCollateral` would do to `obligation`, given
+/// the obligation and both reserves as refreshed in the current slot. Follows the program's
+/// checks and rounding, but not the lending market's whitelisted liquidator.
+pub fn preview_liquidation(
+    obligation: &Obligation,
+    repay_reserve_pubkey: &Pubkey,
+    repay_reserve: &Reserve,
+    withdraw_reserve_pubkey: &Pubkey,
+    withdraw_reserve: &Reserve,
+    liquidity_amount: u64,
+) -> Result<LiquidationPreview, ProgramError> {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(LendingError::InvalidAmount.into());
+    }
+    if obligation.deposited_value == Decimal::zero() {
+        msg!("Obligation deposited value is zero");
+        return Err(LendingError::ObligationDepositsZero.into());
+    }
+    if obligation.borrowed_value == Decimal::zero() {
+        msg!("Obligation borrowed value is zero");
+        return Err(LendingError::ObligationBorrowsZero.into());
+    }
+    if obligation.borrowed_value < obligation.unhealthy_borrow_value && !obligation.closeable {
+        msg!("Obligation must be unhealthy or marked as closeable to be liquidated");
+        return Err(LendingError::ObligationHealthy.into());
+    }
+
+    let (liquidity, liquidity_index) =
+        obligation.find_liquidity_in_borrows(*repay_reserve_pubkey)?;
+    if liquidity.market_value == Decimal::zero() {
+        msg!("Obligation borrow value is zero");
+        return Err(LendingError::ObligationLiquidityEmpty.into());
+    }
+    if liquidity_index != 0 {
+        msg!("Obligation borrow is not the first liquidity in the borrows list");
+        return Err(LendingError::InvalidAccountInput.into());
+    }
+
+    let (collateral, _) = obligation.find_collateral_in_deposits(*withdraw_reserve_pubkey)?;
+    if collateral.market_value == Decimal::zero() {
+        msg!("Obligation deposit value is zero");
+        return Err(LendingError::ObligationCollateralEmpty.into());
+    }
+
+    let bonus = withdraw_reserve.calculate_bonus(obligation)?;
+    let CalculateLiquidationResult {
+        settle_amount,
+        repay_amount,
+        withdraw_amount,
+    } = withdraw_reserve.calculate_liquidation(
+        liquidity_amount,
+        obligation,
+        liquidity,
+        collateral,
+        &bonus,
+    )?;
+    if repay_amount == 0 {
+        msg!("Liquidation is too small to transfer liquidity");
+        return Err(LendingError::LiquidationTooSmall.into());
+    }
+    if withdraw_amount == 0 {
+        msg!("Liquidation is too small to receive collateral");
+        return Err(LendingError::LiquidationTooSmall.into());
+    }
+
+    // the repay is already applied when the withdraw reserve is reloaded to redeem
+    let mut withdraw_reserve = withdraw_reserve.clone();
+    if repay_reserve_pubkey == withdraw_reserve_pubkey {
+        withdraw_reserve
+            .liquidity
+            .repay(repay_amount, settle_amount)?;
+    }
+
+    let collateral_exchange_rate = withdraw_reserve.collateral_exchange_rate()?;
+    let max_redeemable_collateral = collateral_exchange_rate
+        .liquidity_to_collateral(withdraw_reserve.liquidity.available_amount)?;
+    let redeemed_collateral_amount = min(withdraw_amount, max_redeemable_collateral);
+
+    let (redeemed_liquidity_amount, protocol_fee) = if redeemed_collateral_amount != 0 {
+        let redeemed_liquidity_amount =
+            withdraw_reserve.redeem_collateral(redeemed_collateral_amount)?;
+        let protocol_fee = withdraw_reserve
+            .calculate_protocol_liquidation_fee(redeemed_liquidity_amount, &bonus)?;
+        (redeemed_liquidity_amount, protocol_fee)
+    } else {
+        (0, 0)
+    };
+
+    let unredeemed_liquidity = collateral_exchange_rate.decimal_collateral_to_liquidity(
+        Decimal::from(withdraw_amount - redeemed_collateral_amount),
+    )?;
+    let received_value = withdraw_reserve.market_value(
+        Decimal::from(redeemed_liquidity_amount)
+            .saturating_sub(Decimal::from(protocol_fee))
+            .try_add(unredeemed_liquidity)?,
+    )?;
+
+    Ok(LiquidationPreview {
+        repay_value: repay_reserve.market_value(Decimal::from(repay_amount))?,
+        received_value,
+        bonus,
+        settle_amount,
+        repay_amount,
+        withdraw_amount,
+        redeemed_collateral_amount,
+        redeemed_liquidity_amount,
+        protocol_fee,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn preview_same_reserve_redeems_repaid_liquidity() {
+        let reserve_pubkey = Pubkey::new_unique();
+        // no liquidity is available until the liquidator repays
+        let reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 0,
+                available_amount: 0,
+                borrowed_amount_wads: Decimal::from(1_000u64),
+                market_price: Decimal::one(),
+                ..ReserveLiquidity::default()
+            },
+            collateral: ReserveCollateral {
+                mint_total_supply: 1_000,
+                ..ReserveCollateral::default()
+            },
+            config: ReserveConfig {
+                liquidation_bonus: 5,
+                max_liquidation_bonus: 5,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+        let obligation = Obligation {
+            deposits: vec![ObligationCollateral {
+                deposit_reserve: reserve_pubkey,
+                deposited_amount: 1_000,
+                market_value: Decimal::from(1_000u64),
+                ..ObligationCollateral::default()
+            }],
+            borrows: vec![ObligationLiquidity {
+                borrow_reserve: reserve_pubkey,
+                borrowed_amount_wads: Decimal::from(900u64),
+                market_value: Decimal::from(900u64),
+                ..ObligationLiquidity::default()
+            }],
+            deposited_value: Decimal::from(1_000u64),
+            borrowed_value: Decimal::from(900u64),
+            unhealthy_borrow_value: Decimal::from(850u64),
+            super_unhealthy_borrow_value: Decimal::from(850u64),
+            ..Obligation::default()
+        };
+
+        let preview = preview_liquidation(
+            &obligation,
+            &reserve_pubkey,
+            &reserve,
+            &reserve_pubkey,
+            &reserve,
+            u64::MAX,
+        )
+        .unwrap();
+
+        // 20% of 900 is repaid, and 180 * 1.05 cTokens are seized but only the 180 repaid can be
+        // redeemed
+        assert_eq!(preview.repay_amount, 180);
+        assert_eq!(preview.withdraw_amount, 189);
+        assert_eq!(preview.redeemed_collateral_amount, 180);
+        assert_eq!(preview.redeemed_liquidity_amount, 180);
+        assert_eq!(preview.protocol_fee, 1);
+        assert_eq!(preview.repay_value, Decimal::from(180u64));
+        assert_eq!(preview.received_value, Decimal::from(188u64));
+        assert_eq!(preview.profit(), Decimal::from(8u64));
+    }
+}
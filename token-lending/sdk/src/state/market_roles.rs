@@ -0,0 +1,129 @@
+use super::*;
+
+use crate::error::LendingError;
+use bytemuck::checked::try_from_bytes;
+use bytemuck::{Pod, Zeroable};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use static_assertions::assert_eq_size;
+
+/// padding size
+pub const MARKET_ROLES_PADDING_SIZE: usize = 128;
+
+/// Role a `MarketRoles` account can grant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketRole {
+    /// Can change the reserve config fields not governed by another role
+    ConfigAdmin,
+    /// Can change reserve oracles and how their prices are adjusted
+    OracleAdmin,
+    /// Can change reserve fees, on markets owned by the protocol
+    FeeAdmin,
+    /// Can disable reserve and lending market outflows, and pause the lending market
+    PauseGuardian,
+    /// Can change deposit, borrow and outflow limits
+    LimitsAdmin,
+}
+
+impl MarketRole {
+    /// Every role
+    pub const ALL: [MarketRole; 5] = [
+        MarketRole::ConfigAdmin,
+        MarketRole::OracleAdmin,
+        MarketRole::FeeAdmin,
+        MarketRole::PauseGuardian,
+        MarketRole::LimitsAdmin,
+    ];
+}
+
+/// Keys that administer a lending market on behalf of its owner, each limited to the fields its
+/// role governs. Must be a pda with seeds [lending_market, "MarketRoles"]. Unassigned roles are
+/// the default pubkey.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct MarketRoles {
+    /// Bump seed
+    pub bump_seed: u8,
+    /// Config admin
+    pub config_admin: Pubkey,
+    /// Oracle admin
+    pub oracle_admin: Pubkey,
+    /// Fee admin
+    pub fee_admin: Pubkey,
+    /// Pause guardian
+    pub pause_guardian: Pubkey,
+    /// Limits admin
+    pub limits_admin: Pubkey,
+    /// Padding
+    pub padding: [u8; MARKET_ROLES_PADDING_SIZE],
+}
+
+impl MarketRoles {
+    /// Create a MarketRoles reference from instruction data
+    pub fn new_from_bytes(data: &[u8]) -> Result<&MarketRoles, ProgramError> {
+        try_from_bytes::<MarketRoles>(&data[1..]).map_err(|_| {
+            msg!("Failed to deserialize MarketRoles");
+            LendingError::InstructionUnpackError.into()
+        })
+    }
+
+    /// Create a MarketRoles reference from account data
+    pub fn from_account_data(data: &[u8]) -> Result<&MarketRoles, ProgramError> {
+        try_from_bytes::<MarketRoles>(data).map_err(|_| {
+            msg!("Failed to deserialize MarketRoles account");
+            LendingError::InvalidAccountInput.into()
+        })
+    }
+
+    /// Key assigned to a role
+    pub fn holder(&self, role: MarketRole) -> &Pubkey {
+        match role {
+            MarketRole::ConfigAdmin => &self.config_admin,
+            MarketRole::OracleAdmin => &self.oracle_admin,
+            MarketRole::FeeAdmin => &self.fee_admin,
+            MarketRole::PauseGuardian => &self.pause_guardian,
+            MarketRole::LimitsAdmin => &self.limits_admin,
+        }
+    }
+
+    /// Whether `signer` holds `role`
+    pub fn has_role(&self, role: MarketRole, signer: &Pubkey) -> bool {
+        *signer != Pubkey::default() && self.holder(role) == signer
+    }
+
+    /// Whether `signer` holds any role
+    pub fn has_any_role(&self, signer: &Pubkey) -> bool {
+        MarketRole::ALL
+            .iter()
+            .any(|role| self.has_role(*role, signer))
+    }
+}
+
+unsafe impl Zeroable for MarketRoles {}
+unsafe impl Pod for MarketRoles {}
+
+assert_eq_size!(MarketRoles, [u8; 1 + 5 * 32 + MARKET_ROLES_PADDING_SIZE]);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn has_role() {
+        let signer = Pubkey::new_unique();
+        let mut roles = MarketRoles::zeroed();
+        roles.limits_admin = signer;
+
+        assert!(roles.has_role(MarketRole::LimitsAdmin, &signer));
+        assert!(!roles.has_role(MarketRole::ConfigAdmin, &signer));
+        assert!(roles.has_any_role(&signer));
+        assert!(!roles.has_any_role(&Pubkey::new_unique()));
+
+        // unassigned roles are never held
+        assert!(!roles.has_role(MarketRole::ConfigAdmin, &Pubkey::default()));
+        assert!(!roles.has_any_role(&Pubkey::default()));
+
+        let data = bytemuck::bytes_of(&roles);
+        assert_eq!(MarketRoles::from_account_data(data).unwrap(), &roles);
+    }
+}
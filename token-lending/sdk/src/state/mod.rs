@@ -1,16 +1,28 @@
 //! State types
 
+mod config_timelock;
+mod flash_loan_allowlist;
 mod last_update;
 mod lending_market;
 mod lending_market_metadata;
+mod liquidation_preview;
+mod market_roles;
 mod obligation;
+mod pending_market_owner;
+mod protocol_config;
 mod rate_limiter;
 mod reserve;
 
+pub use config_timelock::*;
+pub use flash_loan_allowlist::*;
 pub use last_update::*;
 pub use lending_market::*;
 pub use lending_market_metadata::*;
+pub use liquidation_preview::*;
+pub use market_roles::*;
 pub use obligation::*;
+pub use pending_market_owner::*;
+pub use protocol_config::*;
 pub use rate_limiter::*;
 pub use reserve::*;
 
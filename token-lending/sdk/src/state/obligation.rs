@@ -63,6 +63,18 @@ pub struct Obligation {
     pub borrowing_isolated_asset: bool,
     /// Obligation can be marked as closeable
     pub closeable: bool,
+    /// True if the obligation was last refreshed without its lending market account, which caps
+    /// its borrow values at the default global borrow values instead of the lending market's
+    pub refreshed_without_lending_market: bool,
+    /// Start slot of the obligation's current outflow window. See
+    /// `LendingMarket::obligation_max_outflow`
+    pub outflow_window_start: Slot,
+    /// Value of borrows and withdraws out of the obligation in the previous outflow window, in
+    /// whole units of the quote currency rounded up
+    pub outflow_prev_value: u32,
+    /// Value of borrows and withdraws out of the obligation in the current outflow window, in
+    /// whole units of the quote currency rounded up
+    pub outflow_cur_value: u32,
 }
 
 impl Obligation {
@@ -174,16 +186,79 @@ impl Obligation {
             .try_sub(self.borrowed_value_upper_bound)
     }
 
+    /// Remaining outflow allowed from the obligation at `cur_slot`, in the quote currency
+    pub fn remaining_outflow(
+        &self,
+        lending_market: &LendingMarket,
+        cur_slot: Slot,
+    ) -> Result<Decimal, ProgramError> {
+        match self.outflow_rate_limiter(lending_market, cur_slot) {
+            Some(rate_limiter) => rate_limiter.projected_remaining_outflow(cur_slot),
+            None => Ok(Decimal::from(u64::MAX)),
+        }
+    }
+
+    /// Record borrows or withdraws worth `value` against the obligation's outflow limit. Errors if
+    /// the limit would be exceeded.
+    pub fn update_outflow(
+        &mut self,
+        lending_market: &LendingMarket,
+        cur_slot: Slot,
+        value: Decimal,
+    ) -> ProgramResult {
+        let mut rate_limiter = match self.outflow_rate_limiter(lending_market, cur_slot) {
+            Some(rate_limiter) => rate_limiter,
+            None => return Ok(()),
+        };
+
+        rate_limiter.update(cur_slot, Decimal::from(value.try_ceil_u64()?))?;
+
+        // outflows are whole units, so the quantities are too
+        let (prev_qty, window_start, cur_qty) = rate_limiter.state();
+        self.outflow_window_start = window_start;
+        self.outflow_prev_value = prev_qty.try_floor_u64()?.min(u32::MAX as u64) as u32;
+        self.outflow_cur_value = cur_qty.try_floor_u64()?.min(u32::MAX as u64) as u32;
+        Ok(())
+    }
+
+    /// Sliding window rate limiter over the obligation's outflows, or None if the lending market
+    /// doesn't limit obligation outflows
+    fn outflow_rate_limiter(
+        &self,
+        lending_market: &LendingMarket,
+        cur_slot: Slot,
+    ) -> Option<RateLimiter> {
+        if !lending_market.has_obligation_outflow_limit() {
+            return None;
+        }
+
+        Some(RateLimiter::from_state(
+            RateLimiterConfig {
+                window_duration: lending_market.obligation_outflow_window,
+                max_outflow: lending_market.obligation_max_outflow as u64,
+                net_flow: false,
+            },
+            Decimal::from(self.outflow_prev_value as u64),
+            self.outflow_window_start,
+            Decimal::from(self.outflow_cur_value as u64),
+            cur_slot,
+        ))
+    }
+
+    /// Calculate the maximum borrowed value that can be liquidated at once
+    pub fn max_liquidation_value(&self) -> Result<Decimal, ProgramError> {
+        Ok(self
+            .borrowed_value
+            .try_mul(Rate::from_percent(LIQUIDATION_CLOSE_FACTOR))?
+            .min(Decimal::from(MAX_LIQUIDATABLE_VALUE_AT_ONCE)))
+    }
+
     /// Calculate the maximum liquidation amount for a given liquidity
     pub fn max_liquidation_amount(
         &self,
         liquidity: &ObligationLiquidity,
     ) -> Result<Decimal, ProgramError> {
-        let max_liquidation_value = self
-            .borrowed_value
-            .try_mul(Rate::from_percent(LIQUIDATION_CLOSE_FACTOR))?
-            .min(liquidity.market_value)
-            .min(Decimal::from(MAX_LIQUIDATABLE_VALUE_AT_ONCE));
+        let max_liquidation_value = self.max_liquidation_value()?.min(liquidity.market_value);
 
         let max_liquidation_pct = max_liquidation_value.try_div(liquidity.market_value)?;
         liquidity.borrowed_amount_wads.try_mul(max_liquidation_pct)
@@ -436,8 +511,10 @@ impl Pack for Obligation {
             borrowing_isolated_asset,
             super_unhealthy_borrow_value,
             unweighted_borrowed_value,
-            closeable,
-            _padding,
+            flags,
+            outflow_window_start,
+            outflow_prev_value,
+            outflow_cur_value,
             deposits_len,
             borrows_len,
             data_flat,
@@ -457,7 +534,9 @@ impl Pack for Obligation {
             16,
             16,
             1,
-            14,
+            6,
+            4,
+            4,
             1,
             1,
             OBLIGATION_COLLATERAL_LEN + (OBLIGATION_LIQUIDITY_LEN * (MAX_OBLIGATION_RESERVES - 1))
@@ -480,7 +559,13 @@ impl Pack for Obligation {
             super_unhealthy_borrow_value,
         );
         pack_decimal(self.unweighted_borrowed_value, unweighted_borrowed_value);
-        pack_bool(self.closeable, closeable);
+        // closeable is bit 0 of the flags, refreshed without the lending market is bit 1
+        *flags =
+            [u8::from(self.closeable) | (u8::from(self.refreshed_without_lending_market) << 1)];
+        // slots fit in 48 bits for millennia
+        outflow_window_start.copy_from_slice(&self.outflow_window_start.to_le_bytes()[..6]);
+        *outflow_prev_value = self.outflow_prev_value.to_le_bytes();
+        *outflow_cur_value = self.outflow_cur_value.to_le_bytes();
 
         *deposits_len = u8::try_from(self.deposits.len()).unwrap().to_le_bytes();
         *borrows_len = u8::try_from(self.borrows.len()).unwrap().to_le_bytes();
@@ -545,8 +630,10 @@ impl Pack for Obligation {
             borrowing_isolated_asset,
             super_unhealthy_borrow_value,
             unweighted_borrowed_value,
-            closeable,
-            _padding,
+            flags,
+            outflow_window_start,
+            outflow_prev_value,
+            outflow_cur_value,
             deposits_len,
             borrows_len,
             data_flat,
@@ -566,7 +653,9 @@ impl Pack for Obligation {
             16,
             16,
             1,
-            14,
+            6,
+            4,
+            4,
             1,
             1,
             OBLIGATION_COLLATERAL_LEN + (OBLIGATION_LIQUIDITY_LEN * (MAX_OBLIGATION_RESERVES - 1))
@@ -577,6 +666,10 @@ impl Pack for Obligation {
             msg!("Obligation version does not match lending program version");
             return Err(ProgramError::InvalidAccountData);
         }
+        if flags[0] > 0b11 {
+            msg!("Obligation flags cannot be unpacked");
+            return Err(ProgramError::InvalidAccountData);
+        }
 
         let deposits_len = u8::from_le_bytes(*deposits_len);
         let borrows_len = u8::from_le_bytes(*borrows_len);
@@ -639,7 +732,15 @@ impl Pack for Obligation {
             unhealthy_borrow_value: unpack_decimal(unhealthy_borrow_value),
             super_unhealthy_borrow_value: unpack_decimal(super_unhealthy_borrow_value),
             borrowing_isolated_asset: unpack_bool(borrowing_isolated_asset)?,
-            closeable: unpack_bool(closeable)?,
+            closeable: flags[0] & 0b01 != 0,
+            refreshed_without_lending_market: flags[0] & 0b10 != 0,
+            outflow_window_start: {
+                let mut slot = [0u8; 8];
+                slot[..6].copy_from_slice(outflow_window_start);
+                u64::from_le_bytes(slot)
+            },
+            outflow_prev_value: u32::from_le_bytes(*outflow_prev_value),
+            outflow_cur_value: u32::from_le_bytes(*outflow_cur_value),
         })
     }
 }
@@ -691,6 +792,10 @@ mod test {
                 super_unhealthy_borrow_value: rand_decimal(),
                 borrowing_isolated_asset: rng.gen(),
                 closeable: rng.gen(),
+                refreshed_without_lending_market: rng.gen(),
+                outflow_window_start: rng.gen_range(0..1 << 48),
+                outflow_prev_value: rng.gen(),
+                outflow_cur_value: rng.gen(),
             };
 
             let mut packed = [0u8; OBLIGATION_LEN];
@@ -700,6 +805,78 @@ mod test {
         }
     }
 
+    #[test]
+    fn obligation_outflow_limit() {
+        // independent of the lending market rate limiter, which is disabled by default
+        let mut lending_market = LendingMarket {
+            obligation_outflow_window: 10,
+            ..LendingMarket::default()
+        };
+        let mut obligation = Obligation::default();
+
+        // disabled by default
+        assert_eq!(
+            obligation.remaining_outflow(&lending_market, 5),
+            Ok(Decimal::from(u64::MAX))
+        );
+        assert_eq!(
+            obligation.update_outflow(&lending_market, 5, Decimal::from(100u64)),
+            Ok(())
+        );
+        assert_eq!(obligation.outflow_cur_value, 0);
+
+        lending_market.obligation_max_outflow = 10;
+
+        // outflows are rounded up to whole units
+        assert_eq!(
+            obligation.update_outflow(&lending_market, 12, Decimal::from_percent(50)),
+            Ok(())
+        );
+        assert_eq!(obligation.outflow_window_start, 10);
+        assert_eq!(obligation.outflow_cur_value, 1);
+        assert_eq!(
+            obligation.remaining_outflow(&lending_market, 19),
+            Ok(Decimal::from(9u64))
+        );
+        assert_eq!(
+            obligation.update_outflow(&lending_market, 19, Decimal::from(10u64)),
+            Err(LendingError::OutflowRateLimitExceeded.into())
+        );
+        assert_eq!(
+            obligation.update_outflow(&lending_market, 19, Decimal::from(9u64)),
+            Ok(())
+        );
+
+        // the previous window's outflow still counts, weighted by how much of the current window
+        // is left
+        assert_eq!(
+            obligation.remaining_outflow(&lending_market, 20),
+            Ok(Decimal::from(1u64))
+        );
+        assert_eq!(
+            obligation.update_outflow(&lending_market, 20, Decimal::from(2u64)),
+            Err(LendingError::OutflowRateLimitExceeded.into())
+        );
+        assert_eq!(
+            obligation.remaining_outflow(&lending_market, 25),
+            Ok(Decimal::from(6u64))
+        );
+        assert_eq!(
+            obligation.update_outflow(&lending_market, 25, Decimal::from(6u64)),
+            Ok(())
+        );
+        assert_eq!(obligation.outflow_window_start, 20);
+        assert_eq!(obligation.outflow_prev_value, 10);
+        assert_eq!(obligation.outflow_cur_value, 6);
+
+        // state from a different window duration is reset
+        lending_market.obligation_outflow_window = 15;
+        assert_eq!(
+            obligation.remaining_outflow(&lending_market, 25),
+            Ok(Decimal::from(10u64))
+        );
+    }
+
     #[test]
     fn obligation_accrue_interest_failure() {
         assert_eq!(
@@ -0,0 +1,44 @@
+use super::*;
+
+use crate::error::LendingError;
+use bytemuck::checked::try_from_bytes;
+use bytemuck::{Pod, Zeroable};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use static_assertions::assert_eq_size;
+
+/// padding size
+pub const PENDING_MARKET_OWNER_PADDING_SIZE: usize = 64;
+
+/// Lending market owner proposed by the current owner, which takes over once it signs
+/// `AcceptMarketOwner`. Must be a pda with seeds [lending_market, "PendingMarketOwner"].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct PendingMarketOwner {
+    /// Bump seed
+    pub bump_seed: u8,
+    /// Proposed lending market owner
+    pub pending_owner: Pubkey,
+    /// Lending market owner that proposed the transfer, refunded the rent when it's accepted
+    pub proposed_by: Pubkey,
+    /// Padding
+    pub padding: [u8; PENDING_MARKET_OWNER_PADDING_SIZE],
+}
+
+impl PendingMarketOwner {
+    /// Create a PendingMarketOwner reference from account data
+    pub fn from_account_data(data: &[u8]) -> Result<&PendingMarketOwner, ProgramError> {
+        try_from_bytes::<PendingMarketOwner>(data).map_err(|_| {
+            msg!("Failed to deserialize PendingMarketOwner account");
+            LendingError::InvalidAccountInput.into()
+        })
+    }
+}
+
+unsafe impl Zeroable for PendingMarketOwner {}
+unsafe impl Pod for PendingMarketOwner {}
+
+assert_eq_size!(
+    PendingMarketOwner,
+    [u8; 1 + 32 + 32 + PENDING_MARKET_OWNER_PADDING_SIZE],
+);
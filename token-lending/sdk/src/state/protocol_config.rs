@@ -0,0 +1,191 @@
+use super::*;
+
+use crate::error::LendingError;
+use bytemuck::checked::try_from_bytes;
+use bytemuck::{Pod, Zeroable};
+use solana_program::entrypoint::ProgramResult;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use static_assertions::assert_eq_size;
+
+/// padding size
+pub const PROTOCOL_CONFIG_PADDING_SIZE: usize = 128;
+
+/// Program wide config. The protocol authority can change the protocol fees of reserves on
+/// permissionless markets, and the reserves it creates on its own markets aren't given the
+/// default protocol fees. Must be a pda with seeds ["ProtocolConfig"].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct ProtocolConfig {
+    /// Bump seed
+    pub bump_seed: u8,
+    /// Protocol authority
+    pub authority: Pubkey,
+    /// Protocol liquidation fee of reserves created on permissionless markets, in deca bps
+    pub default_protocol_liquidation_fee: u8,
+    /// Protocol take rate of reserves created on permissionless markets, in percent
+    pub default_protocol_take_rate: u8,
+    /// Lowest protocol liquidation fee a reserve can be configured with, in deca bps
+    pub min_protocol_liquidation_fee: u8,
+    /// Highest protocol liquidation fee a reserve can be configured with, in deca bps
+    pub max_protocol_liquidation_fee: u8,
+    /// Lowest protocol take rate a reserve can be configured with, in percent
+    pub min_protocol_take_rate: u8,
+    /// Highest protocol take rate a reserve can be configured with, in percent
+    pub max_protocol_take_rate: u8,
+    /// Padding
+    pub padding: [u8; PROTOCOL_CONFIG_PADDING_SIZE],
+}
+
+impl ProtocolConfig {
+    /// Create a ProtocolConfig reference from instruction data
+    pub fn new_from_bytes(data: &[u8]) -> Result<&ProtocolConfig, ProgramError> {
+        try_from_bytes::<ProtocolConfig>(&data[1..]).map_err(|_| {
+            msg!("Failed to deserialize ProtocolConfig");
+            LendingError::InstructionUnpackError.into()
+        })
+    }
+
+    /// Create a ProtocolConfig reference from account data
+    pub fn from_account_data(data: &[u8]) -> Result<&ProtocolConfig, ProgramError> {
+        try_from_bytes::<ProtocolConfig>(data).map_err(|_| {
+            msg!("Failed to deserialize ProtocolConfig account");
+            LendingError::InvalidAccountInput.into()
+        })
+    }
+
+    /// Validates the protocol authority and that the defaults are within the bounds
+    pub fn validate(&self) -> ProgramResult {
+        if self.authority == Pubkey::default() {
+            msg!("Protocol authority must be set");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.max_protocol_liquidation_fee > MAX_PROTOCOL_LIQUIDATION_FEE_DECA_BPS {
+            msg!(
+                "Max protocol liquidation fee must be in range [0, {}] deca bps",
+                MAX_PROTOCOL_LIQUIDATION_FEE_DECA_BPS
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if self.max_protocol_take_rate > 100 {
+            msg!("Max protocol take rate must be in range [0, 100]");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if !(self.min_protocol_liquidation_fee..=self.max_protocol_liquidation_fee)
+            .contains(&self.default_protocol_liquidation_fee)
+        {
+            msg!("Default protocol liquidation fee must be within the protocol liquidation fee bounds");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if !(self.min_protocol_take_rate..=self.max_protocol_take_rate)
+            .contains(&self.default_protocol_take_rate)
+        {
+            msg!("Default protocol take rate must be within the protocol take rate bounds");
+            return Err(LendingError::InvalidConfig.into());
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the protocol fees of a reserve config are out of bounds
+    pub fn validate_protocol_fees(&self, config: &ReserveConfig) -> ProgramResult {
+        if !(self.min_protocol_liquidation_fee..=self.max_protocol_liquidation_fee)
+            .contains(&config.protocol_liquidation_fee)
+        {
+            msg!(
+                "Protocol liquidation fee must be in range [{}, {}] deca bps",
+                self.min_protocol_liquidation_fee,
+                self.max_protocol_liquidation_fee
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+        if !(self.min_protocol_take_rate..=self.max_protocol_take_rate)
+            .contains(&config.protocol_take_rate)
+        {
+            msg!(
+                "Protocol take rate must be in range [{}, {}]",
+                self.min_protocol_take_rate,
+                self.max_protocol_take_rate
+            );
+            return Err(LendingError::InvalidConfig.into());
+        }
+
+        Ok(())
+    }
+}
+
+unsafe impl Zeroable for ProtocolConfig {}
+unsafe impl Pod for ProtocolConfig {}
+
+assert_eq_size!(
+    ProtocolConfig,
+    [u8; 1 + 32 + 6 + PROTOCOL_CONFIG_PADDING_SIZE],
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn protocol_config() -> ProtocolConfig {
+        ProtocolConfig {
+            authority: Pubkey::new_unique(),
+            default_protocol_liquidation_fee: 10,
+            default_protocol_take_rate: 5,
+            max_protocol_liquidation_fee: 50,
+            max_protocol_take_rate: 20,
+            ..ProtocolConfig::zeroed()
+        }
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(protocol_config().validate(), Ok(()));
+
+        for invalid in [
+            ProtocolConfig {
+                authority: Pubkey::default(),
+                ..protocol_config()
+            },
+            ProtocolConfig {
+                max_protocol_take_rate: 101,
+                ..protocol_config()
+            },
+            ProtocolConfig {
+                min_protocol_liquidation_fee: 11,
+                ..protocol_config()
+            },
+            ProtocolConfig {
+                max_protocol_take_rate: 4,
+                ..protocol_config()
+            },
+        ] {
+            assert_eq!(invalid.validate(), Err(LendingError::InvalidConfig.into()));
+        }
+    }
+
+    #[test]
+    fn validate_protocol_fees() {
+        let protocol_config = protocol_config();
+        let config = ReserveConfig {
+            protocol_liquidation_fee: 50,
+            protocol_take_rate: 0,
+            ..ReserveConfig::default()
+        };
+        assert_eq!(protocol_config.validate_protocol_fees(&config), Ok(()));
+
+        assert_eq!(
+            protocol_config.validate_protocol_fees(&ReserveConfig {
+                protocol_liquidation_fee: 51,
+                ..config
+            }),
+            Err(LendingError::InvalidConfig.into())
+        );
+        assert_eq!(
+            protocol_config.validate_protocol_fees(&ReserveConfig {
+                protocol_take_rate: 21,
+                ..config
+            }),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+}
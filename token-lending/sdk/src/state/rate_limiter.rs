@@ -24,7 +24,8 @@ pub struct RateLimiter {
     prev_qty: Decimal,
     /// window_start is the start of the current window
     window_start: Slot,
-    /// cur qty is the sum of all outflows from [window_start, window_start + config.window_duration)
+    /// cur qty is the sum of all outflows from [window_start, window_start + config.window_duration).
+    /// in net flow mode, inflows in the current window are subtracted from it.
     cur_qty: Decimal,
 }
 
@@ -35,6 +36,9 @@ pub struct RateLimiterConfig {
     pub window_duration: u64,
     /// Rate limiter param. Max outflow of tokens in a window
     pub max_outflow: u64,
+    /// If set, inflows (deposits and repays) in the current window are credited against its
+    /// outflows, so only net outflow is limited. The current window's quantity is floored at zero.
+    pub net_flow: bool,
 }
 
 impl RateLimiter {
@@ -54,6 +58,36 @@ impl RateLimiter {
         }
     }
 
+    /// Rate limiter whose state is stored apart from its config, eg on an obligation while the
+    /// config is on the lending market. State that doesn't line up with the config's windows, eg
+    /// because the config changed since the state was stored, is reset.
+    pub fn from_state(
+        config: RateLimiterConfig,
+        prev_qty: Decimal,
+        window_start: Slot,
+        cur_qty: Decimal,
+        cur_slot: u64,
+    ) -> Self {
+        if config.window_duration == 0
+            || window_start / config.window_duration * config.window_duration != window_start
+            || window_start > cur_slot
+        {
+            return Self::new(config, cur_slot);
+        }
+
+        Self {
+            config,
+            prev_qty,
+            window_start,
+            cur_qty,
+        }
+    }
+
+    /// The previous window's quantity, the current window's start and its quantity
+    pub fn state(&self) -> (Decimal, Slot, Decimal) {
+        (self.prev_qty, self.window_start, self.cur_qty)
+    }
+
     fn _update(&mut self, cur_slot: u64) -> Result<(), ProgramError> {
         if cur_slot < self.window_start {
             msg!("Current slot is less than window start, which is impossible");
@@ -121,6 +155,37 @@ impl RateLimiter {
         Ok(diff)
     }
 
+    /// Calculate remaining outflow at `cur_slot` without updating the rate limiter's state
+    pub fn projected_remaining_outflow(&self, cur_slot: u64) -> Result<Decimal, ProgramError> {
+        let mut rate_limiter = *self;
+        rate_limiter.remaining_outflow(cur_slot)
+    }
+
+    /// First slot at or after `cur_slot` at which the outflows recorded so far no longer count
+    /// against the limit, ie when the full max outflow is available again
+    pub fn full_recovery_slot(&self, cur_slot: u64) -> Result<Slot, ProgramError> {
+        if self.config.window_duration == 0 {
+            return Ok(cur_slot);
+        }
+
+        let mut rate_limiter = *self;
+        rate_limiter._update(cur_slot)?;
+
+        // the previous window's weight reaches zero on the last slot of the current window
+        let cur_window_end = rate_limiter
+            .window_start
+            .saturating_add(self.config.window_duration - 1);
+        let recovery_slot = if rate_limiter.cur_qty > Decimal::zero() {
+            cur_window_end.saturating_add(self.config.window_duration)
+        } else if rate_limiter.prev_qty > Decimal::zero() {
+            cur_window_end
+        } else {
+            cur_slot
+        };
+
+        Ok(std::cmp::max(cur_slot, recovery_slot))
+    }
+
     /// update rate limiter with new quantity. errors if rate limit has been reached
     pub fn update(&mut self, cur_slot: u64, qty: Decimal) -> Result<(), ProgramError> {
         // rate limiter is disabled if window duration == 0. this is here because we don't want to
@@ -139,6 +204,23 @@ impl RateLimiter {
             Ok(())
         }
     }
+
+    /// credit an inflow against the current window's outflow. no-op unless the rate limiter is in
+    /// net flow mode.
+    pub fn credit_inflow(&mut self, cur_slot: u64, qty: Decimal) -> Result<(), ProgramError> {
+        if self.config.window_duration == 0 || !self.config.net_flow {
+            return Ok(());
+        }
+
+        self._update(cur_slot)?;
+
+        self.cur_qty = if qty >= self.cur_qty {
+            Decimal::zero()
+        } else {
+            self.cur_qty.try_sub(qty)?
+        };
+        Ok(())
+    }
 }
 
 impl Default for RateLimiter {
@@ -147,6 +229,7 @@ impl Default for RateLimiter {
             RateLimiterConfig {
                 window_duration: 1,
                 max_outflow: u64::MAX,
+                net_flow: false,
             },
             1,
         )
@@ -161,7 +244,8 @@ impl IsInitialized for RateLimiter {
     }
 }
 
-/// Size of RateLimiter when packed into account
+/// Size of RateLimiter when packed into account. `config.net_flow` isn't part of it, the accounts
+/// owning a rate limiter pack it separately.
 pub const RATE_LIMITER_LEN: usize = 56;
 impl Pack for RateLimiter {
     const LEN: usize = RATE_LIMITER_LEN;
@@ -196,6 +280,7 @@ impl Pack for RateLimiter {
             config: RateLimiterConfig {
                 max_outflow: u64::from_le_bytes(*config_max_outflow_src),
                 window_duration: u64::from_le_bytes(*config_window_duration_src),
+                net_flow: false,
             },
             prev_qty: unpack_decimal(prev_qty_src),
             window_start: u64::from_le_bytes(*window_start_src),
@@ -217,6 +302,7 @@ pub fn rand_rate_limiter() -> RateLimiter {
         config: RateLimiterConfig {
             window_duration: rng.gen(),
             max_outflow: rng.gen(),
+            net_flow: rng.gen(),
         },
         prev_qty: rand_decimal(),
         window_start: rng.gen(),
@@ -234,6 +320,7 @@ mod test {
             RateLimiterConfig {
                 window_duration: 10,
                 max_outflow: 100,
+                net_flow: false,
             },
             10,
         );
@@ -300,4 +387,108 @@ mod test {
         }
         println!("{:#?}", rate_limiter);
     }
+
+    #[test]
+    fn test_net_flow_rate_limiter() {
+        let mut rate_limiter = RateLimiter::new(
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 100,
+                net_flow: false,
+            },
+            10,
+        );
+
+        // inflows are ignored outside of net flow mode
+        assert_eq!(rate_limiter.update(10, Decimal::from(100u64)), Ok(()));
+        assert_eq!(rate_limiter.credit_inflow(10, Decimal::from(50u64)), Ok(()));
+        assert_eq!(rate_limiter.remaining_outflow(10), Ok(Decimal::zero()));
+
+        let mut rate_limiter = RateLimiter::new(
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 100,
+                net_flow: true,
+            },
+            10,
+        );
+
+        assert_eq!(rate_limiter.update(10, Decimal::from(100u64)), Ok(()));
+        assert_eq!(
+            rate_limiter.update(11, Decimal::from(1u64)),
+            Err(LendingError::OutflowRateLimitExceeded.into())
+        );
+
+        // an inflow frees up capacity in the same window
+        assert_eq!(rate_limiter.credit_inflow(11, Decimal::from(60u64)), Ok(()));
+        assert_eq!(rate_limiter.remaining_outflow(11), Ok(Decimal::from(60u64)));
+        assert_eq!(rate_limiter.update(11, Decimal::from(60u64)), Ok(()));
+        assert_eq!(rate_limiter.remaining_outflow(11), Ok(Decimal::zero()));
+
+        // the current window is floored at zero, so inflows can't be banked
+        assert_eq!(
+            rate_limiter.credit_inflow(12, Decimal::from(1000u64)),
+            Ok(())
+        );
+        assert_eq!(
+            rate_limiter.remaining_outflow(12),
+            Ok(Decimal::from(100u64))
+        );
+
+        // inflows don't reduce the previous window's outflow
+        assert_eq!(rate_limiter.update(19, Decimal::from(100u64)), Ok(()));
+        assert_eq!(
+            rate_limiter.credit_inflow(20, Decimal::from(100u64)),
+            Ok(())
+        );
+        assert_eq!(rate_limiter.remaining_outflow(20), Ok(Decimal::from(10u64)));
+    }
+
+    #[test]
+    fn test_rate_limiter_projection() {
+        let mut rate_limiter = RateLimiter::new(
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 100,
+                net_flow: false,
+            },
+            10,
+        );
+        assert_eq!(rate_limiter.full_recovery_slot(12), Ok(12));
+
+        assert_eq!(rate_limiter.update(12, Decimal::from(40u64)), Ok(()));
+        let before = rate_limiter;
+
+        // projections don't touch the rate limiter's state
+        assert_eq!(
+            rate_limiter.projected_remaining_outflow(12),
+            Ok(Decimal::from(60u64))
+        );
+        assert_eq!(
+            rate_limiter.projected_remaining_outflow(25),
+            Ok(Decimal::from(84u64))
+        );
+        assert_eq!(rate_limiter, before);
+
+        // the outflow in [10, 20) stops counting on slot 29
+        assert_eq!(rate_limiter.full_recovery_slot(12), Ok(29));
+        assert_eq!(rate_limiter.full_recovery_slot(25), Ok(29));
+        assert_eq!(
+            rate_limiter.projected_remaining_outflow(28),
+            Ok(Decimal::from(96u64))
+        );
+        assert_eq!(
+            rate_limiter.projected_remaining_outflow(29),
+            Ok(Decimal::from(100u64))
+        );
+        assert_eq!(rate_limiter.full_recovery_slot(40), Ok(40));
+
+        // disabled rate limiters are always fully recovered
+        let rate_limiter = RateLimiter::new(RateLimiterConfig::default(), 10);
+        assert_eq!(
+            rate_limiter.projected_remaining_outflow(10),
+            Ok(Decimal::from(u64::MAX))
+        );
+        assert_eq!(rate_limiter.full_recovery_slot(10), Ok(10));
+    }
 }
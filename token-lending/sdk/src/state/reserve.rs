@@ -23,6 +23,9 @@ use std::{
 /// Percentage of an obligation that can be repaid during each liquidation call
 pub const LIQUIDATION_CLOSE_FACTOR: u8 = 20;
 
+/// Longest a liquidation threshold ramp can last, about a year of slots
+pub const MAX_LIQUIDATION_THRESHOLD_RAMP_SLOTS: u64 = 80_000_000;
+
 /// Obligation borrow amount that is small enough to close out
 pub const LIQUIDATION_CLOSE_AMOUNT: u64 = 2;
 
@@ -60,6 +63,41 @@ pub struct Reserve {
     pub rate_limiter: RateLimiter,
     /// Attributed borrows in USD
     pub attributed_borrow_value: Decimal,
+    /// Ramp of the liquidation thresholds down to the config's, if one was started
+    pub liquidation_threshold_ramp: Option<LiquidationThresholdRamp>,
+}
+
+/// Linear ramp of a reserve's liquidation thresholds from the values they had when it started to
+/// the reserve config's, so that lowering them doesn't make obligations liquidatable at once
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LiquidationThresholdRamp {
+    /// Liquidation threshold when the ramp started
+    pub start_liquidation_threshold: u8,
+    /// Max liquidation threshold when the ramp started
+    pub start_max_liquidation_threshold: u8,
+    /// Slot the ramp started in
+    pub start_slot: Slot,
+    /// Slot the config's liquidation thresholds are reached in
+    pub end_slot: Slot,
+}
+
+impl LiquidationThresholdRamp {
+    /// Linearly interpolates between `start` and `end` in `slot`, rounding towards `start`
+    pub fn interpolate(&self, start: u8, end: u8, slot: Slot) -> u8 {
+        if slot >= self.end_slot {
+            return end;
+        }
+
+        let elapsed = slot.saturating_sub(self.start_slot) as i128;
+        let duration = (self.end_slot - self.start_slot) as i128;
+        let delta = end as i128 - start as i128;
+        (start as i128 + delta * elapsed / duration) as u8
+    }
+
+    /// Share of the ramp completed in `slot`, in percent
+    pub fn progress(&self, slot: Slot) -> u8 {
+        self.interpolate(0, 100, slot)
+    }
 }
 
 impl Reserve {
@@ -80,6 +118,68 @@ impl Reserve {
         self.config = params.config;
         self.rate_limiter = RateLimiter::new(params.rate_limiter_config, params.current_slot);
         self.attributed_borrow_value = Decimal::zero();
+        self.liquidation_threshold_ramp = None;
+    }
+
+    /// Liquidation threshold obligations are refreshed with in `slot`, following the liquidation
+    /// threshold ramp if one is in progress
+    pub fn liquidation_threshold(&self, slot: Slot) -> u8 {
+        match &self.liquidation_threshold_ramp {
+            Some(ramp) => ramp.interpolate(
+                ramp.start_liquidation_threshold,
+                self.config.liquidation_threshold,
+                slot,
+            ),
+            None => self.config.liquidation_threshold,
+        }
+    }
+
+    /// Max liquidation threshold obligations are refreshed with in `slot`, following the
+    /// liquidation threshold ramp if one is in progress
+    pub fn max_liquidation_threshold(&self, slot: Slot) -> u8 {
+        match &self.liquidation_threshold_ramp {
+            Some(ramp) => max(
+                ramp.interpolate(
+                    ramp.start_max_liquidation_threshold,
+                    self.config.max_liquidation_threshold,
+                    slot,
+                ),
+                self.liquidation_threshold(slot),
+            ),
+            None => self.config.max_liquidation_threshold,
+        }
+    }
+
+    /// Sets the config, ramping the liquidation thresholds down to it over `ramp_slots` if it
+    /// lowers either of them. A config that keeps both thresholds leaves a ramp in progress as is.
+    pub fn set_config_with_liquidation_threshold_ramp(
+        &mut self,
+        config: ReserveConfig,
+        ramp_slots: u64,
+        slot: Slot,
+    ) {
+        let unchanged = config.liquidation_threshold == self.config.liquidation_threshold
+            && config.max_liquidation_threshold == self.config.max_liquidation_threshold;
+        let start_liquidation_threshold = self.liquidation_threshold(slot);
+        let start_max_liquidation_threshold = self.max_liquidation_threshold(slot);
+        self.config = config;
+
+        if unchanged {
+            return;
+        }
+
+        let lowered = self.config.liquidation_threshold < start_liquidation_threshold
+            || self.config.max_liquidation_threshold < start_max_liquidation_threshold;
+        self.liquidation_threshold_ramp = if ramp_slots > 0 && lowered {
+            Some(LiquidationThresholdRamp {
+                start_liquidation_threshold,
+                start_max_liquidation_threshold,
+                start_slot: slot,
+                end_slot: slot.saturating_add(ramp_slots),
+            })
+        } else {
+            None
+        };
     }
 
     /// get borrow weight. Guaranteed to be greater than 1
@@ -578,6 +678,76 @@ impl Reserve {
                 .try_floor_u64()?,
         ))
     }
+
+    /// Project the outflow rate limits that apply to a borrow or withdraw against this reserve at
+    /// `slot`. The market limit is converted to liquidity tokens at the reserve's upper bound
+    /// price, so the reserve should be refreshed first.
+    pub fn outflow_limits(
+        &self,
+        lending_market: &LendingMarket,
+        slot: Slot,
+    ) -> Result<ReserveOutflowLimits, ProgramError> {
+        let lending_market_remaining_outflow = lending_market
+            .rate_limiter
+            .projected_remaining_outflow(slot)?;
+        let reserve_remaining_outflow = self.rate_limiter.projected_remaining_outflow(slot)?;
+
+        let lending_market_limit = if lending_market.rate_limiter.config.window_duration == 0 {
+            None
+        } else {
+            Some(self.usd_to_liquidity_amount_lower_bound(lending_market_remaining_outflow)?)
+        };
+        let reserve_limited = self.rate_limiter.config.window_duration != 0;
+
+        let (max_outflow_liquidity_amount, binding_limit) =
+            match (lending_market_limit, reserve_limited) {
+                (Some(amount), _) if amount < reserve_remaining_outflow => {
+                    (amount, OutflowLimit::LendingMarket)
+                }
+                (_, true) => (reserve_remaining_outflow, OutflowLimit::Reserve),
+                (Some(amount), false) => (amount, OutflowLimit::LendingMarket),
+                (None, false) => (reserve_remaining_outflow, OutflowLimit::None),
+            };
+
+        Ok(ReserveOutflowLimits {
+            lending_market_remaining_outflow,
+            lending_market_full_recovery_slot: lending_market
+                .rate_limiter
+                .full_recovery_slot(slot)?,
+            reserve_remaining_outflow,
+            reserve_full_recovery_slot: self.rate_limiter.full_recovery_slot(slot)?,
+            max_outflow_liquidity_amount,
+            binding_limit,
+        })
+    }
+}
+
+/// Rate limiter bounding the outflow of a reserve
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutflowLimit {
+    /// Neither rate limiter is enabled
+    None,
+    /// Lending market rate limiter
+    LendingMarket,
+    /// Reserve rate limiter
+    Reserve,
+}
+
+/// Projected outflow limits for a borrow or withdraw against a reserve
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReserveOutflowLimits {
+    /// Remaining lending market outflow, in the quote currency
+    pub lending_market_remaining_outflow: Decimal,
+    /// Slot at which the lending market's full max outflow is available again
+    pub lending_market_full_recovery_slot: Slot,
+    /// Remaining reserve outflow, in liquidity tokens
+    pub reserve_remaining_outflow: Decimal,
+    /// Slot at which the reserve's full max outflow is available again
+    pub reserve_full_recovery_slot: Slot,
+    /// Max liquidity that can currently leave the reserve through a borrow or withdraw
+    pub max_outflow_liquidity_amount: Decimal,
+    /// Rate limiter that sets max_outflow_liquidity_amount
+    pub binding_limit: OutflowLimit,
 }
 
 /// Initialize a reserve
@@ -668,6 +838,9 @@ pub struct ReserveLiquidity {
     pub smoothed_market_price: Decimal,
     /// Extra price obtained from the optional extra oracle
     pub extra_market_price: Option<Decimal>,
+    /// Whether the oracle price was outside the peg band on the last refresh. Only set for
+    /// reserves with a pegged price.
+    pub depegged: bool,
 }
 
 impl ReserveLiquidity {
@@ -686,6 +859,7 @@ impl ReserveLiquidity {
             market_price: params.market_price,
             smoothed_market_price: params.smoothed_market_price,
             extra_market_price: None,
+            depegged: false,
         }
     }
 
@@ -982,6 +1156,14 @@ pub struct ReserveConfig {
     pub attributed_borrow_limit_open: u64,
     /// Close Attributed Borrow limit in USD
     pub attributed_borrow_limit_close: u64,
+    /// Underlying price oracle for stake pool (LST) reserves. When set, the primary oracle is the
+    /// stake pool account and the price is the pool's exchange rate times this oracle's price.
+    pub underlying_oracle_pubkey: Option<Pubkey>,
+    /// Whether the reserve reports a fixed $1 price instead of its oracle price (stablecoins)
+    pub peg_mode: PegMode,
+    /// Max deviation of the oracle price from $1, in basis points, before a pegged reserve is
+    /// considered depegged
+    pub peg_max_deviation_bps: u16,
 }
 
 /// validates reserve configs
@@ -1086,6 +1268,13 @@ pub fn validate_reserve_config(config: ReserveConfig) -> ProgramResult {
         return Err(LendingError::InvalidConfig.into());
     }
 
+    if config.peg_mode != PegMode::Disabled
+        && (config.peg_max_deviation_bps == 0 || config.peg_max_deviation_bps > 10_000)
+    {
+        msg!("peg max deviation must be in range (0, 10000] bps for pegged reserves");
+        return Err(LendingError::InvalidConfig.into());
+    }
+
     Ok(())
 }
 
@@ -1110,6 +1299,31 @@ impl FromStr for ReserveType {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, FromPrimitive)]
+/// Pegged price mode of the reserve
+pub enum PegMode {
+    #[default]
+    /// the reserve is priced by its oracle
+    Disabled = 0,
+    /// the reserve is priced at $1, or at the oracle price while depegged
+    FallbackToOracle = 1,
+    /// the reserve is priced at $1, or at the oracle price while depegged. Borrows are halted
+    /// while depegged.
+    HaltBorrows = 2,
+}
+
+impl FromStr for PegMode {
+    type Err = ProgramError;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "Disabled" => Ok(PegMode::Disabled),
+            "FallbackToOracle" => Ok(PegMode::FallbackToOracle),
+            "HaltBorrows" => Ok(PegMode::HaltBorrows),
+            _ => Err(LendingError::InvalidConfig.into()),
+        }
+    }
+}
+
 /// Additional fee information on a reserve
 ///
 /// These exist separately from interest accrual fees, and are specifically for the program owner
@@ -1141,14 +1355,25 @@ impl ReserveFees {
         self.calculate_fees(borrow_amount, self.borrow_fee_wad, fee_calculation)
     }
 
-    /// Calculate the owner and host fees on flash loan
+    /// Calculate the owner and host fees on flash loan, waiving `fee_discount_bps` basis points
+    /// of the flash loan fee rate
     pub fn calculate_flash_loan_fees(
         &self,
         flash_loan_amount: Decimal,
+        fee_discount_bps: u16,
     ) -> Result<(u64, u64), ProgramError> {
+        if fee_discount_bps > MAX_FLASH_LOAN_FEE_DISCOUNT_BPS {
+            msg!("Flash loan fee discount must be in range [0, 10000] bps");
+            return Err(LendingError::InvalidConfig.into());
+        }
+        let flash_loan_fee_wad = (self.flash_loan_fee_wad as u128)
+            .checked_mul((MAX_FLASH_LOAN_FEE_DISCOUNT_BPS - fee_discount_bps) as u128)
+            .and_then(|fee| fee.checked_div(MAX_FLASH_LOAN_FEE_DISCOUNT_BPS as u128))
+            .ok_or(LendingError::MathOverflow)? as u64;
+
         let (total_fees, host_fee) = self.calculate_fees(
             flash_loan_amount,
-            self.flash_loan_fee_wad,
+            flash_loan_fee_wad,
             FeeCalculation::Exclusive,
         )?;
 
@@ -1223,6 +1448,9 @@ impl IsInitialized for Reserve {
     }
 }
 
+/// Liquidation threshold ramp slots are packed into 5 bytes to fit the reserve's padding, enough
+/// for thousands of years of slots
+const RAMP_SLOT_LEN: usize = 5;
 const RESERVE_LEN: usize = 619; // 1 + 8 + 1 + 32 + 32 + 1 + 32 + 32 + 32 + 8 + 16 + 16 + 16 + 32 + 8 + 32 + 1 + 1 + 1 + 1 + 1 + 1 + 1 + 8 + 8 + 1 + 8 + 8 + 32 + 1 + 1 + 16 + 230
 impl Pack for Reserve {
     const LEN: usize = RESERVE_LEN;
@@ -1279,7 +1507,15 @@ impl Pack for Reserve {
             attributed_borrow_value,
             config_attributed_borrow_limit_open,
             config_attributed_borrow_limit_close,
-            _padding,
+            config_underlying_oracle_pubkey,
+            config_peg_mode,
+            config_peg_max_deviation_bps,
+            liquidity_depegged,
+            rate_limiter_net_flow,
+            ramp_start_liquidation_threshold,
+            ramp_start_max_liquidation_threshold,
+            ramp_start_slot,
+            ramp_end_slot,
         ) = mut_array_refs![
             output,
             1,
@@ -1329,7 +1565,15 @@ impl Pack for Reserve {
             16,
             8,
             8,
-            49
+            PUBKEY_BYTES,
+            1,
+            2,
+            1,
+            1,
+            1,
+            1,
+            RAMP_SLOT_LEN,
+            RAMP_SLOT_LEN
         ];
 
         // reserve
@@ -1413,6 +1657,21 @@ impl Pack for Reserve {
             self.config.attributed_borrow_limit_open.to_le_bytes();
         *config_attributed_borrow_limit_close =
             self.config.attributed_borrow_limit_close.to_le_bytes();
+        match self.config.underlying_oracle_pubkey {
+            Some(pubkey) => config_underlying_oracle_pubkey.copy_from_slice(pubkey.as_ref()),
+            None => config_underlying_oracle_pubkey.copy_from_slice(&[0u8; PUBKEY_BYTES]),
+        };
+        *config_peg_mode = (self.config.peg_mode as u8).to_le_bytes();
+        *config_peg_max_deviation_bps = self.config.peg_max_deviation_bps.to_le_bytes();
+        pack_bool(self.liquidity.depegged, liquidity_depegged);
+        pack_bool(self.rate_limiter.config.net_flow, rate_limiter_net_flow);
+
+        // a ramp that ends in slot 0 is no ramp
+        let ramp = self.liquidation_threshold_ramp.unwrap_or_default();
+        *ramp_start_liquidation_threshold = ramp.start_liquidation_threshold.to_le_bytes();
+        *ramp_start_max_liquidation_threshold = ramp.start_max_liquidation_threshold.to_le_bytes();
+        ramp_start_slot.copy_from_slice(&ramp.start_slot.to_le_bytes()[..RAMP_SLOT_LEN]);
+        ramp_end_slot.copy_from_slice(&ramp.end_slot.to_le_bytes()[..RAMP_SLOT_LEN]);
 
         pack_decimal(self.attributed_borrow_value, attributed_borrow_value);
     }
@@ -1469,7 +1728,15 @@ impl Pack for Reserve {
             attributed_borrow_value,
             config_attributed_borrow_limit_open,
             config_attributed_borrow_limit_close,
-            _padding,
+            config_underlying_oracle_pubkey,
+            config_peg_mode,
+            config_peg_max_deviation_bps,
+            liquidity_depegged,
+            rate_limiter_net_flow,
+            ramp_start_liquidation_threshold,
+            ramp_start_max_liquidation_threshold,
+            ramp_start_slot,
+            ramp_end_slot,
         ) = array_refs![
             input,
             1,
@@ -1519,7 +1786,15 @@ impl Pack for Reserve {
             16,
             8,
             8,
-            49
+            PUBKEY_BYTES,
+            1,
+            2,
+            1,
+            1,
+            1,
+            1,
+            RAMP_SLOT_LEN,
+            RAMP_SLOT_LEN
         ];
 
         let version = u8::from_le_bytes(*version);
@@ -1543,6 +1818,9 @@ impl Pack for Reserve {
             u8::from_le_bytes(*config_max_liquidation_threshold),
         );
 
+        let mut rate_limiter = RateLimiter::unpack_from_slice(rate_limiter)?;
+        rate_limiter.config.net_flow = unpack_bool(rate_limiter_net_flow)?;
+
         Ok(Self {
             version,
             last_update: LastUpdate {
@@ -1574,6 +1852,7 @@ impl Pack for Reserve {
                         return Err(ProgramError::InvalidAccountData);
                     }
                 },
+                depegged: unpack_bool(liquidity_depegged)?,
             },
             collateral: ReserveCollateral {
                 mint_pubkey: Pubkey::new_from_array(*collateral_mint_pubkey),
@@ -1644,13 +1923,45 @@ impl Pack for Reserve {
                         value
                     }
                 },
+                underlying_oracle_pubkey: if config_underlying_oracle_pubkey == &[0; 32] {
+                    None
+                } else {
+                    Some(Pubkey::new_from_array(*config_underlying_oracle_pubkey))
+                },
+                peg_mode: match PegMode::from_u8(config_peg_mode[0]) {
+                    Some(peg_mode) => peg_mode,
+                    None => {
+                        msg!("Invalid peg mode");
+                        return Err(ProgramError::InvalidAccountData);
+                    }
+                },
+                peg_max_deviation_bps: u16::from_le_bytes(*config_peg_max_deviation_bps),
             },
-            rate_limiter: RateLimiter::unpack_from_slice(rate_limiter)?,
+            rate_limiter,
             attributed_borrow_value: unpack_decimal(attributed_borrow_value),
+            liquidation_threshold_ramp: match unpack_ramp_slot(ramp_end_slot) {
+                0 => None,
+                end_slot => Some(LiquidationThresholdRamp {
+                    start_liquidation_threshold: u8::from_le_bytes(
+                        *ramp_start_liquidation_threshold,
+                    ),
+                    start_max_liquidation_threshold: u8::from_le_bytes(
+                        *ramp_start_max_liquidation_threshold,
+                    ),
+                    start_slot: unpack_ramp_slot(ramp_start_slot),
+                    end_slot,
+                }),
+            },
         })
     }
 }
 
+fn unpack_ramp_slot(src: &[u8; RAMP_SLOT_LEN]) -> Slot {
+    let mut slot = [0u8; 8];
+    slot[..RAMP_SLOT_LEN].copy_from_slice(src);
+    u64::from_le_bytes(slot)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1703,6 +2014,7 @@ mod test {
                     market_price: rand_decimal(),
                     smoothed_market_price: rand_decimal(),
                     extra_market_price,
+                    depegged: rng.gen(),
                 },
                 collateral: ReserveCollateral {
                     mint_pubkey: Pubkey::new_unique(),
@@ -1737,9 +2049,26 @@ mod test {
                     extra_oracle_pubkey,
                     attributed_borrow_limit_open: rng.gen(),
                     attributed_borrow_limit_close: rng.gen(),
+                    underlying_oracle_pubkey: if rng.gen_bool(0.5) {
+                        Some(Pubkey::new_unique())
+                    } else {
+                        None
+                    },
+                    peg_mode: PegMode::from_u8(rng.gen::<u8>() % 3).unwrap(),
+                    peg_max_deviation_bps: rng.gen(),
                 },
                 rate_limiter: rand_rate_limiter(),
                 attributed_borrow_value: rand_decimal(),
+                liquidation_threshold_ramp: if rng.gen_bool(0.5) {
+                    Some(LiquidationThresholdRamp {
+                        start_liquidation_threshold: rng.gen(),
+                        start_max_liquidation_threshold: rng.gen(),
+                        start_slot: rng.gen::<u32>() as u64,
+                        end_slot: rng.gen::<u32>() as u64 + 1,
+                    })
+                } else {
+                    None
+                },
             };
 
             let mut packed = [0u8; Reserve::LEN];
@@ -1749,6 +2078,91 @@ mod test {
         }
     }
 
+    #[test]
+    fn liquidation_threshold_ramp() {
+        let mut reserve = Reserve {
+            config: ReserveConfig {
+                loan_to_value_ratio: 30,
+                liquidation_threshold: 80,
+                max_liquidation_threshold: 90,
+                ..ReserveConfig::default()
+            },
+            ..Reserve::default()
+        };
+
+        reserve.set_config_with_liquidation_threshold_ramp(
+            ReserveConfig {
+                liquidation_threshold: 30,
+                max_liquidation_threshold: 40,
+                ..reserve.config
+            },
+            100,
+            1_000,
+        );
+        assert_eq!(
+            reserve.liquidation_threshold_ramp,
+            Some(LiquidationThresholdRamp {
+                start_liquidation_threshold: 80,
+                start_max_liquidation_threshold: 90,
+                start_slot: 1_000,
+                end_slot: 1_100,
+            })
+        );
+
+        assert_eq!(reserve.liquidation_threshold(1_000), 80);
+        assert_eq!(reserve.max_liquidation_threshold(1_000), 90);
+        assert_eq!(reserve.liquidation_threshold(1_050), 55);
+        assert_eq!(reserve.max_liquidation_threshold(1_050), 65);
+        // rounds towards the start
+        assert_eq!(reserve.liquidation_threshold(1_001), 80);
+        assert_eq!(reserve.liquidation_threshold(1_100), 30);
+        assert_eq!(reserve.max_liquidation_threshold(2_000), 40);
+
+        // unrelated changes keep the ramp going
+        reserve.set_config_with_liquidation_threshold_ramp(
+            ReserveConfig {
+                deposit_limit: 1,
+                ..reserve.config
+            },
+            0,
+            1_050,
+        );
+        assert_eq!(reserve.liquidation_threshold(1_050), 55);
+
+        // lowering the thresholds further restarts the ramp from where it is
+        reserve.set_config_with_liquidation_threshold_ramp(
+            ReserveConfig {
+                liquidation_threshold: 30,
+                max_liquidation_threshold: 35,
+                ..reserve.config
+            },
+            10,
+            1_050,
+        );
+        assert_eq!(
+            reserve.liquidation_threshold_ramp,
+            Some(LiquidationThresholdRamp {
+                start_liquidation_threshold: 55,
+                start_max_liquidation_threshold: 65,
+                start_slot: 1_050,
+                end_slot: 1_060,
+            })
+        );
+
+        // changes without a ramp take effect immediately
+        reserve.set_config_with_liquidation_threshold_ramp(
+            ReserveConfig {
+                liquidation_threshold: 31,
+                ..reserve.config
+            },
+            0,
+            1_055,
+        );
+        assert_eq!(reserve.liquidation_threshold_ramp, None);
+        assert_eq!(reserve.liquidation_threshold(1_055), 31);
+        assert_eq!(reserve.max_liquidation_threshold(1_055), 35);
+    }
+
     const MAX_LIQUIDITY: u64 = u64::MAX / 5;
 
     fn utilizations() -> impl Strategy<Value = (u8, u8)> {
@@ -2021,7 +2435,7 @@ mod test {
                 flash_loan_fee_wad,
                 host_fee_percentage,
             };
-            let (origination_fee, host_fee) = fees.calculate_flash_loan_fees(Decimal::from(borrow_amount))?;
+            let (origination_fee, host_fee) = fees.calculate_flash_loan_fees(Decimal::from(borrow_amount), 0)?;
 
             // The total fee can't be greater than the amount borrowed, as long
             // as amount borrowed is greater than 2.
@@ -2137,6 +2551,37 @@ mod test {
         assert_eq!(host_fee, 0); // 0 host fee
     }
 
+    #[test]
+    fn flash_loan_fee_calculation_discount() {
+        let fees = ReserveFees {
+            borrow_fee_wad: 0,
+            flash_loan_fee_wad: 3_000_000_000_000_000, // 0.3%
+            host_fee_percentage: 20,
+        };
+
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(100_000u64), 0)
+                .unwrap(),
+            (240, 60)
+        );
+        // half the fee rate is waived
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(100_000u64), 5_000)
+                .unwrap(),
+            (120, 30)
+        );
+        // the whole fee is waived, including the minimum fee
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(100_000u64), 10_000)
+                .unwrap(),
+            (0, 0)
+        );
+        assert_eq!(
+            fees.calculate_flash_loan_fees(Decimal::from(100_000u64), 10_001),
+            Err(LendingError::InvalidConfig.into())
+        );
+    }
+
     #[test]
     fn calculate_protocol_liquidation_fee() {
         let reserve = Reserve {
@@ -2301,6 +2746,71 @@ mod test {
         );
     }
 
+    #[test]
+    fn outflow_limits() {
+        let lending_market = LendingMarket {
+            rate_limiter: RateLimiter::new(
+                RateLimiterConfig {
+                    window_duration: 10,
+                    max_outflow: 100,
+                    net_flow: false,
+                },
+                0,
+            ),
+            ..LendingMarket::default()
+        };
+        let mut reserve = Reserve {
+            liquidity: ReserveLiquidity {
+                mint_decimals: 0,
+                market_price: Decimal::from(10u64),
+                smoothed_market_price: Decimal::from(10u64),
+                ..ReserveLiquidity::default()
+            },
+            rate_limiter: RateLimiter::new(
+                RateLimiterConfig {
+                    window_duration: 10,
+                    max_outflow: 20,
+                    net_flow: false,
+                },
+                0,
+            ),
+            ..Reserve::default()
+        };
+
+        // 100 USD of market outflow is 10 tokens, tighter than the reserve's 20 tokens
+        let limits = reserve.outflow_limits(&lending_market, 5).unwrap();
+        assert_eq!(
+            limits,
+            ReserveOutflowLimits {
+                lending_market_remaining_outflow: Decimal::from(100u64),
+                lending_market_full_recovery_slot: 5,
+                reserve_remaining_outflow: Decimal::from(20u64),
+                reserve_full_recovery_slot: 5,
+                max_outflow_liquidity_amount: Decimal::from(10u64),
+                binding_limit: OutflowLimit::LendingMarket,
+            }
+        );
+
+        reserve
+            .rate_limiter
+            .update(5, Decimal::from(15u64))
+            .unwrap();
+        let limits = reserve.outflow_limits(&lending_market, 5).unwrap();
+        assert_eq!(limits.max_outflow_liquidity_amount, Decimal::from(5u64));
+        assert_eq!(limits.binding_limit, OutflowLimit::Reserve);
+        assert_eq!(limits.reserve_full_recovery_slot, 19);
+
+        // both rate limiters disabled
+        let lending_market = LendingMarket {
+            rate_limiter: RateLimiter::new(RateLimiterConfig::default(), 0),
+            ..LendingMarket::default()
+        };
+        reserve.rate_limiter = RateLimiter::new(RateLimiterConfig::default(), 0);
+        let limits = reserve.outflow_limits(&lending_market, 5).unwrap();
+        assert_eq!(limits.max_outflow_liquidity_amount, Decimal::from(u64::MAX));
+        assert_eq!(limits.binding_limit, OutflowLimit::None);
+    }
+
     #[derive(Debug, Clone)]
     struct ReserveConfigTestCase {
         config: ReserveConfig,